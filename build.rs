@@ -0,0 +1,9 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=proto");
+    #[cfg(feature = "grpc")]
+    {
+        let descriptors = protox::compile(["merkle_trie.proto"], ["proto"]).unwrap();
+        tonic_build::configure().compile_fds(descriptors).unwrap();
+    }
+}
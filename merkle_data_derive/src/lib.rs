@@ -0,0 +1,56 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Fields, Index};
+
+/// Derives `MerkleData` for a struct whose fields all implement it. The struct commits to each
+/// field's bytes in declaration order, each preceded by its length as a big-endian `u64`, so no
+/// two distinct values encode the same way however their fields' bytes run together.
+#[proc_macro_derive(MerkleData)]
+pub fn derive_merkle_data(input: TokenStream) -> TokenStream {
+    let mut input = parse_macro_input!(input as DeriveInput);
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return syn::Error::new_spanned(
+                &input.ident,
+                "MerkleData can only be derived for structs",
+            )
+            .to_compile_error()
+            .into()
+        }
+    };
+    let accessors: Vec<_> = match fields {
+        Fields::Named(fields) => fields
+            .named
+            .iter()
+            .map(|field| {
+                let name = field.ident.as_ref().unwrap();
+                quote!(#name)
+            })
+            .collect(),
+        Fields::Unnamed(fields) => (0..fields.unnamed.len())
+            .map(|position| {
+                let position = Index::from(position);
+                quote!(#position)
+            })
+            .collect(),
+        Fields::Unit => vec![],
+    };
+
+    let merkle_data = quote!(::binary_tree_blockchain::merkle_data::MerkleData);
+    for param in input.generics.type_params_mut() {
+        param.bounds.push(parse_quote!(#merkle_data));
+    }
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    quote! {
+        impl #impl_generics #merkle_data for #name #type_generics #where_clause {
+            fn merkle_bytes(&self) -> ::std::borrow::Cow<'_, [u8]> {
+                let mut bytes = ::std::vec::Vec::new();
+                #(::binary_tree_blockchain::merkle_data::push_field(&mut bytes, &self.#accessors);)*
+                ::std::borrow::Cow::Owned(bytes)
+            }
+        }
+    }
+    .into()
+}
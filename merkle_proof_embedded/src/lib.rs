@@ -0,0 +1,128 @@
+//! A proof verifier for targets without an allocator, e.g. microcontrollers and HSMs. It reads
+//! a proof in its `to_bytes` encoding straight from a borrowed slice and streams every node
+//! through the hasher, so nothing is copied or allocated. The crate is `#![no_std]` and doesn't
+//! use `alloc`; `binary_tree_blockchain` re-exports it as its `embedded` module.
+
+#![no_std]
+
+use core::convert::TryInto;
+
+/// A hash function fed incrementally, with fixed-size digests. A node hashes the concatenation
+/// of its data hash and its children's roots, as `MerkleHasher::combine_children` does by
+/// default, so a `StreamingHasher` agrees with any `MerkleHasher` that hashes the same bytes
+/// and combines by concatenation, such as `DigestHasher` and `Blake3Hasher`.
+pub trait StreamingHasher {
+    type Digest: AsRef<[u8]> + PartialEq;
+    type State;
+
+    fn start(&self) -> Self::State;
+
+    fn update(&self, state: &mut Self::State, bytes: &[u8]);
+
+    fn finish(&self, state: Self::State) -> Self::Digest;
+
+    /// The length of every digest, which each hash in a proof must match.
+    fn digest_len(&self) -> usize;
+}
+
+/// Whether `a` and `b` are equal, in time that depends only on their lengths: every byte pair
+/// is compared, and `black_box` keeps the compiler from stopping at the first difference.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let difference = a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y));
+    core::hint::black_box(difference) == 0
+}
+
+// The next `len` bytes of a proof, advancing past them.
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    let taken = bytes.get(..len)?;
+    *bytes = &bytes[len..];
+    Some(taken)
+}
+
+fn take_u16(bytes: &mut &[u8]) -> Option<u16> {
+    Some(u16::from_be_bytes(take(bytes, 2)?.try_into().ok()?))
+}
+
+fn take_hash<'a, H: StreamingHasher>(hasher: &H, bytes: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = take_u16(bytes)? as usize;
+    (len == hasher.digest_len()).then_some(())?;
+    take(bytes, len)
+}
+
+/// Whether `proof`, a format 1 or 2 proof as written by `MerkleProof::to_bytes`, shows `value` (its
+/// `merkle_bytes`) stored under `key` in the trie with root `root`. Runs without allocating.
+pub fn verify_proof_bytes<H: StreamingHasher>(
+    hasher: &H,
+    proof: &[u8],
+    root: &[u8],
+    key: u32,
+    value: &[u8],
+) -> bool {
+    root_for(hasher, proof, key, value)
+        .is_some_and(|computed| constant_time_eq(computed.as_ref(), root))
+}
+
+fn root_for<H: StreamingHasher>(
+    hasher: &H,
+    mut bytes: &[u8],
+    key: u32,
+    value: &[u8],
+) -> Option<H::Digest> {
+    let bytes = &mut bytes;
+    let version = take(bytes, 1)?[0];
+    if !(1..=2).contains(&version) {
+        return None;
+    }
+    if u32::from_be_bytes(take(bytes, 4)?.try_into().ok()?) != key {
+        return None;
+    }
+    let arity = take_u16(bytes)? as usize;
+    if !arity.is_power_of_two() || !(2..=256).contains(&arity) {
+        return None;
+    }
+    let bits_per_digit = arity.trailing_zeros();
+    let depth = (u32::BITS - key.leading_zeros()).div_ceil(bits_per_digit);
+
+    let mut state = hasher.start();
+    hasher.update(&mut state, value);
+    let mut hash = hasher.finish(state);
+    match take_u16(bytes)? as usize {
+        0 => {}
+        count if count == arity => {
+            let mut state = hasher.start();
+            hasher.update(&mut state, hash.as_ref());
+            for _ in 0..arity {
+                hasher.update(&mut state, take_hash(hasher, bytes)?);
+            }
+            hash = hasher.finish(state);
+        }
+        _ => return None,
+    }
+
+    if take(bytes, 1)?[0] as u32 != depth {
+        return None;
+    }
+    for ancestor_depth in (0..depth).rev() {
+        let digit = ((key >> (ancestor_depth * bits_per_digit)) as usize) & (arity - 1);
+        if version >= 2 && take(bytes, 1)?[0] as usize != digit {
+            return None;
+        }
+        let mut state = hasher.start();
+        hasher.update(&mut state, take_hash(hasher, bytes)?);
+        if take_u16(bytes)? as usize != arity - 1 {
+            return None;
+        }
+        for slot in 0..arity {
+            if slot == digit {
+                hasher.update(&mut state, hash.as_ref());
+            } else {
+                hasher.update(&mut state, take_hash(hasher, bytes)?);
+            }
+        }
+        hash = hasher.finish(state);
+    }
+    bytes.is_empty().then_some(hash)
+}
@@ -0,0 +1,185 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::checkpoint::invalid;
+use crate::hasher::{hashes_equal, MerkleHasher};
+
+/// Publishes roots to a system outside the trie's control, e.g. a public chain, a timestamping
+/// authority or an append-only file, so that a later root can be shown to extend an earlier
+/// one that was committed to at a known time.
+pub trait Anchor<D> {
+    type Error;
+
+    fn anchor(&mut self, root: &D) -> Result<(), Self::Error>;
+}
+
+/// One line of a `FileAnchor`'s file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnchorRecord<D> {
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub root: D,
+    /// The hash of the previous record's link, the timestamp and the root, so that no record can
+    /// be changed, dropped or reordered without rewriting every one after it.
+    pub link: D,
+}
+
+/// The reference `Anchor`: an append-only file with one `timestamp root link` line per root,
+/// hashes in `MerkleHasher::hash_to_string` form. The first record links from the empty hash.
+/// Publishing the latest link elsewhere anchors every record before it.
+pub struct FileAnchor<H: MerkleHasher> {
+    hasher: H,
+    file: File,
+    last_link: H::Hash,
+}
+
+impl<H: MerkleHasher> FileAnchor<H> {
+    /// Opens or creates the file at `path`, checking the chain of any records it holds.
+    pub fn open<P: AsRef<Path>>(path: P, hasher: H) -> io::Result<Self> {
+        let path = path.as_ref();
+        let last_link = match fs::read_to_string(path) {
+            Ok(contents) => Self::parse(&contents, &hasher)?
+                .pop()
+                .map(|record| record.link),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+            Err(error) => return Err(error),
+        };
+        Ok(FileAnchor {
+            last_link: last_link.unwrap_or_else(|| hasher.empty_hash()),
+            file: OpenOptions::new().create(true).append(true).open(path)?,
+            hasher,
+        })
+    }
+
+    /// Reads every record in the file at `path`; `InvalidData` if a line is malformed or a
+    /// link doesn't follow from the records before it.
+    pub fn read<P: AsRef<Path>>(path: P, hasher: &H) -> io::Result<Vec<AnchorRecord<H::Hash>>> {
+        Self::parse(&fs::read_to_string(path)?, hasher)
+    }
+
+    /// The link of the latest record, or the empty hash before the first.
+    pub fn last_link(&self) -> &H::Hash {
+        &self.last_link
+    }
+
+    /// Appends a record of `root` at `timestamp`, in seconds since the Unix epoch, and syncs
+    /// the file.
+    pub fn anchor_at(&mut self, root: &H::Hash, timestamp: u64) -> io::Result<()> {
+        let link = chain_link(&self.hasher, &self.last_link, timestamp, root);
+        let line = format!(
+            "{timestamp} {} {}\n",
+            H::hash_to_string(root),
+            H::hash_to_string(&link)
+        );
+        self.file.write_all(line.as_bytes())?;
+        self.file.sync_data()?;
+        self.last_link = link;
+        Ok(())
+    }
+
+    fn parse(contents: &str, hasher: &H) -> io::Result<Vec<AnchorRecord<H::Hash>>> {
+        let mut previous = hasher.empty_hash();
+        let mut records = vec![];
+        for line in contents.lines() {
+            let mut fields = line.split(' ');
+            let (Some(timestamp), Some(root), Some(link), None) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                return Err(invalid("malformed anchor record"));
+            };
+            let record = AnchorRecord {
+                timestamp: timestamp
+                    .parse()
+                    .map_err(|_| invalid("malformed anchor timestamp"))?,
+                root: H::hash_from_string(root).ok_or_else(|| invalid("malformed anchor root"))?,
+                link: H::hash_from_string(link).ok_or_else(|| invalid("malformed anchor link"))?,
+            };
+            let expected = chain_link(hasher, &previous, record.timestamp, &record.root);
+            if !hashes_equal(expected.as_ref(), record.link.as_ref()) {
+                return Err(invalid("anchor chain is broken"));
+            }
+            previous = record.link.clone();
+            records.push(record);
+        }
+        Ok(records)
+    }
+}
+
+// The link of a record of `root` at `timestamp` following the link `previous`.
+pub(crate) fn chain_link<H: MerkleHasher>(
+    hasher: &H,
+    previous: &H::Hash,
+    timestamp: u64,
+    root: &H::Hash,
+) -> H::Hash {
+    let mut bytes = previous.as_ref().to_vec();
+    bytes.extend_from_slice(&timestamp.to_be_bytes());
+    bytes.extend_from_slice(root.as_ref());
+    hasher.hash(&bytes)
+}
+
+impl<H: MerkleHasher> Anchor<H::Hash> for FileAnchor<H> {
+    type Error = io::Error;
+
+    fn anchor(&mut self, root: &H::Hash) -> io::Result<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        self.anchor_at(root, timestamp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+    use crate::trie_node::trie_node::TrieNode;
+
+    #[test]
+    fn anchored_roots_form_a_checked_chain() {
+        let path = std::env::temp_dir().join(format!("anchor-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut trie: TrieNode<u32> = TrieNode::new();
+        let mut roots = vec![];
+        let mut anchor = FileAnchor::open(&path, StdMerkleHasher).unwrap();
+        for key in 0..3 {
+            trie.insert(key, key);
+            roots.push(trie.merkle_root());
+            anchor
+                .anchor_at(roots.last().unwrap(), 100 + key as u64)
+                .unwrap();
+        }
+        drop(anchor);
+
+        // Reopening continues the chain.
+        let mut anchor = FileAnchor::open(&path, StdMerkleHasher).unwrap();
+        trie.insert(3, 3);
+        roots.push(trie.merkle_root());
+        anchor.anchor(roots.last().unwrap()).unwrap();
+        let records = FileAnchor::read(&path, &StdMerkleHasher).unwrap();
+        assert_eq!(
+            records
+                .iter()
+                .map(|record| &record.root)
+                .collect::<Vec<_>>(),
+            roots.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(records[1].timestamp, 101);
+        assert!(records[3].timestamp > 101);
+        assert_eq!(&records[3].link, anchor.last_link());
+
+        // Dropping a record breaks the chain.
+        let contents = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        fs::write(&path, format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+        let error = FileAnchor::read(&path, &StdMerkleHasher).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(FileAnchor::open(&path, StdMerkleHasher).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,451 @@
+use crate::trie_node::trie_node::TrieNode;
+use crate::{
+    hasher::{hashes_equal, MerkleHasher, StdMerkleHasher},
+    merkle_data::MerkleData,
+    proof::MerkleProof,
+};
+
+/// A verifiable log: entries are stored in an eagerly hashed trie under sequential indices, so
+/// the running root is always current. The root after every append is kept, which makes
+/// `root_at` O(1) at the cost of one hash per entry.
+#[derive(Debug, PartialEq)]
+pub struct AppendLog<T: MerkleData, H: MerkleHasher = StdMerkleHasher, const N: usize = 2> {
+    trie: TrieNode<T, H, N>,
+    roots: Vec<H::Hash>,
+}
+
+impl<T: MerkleData + PartialEq, H: MerkleHasher + Default, const N: usize> Default
+    for AppendLog<T, H, N>
+{
+    fn default() -> Self {
+        AppendLog::with_hasher(H::default())
+    }
+}
+
+impl<T: MerkleData + PartialEq, H: MerkleHasher + Default, const N: usize> AppendLog<T, H, N> {
+    pub fn new() -> Self {
+        AppendLog::default()
+    }
+}
+
+impl<T: MerkleData + PartialEq, H: MerkleHasher, const N: usize> AppendLog<T, H, N> {
+    pub fn with_hasher(hasher: H) -> Self {
+        let mut trie = TrieNode::with_hasher(hasher);
+        trie.set_eager_hashing(true);
+        let roots = vec![trie.merkle_root()];
+        AppendLog { trie, roots }
+    }
+
+    /// Appends `data` and returns its index.
+    pub fn append(&mut self, data: T) -> u32 {
+        let index = u32::try_from(self.len()).expect("append log is full");
+        self.trie.insert(index, data);
+        self.roots.push(self.trie.merkle_root());
+        index
+    }
+
+    pub fn len(&self) -> usize {
+        self.roots.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: u32) -> Option<&T> {
+        self.trie.find_by_key(index)?.get_data()
+    }
+
+    pub fn root(&self) -> &H::Hash {
+        self.roots.last().unwrap()
+    }
+
+    /// The root the log had when it held its first `len` entries.
+    pub fn root_at(&self, len: usize) -> Option<&H::Hash> {
+        self.roots.get(len)
+    }
+
+    /// Proves the entry at `index` against the current root.
+    pub fn inclusion_proof(&mut self, index: u32) -> Option<MerkleProof<H::Hash>> {
+        self.trie.generate_proof(index)
+    }
+
+    pub fn trie(&self) -> &TrieNode<T, H, N> {
+        &self.trie
+    }
+}
+
+/// An append-only log for strictly sequential indices, hashed as a binary tree without the
+/// trie. Like a binary counter, each level keeps the roots of its complete subtrees, the last
+/// one pending until a sibling of the same size completes it: an append carries up only as
+/// far as that, O(1) amortized and O(log n) at worst, and no append ever walks the tree.
+/// Entries hash as trie values do and nodes like a value-less trie node with two children.
+/// The root bags the pending roots, lowest first, into the higher ones, so its roots are not
+/// those of an `AppendLog` holding the same entries.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SequentialLog<T, H: MerkleHasher = StdMerkleHasher> {
+    hasher: H,
+    entries: Vec<T>,
+    // `levels[level][i]` is the root of the `i`-th complete subtree of `2^level` entries; the
+    // last one is pending when the level holds an odd number.
+    levels: Vec<Vec<H::Hash>>,
+}
+
+impl<T: MerkleData, H: MerkleHasher + Default> Default for SequentialLog<T, H> {
+    fn default() -> Self {
+        SequentialLog::with_hasher(H::default())
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher + Default> SequentialLog<T, H> {
+    pub fn new() -> Self {
+        SequentialLog::default()
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher> SequentialLog<T, H> {
+    pub fn with_hasher(hasher: H) -> Self {
+        SequentialLog {
+            hasher,
+            entries: vec![],
+            levels: vec![],
+        }
+    }
+
+    fn node(&self, left: &H::Hash, right: &H::Hash) -> H::Hash {
+        self.hasher
+            .combine_children(&self.hasher.empty_hash(), &[left.clone(), right.clone()])
+    }
+
+    /// Appends `data` and returns its index.
+    pub fn append(&mut self, data: T) -> u64 {
+        let index = self.entries.len() as u64;
+        let mut node = self.hasher.hash(&data.merkle_bytes());
+        self.entries.push(data);
+        for level in 0.. {
+            if level == self.levels.len() {
+                self.levels.push(vec![]);
+            }
+            self.levels[level].push(node);
+            let completed = &self.levels[level];
+            if completed.len() % 2 == 1 {
+                break;
+            }
+            node = self.node(
+                &completed[completed.len() - 2],
+                &completed[completed.len() - 1],
+            );
+        }
+        index
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, index: u64) -> Option<&T> {
+        self.entries.get(usize::try_from(index).ok()?)
+    }
+
+    /// The roots of the complete subtrees not yet paired, by level, lowest first: one for each
+    /// set bit of the length.
+    pub fn pending(&self) -> impl Iterator<Item = (usize, &H::Hash)> {
+        self.levels
+            .iter()
+            .enumerate()
+            .filter(|(_, nodes)| nodes.len() % 2 == 1)
+            .map(|(level, nodes)| (level, nodes.last().unwrap()))
+    }
+
+    /// The root over every entry, the empty hash if there are none, from the pending roots
+    /// alone.
+    pub fn current_root(&self) -> H::Hash {
+        self.bag(usize::MAX)
+            .unwrap_or_else(|| self.hasher.empty_hash())
+    }
+
+    // The pending roots below `level` bagged together, if there are any.
+    fn bag(&self, level: usize) -> Option<H::Hash> {
+        self.pending()
+            .take_while(|(pending, _)| *pending < level)
+            .fold(None, |bagged, (_, peak)| match bagged {
+                Some(bagged) => Some(self.node(peak, &bagged)),
+                None => Some(peak.clone()),
+            })
+    }
+
+    /// Proves that the entry at `index` is the log's `index`-th, against the current root.
+    pub fn inclusion_proof(&self, index: u64) -> Option<IndexProof<H::Hash>> {
+        let len = self.len() as u64;
+        let (peak, _) = peak_of(index, len)?;
+        Some(IndexProof {
+            index,
+            len,
+            siblings: (0..peak)
+                .map(|level| self.levels[level][(index >> level) as usize ^ 1].clone())
+                .collect(),
+            lower: self.bag(peak),
+            higher: self
+                .pending()
+                .filter(|(level, _)| *level > peak)
+                .map(|(_, root)| root.clone())
+                .collect(),
+        })
+    }
+
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+}
+
+// The level of the pending subtree of a log of `len` entries that holds `index`, and the index
+// it starts at. The pending subtrees cover the entries highest level first.
+fn peak_of(index: u64, len: u64) -> Option<(usize, u64)> {
+    let mut start = 0;
+    for level in (0..u64::BITS as usize)
+        .rev()
+        .filter(|level| len >> level & 1 == 1)
+    {
+        if index - start < 1 << level {
+            return Some((level, start));
+        }
+        start += 1 << level;
+    }
+    None
+}
+
+/// Proof that a value is a `SequentialLog`'s `index`-th entry when it held `len`. The index
+/// and length fix the proof's shape, which side each sibling is on and which pending roots
+/// come below and above the entry's, so it verifies at no other position. It can verify
+/// under another length that gives the same shape; the root is what fixes the length.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexProof<D> {
+    pub index: u64,
+    pub len: u64,
+    /// The siblings up the entry's pending subtree, lowest first.
+    pub siblings: Vec<D>,
+    /// The pending roots below the entry's, bagged, if there are any.
+    pub lower: Option<D>,
+    /// The pending roots above the entry's, lowest first.
+    pub higher: Vec<D>,
+}
+
+impl<D: Clone + AsRef<[u8]>> IndexProof<D> {
+    /// The root this proof commits `value` to, or `None` if its shape doesn't fit its index
+    /// and length.
+    pub fn root_for<H, V>(&self, hasher: &H, value: &V) -> Option<D>
+    where
+        H: MerkleHasher<Hash = D>,
+        V: MerkleData + ?Sized,
+    {
+        let (peak, _) = peak_of(self.index, self.len)?;
+        let has_lower = self.len & ((1 << peak) - 1) != 0;
+        let higher = (self.len >> peak >> 1).count_ones() as usize;
+        if self.siblings.len() != peak
+            || self.lower.is_some() != has_lower
+            || self.higher.len() != higher
+        {
+            return None;
+        }
+        let node = |left: &D, right: &D| {
+            hasher.combine_children(&hasher.empty_hash(), &[left.clone(), right.clone()])
+        };
+        let mut hash = hasher.hash(&value.merkle_bytes());
+        for (level, sibling) in self.siblings.iter().enumerate() {
+            hash = match self.index >> level & 1 {
+                0 => node(&hash, sibling),
+                _ => node(sibling, &hash),
+            };
+        }
+        if let Some(lower) = &self.lower {
+            hash = node(&hash, lower);
+        }
+        for higher in &self.higher {
+            hash = node(higher, &hash);
+        }
+        Some(hash)
+    }
+
+    pub fn verify<H, V>(&self, hasher: &H, root: &D, value: &V) -> bool
+    where
+        H: MerkleHasher<Hash = D>,
+        V: MerkleData + ?Sized,
+    {
+        self.root_for(hasher, value)
+            .is_some_and(|computed| hashes_equal(computed.as_ref(), root.as_ref()))
+    }
+
+    // index u64 | len u64 | sibling count u8 | siblings | lower present u8 [, lower]
+    // | higher count u8 | higher, with every hash as u16 len + bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        fn push_hash(bytes: &mut Vec<u8>, hash: &[u8]) {
+            bytes.extend_from_slice(&(hash.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(hash);
+        }
+        let mut bytes = self.index.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&self.len.to_be_bytes());
+        bytes.push(self.siblings.len() as u8);
+        for sibling in &self.siblings {
+            push_hash(&mut bytes, sibling.as_ref());
+        }
+        bytes.push(self.lower.is_some() as u8);
+        if let Some(lower) = &self.lower {
+            push_hash(&mut bytes, lower.as_ref());
+        }
+        bytes.push(self.higher.len() as u8);
+        for higher in &self.higher {
+            push_hash(&mut bytes, higher.as_ref());
+        }
+        bytes
+    }
+
+    /// Decodes a proof written by `to_bytes`; `None` if the bytes are malformed.
+    pub fn from_bytes<H: MerkleHasher<Hash = D>>(mut bytes: &[u8]) -> Option<Self> {
+        fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+            let taken = bytes.get(..len)?;
+            *bytes = &bytes[len..];
+            Some(taken)
+        }
+        fn take_hash<H: MerkleHasher>(bytes: &mut &[u8]) -> Option<H::Hash> {
+            let len = u16::from_be_bytes(take(bytes, 2)?.try_into().ok()?) as usize;
+            H::hash_from_bytes(take(bytes, len)?)
+        }
+        fn take_hashes<H: MerkleHasher>(bytes: &mut &[u8]) -> Option<Vec<H::Hash>> {
+            let count = take(bytes, 1)?[0];
+            (0..count).map(|_| take_hash::<H>(bytes)).collect()
+        }
+
+        let index = u64::from_be_bytes(take(&mut bytes, 8)?.try_into().ok()?);
+        let len = u64::from_be_bytes(take(&mut bytes, 8)?.try_into().ok()?);
+        let siblings = take_hashes::<H>(&mut bytes)?;
+        let lower = match take(&mut bytes, 1)?[0] {
+            0 => None,
+            _ => Some(take_hash::<H>(&mut bytes)?),
+        };
+        let higher = take_hashes::<H>(&mut bytes)?;
+        bytes.is_empty().then_some(IndexProof {
+            index,
+            len,
+            siblings,
+            lower,
+            higher,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn append_assigns_sequential_indices() {
+        let mut log: AppendLog<String> = AppendLog::new();
+        assert!(log.is_empty());
+        let empty_root = log.root().clone();
+        assert_eq!(log.append("first".to_string()), 0);
+        assert_eq!(log.append("second".to_string()), 1);
+        assert_eq!(log.append("third".to_string()), 2);
+        assert_eq!(log.len(), 3);
+        assert_eq!(log.get(1), Some(&"second".to_string()));
+        assert_eq!(log.get(3), None);
+
+        let mut prefix: TrieNode<String> = TrieNode::new();
+        prefix.insert(0, "first".to_string());
+        prefix.insert(1, "second".to_string());
+        assert_eq!(log.root_at(2), Some(&prefix.merkle_root()));
+        assert_eq!(log.root_at(0), Some(&empty_root));
+        assert_eq!(log.root_at(4), None);
+    }
+
+    #[test]
+    fn inclusion_proofs_by_index() {
+        let mut log: AppendLog<u64> = AppendLog::new();
+        for value in 100..120 {
+            log.append(value);
+        }
+        let root = log.root().clone();
+        for index in 0..20 {
+            let proof = log.inclusion_proof(index).unwrap();
+            assert!(proof.verify(&StdMerkleHasher, &root, &(100 + index as u64)));
+        }
+        assert_eq!(log.inclusion_proof(20), None);
+    }
+
+    #[test]
+    fn sequential_log_roots_bag_the_pending_subtrees() {
+        fn node(left: &str, right: &str) -> String {
+            StdMerkleHasher.combine_children(
+                &StdMerkleHasher.empty_hash(),
+                &[left.to_string(), right.to_string()],
+            )
+        }
+        fn subtree(entries: &[u64]) -> String {
+            match entries {
+                [entry] => StdMerkleHasher.hash(&entry.merkle_bytes()),
+                _ => {
+                    let (left, right) = entries.split_at(entries.len() / 2);
+                    node(&subtree(left), &subtree(right))
+                }
+            }
+        }
+        // The root of `entries`, split into complete subtrees from the left.
+        fn bagged(entries: &[u64]) -> String {
+            let mut peaks = vec![];
+            let mut rest = entries;
+            while !rest.is_empty() {
+                let (complete, after) = rest.split_at(1 << rest.len().ilog2());
+                peaks.push(subtree(complete));
+                rest = after;
+            }
+            let lowest = peaks.pop().unwrap_or_else(|| StdMerkleHasher.empty_hash());
+            peaks
+                .iter()
+                .rev()
+                .fold(lowest, |bagged, peak| node(peak, &bagged))
+        }
+
+        let mut log: SequentialLog<u64> = SequentialLog::new();
+        assert_eq!(log.current_root(), StdMerkleHasher.empty_hash());
+        let entries: Vec<u64> = (100..123).collect();
+        for (index, entry) in entries.iter().enumerate() {
+            assert_eq!(log.append(*entry), index as u64);
+            assert_eq!(log.current_root(), bagged(&entries[..=index]));
+            assert_eq!(log.pending().count(), (index + 1).count_ones() as usize);
+        }
+        // 23 = 16 + 4 + 2 + 1
+        let levels: Vec<usize> = log.pending().map(|(level, _)| level).collect();
+        assert_eq!(levels, [0, 1, 2, 4]);
+        assert_eq!(log.get(22), Some(&122));
+        assert_eq!(log.get(23), None);
+
+        let root = log.current_root();
+        for index in 0..23 {
+            let proof = log.inclusion_proof(index).unwrap();
+            assert!(proof.verify(&StdMerkleHasher, &root, &(100 + index)));
+            let decoded = IndexProof::from_bytes::<StdMerkleHasher>(&proof.to_bytes());
+            assert_eq!(decoded.as_ref(), Some(&proof));
+        }
+        assert_eq!(log.inclusion_proof(23), None);
+
+        // The proof shows the position as well as the entry.
+        let proof = log.inclusion_proof(18).unwrap();
+        assert!(!proof.verify(&StdMerkleHasher, &root, &119u64));
+        for index in [16, 19, 22, 23, u64::MAX] {
+            let moved = IndexProof {
+                index,
+                ..proof.clone()
+            };
+            assert!(!moved.verify(&StdMerkleHasher, &root, &118u64));
+        }
+        let shorter = IndexProof {
+            len: 20,
+            ..proof.clone()
+        };
+        assert!(!shorter.verify(&StdMerkleHasher, &root, &118u64));
+    }
+}
@@ -0,0 +1,70 @@
+use std::{ops::Deref, sync::Arc};
+
+use crate::trie_node::trie_node::TrieNode;
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// A trie behind an `Arc`, for read-mostly sharing: cloning only bumps a reference count, and
+/// the nodes (with their cached hashes) are copied the first time a shared handle is mutated.
+#[derive(Debug)]
+pub struct ArcTrie<T: MerkleData, H: MerkleHasher, const N: usize = 2>(Arc<TrieNode<T, H, N>>);
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> Clone for ArcTrie<T, H, N> {
+    fn clone(&self) -> Self {
+        ArcTrie(Arc::clone(&self.0))
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> Deref for ArcTrie<T, H, N> {
+    type Target = TrieNode<T, H, N>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: MerkleData + Clone, H: MerkleHasher + Clone, const N: usize> ArcTrie<T, H, N> {
+    /// Mutable access, copying the trie first if any other handle shares it.
+    pub fn make_mut(&mut self) -> &mut TrieNode<T, H, N> {
+        Arc::make_mut(&mut self.0)
+    }
+
+    pub fn into_inner(self) -> TrieNode<T, H, N> {
+        Arc::unwrap_or_clone(self.0)
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> ArcTrie<T, H, N> {
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> From<TrieNode<T, H, N>> for ArcTrie<T, H, N> {
+    fn from(trie: TrieNode<T, H, N>) -> Self {
+        ArcTrie(Arc::new(trie))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn clones_keep_caches_and_arc_clones_share_until_written() {
+        let mut node: TrieNode<u32> = (1..10).map(|key| (key, key)).collect();
+        let root = node.merkle_root();
+        let copy = node.clone();
+        assert_eq!(copy.current_root(), Some(&root));
+
+        let shared: ArcTrie<u32, StdMerkleHasher> = ArcTrie::from(node);
+        let mut writer = shared.clone();
+        assert!(writer.ptr_eq(&shared));
+        writer.make_mut().insert(3, 30);
+        assert!(!writer.ptr_eq(&shared));
+        assert_eq!(shared.current_root(), Some(&root));
+        assert_eq!(writer.find_by_key(3).unwrap().get_data(), Some(&30));
+        assert_ne!(writer.into_inner().merkle_root(), root);
+    }
+}
@@ -0,0 +1,887 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    future::Future,
+    marker::PhantomData,
+    str::FromStr,
+    sync::Mutex,
+};
+
+use crate::{
+    codec::{DisplayCodec, ValueCodec},
+    hasher::{hashes_equal, MerkleHasher},
+    merkle_data::MerkleData,
+    proof::{MerkleProof, ProofLevel, PROOF_FORMAT_VERSION},
+};
+
+pub type NodeId = u64;
+
+// Stands for the root of an empty trie, which is never stored.
+const EMPTY_ID: NodeId = 0;
+// Holds the next unallocated node id and the root of every live version, so a trie can be
+// reopened from its store.
+pub(crate) const META_ID: NodeId = NodeId::MAX;
+// Holds the root ids of released versions whose nodes are yet to be reclaimed.
+const RELEASED_ID: NodeId = NodeId::MAX - 1;
+
+/// The size, in encoded bytes, above which `AsyncTrie` stores a value in a blob of its own.
+pub const DEFAULT_SPILL_THRESHOLD: usize = 1024;
+
+/// A key-value backend for trie nodes, e.g. S3, DynamoDB or a remote KV service. Nodes are
+/// opaque byte records addressed by id; `put` overwrites.
+pub trait AsyncNodeStore {
+    type Error: Debug;
+
+    fn get(&self, id: NodeId) -> impl Future<Output = Result<Option<Vec<u8>>, Self::Error>> + Send;
+
+    fn put(
+        &self,
+        id: NodeId,
+        bytes: Vec<u8>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+
+    /// Removes a node; removing a missing node is not an error.
+    fn delete(&self, id: NodeId) -> impl Future<Output = Result<(), Self::Error>> + Send;
+}
+
+/// A blocking backend, e.g. a local file or embedded database. Every `NodeStore` is also an
+/// `AsyncNodeStore` whose futures complete immediately.
+pub trait NodeStore {
+    type Error: Debug;
+
+    fn get(&self, id: NodeId) -> Result<Option<Vec<u8>>, Self::Error>;
+
+    fn put(&self, id: NodeId, bytes: Vec<u8>) -> Result<(), Self::Error>;
+
+    /// Removes a node; removing a missing node is not an error.
+    fn delete(&self, id: NodeId) -> Result<(), Self::Error>;
+}
+
+impl<S> AsyncNodeStore for S
+where
+    S: NodeStore + Sync,
+    S::Error: Send,
+{
+    type Error = S::Error;
+
+    fn get(&self, id: NodeId) -> impl Future<Output = Result<Option<Vec<u8>>, Self::Error>> + Send {
+        std::future::ready(NodeStore::get(self, id))
+    }
+
+    fn put(
+        &self,
+        id: NodeId,
+        bytes: Vec<u8>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        std::future::ready(NodeStore::put(self, id, bytes))
+    }
+
+    fn delete(&self, id: NodeId) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        std::future::ready(NodeStore::delete(self, id))
+    }
+}
+
+#[derive(Debug)]
+pub enum StoreError<E> {
+    Backend(E),
+    Corrupt(&'static str),
+    UnknownVersion(u64),
+    /// The version is one of the latest ones the trie is set to retain.
+    VersionRetained(u64),
+}
+
+impl<E> From<E> for StoreError<E> {
+    fn from(error: E) -> Self {
+        StoreError::Backend(error)
+    }
+}
+
+/// An in-process store, mostly for tests and as a reference implementation.
+#[derive(Debug, Default)]
+pub struct MemoryNodeStore {
+    nodes: Mutex<HashMap<NodeId, Vec<u8>>>,
+}
+
+impl MemoryNodeStore {
+    pub fn new() -> Self {
+        MemoryNodeStore::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl NodeStore for MemoryNodeStore {
+    type Error = std::convert::Infallible;
+
+    fn get(&self, id: NodeId) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(self.nodes.lock().unwrap().get(&id).cloned())
+    }
+
+    fn put(&self, id: NodeId, bytes: Vec<u8>) -> Result<(), Self::Error> {
+        self.nodes.lock().unwrap().insert(id, bytes);
+        Ok(())
+    }
+
+    fn delete(&self, id: NodeId) -> Result<(), Self::Error> {
+        self.nodes.lock().unwrap().remove(&id);
+        Ok(())
+    }
+}
+
+/// A node as kept in a store. Each child link carries the child's merkle root, so a node's
+/// hash, and a proof level for it, never needs its siblings to be fetched. A large value is
+/// kept in a blob record instead, linked by id and by the hash of its bytes, so copies of the
+/// node on later versions' paths share it.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct StoredNode<D, const N: usize> {
+    pub(crate) data: Option<Vec<u8>>,
+    pub(crate) spilled: Option<(NodeId, D)>,
+    pub(crate) data_hash: D,
+    pub(crate) children: [Option<(NodeId, D)>; N],
+}
+
+impl<D: Clone + AsRef<[u8]>, const N: usize> StoredNode<D, N> {
+    fn empty<H: MerkleHasher<Hash = D>>(hasher: &H) -> Self {
+        StoredNode {
+            data: None,
+            spilled: None,
+            data_hash: hasher.empty_hash(),
+            children: std::array::from_fn(|_| None),
+        }
+    }
+
+    fn child_roots<H: MerkleHasher<Hash = D>>(&self, hasher: &H, skip: Option<usize>) -> Vec<D> {
+        self.children
+            .iter()
+            .enumerate()
+            .filter(|(digit, _)| Some(*digit) != skip)
+            .map(|(_, child)| match child {
+                Some((_, root)) => root.clone(),
+                None => hasher.empty_hash(),
+            })
+            .collect()
+    }
+
+    pub(crate) fn has_value(&self) -> bool {
+        self.data.is_some() || self.spilled.is_some()
+    }
+
+    pub(crate) fn merkle_root<H: MerkleHasher<Hash = D>>(&self, hasher: &H) -> D {
+        if self.children.iter().all(|child| child.is_none()) {
+            return self.data_hash.clone();
+        }
+        hasher.combine_children(&self.data_hash, &self.child_roots(hasher, None))
+    }
+
+    // flags u8 | data hash (u16 len + bytes) | per child: present u8 [, id u64, root (u16 len
+    // + bytes)] | value (u32 len + bytes), if flags & 1 | blob id u64, blob hash (u16 len +
+    // bytes), if flags & 2
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![self.data.is_some() as u8 | (self.spilled.is_some() as u8) << 1];
+        let push_hash = |bytes: &mut Vec<u8>, hash: &D| {
+            bytes.extend_from_slice(&(hash.as_ref().len() as u16).to_be_bytes());
+            bytes.extend_from_slice(hash.as_ref());
+        };
+        push_hash(&mut bytes, &self.data_hash);
+        for child in &self.children {
+            match child {
+                Some((id, root)) => {
+                    bytes.push(1);
+                    bytes.extend_from_slice(&id.to_be_bytes());
+                    push_hash(&mut bytes, root);
+                }
+                None => bytes.push(0),
+            }
+        }
+        if let Some(data) = &self.data {
+            bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(data);
+        }
+        if let Some((id, hash)) = &self.spilled {
+            bytes.extend_from_slice(&id.to_be_bytes());
+            push_hash(&mut bytes, hash);
+        }
+        bytes
+    }
+
+    pub(crate) fn decode<H: MerkleHasher<Hash = D>>(mut bytes: &[u8]) -> Option<Self> {
+        fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+            let taken = bytes.get(..len)?;
+            *bytes = &bytes[len..];
+            Some(taken)
+        }
+        fn take_hash<H: MerkleHasher>(bytes: &mut &[u8]) -> Option<H::Hash> {
+            let len = u16::from_be_bytes(take(bytes, 2)?.try_into().ok()?) as usize;
+            H::hash_from_bytes(take(bytes, len)?)
+        }
+
+        let flags = take(&mut bytes, 1)?[0];
+        let data_hash = take_hash::<H>(&mut bytes)?;
+        let mut children: [Option<(NodeId, D)>; N] = std::array::from_fn(|_| None);
+        for child in children.iter_mut() {
+            if take(&mut bytes, 1)?[0] != 0 {
+                let id = NodeId::from_be_bytes(take(&mut bytes, 8)?.try_into().ok()?);
+                *child = Some((id, take_hash::<H>(&mut bytes)?));
+            }
+        }
+        let data = if flags & 1 != 0 {
+            let len = u32::from_be_bytes(take(&mut bytes, 4)?.try_into().ok()?) as usize;
+            Some(take(&mut bytes, len)?.to_vec())
+        } else {
+            None
+        };
+        let spilled = if flags & 2 != 0 {
+            let id = NodeId::from_be_bytes(take(&mut bytes, 8)?.try_into().ok()?);
+            Some((id, take_hash::<H>(&mut bytes)?))
+        } else {
+            None
+        };
+        bytes.is_empty().then_some(StoredNode {
+            data,
+            spilled,
+            data_hash,
+            children,
+        })
+    }
+}
+
+/// The lookup, insert and proof operations of a trie whose nodes live in an `AsyncNodeStore`.
+/// Each operation fetches only the nodes on the key's path. Nodes are never overwritten: an
+/// insert writes a fresh copy of its path and commits it as a new version, so older versions
+/// stay readable until `prune_versions_older_than` collects them, or until they are released
+/// one by one with `release_version` and collected by `reclaim_released`. Values are stored
+/// through `C`, by default as their `ToString` rendering; those longer than the spill
+/// threshold go in blob records of their own, so rewriting a path doesn't copy them.
+pub struct AsyncTrie<T, H: MerkleHasher, S, const N: usize = 2, C = DisplayCodec> {
+    store: S,
+    hasher: H,
+    codec: C,
+    next_id: NodeId,
+    // Root node of every live version, oldest first; the last one is current.
+    versions: Vec<(u64, NodeId)>,
+    // Root node of every released version whose nodes haven't been reclaimed.
+    released: Vec<NodeId>,
+    // How many of the latest versions can't be released or pruned.
+    retained: usize,
+    spill_threshold: usize,
+    root: H::Hash,
+    values: PhantomData<fn() -> T>,
+}
+
+impl<T, H, S, const N: usize> AsyncTrie<T, H, S, N>
+where
+    T: MerkleData + ToString + FromStr,
+    H: MerkleHasher,
+    S: AsyncNodeStore,
+{
+    /// Opens the trie kept in `store`, which may be empty.
+    pub async fn open(store: S, hasher: H) -> Result<Self, StoreError<S::Error>> {
+        Self::open_with_codec(store, hasher, DisplayCodec).await
+    }
+}
+
+impl<T, H, S, const N: usize, C> AsyncTrie<T, H, S, N, C>
+where
+    T: MerkleData,
+    H: MerkleHasher,
+    S: AsyncNodeStore,
+    C: ValueCodec<T>,
+{
+    /// Opens the trie kept in `store`, storing values through `codec`. The codec must match
+    /// the one the store was written with.
+    pub async fn open_with_codec(
+        store: S,
+        hasher: H,
+        codec: C,
+    ) -> Result<Self, StoreError<S::Error>> {
+        let (next_id, versions) = match store.get(META_ID).await? {
+            Some(meta) => {
+                Self::decode_meta(&meta).ok_or(StoreError::Corrupt("malformed metadata"))?
+            }
+            None => (EMPTY_ID + 1, vec![(0, EMPTY_ID)]),
+        };
+        let released = match store.get(RELEASED_ID).await? {
+            Some(released) => released
+                .chunks(8)
+                .map(|id| id.try_into().map(NodeId::from_be_bytes))
+                .collect::<Result<_, _>>()
+                .map_err(|_| StoreError::Corrupt("malformed released versions"))?,
+            None => vec![],
+        };
+        let mut trie = AsyncTrie {
+            root: hasher.empty_hash(),
+            store,
+            hasher,
+            codec,
+            next_id,
+            versions,
+            released,
+            retained: 1,
+            spill_threshold: DEFAULT_SPILL_THRESHOLD,
+            values: PhantomData,
+        };
+        trie.root = trie
+            .load_root(trie.root_id())
+            .await?
+            .merkle_root(&trie.hasher);
+        Ok(trie)
+    }
+
+    // next_id u64 | per live version: version u64, root id u64
+    fn encode_meta(&self) -> Vec<u8> {
+        let mut meta = self.next_id.to_be_bytes().to_vec();
+        for (version, root_id) in &self.versions {
+            meta.extend_from_slice(&version.to_be_bytes());
+            meta.extend_from_slice(&root_id.to_be_bytes());
+        }
+        meta
+    }
+
+    fn decode_meta(meta: &[u8]) -> Option<(NodeId, Vec<(u64, NodeId)>)> {
+        let (next_id, versions) = meta.split_first_chunk::<8>()?;
+        if versions.is_empty() || versions.len() % 16 != 0 {
+            return None;
+        }
+        let versions = versions
+            .chunks(16)
+            .map(|pair| {
+                let (version, root_id) = pair.split_at(8);
+                (
+                    u64::from_be_bytes(version.try_into().unwrap()),
+                    NodeId::from_be_bytes(root_id.try_into().unwrap()),
+                )
+            })
+            .collect();
+        Some((NodeId::from_be_bytes(*next_id), versions))
+    }
+
+    pub fn merkle_root(&self) -> &H::Hash {
+        &self.root
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    /// The current version, bumped by every insert.
+    pub fn version(&self) -> u64 {
+        self.versions.last().unwrap().0
+    }
+
+    /// Every version that can still be read, oldest first.
+    pub fn versions(&self) -> impl Iterator<Item = u64> + '_ {
+        self.versions.iter().map(|(version, _)| *version)
+    }
+
+    /// How many of the latest versions `release_version` and `prune_versions_older_than`
+    /// leave alone, at least 1 (the current version), which is the default. The setting isn't
+    /// stored, so set it again after reopening the trie.
+    pub fn set_retained_versions(&mut self, retained: usize) {
+        self.retained = retained.max(1);
+    }
+
+    pub fn retained_versions(&self) -> usize {
+        self.retained
+    }
+
+    /// The encoded size above which a value is stored in a blob rather than in its node,
+    /// `DEFAULT_SPILL_THRESHOLD` unless set. It only affects later inserts: values are read
+    /// back either way. The setting isn't stored.
+    pub fn set_spill_threshold(&mut self, bytes: usize) {
+        self.spill_threshold = bytes;
+    }
+
+    pub fn spill_threshold(&self) -> usize {
+        self.spill_threshold
+    }
+
+    fn root_id(&self) -> NodeId {
+        self.versions.last().unwrap().1
+    }
+
+    fn version_root_id(&self, version: u64) -> Result<NodeId, StoreError<S::Error>> {
+        self.versions
+            .iter()
+            .find(|(live, _)| *live == version)
+            .map(|(_, root_id)| *root_id)
+            .ok_or(StoreError::UnknownVersion(version))
+    }
+
+    async fn load(&self, id: NodeId) -> Result<StoredNode<H::Hash, N>, StoreError<S::Error>> {
+        let bytes = self
+            .store
+            .get(id)
+            .await?
+            .ok_or(StoreError::Corrupt("dangling child link"))?;
+        StoredNode::decode::<H>(&bytes).ok_or(StoreError::Corrupt("malformed node"))
+    }
+
+    async fn load_root(
+        &self,
+        root_id: NodeId,
+    ) -> Result<StoredNode<H::Hash, N>, StoreError<S::Error>> {
+        match root_id {
+            EMPTY_ID => Ok(StoredNode::empty(&self.hasher)),
+            root_id => self.load(root_id).await,
+        }
+    }
+
+    fn digits(key: u32) -> Vec<usize> {
+        let bits_per_digit = N.trailing_zeros();
+        let depth = (u32::BITS - key.leading_zeros()).div_ceil(bits_per_digit);
+        (0..depth)
+            .map(|d| ((key >> (d * bits_per_digit)) as usize) & (N - 1))
+            .collect()
+    }
+
+    // The nodes from the root of `root_id` down to `key`, or `None` if the path stops short.
+    async fn path(
+        &self,
+        root_id: NodeId,
+        key: u32,
+    ) -> Result<Option<Vec<StoredNode<H::Hash, N>>>, StoreError<S::Error>> {
+        let mut path = vec![self.load_root(root_id).await?];
+        for digit in Self::digits(key) {
+            let Some((child, _)) = &path.last().unwrap().children[digit] else {
+                return Ok(None);
+            };
+            let child = self.load(*child).await?;
+            path.push(child);
+        }
+        Ok(Some(path))
+    }
+
+    pub async fn get(&self, key: u32) -> Result<Option<T>, StoreError<S::Error>> {
+        self.get_at(self.version(), key).await
+    }
+
+    /// The value under `key` as of `version`, which must not have been pruned.
+    pub async fn get_at(&self, version: u64, key: u32) -> Result<Option<T>, StoreError<S::Error>> {
+        let Some(mut path) = self.path(self.version_root_id(version)?, key).await? else {
+            return Ok(None);
+        };
+        let node = path.pop().unwrap();
+        let data = match (node.data, node.spilled) {
+            (Some(data), _) => data,
+            (None, Some((blob, hash))) => {
+                let data = self
+                    .store
+                    .get(blob)
+                    .await?
+                    .ok_or(StoreError::Corrupt("dangling blob link"))?;
+                if !hashes_equal(self.hasher.hash(&data).as_ref(), hash.as_ref()) {
+                    return Err(StoreError::Corrupt("blob doesn't match its hash"));
+                }
+                data
+            }
+            (None, None) => return Ok(None),
+        };
+        let value = self
+            .codec
+            .decode(&data)
+            .ok_or(StoreError::Corrupt("unparseable value"))?;
+        Ok(Some(value))
+    }
+
+    pub async fn insert(&mut self, key: u32, value: T) -> Result<(), StoreError<S::Error>> {
+        let digits = Self::digits(key);
+        let mut path = vec![self.load_root(self.root_id()).await?];
+        for digit in &digits {
+            let node = match &path.last().unwrap().children[*digit] {
+                Some((child, _)) => self.load(*child).await?,
+                None => StoredNode::empty(&self.hasher),
+            };
+            path.push(node);
+        }
+
+        let target = path.last_mut().unwrap();
+        target.data_hash = self.hasher.hash(&value.merkle_bytes());
+        let data = self.codec.encode(&value);
+        if data.len() > self.spill_threshold {
+            let blob = self.next_id;
+            self.next_id += 1;
+            target.spilled = Some((blob, self.hasher.hash(&data)));
+            target.data = None;
+            self.store.put(blob, data).await?;
+        } else {
+            target.spilled = None;
+            target.data = Some(data);
+        }
+        let mut child_link = None;
+        for (mut node, digit) in path
+            .into_iter()
+            .rev()
+            .zip(digits.iter().map(Some).rev().chain([None]))
+        {
+            if let Some((digit, link)) = child_link.take() {
+                node.children[digit] = Some(link);
+            }
+            let id = self.next_id;
+            self.next_id += 1;
+            let root = node.merkle_root(&self.hasher);
+            self.store.put(id, node.encode()).await?;
+            match digit {
+                Some(digit) => child_link = Some((*digit, (id, root))),
+                None => {
+                    self.versions.push((self.version() + 1, id));
+                    self.root = root;
+                }
+            }
+        }
+        // The new version only becomes visible once its nodes are all written.
+        self.store.put(META_ID, self.encode_meta()).await?;
+        Ok(())
+    }
+
+    /// Forgets every version before `version` (the retained latest versions are always kept)
+    /// and deletes the nodes and blobs only they could reach. Returns the number of records
+    /// deleted.
+    pub async fn prune_versions_older_than(
+        &mut self,
+        version: u64,
+    ) -> Result<usize, StoreError<S::Error>> {
+        let keep_from = self
+            .versions
+            .iter()
+            .position(|(live, _)| *live >= version)
+            .unwrap_or(self.versions.len())
+            .min(self.versions.len().saturating_sub(self.retained));
+        let pruned: Vec<NodeId> = self
+            .versions
+            .drain(..keep_from)
+            .map(|(_, root_id)| root_id)
+            .collect();
+        self.store.put(META_ID, self.encode_meta()).await?;
+        self.delete_unreachable(pruned).await
+    }
+
+    /// Marks `version` as no longer needed, so it can't be read any more. Its nodes stay in
+    /// the store until `reclaim_released`, which collects every released version in one pass.
+    /// Fails for one of the retained latest versions.
+    pub async fn release_version(&mut self, version: u64) -> Result<(), StoreError<S::Error>> {
+        let position = self
+            .versions
+            .iter()
+            .position(|(live, _)| *live == version)
+            .ok_or(StoreError::UnknownVersion(version))?;
+        if position + self.retained >= self.versions.len() {
+            return Err(StoreError::VersionRetained(version));
+        }
+        let (_, root_id) = self.versions.remove(position);
+        self.released.push(root_id);
+        // The root is recorded as released before the version is dropped, so a failure in
+        // between leaves it live and releasable again rather than unreachable.
+        self.store.put(RELEASED_ID, self.encode_released()).await?;
+        self.store.put(META_ID, self.encode_meta()).await?;
+        Ok(())
+    }
+
+    /// Deletes the nodes only released versions could reach. Returns the number of records
+    /// deleted.
+    pub async fn reclaim_released(&mut self) -> Result<usize, StoreError<S::Error>> {
+        let released = std::mem::take(&mut self.released);
+        let deleted = self.delete_unreachable(released).await?;
+        self.store.delete(RELEASED_ID).await?;
+        Ok(deleted)
+    }
+
+    // root id u64 per released version
+    fn encode_released(&self) -> Vec<u8> {
+        self.released
+            .iter()
+            .flat_map(|root_id| root_id.to_be_bytes())
+            .collect()
+    }
+
+    // Deletes the nodes, and their blobs, reachable from `roots` but not from any live version.
+    async fn delete_unreachable(
+        &mut self,
+        roots: Vec<NodeId>,
+    ) -> Result<usize, StoreError<S::Error>> {
+        // Mark everything the live versions reach. A marked node's subtree is marked with it,
+        // since nodes are immutable, so the sweep below stops at the first marked node.
+        let mut live = HashSet::new();
+        let mut stack: Vec<NodeId> = self.versions.iter().map(|(_, root_id)| *root_id).collect();
+        while let Some(id) = stack.pop() {
+            if id == EMPTY_ID || !live.insert(id) {
+                continue;
+            }
+            let node = self.load(id).await?;
+            live.extend(node.spilled.map(|(blob, _)| blob));
+            stack.extend(node.children.iter().flatten().map(|(child, _)| *child));
+        }
+
+        let mut deleted = HashSet::new();
+        let mut stack = roots;
+        while let Some(id) = stack.pop() {
+            if id == EMPTY_ID || live.contains(&id) || !deleted.insert(id) {
+                continue;
+            }
+            // Left behind by an earlier prune that failed part way.
+            let Some(bytes) = self.store.get(id).await? else {
+                continue;
+            };
+            let node = StoredNode::<H::Hash, N>::decode::<H>(&bytes)
+                .ok_or(StoreError::Corrupt("malformed node"))?;
+            stack.extend(node.children.iter().flatten().map(|(child, _)| *child));
+            if let Some((blob, _)) = node.spilled {
+                if !live.contains(&blob) && deleted.insert(blob) {
+                    self.store.delete(blob).await?;
+                }
+            }
+            self.store.delete(id).await?;
+        }
+        Ok(deleted.len())
+    }
+
+    pub async fn generate_proof(
+        &self,
+        key: u32,
+    ) -> Result<Option<MerkleProof<H::Hash>>, StoreError<S::Error>> {
+        let Some(mut path) = self.path(self.root_id(), key).await? else {
+            return Ok(None);
+        };
+        let target = path.pop().unwrap();
+        if !target.has_value() {
+            return Ok(None);
+        }
+        let children_roots = if target.children.iter().all(|child| child.is_none()) {
+            vec![]
+        } else {
+            target.child_roots(&self.hasher, None)
+        };
+        let levels = path
+            .iter()
+            .zip(Self::digits(key))
+            .rev()
+            .map(|(node, digit)| ProofLevel {
+                data_hash: node.data_hash.clone(),
+                siblings: node.child_roots(&self.hasher, Some(digit)),
+                position: digit as u8,
+            })
+            .collect();
+        Ok(Some(MerkleProof {
+            version: PROOF_FORMAT_VERSION,
+            key,
+            arity: N,
+            children_roots,
+            levels,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+    use crate::trie_node::trie_node::TrieNode;
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let mut context = std::task::Context::from_waker(std::task::Waker::noop());
+        loop {
+            if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+        }
+    }
+
+    #[test]
+    fn async_trie_matches_in_memory_trie() {
+        block_on(async {
+            let mut trie: AsyncTrie<String, _, _> =
+                AsyncTrie::open(MemoryNodeStore::new(), StdMerkleHasher)
+                    .await
+                    .unwrap();
+            let mut expected: TrieNode<String> = TrieNode::new();
+            assert_eq!(trie.merkle_root(), &expected.merkle_root());
+            for key in [0, 1, 2, 6, 13, 1000, 6] {
+                let value = format!("value {key}");
+                trie.insert(key, value.clone()).await.unwrap();
+                expected.insert(key, value);
+                assert_eq!(trie.merkle_root(), &expected.merkle_root());
+            }
+            assert_eq!(trie.get(13).await.unwrap(), Some("value 13".to_string()));
+            assert_eq!(trie.get(4).await.unwrap(), None);
+
+            let root = trie.merkle_root().clone();
+            let proof = trie.generate_proof(6).await.unwrap().unwrap();
+            assert_eq!(Some(&proof), expected.generate_proof(6).as_ref());
+            assert!(proof.verify(&StdMerkleHasher, &root, "value 6"));
+            assert_eq!(trie.generate_proof(3).await.unwrap(), None);
+        });
+    }
+
+    #[test]
+    fn reopening_restores_root_and_ids() {
+        block_on(async {
+            let store = MemoryNodeStore::new();
+            let mut trie: AsyncTrie<u32, _, _> =
+                AsyncTrie::open(store, StdMerkleHasher).await.unwrap();
+            for key in 1..10 {
+                trie.insert(key, key * 3).await.unwrap();
+            }
+            let root = trie.merkle_root().clone();
+            let stored_nodes = trie.store().len();
+
+            let mut reopened: AsyncTrie<u32, _, _> =
+                AsyncTrie::open(trie.store, StdMerkleHasher).await.unwrap();
+            assert_eq!(reopened.merkle_root(), &root);
+            reopened.insert(12, 36).await.unwrap();
+            // A fresh copy of the five nodes on the path to key 12.
+            assert_eq!(reopened.store().len(), stored_nodes + 5);
+            assert_eq!(reopened.get(3).await.unwrap(), Some(9));
+            assert_eq!(reopened.version(), 10);
+        });
+    }
+
+    #[test]
+    fn pruning_keeps_only_nodes_of_live_versions() {
+        block_on(async {
+            let mut trie: AsyncTrie<u32, _, _> =
+                AsyncTrie::open(MemoryNodeStore::new(), StdMerkleHasher)
+                    .await
+                    .unwrap();
+            let mut expected: TrieNode<u32> = TrieNode::new();
+            for key in 1..30 {
+                trie.insert(key % 10, key).await.unwrap();
+                expected.insert(key % 10, key);
+            }
+            assert_eq!(trie.get_at(12, 2).await.unwrap(), Some(12));
+
+            let deleted = trie.prune_versions_older_than(25).await.unwrap();
+            assert!(deleted > 0);
+            assert_eq!(trie.versions().collect::<Vec<_>>(), [25, 26, 27, 28, 29]);
+            assert!(matches!(
+                trie.get_at(12, 2).await,
+                Err(StoreError::UnknownVersion(12))
+            ));
+            assert_eq!(trie.get_at(25, 5).await.unwrap(), Some(25));
+
+            trie.prune_versions_older_than(u64::MAX).await.unwrap();
+            assert_eq!(trie.versions().collect::<Vec<_>>(), [29]);
+            // The live nodes plus the metadata record.
+            assert_eq!(trie.store().len(), expected.metrics().node_count + 1);
+            assert_eq!(trie.merkle_root(), &expected.merkle_root());
+            assert_eq!(trie.get(9).await.unwrap(), Some(29));
+        });
+    }
+
+    #[test]
+    fn released_versions_are_reclaimed_except_the_retained_ones() {
+        block_on(async {
+            let mut trie: AsyncTrie<u32, _, _> =
+                AsyncTrie::open(MemoryNodeStore::new(), StdMerkleHasher)
+                    .await
+                    .unwrap();
+            for key in 1..=10 {
+                trie.insert(key % 4, key).await.unwrap();
+            }
+            trie.set_retained_versions(3);
+            assert!(matches!(
+                trie.release_version(9).await,
+                Err(StoreError::VersionRetained(9))
+            ));
+            assert!(matches!(
+                trie.prune_versions_older_than(u64::MAX).await,
+                Ok(deleted) if deleted > 0
+            ));
+            assert_eq!(trie.versions().collect::<Vec<_>>(), [8, 9, 10]);
+
+            for key in 11..=14 {
+                trie.insert(key % 4, key).await.unwrap();
+            }
+            trie.release_version(8).await.unwrap();
+            trie.release_version(10).await.unwrap();
+            assert!(matches!(
+                trie.get_at(10, 2).await,
+                Err(StoreError::UnknownVersion(10))
+            ));
+            assert!(matches!(
+                trie.release_version(10).await,
+                Err(StoreError::UnknownVersion(10))
+            ));
+            assert_eq!(trie.get_at(9, 1).await.unwrap(), Some(9));
+
+            // Released versions survive a reopen until they are reclaimed.
+            let stored_nodes = trie.store().len();
+            let mut trie: AsyncTrie<u32, _, _> =
+                AsyncTrie::open(trie.into_store(), StdMerkleHasher)
+                    .await
+                    .unwrap();
+            assert!(trie.reclaim_released().await.unwrap() > 0);
+            assert!(trie.store().len() < stored_nodes);
+            assert_eq!(trie.reclaim_released().await.unwrap(), 0);
+            assert_eq!(trie.versions().collect::<Vec<_>>(), [9, 11, 12, 13, 14]);
+            assert_eq!(trie.get_at(9, 1).await.unwrap(), Some(9));
+            assert_eq!(trie.get_at(11, 2).await.unwrap(), Some(10));
+            assert_eq!(trie.get(3).await.unwrap(), Some(11));
+        });
+    }
+
+    #[test]
+    fn large_values_spill_to_shared_blobs() {
+        block_on(async {
+            let mut trie: AsyncTrie<String, _, _> =
+                AsyncTrie::open(MemoryNodeStore::new(), StdMerkleHasher)
+                    .await
+                    .unwrap();
+            trie.set_spill_threshold(16);
+            let mut expected: TrieNode<String> = TrieNode::new();
+            let large = "x".repeat(100);
+            for (key, value) in [(5, large.clone()), (1, "small".to_string())] {
+                trie.insert(key, value.clone()).await.unwrap();
+                expected.insert(key, value);
+            }
+            assert_eq!(trie.merkle_root(), &expected.merkle_root());
+            assert_eq!(trie.get(5).await.unwrap(), Some(large.clone()));
+            let proof = trie.generate_proof(5).await.unwrap().unwrap();
+            assert!(proof.verify(&StdMerkleHasher, trie.merkle_root(), large.as_str()));
+
+            // Rewriting the path to key 5 shares its blob rather than copying the value.
+            let records = trie.store().len();
+            trie.insert(0, "root".to_string()).await.unwrap();
+            assert_eq!(trie.store().len(), records + 1);
+            let stored_blobs = |trie: &AsyncTrie<String, _, MemoryNodeStore>| {
+                let nodes = trie.store().nodes.lock().unwrap();
+                nodes
+                    .values()
+                    .filter(|bytes| *bytes == large.as_bytes())
+                    .count()
+            };
+            assert_eq!(stored_blobs(&trie), 1);
+
+            // An overwritten blob goes once no live version links to it.
+            trie.insert(5, "short".to_string()).await.unwrap();
+            trie.prune_versions_older_than(u64::MAX).await.unwrap();
+            assert_eq!(stored_blobs(&trie), 0);
+            trie.insert(5, large.clone()).await.unwrap();
+            assert_eq!(trie.get(5).await.unwrap(), Some(large.clone()));
+
+            let blob = {
+                let nodes = trie.store().nodes.lock().unwrap();
+                *nodes
+                    .iter()
+                    .find(|(_, bytes)| *bytes == large.as_bytes())
+                    .unwrap()
+                    .0
+            };
+            NodeStore::put(trie.store(), blob, b"y".repeat(100)).unwrap();
+            assert!(matches!(
+                trie.get(5).await,
+                Err(StoreError::Corrupt("blob doesn't match its hash"))
+            ));
+        });
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::VecDeque;
+
+use crate::trie_node::trie_node::TrieNode;
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// One write to an `AuditedTrie`: the value hashes under `key` before and after (`None` where
+/// there was no value) and the root the trie had once the write was done.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MutationRecord<D> {
+    /// Counts every write since the trie was wrapped, so a gap in the log shows how many
+    /// records were evicted.
+    pub sequence: u64,
+    pub key: u32,
+    pub old_value_hash: Option<D>,
+    pub new_value_hash: Option<D>,
+    pub root: D,
+}
+
+/// A trie that journals its last `capacity` writes, tying each root change to the writes that
+/// caused it. Every write brings the root up to date, which costs O(depth) hashes with the
+/// caches warm.
+pub struct AuditedTrie<T: MerkleData, H: MerkleHasher, const N: usize = 2> {
+    trie: TrieNode<T, H, N>,
+    capacity: usize,
+    // Oldest first.
+    log: VecDeque<MutationRecord<H::Hash>>,
+    next_sequence: u64,
+}
+
+impl<T: MerkleData + PartialEq, H: MerkleHasher, const N: usize> AuditedTrie<T, H, N> {
+    pub fn new(trie: TrieNode<T, H, N>, capacity: usize) -> Self {
+        AuditedTrie {
+            trie,
+            capacity,
+            log: VecDeque::new(),
+            next_sequence: 0,
+        }
+    }
+
+    pub fn trie(&self) -> &TrieNode<T, H, N> {
+        &self.trie
+    }
+
+    pub fn into_trie(self) -> TrieNode<T, H, N> {
+        self.trie
+    }
+
+    /// The journaled writes, oldest first.
+    pub fn mutation_log(&self) -> impl Iterator<Item = &MutationRecord<H::Hash>> + '_ {
+        self.log.iter()
+    }
+
+    fn value_hash(&self, key: u32) -> Option<H::Hash> {
+        let value = self.trie.get(key)?;
+        Some(self.trie.hasher().hash(&value.merkle_bytes()))
+    }
+
+    fn record(&mut self, key: u32, old_value_hash: Option<H::Hash>, root: H::Hash) {
+        let new_value_hash = self.value_hash(key);
+        self.log.push_back(MutationRecord {
+            sequence: self.next_sequence,
+            key,
+            old_value_hash,
+            new_value_hash,
+            root,
+        });
+        self.next_sequence += 1;
+        while self.log.len() > self.capacity {
+            self.log.pop_front();
+        }
+    }
+
+    pub fn insert(&mut self, key: u32, data: T) -> H::Hash {
+        let old_value_hash = self.value_hash(key);
+        self.trie.insert(key, data);
+        let root = self.trie.merkle_root();
+        self.record(key, old_value_hash, root.clone());
+        root
+    }
+
+    /// `TrieNode::remove_subtree`, journaling one record per removed value, all with the root
+    /// after the whole subtree is gone.
+    pub fn remove_subtree(&mut self, prefix: u32, prefix_len: u32) -> H::Hash {
+        assert!(
+            TrieNode::<T, H, N>::prefix_fits(prefix_len),
+            "prefix must fit in a u32 key"
+        );
+        let shift = prefix_len * TrieNode::<T, H, N>::BITS_PER_DIGIT;
+        let mask = ((1u64 << shift) - 1) as u32;
+        let removed: Vec<(u32, Option<H::Hash>)> = self
+            .trie
+            .iter()
+            .filter(|(key, _)| {
+                key & mask == prefix & mask && TrieNode::<T, H, N>::key_depth(*key) >= prefix_len
+            })
+            .map(|(key, value)| (key, Some(self.trie.hasher().hash(&value.merkle_bytes()))))
+            .collect();
+        self.trie.remove_subtree(prefix, prefix_len);
+        let root = self.trie.merkle_root();
+        for (key, old_value_hash) in removed {
+            self.record(key, old_value_hash, root.clone());
+        }
+        root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn journal_ties_roots_to_writes() {
+        let mut trie = AuditedTrie::new(TrieNode::<u32>::new(), 3);
+        let hash = |value: u32| StdMerkleHasher.hash(&value.merkle_bytes());
+        let first = trie.insert(5, 50);
+        assert_eq!(
+            trie.mutation_log().collect::<Vec<_>>(),
+            [&MutationRecord {
+                sequence: 0,
+                key: 5,
+                old_value_hash: None,
+                new_value_hash: Some(hash(50)),
+                root: first.clone(),
+            }]
+        );
+        let second = trie.insert(5, 51);
+        trie.insert(13, 130);
+        trie.insert(2, 20);
+        let removed = trie.remove_subtree(5, 3);
+
+        let log: Vec<_> = trie.mutation_log().cloned().collect();
+        assert_eq!(log.len(), 3);
+        assert_eq!(log[0].sequence, 3);
+        assert_eq!(
+            log[1..],
+            [
+                MutationRecord {
+                    sequence: 4,
+                    key: 5,
+                    old_value_hash: Some(hash(51)),
+                    new_value_hash: None,
+                    root: removed.clone(),
+                },
+                MutationRecord {
+                    sequence: 5,
+                    key: 13,
+                    old_value_hash: Some(hash(130)),
+                    new_value_hash: None,
+                    root: removed.clone(),
+                },
+            ]
+        );
+        assert_ne!(first, second);
+        let mut expected: TrieNode<u32> = [(5, 51), (13, 130), (2, 20)].into_iter().collect();
+        expected.remove_subtree(5, 3);
+        assert_eq!(removed, expected.merkle_root());
+    }
+}
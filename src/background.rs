@@ -0,0 +1,141 @@
+use std::{
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread::{self, JoinHandle},
+};
+
+use crate::trie_node::trie_node::TrieNode;
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+#[derive(Default)]
+struct Progress {
+    // Bumped by every mutation; `hashed` is the latest one the worker has caught up with.
+    generation: u64,
+    hashed: u64,
+    shutdown: bool,
+}
+
+struct Shared<T: MerkleData, H: MerkleHasher, const N: usize> {
+    trie: Mutex<TrieNode<T, H, N>>,
+    progress: Mutex<Progress>,
+    changed: Condvar,
+}
+
+/// A trie whose invalidated hashes are recomputed on a worker thread after each mutation, so
+/// that `wait_for_root` usually finds the root already cached. The worker holds the trie's lock
+/// while it hashes, so a mutation arriving meanwhile waits for that pass to finish.
+pub struct BackgroundHashedTrie<T: MerkleData, H: MerkleHasher, const N: usize = 2> {
+    shared: Arc<Shared<T, H, N>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl<T, H, const N: usize> BackgroundHashedTrie<T, H, N>
+where
+    T: MerkleData + PartialEq + Send + 'static,
+    H: MerkleHasher + Send + 'static,
+    H::Hash: Send,
+{
+    pub fn new(trie: TrieNode<T, H, N>) -> Self {
+        let shared = Arc::new(Shared {
+            trie: Mutex::new(trie),
+            progress: Mutex::new(Progress::default()),
+            changed: Condvar::new(),
+        });
+        let worker = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || Self::work(&shared))
+        };
+        BackgroundHashedTrie {
+            shared,
+            worker: Some(worker),
+        }
+    }
+
+    fn work(shared: &Shared<T, H, N>) {
+        let mut progress = shared.progress.lock().unwrap();
+        loop {
+            progress = shared
+                .changed
+                .wait_while(progress, |progress| {
+                    progress.hashed == progress.generation && !progress.shutdown
+                })
+                .unwrap();
+            if progress.shutdown {
+                return;
+            }
+            let generation = progress.generation;
+            drop(progress);
+            shared.trie.lock().unwrap().merkle_root();
+            progress = shared.progress.lock().unwrap();
+            progress.hashed = generation;
+            shared.changed.notify_all();
+        }
+    }
+
+    /// Runs `mutate` on the trie, then hands rehashing to the worker.
+    pub fn update<R>(&self, mutate: impl FnOnce(&mut TrieNode<T, H, N>) -> R) -> R {
+        let result = mutate(&mut self.shared.trie.lock().unwrap());
+        self.shared.progress.lock().unwrap().generation += 1;
+        self.shared.changed.notify_all();
+        result
+    }
+
+    pub fn insert(&self, key: u32, data: T) {
+        self.update(|trie| trie.insert(key, data));
+    }
+
+    /// Locks the trie for reading. Hashes the worker has not reached yet are not cached.
+    pub fn read(&self) -> MutexGuard<'_, TrieNode<T, H, N>> {
+        self.shared.trie.lock().unwrap()
+    }
+
+    /// Blocks until the worker has rehashed every mutation made so far.
+    pub fn flush(&self) {
+        let progress = self.shared.progress.lock().unwrap();
+        let generation = progress.generation;
+        let _caught_up = self
+            .shared
+            .changed
+            .wait_while(progress, |progress| progress.hashed < generation)
+            .unwrap();
+    }
+
+    /// The root as of every mutation made so far, computed by the worker.
+    pub fn wait_for_root(&self) -> H::Hash {
+        self.flush();
+        self.read().merkle_root()
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> Drop for BackgroundHashedTrie<T, H, N> {
+    fn drop(&mut self) {
+        self.shared.progress.lock().unwrap().shutdown = true;
+        self.shared.changed.notify_all();
+        if let Some(worker) = self.worker.take() {
+            worker.join().unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn worker_keeps_root_cached() {
+        let trie: BackgroundHashedTrie<u32, crate::hasher::StdMerkleHasher> =
+            BackgroundHashedTrie::new(TrieNode::new());
+        let mut expected: TrieNode<u32> = TrieNode::new();
+        for key in 0..500 {
+            trie.insert(key, key * 2);
+            expected.insert(key, key * 2);
+        }
+        trie.flush();
+        assert_eq!(trie.read().current_root(), Some(&expected.merkle_root()));
+
+        trie.update(|trie| trie.remove_subtree(0b1, 1));
+        expected.remove_subtree(0b1, 1);
+        assert_eq!(trie.wait_for_root(), expected.merkle_root());
+        assert_eq!(*trie.read(), expected);
+    }
+}
@@ -0,0 +1,98 @@
+use std::{fs, path::Path, process::ExitCode};
+
+use binary_tree_blockchain::hasher::{MerkleHasher, StdMerkleHasher};
+use binary_tree_blockchain::test_vectors::{fixture_shapes, from_entries};
+
+const USAGE: &str = "usage:
+  gen-fixtures <out-dir> [--seed <n>]
+
+Writes <out-dir>/<hasher>/arity-<n>/<shape>.json for every hash backend built in (std, plus
+blake3 with the blake3 feature), arity 2, 4, 16 and 256, and each shape in
+`test_vectors::fixture_shapes`: the entries, root, proofs and a multiproof, as
+`TestVectors::to_json` renders them. The same seed always gives the same fixtures.";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(written) => {
+            println!("wrote {written} fixtures");
+            ExitCode::SUCCESS
+        }
+        Err(message) => {
+            eprintln!("error: {message}\n\n{USAGE}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<usize, String> {
+    let (out, seed) = match args {
+        [out] => (out, 0),
+        [out, flag, seed] if flag == "--seed" => {
+            (out, seed.parse().map_err(|_| format!("bad seed {seed:?}"))?)
+        }
+        _ => return Err("expected an output directory".to_string()),
+    };
+    let out = Path::new(out);
+    let written = write_hasher::<StdMerkleHasher>(out, "std", seed)?;
+    #[cfg(feature = "blake3")]
+    let written = written
+        + write_hasher::<binary_tree_blockchain::hasher::Blake3Hasher>(out, "blake3", seed)?;
+    Ok(written)
+}
+
+fn write_hasher<H: MerkleHasher + Default>(
+    out: &Path,
+    name: &str,
+    seed: u64,
+) -> Result<usize, String> {
+    let out = out.join(name);
+    Ok(write_arity::<H, 2>(&out, seed)?
+        + write_arity::<H, 4>(&out, seed)?
+        + write_arity::<H, 16>(&out, seed)?
+        + write_arity::<H, 256>(&out, seed)?)
+}
+
+fn write_arity<H: MerkleHasher + Default, const N: usize>(
+    out: &Path,
+    seed: u64,
+) -> Result<usize, String> {
+    let dir = out.join(format!("arity-{N}"));
+    fs::create_dir_all(&dir).map_err(|error| format!("{}: {error}", dir.display()))?;
+    let shapes = fixture_shapes(seed);
+    for shape in &shapes {
+        let json =
+            from_entries::<H, N>(seed, shape.entries.clone(), &shape.proof_keys).to_json::<H>();
+        let path = dir.join(format!("{}.json", shape.name));
+        fs::write(&path, json).map_err(|error| format!("{}: {error}", path.display()))?;
+    }
+    Ok(shapes.len())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn writes_one_fixture_per_backend_arity_and_shape() {
+        let out = std::env::temp_dir().join(format!("gen-fixtures-{}", std::process::id()));
+        let out_arg = out.to_str().unwrap().to_string();
+        let written = run(&[out_arg.clone(), "--seed".to_string(), "3".to_string()]).unwrap();
+        let shapes = fixture_shapes(3).len();
+        assert_eq!(written % (4 * shapes), 0);
+        let dense = fs::read_to_string(out.join("std/arity-16/dense.json")).unwrap();
+        assert!(dense.starts_with("{\"seed\":3,\"arity\":16,"));
+        assert_eq!(
+            run(&[out_arg.clone(), "--seed".to_string(), "3".to_string()]),
+            Ok(written)
+        );
+        assert_eq!(
+            fs::read_to_string(out.join("std/arity-16/dense.json")).unwrap(),
+            dense
+        );
+        assert!(run(&[]).is_err());
+        assert!(run(&[out_arg, "--seed".to_string(), "x".to_string()]).is_err());
+        fs::remove_dir_all(out).unwrap();
+    }
+}
@@ -0,0 +1,107 @@
+/// A path from the root as a run of up to 64 bits, walked least significant first: each step
+/// consumes the next `log2(N)` bits as the digit to follow, so the path of key `k` in a trie of
+/// arity `N` is `k` itself, as long as its digit count. Unlike a key, a path can name nodes
+/// whose last digit is 0, and prefixes of a key's path name the subtries above it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct BitPath {
+    bits: u64,
+    len: u32,
+}
+
+impl BitPath {
+    pub const MAX_LEN: u32 = u64::BITS;
+
+    /// The first `len` bits of `bits`; higher bits are dropped.
+    pub fn new(bits: u64, len: u32) -> Self {
+        assert!(len <= Self::MAX_LEN, "a path holds at most 64 bits");
+        BitPath {
+            bits: bits & Self::mask(len),
+            len,
+        }
+    }
+
+    pub fn bits(&self) -> u64 {
+        self.bits
+    }
+
+    /// The length in bits.
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of whole digits of `bits_per_digit` bits.
+    pub fn depth(&self, bits_per_digit: u32) -> u32 {
+        self.len / bits_per_digit
+    }
+
+    /// The digit followed at `depth`.
+    pub fn digit(&self, depth: u32, bits_per_digit: u32) -> usize {
+        debug_assert!((depth + 1) * bits_per_digit <= self.len);
+        ((self.bits >> (depth * bits_per_digit)) & Self::mask(bits_per_digit)) as usize
+    }
+
+    /// Every whole digit, in walk order.
+    pub fn digits(&self, bits_per_digit: u32) -> impl Iterator<Item = usize> + '_ {
+        (0..self.depth(bits_per_digit)).map(move |depth| self.digit(depth, bits_per_digit))
+    }
+
+    /// The first `len` bits.
+    pub fn prefix(&self, len: u32) -> Self {
+        BitPath::new(self.bits, len.min(self.len))
+    }
+
+    pub fn starts_with(&self, prefix: &BitPath) -> bool {
+        prefix.len <= self.len && self.prefix(prefix.len) == *prefix
+    }
+
+    /// The path one digit further down.
+    pub fn child(&self, digit: usize, bits_per_digit: u32) -> Self {
+        BitPath::new(
+            self.bits | (digit as u64) << self.len,
+            self.len + bits_per_digit,
+        )
+    }
+
+    fn mask(len: u32) -> u64 {
+        u64::MAX.checked_shl(len).map_or(u64::MAX, |high| !high)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+    use crate::trie_node::trie_node::TrieNode;
+
+    #[test]
+    fn paths_walk_keys_and_prefixes() {
+        let path = TrieNode::<u32>::key_path(0b1101);
+        assert_eq!(path, BitPath::new(0b1101, 4));
+        assert_eq!(path.digits(1).collect::<Vec<_>>(), [1, 0, 1, 1]);
+        assert!(path.starts_with(&BitPath::new(0b01, 2)));
+        assert!(!path.starts_with(&BitPath::new(0b11, 2)));
+        assert_eq!(BitPath::new(0b01, 2).child(1, 1).child(1, 1), path);
+        assert_eq!(
+            TrieNode::<u32, StdMerkleHasher, 16>::key_path(0x1f)
+                .digits(4)
+                .count(),
+            2
+        );
+
+        let mut node: TrieNode<u32> = [(0b1101, 1), (0b1010, 2)].into_iter().collect();
+        node.merkle_root();
+        assert_eq!(
+            node.find_by_path(&path).and_then(|node| node.get_data()),
+            Some(&1)
+        );
+        // A value-less node above key 0b1010 that no key can reach.
+        assert!(node.find_by_path(&BitPath::new(0b010, 3)).is_some());
+        assert!(node.find_by_path(&BitPath::new(0b111, 3)).is_none());
+        assert_eq!(TrieNode::<u32>::path_to_node(0b1101), [1, 1, 0, 1]);
+    }
+}
@@ -0,0 +1,148 @@
+use sha2::{Digest, Sha256};
+
+use crate::hasher::hashes_equal;
+
+pub type Sha256dHash = [u8; 32];
+
+pub fn sha256d(bytes: &[u8]) -> Sha256dHash {
+    Sha256::digest(Sha256::digest(bytes)).into()
+}
+
+fn combine(left: &Sha256dHash, right: &Sha256dHash) -> Sha256dHash {
+    let mut concatenated = [0u8; 64];
+    concatenated[..32].copy_from_slice(left);
+    concatenated[32..].copy_from_slice(right);
+    sha256d(&concatenated)
+}
+
+/// A Bitcoin transaction merkle tree over an ordered list of leaf hashes (txids in internal
+/// byte order, i.e. reversed from how block explorers display them). Each level pairs adjacent
+/// hashes with double-SHA256, duplicating the last hash of a level with an odd count.
+///
+/// The duplication rule means a list ending in a repeated pair of hashes has the same root as
+/// the list without the repeat (CVE-2012-2459); callers that accept untrusted leaf lists must
+/// reject duplicates themselves, as Bitcoin Core does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockMerkleTree {
+    levels: Vec<Vec<Sha256dHash>>,
+}
+
+/// The sibling hashes from a leaf up to the root, as in a `merkleblock` partial tree or an
+/// Electrum `blockchain.transaction.get_merkle` response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleBranch {
+    pub index: usize,
+    pub hashes: Vec<Sha256dHash>,
+}
+
+impl BlockMerkleTree {
+    pub fn new(leaves: Vec<Sha256dHash>) -> Self {
+        let mut levels = vec![leaves];
+        while levels.last().unwrap().len() > 1 {
+            let level = levels.last().unwrap();
+            let next = level
+                .chunks(2)
+                .map(|pair| combine(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+                .collect();
+            levels.push(next);
+        }
+        BlockMerkleTree { levels }
+    }
+
+    pub fn leaf_count(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    pub fn root(&self) -> Option<Sha256dHash> {
+        self.levels.last().unwrap().first().copied()
+    }
+
+    pub fn branch(&self, index: usize) -> Option<MerkleBranch> {
+        if index >= self.leaf_count() {
+            return None;
+        }
+        let mut position = index;
+        let mut hashes = vec![];
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling = position ^ 1;
+            hashes.push(*level.get(sibling).unwrap_or(&level[position]));
+            position /= 2;
+        }
+        Some(MerkleBranch { index, hashes })
+    }
+}
+
+impl MerkleBranch {
+    pub fn root_for(&self, leaf: &Sha256dHash) -> Sha256dHash {
+        let mut position = self.index;
+        let mut hash = *leaf;
+        for sibling in &self.hashes {
+            hash = if position & 1 == 0 {
+                combine(&hash, sibling)
+            } else {
+                combine(sibling, &hash)
+            };
+            position /= 2;
+        }
+        hash
+    }
+
+    pub fn verify(&self, leaf: &Sha256dHash, root: &Sha256dHash) -> bool {
+        hashes_equal(&self.root_for(leaf), root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hex;
+
+    fn from_display_hex(display: &str) -> Sha256dHash {
+        let mut hash = hex::decode_array::<32>(display).unwrap();
+        hash.reverse();
+        hash
+    }
+
+    #[test]
+    fn bitcoin_block_100000() {
+        let txids: Vec<Sha256dHash> = [
+            "8c14f0db3df150123e6f3dbbf30f8b955a8249b62ac1d1ff16284aefa3d06d87",
+            "fff2525b8931402dd09222c50775608f75787bd2b87e56995a7bdd30f79702c4",
+            "6359f0868171b1d194cbee1af2f16ea598ae8fad666d9b012c8ed2b79a236ec4",
+            "e9a66845e05d5abc0ad04ec80f774a7e585c6e8db975962d069a522137b80c1d",
+        ]
+        .iter()
+        .map(|txid| from_display_hex(txid))
+        .collect();
+        let tree = BlockMerkleTree::new(txids.clone());
+        let root = tree.root().unwrap();
+        assert_eq!(
+            root,
+            from_display_hex("f3e94742aca4b5ef85488dc37c06c3282295ffec960994b2c0d5ac2a25a95766")
+        );
+        for (index, txid) in txids.iter().enumerate() {
+            assert!(tree.branch(index).unwrap().verify(txid, &root));
+        }
+        assert!(!tree.branch(0).unwrap().verify(&txids[1], &root));
+        assert_eq!(tree.branch(4), None);
+    }
+
+    #[test]
+    fn odd_levels_duplicate_the_last_hash() {
+        let leaves: Vec<Sha256dHash> = (0u8..3).map(|i| sha256d(&[i])).collect();
+        let tree = BlockMerkleTree::new(leaves.clone());
+        let expected = combine(
+            &combine(&leaves[0], &leaves[1]),
+            &combine(&leaves[2], &leaves[2]),
+        );
+        assert_eq!(tree.root(), Some(expected));
+        assert!(tree.branch(2).unwrap().verify(&leaves[2], &expected));
+
+        assert_eq!(
+            BlockMerkleTree::new(vec![leaves[0]]).root(),
+            Some(leaves[0])
+        );
+        assert_eq!(BlockMerkleTree::new(vec![]).root(), None);
+    }
+}
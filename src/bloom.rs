@@ -0,0 +1,166 @@
+use crate::trie_node::trie_node::{TrieNode, ROOT};
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// A bloom filter over `u32` keys. Answers "definitely absent" or "maybe present"; keys can be
+/// added but not removed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    bit_count: u64,
+    hash_count: u32,
+    inserted: usize,
+}
+
+// splitmix64's finaliser, to spread consecutive keys over the whole filter.
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl BloomFilter {
+    /// Sized so that `false_positive_rate` holds once `expected_keys` keys have been added.
+    pub fn new(expected_keys: usize, false_positive_rate: f64) -> Self {
+        assert!(
+            false_positive_rate > 0.0 && false_positive_rate < 1.0,
+            "false positive rate must be between 0 and 1"
+        );
+        let keys = expected_keys.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let bit_count = (-keys * false_positive_rate.ln() / (ln2 * ln2))
+            .ceil()
+            .max(64.0) as u64;
+        let hash_count = ((bit_count as f64 / keys) * ln2).round().clamp(1.0, 32.0) as u32;
+        BloomFilter {
+            bits: vec![0; bit_count.div_ceil(64) as usize],
+            bit_count,
+            hash_count,
+            inserted: 0,
+        }
+    }
+
+    // Double hashing: the i-th probe is h1 + i * h2.
+    fn probes(&self, key: u32) -> impl Iterator<Item = u64> + '_ {
+        let h1 = mix(key as u64);
+        let h2 = mix(h1) | 1;
+        (0..self.hash_count as u64)
+            .map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % self.bit_count)
+    }
+
+    pub fn insert(&mut self, key: u32) {
+        let probes: Vec<u64> = self.probes(key).collect();
+        for bit in probes {
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+        self.inserted += 1;
+    }
+
+    /// `false` only if `key` was never inserted.
+    pub fn might_contain(&self, key: u32) -> bool {
+        self.probes(key)
+            .all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+
+    /// The expected false positive rate for the keys inserted so far.
+    pub fn false_positive_rate(&self) -> f64 {
+        let k = self.hash_count as f64;
+        (1.0 - (-k * self.inserted as f64 / self.bit_count as f64).exp()).powf(k)
+    }
+
+    pub fn bit_count(&self) -> u64 {
+        self.bit_count
+    }
+
+    pub fn hash_count(&self) -> u32 {
+        self.hash_count
+    }
+
+    /// A filter of the same size with nothing in it.
+    pub fn emptied(&self) -> Self {
+        BloomFilter {
+            bits: vec![0; self.bits.len()],
+            inserted: 0,
+            ..*self
+        }
+    }
+
+    pub(crate) fn heap_bytes(&self) -> usize {
+        self.bits.capacity() * size_of::<u64>()
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    /// Keeps a bloom filter over the keys that hold values, so `find_by_key` and
+    /// `contains_key` answer most lookups of absent keys without walking a path. The keys
+    /// already in the trie are added straight away. Removals leave the filter unchanged, so it
+    /// only ever errs towards "maybe present".
+    pub fn enable_bloom_filter(&mut self, expected_keys: usize, false_positive_rate: f64) {
+        let mut bloom = BloomFilter::new(expected_keys, false_positive_rate);
+        for (key, _) in self.iter() {
+            bloom.insert(key);
+        }
+        self.bloom = Some(bloom);
+    }
+
+    pub fn disable_bloom_filter(&mut self) {
+        self.bloom = None;
+    }
+
+    pub fn bloom_filter(&self) -> Option<&BloomFilter> {
+        self.bloom.as_ref()
+    }
+
+    /// Whether a value is stored under `key`.
+    pub fn contains_key(&self, key: u32) -> bool {
+        if self
+            .bloom
+            .as_ref()
+            .is_some_and(|bloom| !bloom.might_contain(key))
+        {
+            return false;
+        }
+        let mut index = ROOT;
+        for depth in 0..Self::key_depth(key) {
+            match self.node(index).child(Self::digit_at(key, depth)) {
+                Some(child) => index = child,
+                None => return false,
+            }
+        }
+        self.node(index).get_data().is_some()
+    }
+
+    // Records that a value was stored under `key`.
+    pub(crate) fn note_key(&mut self, key: u32) {
+        if let Some(bloom) = &mut self.bloom {
+            bloom.insert(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn bloom_filter_rules_out_absent_keys() {
+        let mut node: TrieNode<u32> = (0..500).map(|key| (key * 2, key)).collect();
+        node.enable_bloom_filter(1000, 0.01);
+        for key in 500..1000 {
+            node.insert(key * 2, key);
+        }
+        let bloom = node.bloom_filter().unwrap();
+        assert!((0..1000).all(|key| bloom.might_contain(key * 2)));
+        let false_positives = (0..1000)
+            .filter(|key| bloom.might_contain(key * 2 + 1))
+            .count();
+        assert!(false_positives < 30, "{false_positives} false positives");
+        assert!(bloom.false_positive_rate() < 0.02);
+
+        assert!(node.contains_key(40));
+        assert!(!node.contains_key(41));
+        assert_eq!(node.find_by_key(1999), None);
+        // Key 1 only leads to other keys, so it holds no value.
+        assert!(!node.contains_key(1));
+    }
+}
@@ -0,0 +1,178 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::trie_node::trie_node::{NodeIndex, TrieNode, ROOT};
+use crate::{hasher::MerkleHasher, instrumentation, merkle_data::MerkleData};
+
+// Nodes hashed between checks of the budget, so the clock is not read for every node and each
+// call makes some progress however small its budget.
+const NODES_PER_CHECK: usize = 64;
+
+/// Cancels a root computation from another thread, e.g. when the user navigates away.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The outcome of a root computation that may stop early.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RootPoll<D> {
+    Ready(D),
+    /// Stopped before reaching the root. The hashes computed so far stay cached, whatever the
+    /// cache policy, so calling again picks up where this call left off; the policy is applied
+    /// once the root is reached.
+    Pending,
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    /// `merkle_root`, giving up once `budget` has elapsed.
+    pub fn merkle_root_with_budget(&mut self, budget: Duration) -> RootPoll<H::Hash> {
+        let started = Instant::now();
+        self.merkle_root_until(NODES_PER_CHECK, |_| started.elapsed() >= budget)
+    }
+
+    /// `merkle_root`, giving up once `token` is cancelled.
+    pub fn merkle_root_cancellable(&mut self, token: &CancellationToken) -> RootPoll<H::Hash> {
+        self.merkle_root_until(NODES_PER_CHECK, |_| token.is_cancelled())
+    }
+
+    /// Advances the root computation by hashing at most `max_nodes` stale nodes, and at least
+    /// one, so a latency-sensitive caller can spread it over frames or ticks instead of
+    /// pausing once in `merkle_root`. Mutations between steps are fine: the next step picks up
+    /// whatever is stale by then.
+    pub fn rehash_step(&mut self, max_nodes: usize) -> RootPoll<H::Hash> {
+        self.merkle_root_until(1, |hashed| hashed >= max_nodes)
+    }
+
+    // Hashes the stale nodes children first, asking `stop` with the count hashed so far every
+    // `check_every` nodes.
+    fn merkle_root_until(
+        &mut self,
+        check_every: usize,
+        mut stop: impl FnMut(usize) -> bool,
+    ) -> RootPoll<H::Hash> {
+        let generation = self.cache_generation;
+        let mut hashed = 0;
+        let mut stack: Vec<(NodeIndex, u32, bool)> = vec![(ROOT, 0, false)];
+        while let Some((index, depth, children_done)) = stack.pop() {
+            let node = self.node(index);
+            if node.cached_merkle_root(generation).is_some() {
+                instrumentation::record_cache_hit();
+                continue;
+            }
+            if !children_done {
+                stack.push((index, depth, true));
+                stack.extend(
+                    node.children()
+                        .iter()
+                        .flatten()
+                        .map(|child| (*child, depth + 1, false)),
+                );
+                continue;
+            }
+            // Every child's root is cached by now, so this only hashes the node itself. Its
+            // hashes are cached whatever the policy, or a later call would have to redo them.
+            instrumentation::record_rehash();
+            if node.cached_data_hash(generation).is_none() {
+                let hash = match node.get_data() {
+                    Some(data) => self.hasher.hash(&data.merkle_bytes()),
+                    None => self.empty_hash().clone(),
+                };
+                self.node_mut(index).set_cached_data_hash(hash, generation);
+            }
+            if !self.node(index).is_leaf() {
+                let hash = self.combine_cached(index);
+                self.node_mut(index)
+                    .set_cached_merkle_root(hash, generation);
+            }
+            if !self.may_cache(index, Some(depth)) {
+                self.held_hashes.push(index);
+            }
+            hashed += 1;
+            if hashed % check_every == 0 && !stack.is_empty() && stop(hashed) {
+                return RootPoll::Pending;
+            }
+        }
+        let root = self
+            .node(ROOT)
+            .cached_merkle_root(generation)
+            .unwrap()
+            .clone();
+        self.drop_held_hashes();
+        RootPoll::Ready(root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn budgeted_roots_resume_from_partial_caches() {
+        let mut expected: TrieNode<u32> = (0..2000).map(|key| (key, key)).collect();
+        let root = expected.merkle_root();
+
+        let mut node: TrieNode<u32> = (0..2000).map(|key| (key, key)).collect();
+        let mut calls = 1;
+        while node.merkle_root_with_budget(Duration::ZERO) == RootPoll::Pending {
+            calls += 1;
+        }
+        assert!(calls > 1);
+        assert_eq!(
+            node.merkle_root_with_budget(Duration::ZERO),
+            RootPoll::Ready(root.clone())
+        );
+
+        let mut node: TrieNode<u32> = (0..2000).map(|key| (key, key)).collect();
+        let token = CancellationToken::new();
+        token.clone().cancel();
+        assert_eq!(node.merkle_root_cancellable(&token), RootPoll::Pending);
+        assert_eq!(
+            node.merkle_root_cancellable(&CancellationToken::new()),
+            RootPoll::Ready(root.clone())
+        );
+        node.insert(7, 0);
+        expected.insert(7, 0);
+        assert_eq!(
+            node.merkle_root_with_budget(Duration::from_secs(60)),
+            RootPoll::Ready(expected.merkle_root())
+        );
+    }
+
+    #[test]
+    fn rehash_steps_are_bounded() {
+        let mut expected: TrieNode<u32> = (0..2000).map(|key| (key, key)).collect();
+        let mut node: TrieNode<u32> = (0..2000).map(|key| (key, key)).collect();
+        let stale = node.metrics().node_count;
+        let mut steps = 1;
+        while node.rehash_step(100) == RootPoll::Pending {
+            steps += 1;
+        }
+        assert_eq!(steps, stale.div_ceil(100));
+        assert_eq!(node.current_root(), Some(&expected.merkle_root()));
+
+        // A change between steps is picked up, one node per step along its path.
+        node.insert(1999, 0);
+        expected.insert(1999, 0);
+        steps = 1;
+        while node.rehash_step(0) == RootPoll::Pending {
+            steps += 1;
+        }
+        assert_eq!(steps, TrieNode::<u32>::key_depth(1999) as usize + 1);
+        assert_eq!(node.rehash_step(0), RootPoll::Ready(expected.merkle_root()));
+    }
+}
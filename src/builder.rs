@@ -0,0 +1,195 @@
+use crate::async_store::{NodeStore, StoreError};
+use crate::cache_policy::CachePolicy;
+use crate::codec::ValueCodec;
+use crate::fixed_depth::FixedDepthTrie;
+use crate::hasher::{MerkleHasher, StdMerkleHasher};
+use crate::limits::{LimitedTrie, TrieLimits};
+use crate::merkle_data::MerkleData;
+use crate::persistent::PersistentTrie;
+use crate::secure::SecureTrie;
+use crate::trie_node::trie_node::TrieNode;
+
+/// When invalidated hashes are recomputed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HashingMode {
+    /// On the next call that needs them, such as `merkle_root`.
+    #[default]
+    Lazy,
+    /// After every mutation, as `TrieNode::set_eager_hashing` does.
+    Eager,
+}
+
+/// The settings a `TrieBuilder` applies to every trie it builds, whatever its kind.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrieConfig {
+    /// Nodes to allocate up front; see `TrieNode::with_capacity`.
+    pub capacity: usize,
+    pub hashing_mode: HashingMode,
+    pub cache_policy: CachePolicy,
+    /// Expected keys and false positive rate of a bloom filter over the keys, if any; see
+    /// `TrieNode::enable_bloom_filter`.
+    pub bloom_filter: Option<(usize, f64)>,
+}
+
+impl TrieConfig {
+    fn apply<T: MerkleData, H: MerkleHasher, const N: usize>(&self, trie: &mut TrieNode<T, H, N>) {
+        trie.set_cache_policy(self.cache_policy);
+        trie.set_eager_hashing(self.hashing_mode == HashingMode::Eager);
+        if let Some((expected_keys, false_positive_rate)) = self.bloom_filter {
+            trie.enable_bloom_filter(expected_keys, false_positive_rate);
+        }
+    }
+}
+
+/// Builds tries from one place as their options grow: the hash algorithm and arity, which are
+/// part of the trie's type, and a `TrieConfig`. The `build_*` and `open_*` methods pick the kind
+/// of trie, since a fixed depth, secure keys and a storage backend each come with their own
+/// wrapper around `TrieNode`.
+#[derive(Debug, Clone)]
+pub struct TrieBuilder<H = StdMerkleHasher, const N: usize = 2> {
+    hasher: H,
+    config: TrieConfig,
+}
+
+impl Default for TrieBuilder {
+    fn default() -> Self {
+        TrieBuilder::new()
+    }
+}
+
+impl TrieBuilder {
+    /// A binary trie hashed with `StdMerkleHasher`, with the default `TrieConfig`.
+    pub fn new() -> Self {
+        TrieBuilder {
+            hasher: StdMerkleHasher,
+            config: TrieConfig::default(),
+        }
+    }
+}
+
+impl<H: MerkleHasher, const N: usize> TrieBuilder<H, N> {
+    pub fn hasher<G: MerkleHasher>(self, hasher: G) -> TrieBuilder<G, N> {
+        TrieBuilder {
+            hasher,
+            config: self.config,
+        }
+    }
+
+    pub fn arity<const M: usize>(self) -> TrieBuilder<H, M> {
+        TrieBuilder {
+            hasher: self.hasher,
+            config: self.config,
+        }
+    }
+
+    pub fn config(&self) -> &TrieConfig {
+        &self.config
+    }
+
+    pub fn with_config(mut self, config: TrieConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn capacity(mut self, nodes: usize) -> Self {
+        self.config.capacity = nodes;
+        self
+    }
+
+    pub fn hashing_mode(mut self, hashing_mode: HashingMode) -> Self {
+        self.config.hashing_mode = hashing_mode;
+        self
+    }
+
+    pub fn cache_policy(mut self, cache_policy: CachePolicy) -> Self {
+        self.config.cache_policy = cache_policy;
+        self
+    }
+
+    pub fn bloom_filter(mut self, expected_keys: usize, false_positive_rate: f64) -> Self {
+        self.config.bloom_filter = Some((expected_keys, false_positive_rate));
+        self
+    }
+
+    pub fn build<T: MerkleData>(self) -> TrieNode<T, H, N> {
+        let mut trie = TrieNode::with_capacity(self.hasher, self.config.capacity);
+        self.config.apply(&mut trie);
+        trie
+    }
+
+    /// A `FixedDepthTrie` of `depth` digits.
+    pub fn build_fixed_depth<T: MerkleData + PartialEq>(
+        self,
+        depth: u32,
+    ) -> FixedDepthTrie<T, H, N> {
+        FixedDepthTrie::from_trie(self.build(), depth)
+    }
+
+    /// A `SecureTrie`, which stores values under hashed keys.
+    pub fn build_secure<T: MerkleData + PartialEq>(self) -> SecureTrie<T, H, N> {
+        SecureTrie::from_trie(self.build())
+    }
+
+    pub fn build_limited<T: MerkleData + PartialEq>(
+        self,
+        limits: TrieLimits,
+    ) -> LimitedTrie<T, H, N> {
+        LimitedTrie::from_trie(self.build(), limits)
+    }
+
+    /// Opens the `PersistentTrie` kept in `store`, storing values through `codec`. The capacity
+    /// is not used, since the arena is sized by what the store holds.
+    pub fn open_persistent<T, S, C>(
+        self,
+        store: S,
+        codec: C,
+    ) -> Result<PersistentTrie<T, H, S, N, C>, StoreError<S::Error>>
+    where
+        T: MerkleData,
+        S: NodeStore,
+        C: ValueCodec<T>,
+    {
+        let mut persistent = PersistentTrie::open_with_codec(store, self.hasher, codec)?;
+        self.config.apply(persistent.trie_mut());
+        Ok(persistent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn builders_configure_each_kind_of_trie() {
+        let builder = TrieBuilder::new()
+            .arity::<4>()
+            .capacity(64)
+            .hashing_mode(HashingMode::Eager)
+            .cache_policy(CachePolicy::AboveDepth(2))
+            .bloom_filter(100, 0.01);
+        let mut trie: TrieNode<u32, StdMerkleHasher, 4> = builder.clone().build();
+        assert!(trie.is_eager_hashing());
+        assert!(trie.bloom_filter().is_some());
+        assert_eq!(trie.cache_policy(), CachePolicy::AboveDepth(2));
+        trie.insert(9, 90);
+        assert!(trie.current_root().is_some());
+        let mut expected: TrieNode<u32, StdMerkleHasher, 4> = TrieNode::new();
+        expected.insert(9, 90);
+        assert_eq!(trie.merkle_root(), expected.merkle_root());
+
+        let mut fixed = builder.clone().build_fixed_depth::<u32>(4);
+        fixed.insert(3, 30).unwrap();
+        assert_eq!(fixed.depth(), 4);
+        assert!(fixed.trie().is_eager_hashing());
+        let mut secure = builder.clone().build_secure::<u32>();
+        secure.insert(3, 30).unwrap();
+        assert!(secure.trie().bloom_filter().is_some());
+        let limited = builder.build_limited::<u32>(TrieLimits::default());
+        assert!(limited.trie().is_eager_hashing());
+
+        let plain: TrieNode<u32> = TrieBuilder::default().build();
+        assert!(!plain.is_eager_hashing());
+        assert_eq!(TrieBuilder::new().config(), &TrieConfig::default());
+    }
+}
@@ -0,0 +1,134 @@
+use crate::trie_node::trie_node::{NodeIndex, TrieNode, ROOT};
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    /// Heap memory held by the current cached hashes, as `MerkleHasher::heap_bytes` counts it.
+    /// Hashes stored inline, such as fixed-size arrays, take none: their slots in the node arena
+    /// are allocated whether or not anything is cached.
+    pub fn cached_hash_bytes(&self) -> usize {
+        self.cached_nodes()
+            .into_iter()
+            .map(|(_, index)| self.cached_bytes_at(index))
+            .sum()
+    }
+
+    /// Drops cached hashes until those left hold at most `budget` bytes, deepest nodes first:
+    /// a deep node's hash is the cheapest to recompute and the least likely to be needed again,
+    /// while the levels near the root keep most of the trie's hashing from being redone.
+    /// Returns the number of nodes whose caches were dropped. The root stays correct, and later
+    /// computations cache the evicted hashes again, so a trie kept within a budget should be
+    /// trimmed again after each burst of reads or writes.
+    pub fn evict_caches(&mut self, budget: usize) -> usize {
+        let mut cached = self.cached_nodes();
+        let mut total: usize = cached
+            .iter()
+            .map(|(_, index)| self.cached_bytes_at(*index))
+            .sum();
+        cached.sort_unstable_by_key(|(depth, _)| *depth);
+        let mut evicted = 0;
+        while total > budget {
+            let Some((_, index)) = cached.pop() else {
+                break;
+            };
+            total -= self.cached_bytes_at(index);
+            self.node_mut(index).clear_cached_hashes();
+            evicted += 1;
+        }
+        evicted
+    }
+
+    fn cached_bytes_at(&self, index: NodeIndex) -> usize {
+        self.node(index)
+            .cached_hashes(self.cache_generation)
+            .map(H::heap_bytes)
+            .sum()
+    }
+
+    // The depth and index of every node holding a current cached hash.
+    fn cached_nodes(&self) -> Vec<(u32, NodeIndex)> {
+        let mut cached = vec![];
+        let mut stack = vec![(0, ROOT)];
+        while let Some((depth, index)) = stack.pop() {
+            let node = self.node(index);
+            if node.cached_hashes(self.cache_generation).next().is_some() {
+                cached.push((depth, index));
+            }
+            stack.extend(
+                node.children()
+                    .iter()
+                    .flatten()
+                    .map(|child| (depth + 1, *child)),
+            );
+        }
+        cached
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn eviction_keeps_the_shallow_caches_within_budget() {
+        let mut node: TrieNode<u32> = (0..500).map(|key| (key, key)).collect();
+        let root = node.merkle_root();
+        let full = node.cached_hash_bytes();
+        assert!(full > 0);
+
+        let evicted = node.evict_caches(full / 4);
+        assert!(evicted > 0);
+        assert!(node.cached_hash_bytes() <= full / 4);
+        assert_eq!(node.current_root(), Some(&root));
+        assert!(node.find_by_key(0).unwrap().cached_merkle_root(0).is_some());
+        assert_eq!(node.find_by_key(499).unwrap().cached_merkle_root(0), None);
+
+        // Evicted hashes are recomputed where needed, and writes still reach the root.
+        let proof = node.generate_proof(499).unwrap();
+        assert!(proof.verify(&crate::hasher::StdMerkleHasher, &root, &499u32));
+        node.retain(|key, value| {
+            *value += (key == 498) as u32;
+            true
+        });
+        let mut expected: TrieNode<u32> = (0..500).map(|key| (key, key)).collect();
+        expected.insert(498, 499);
+        assert_eq!(node.merkle_root(), expected.merkle_root());
+
+        assert_eq!(node.evict_caches(0), node.metrics().node_count);
+        assert_eq!(node.cached_hash_bytes(), 0);
+        assert_eq!(node.merkle_root(), expected.merkle_root());
+    }
+
+    #[test]
+    fn proofs_and_mapped_files_recompute_evicted_hashes() {
+        let mut expected: TrieNode<u32, StdMerkleHasher, 4> =
+            (0..300).map(|key| (key * 3, key)).collect();
+        let mut node = expected.clone();
+        node.merkle_root();
+        let full = node.cached_hash_bytes();
+        assert!(node.evict_caches(full / 4) > 0);
+
+        let proofs: Vec<_> = node
+            .iter_with_proofs()
+            .map(|(key, _, proof)| (key, proof))
+            .collect();
+        assert_eq!(proofs.len(), 300);
+        for (key, proof) in proofs {
+            assert_eq!(Some(proof), expected.generate_proof(key));
+        }
+        #[cfg(feature = "rayon")]
+        {
+            let keys: Vec<u32> = (0..300).map(|key| key * 3).collect();
+            let proofs: Vec<_> = keys
+                .iter()
+                .map(|key| expected.generate_proof(*key))
+                .collect();
+            assert_eq!(node.generate_proofs_parallel(&keys), proofs);
+        }
+        let (mut mapped, mut reference) = (vec![], vec![]);
+        node.write_mapped(&mut mapped).unwrap();
+        expected.write_mapped(&mut reference).unwrap();
+        assert_eq!(mapped, reference);
+    }
+}
@@ -0,0 +1,205 @@
+use crate::trie_node::trie_node::{NodeIndex, TrieNode, ROOT};
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// Which nodes keep their hashes cached. Caching deep nodes pays off when reads and proofs
+/// outnumber writes; in a write-heavy trie their hashes are invalidated again before they are
+/// reused, so keeping only the upper levels saves the memory and the churn. Nodes that don't
+/// keep their hashes are rehashed from below whenever an ancestor's is needed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CachePolicy {
+    #[default]
+    All,
+    /// Only leaves keep their hash, so every root computation recombines the internal nodes
+    /// but no value is hashed twice.
+    LeavesOnly,
+    /// Only nodes less than this many digits deep keep their hashes; `AboveDepth(0)` caches
+    /// nothing.
+    AboveDepth(u32),
+}
+
+impl CachePolicy {
+    // `depth` is `None` where the caller doesn't know it, in which case only a policy that
+    // doesn't depend on it lets the node cache.
+    fn allows(self, depth: Option<u32>, leaf: bool) -> bool {
+        match self {
+            CachePolicy::All => true,
+            CachePolicy::LeavesOnly => leaf,
+            CachePolicy::AboveDepth(limit) => depth.is_some_and(|depth| depth < limit),
+        }
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    pub fn cache_policy(&self) -> CachePolicy {
+        self.cache_policy
+    }
+
+    /// Switches to `policy`, dropping the cached hashes it doesn't keep. The root stays
+    /// correct; only what is recomputed on the next call changes.
+    pub fn set_cache_policy(&mut self, policy: CachePolicy) {
+        self.cache_policy = policy;
+        if policy != CachePolicy::All {
+            let mut stack = vec![(ROOT, 0)];
+            while let Some((index, depth)) = stack.pop() {
+                if !self.may_cache(index, Some(depth)) {
+                    self.node_mut(index).clear_cached_hashes();
+                }
+                stack.extend(
+                    self.node(index)
+                        .children()
+                        .iter()
+                        .flatten()
+                        .map(|child| (*child, depth + 1)),
+                );
+            }
+        }
+        self.rehash_if_eager();
+    }
+
+    pub(crate) fn may_cache(&self, index: NodeIndex, depth: Option<u32>) -> bool {
+        self.cache_policy.allows(depth, self.node(index).is_leaf())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn policies_limit_which_levels_stay_cached() {
+        let mut expected: TrieNode<u32> = (0..300).map(|key| (key, key)).collect();
+        let root = expected.merkle_root();
+        for policy in [
+            CachePolicy::LeavesOnly,
+            CachePolicy::AboveDepth(0),
+            CachePolicy::AboveDepth(3),
+        ] {
+            let mut node: TrieNode<u32> = (0..300).map(|key| (key, key)).collect();
+            node.merkle_root();
+            node.set_cache_policy(policy);
+            assert_eq!(node.cache_policy(), policy);
+            assert_eq!(node.merkle_root(), root);
+
+            let cached = |node: &TrieNode<u32>, key| {
+                node.find_by_key(key)
+                    .unwrap()
+                    .cached_merkle_root(0)
+                    .is_some()
+            };
+            assert_eq!(cached(&node, 0), policy == CachePolicy::AboveDepth(3));
+            assert_eq!(cached(&node, 2), policy == CachePolicy::AboveDepth(3));
+            assert!(!cached(&node, 4));
+            assert_eq!(cached(&node, 299), policy == CachePolicy::LeavesOnly);
+            assert_eq!(
+                node.current_root().is_some(),
+                policy == CachePolicy::AboveDepth(3)
+            );
+
+            // Writes below the cached levels still invalidate the ones above them.
+            node.insert(299, 0);
+            node.insert(1000, 1);
+            node.retain(|key, _| key != 4);
+            let mut changed = expected.clone();
+            changed.insert(299, 0);
+            changed.insert(1000, 1);
+            changed.retain(|key, _| key != 4);
+            assert_eq!(node.merkle_root(), changed.merkle_root());
+            let proof = node.generate_proof(299).unwrap();
+            assert!(proof.verify(&crate::hasher::StdMerkleHasher, &changed.merkle_root(), &0));
+
+            node.set_cache_policy(CachePolicy::All);
+            assert_eq!(node.merkle_root(), changed.merkle_root());
+            assert!(node.current_root().is_some());
+        }
+    }
+
+    #[test]
+    fn proofs_mapped_files_and_copies_work_under_every_policy() {
+        use std::time::Duration;
+
+        use crate::budget::{CancellationToken, RootPoll};
+        use crate::hasher::StdMerkleHasher;
+
+        let mut expected: TrieNode<u32, StdMerkleHasher, 4> =
+            (0..300).map(|key| (key * 3, key)).collect();
+        let keys: Vec<u32> = (0..300).map(|key| key * 3).collect();
+        let mut reference = vec![];
+        expected.write_mapped(&mut reference).unwrap();
+        for policy in [CachePolicy::LeavesOnly, CachePolicy::AboveDepth(2)] {
+            let mut node = expected.clone();
+            node.set_cache_policy(policy);
+            node.merkle_root();
+
+            let proofs: Vec<_> = node
+                .iter_with_proofs()
+                .map(|(key, _, proof)| (key, proof))
+                .collect();
+            assert_eq!(proofs.len(), keys.len());
+            for (key, proof) in proofs {
+                assert_eq!(Some(proof), expected.generate_proof(key));
+            }
+            #[cfg(feature = "rayon")]
+            {
+                let proofs: Vec<_> = keys
+                    .iter()
+                    .map(|key| expected.generate_proof(*key))
+                    .collect();
+                assert_eq!(node.generate_proofs_parallel(&keys), proofs);
+            }
+            let mut mapped = vec![];
+            node.write_mapped(&mut mapped).unwrap();
+            assert_eq!(mapped, reference);
+
+            assert_eq!(node.map_values(|value| value + 1).cache_policy(), policy);
+            assert_eq!(node.filter(|key, _| key % 2 == 0).cache_policy(), policy);
+            let mut drained = node.clone();
+            assert_eq!(drained.drain().count(), keys.len());
+            assert_eq!(drained.cache_policy(), policy);
+
+            // Budgeted roots keep what they hashed until the root is reached, so each call
+            // gets further, and only then drop what the policy doesn't keep.
+            let root = expected.merkle_root();
+            let uncached = || {
+                let mut node: TrieNode<u32, StdMerkleHasher, 4> =
+                    keys.iter().map(|key| (*key, key / 3)).collect();
+                node.set_cache_policy(policy);
+                node
+            };
+            let stale = node.metrics().node_count;
+            let mut stepped = uncached();
+            let mut steps = 1;
+            while stepped.rehash_step(10) == RootPoll::Pending {
+                steps += 1;
+                assert!(steps <= stale);
+            }
+            assert_eq!(steps, stale.div_ceil(10));
+            assert_eq!(
+                stepped.current_root().is_some(),
+                policy == CachePolicy::AboveDepth(2)
+            );
+            assert_eq!(stepped.merkle_root(), root);
+
+            let mut budgeted = uncached();
+            let mut calls = 1;
+            while budgeted.merkle_root_with_budget(Duration::ZERO) == RootPoll::Pending {
+                calls += 1;
+                assert!(calls <= stale);
+            }
+            assert_eq!(budgeted.merkle_root(), root);
+
+            let mut cancellable = uncached();
+            let token = CancellationToken::new();
+            token.cancel();
+            assert_eq!(
+                cancellable.merkle_root_cancellable(&token),
+                RootPoll::Pending
+            );
+            assert_eq!(
+                cancellable.merkle_root_cancellable(&CancellationToken::new()),
+                RootPoll::Ready(root)
+            );
+            assert!(cancellable.held_hashes.is_empty());
+        }
+    }
+}
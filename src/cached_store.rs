@@ -0,0 +1,166 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use crate::async_store::{NodeId, NodeStore};
+use crate::stats::CacheStats;
+
+#[derive(Default)]
+struct Lru {
+    // Each cached record with the tick of its last use; `by_use` orders ids by that tick.
+    records: HashMap<NodeId, (Vec<u8>, u64)>,
+    by_use: BTreeMap<u64, NodeId>,
+    tick: u64,
+    stats: CacheStats,
+}
+
+impl Lru {
+    fn touch(&mut self, id: NodeId) -> Option<&[u8]> {
+        self.tick += 1;
+        let (record, used) = self.records.get_mut(&id)?;
+        self.by_use.remove(used);
+        *used = self.tick;
+        self.by_use.insert(self.tick, id);
+        Some(record)
+    }
+
+    fn insert(&mut self, id: NodeId, record: Vec<u8>, capacity: usize) {
+        self.remove(id);
+        if capacity == 0 {
+            return;
+        }
+        while self.records.len() >= capacity {
+            let (_, oldest) = self.by_use.pop_first().unwrap();
+            self.records.remove(&oldest);
+            self.stats.evictions += 1;
+        }
+        self.tick += 1;
+        self.records.insert(id, (record, self.tick));
+        self.by_use.insert(self.tick, id);
+    }
+
+    fn remove(&mut self, id: NodeId) {
+        if let Some((_, used)) = self.records.remove(&id) {
+            self.by_use.remove(&used);
+        }
+    }
+}
+
+/// Keeps the `capacity` most recently used records of `S` in memory, so repeated lookups and
+/// proofs near hot keys are served without touching the backend. Writes go through to `S`.
+pub struct CachedStore<S> {
+    inner: S,
+    capacity: usize,
+    lru: Mutex<Lru>,
+}
+
+impl<S: NodeStore> CachedStore<S> {
+    pub fn new(inner: S, capacity: usize) -> Self {
+        CachedStore {
+            inner,
+            capacity,
+            lru: Mutex::new(Lru::default()),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let lru = self.lru.lock().unwrap();
+        CacheStats {
+            cached_nodes: lru.records.len(),
+            ..lru.stats
+        }
+    }
+}
+
+impl<S: NodeStore> NodeStore for CachedStore<S> {
+    type Error = S::Error;
+
+    fn get(&self, id: NodeId) -> Result<Option<Vec<u8>>, S::Error> {
+        {
+            let mut lru = self.lru.lock().unwrap();
+            if let Some(record) = lru.touch(id).map(<[u8]>::to_vec) {
+                lru.stats.hits += 1;
+                return Ok(Some(record));
+            }
+            lru.stats.misses += 1;
+        }
+        let record = self.inner.get(id)?;
+        if let Some(record) = &record {
+            let mut lru = self.lru.lock().unwrap();
+            lru.insert(id, record.clone(), self.capacity);
+        }
+        Ok(record)
+    }
+
+    fn put(&self, id: NodeId, bytes: Vec<u8>) -> Result<(), S::Error> {
+        self.lru.lock().unwrap().remove(id);
+        self.inner.put(id, bytes.clone())?;
+        self.lru.lock().unwrap().insert(id, bytes, self.capacity);
+        Ok(())
+    }
+
+    fn delete(&self, id: NodeId) -> Result<(), S::Error> {
+        self.lru.lock().unwrap().remove(id);
+        self.inner.delete(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::async_store::{AsyncTrie, MemoryNodeStore};
+    use crate::hasher::StdMerkleHasher;
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let mut context = std::task::Context::from_waker(std::task::Waker::noop());
+        loop {
+            if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+        }
+    }
+
+    #[test]
+    fn hot_paths_are_served_from_memory() {
+        let store = CachedStore::new(MemoryNodeStore::new(), 2);
+        for id in 1..=3 {
+            store.put(id, vec![id as u8]).unwrap();
+        }
+        assert_eq!(store.stats().evictions, 1);
+        assert_eq!(store.get(1).unwrap(), Some(vec![1]));
+        assert_eq!(store.get(3).unwrap(), Some(vec![3]));
+        assert_eq!(store.get(1).unwrap(), Some(vec![1]));
+        assert_eq!(store.get(2).unwrap(), Some(vec![2]));
+        let stats = store.stats();
+        assert_eq!((stats.hits, stats.misses, stats.cached_nodes), (2, 2, 2));
+
+        block_on(async {
+            let mut trie: AsyncTrie<u32, _, _> = AsyncTrie::open(
+                CachedStore::new(MemoryNodeStore::new(), 64),
+                StdMerkleHasher,
+            )
+            .await
+            .unwrap();
+            for key in 0..40 {
+                trie.insert(key, key).await.unwrap();
+            }
+            trie.generate_proof(17).await.unwrap().unwrap();
+            let before = trie.store().stats();
+            for _ in 0..10 {
+                trie.generate_proof(17).await.unwrap().unwrap();
+            }
+            let after = trie.store().stats();
+            assert_eq!(after.misses, before.misses);
+            assert!(after.hit_rate() > before.hit_rate());
+        });
+    }
+}
@@ -0,0 +1,336 @@
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use crate::codec::ValueCodec;
+use crate::trie_node::trie_node::{Node, NodeIndex, TrieNode, ROOT};
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+const MAGIC: &[u8; 8] = b"MRKLCKPT";
+const VERSION: u8 = 1;
+const NO_CHILD: u32 = u32::MAX;
+
+const HAS_DATA: u8 = 1;
+const HAS_DATA_HASH: u8 = 2;
+const HAS_MERKLE_ROOT: u8 = 4;
+
+pub(crate) fn invalid(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub(crate) struct Reader<'a> {
+    pub(crate) bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err(invalid("file is truncated"));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    pub(crate) fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn hash<H: MerkleHasher>(&mut self) -> io::Result<H::Hash> {
+        let len = self.u16()? as usize;
+        H::hash_from_bytes(self.take(len)?).ok_or_else(|| invalid("malformed hash"))
+    }
+}
+
+pub(crate) fn write_hash<W: Write>(out: &mut W, hash: &[u8]) -> io::Result<()> {
+    let len = u16::try_from(hash.len()).map_err(|_| invalid("hash is too long"))?;
+    out.write_all(&len.to_be_bytes())?;
+    out.write_all(hash)
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    /// Writes every reachable node, its value and its cached hashes to `path`. All hashes are
+    /// computed first so the checkpoint is complete. The file is written next to `path` and
+    /// renamed into place, so a crash never leaves a half-written checkpoint behind.
+    pub fn save_checkpoint<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()>
+    where
+        T: ToString,
+    {
+        self.write_checkpoint(path.as_ref(), |data| data.to_string().into_bytes())
+    }
+
+    /// `save_checkpoint`, with values written by `codec` instead of as their `ToString`
+    /// rendering.
+    pub fn save_checkpoint_with_codec<P, C>(&mut self, path: P, codec: &C) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+        C: ValueCodec<T>,
+    {
+        self.write_checkpoint(path.as_ref(), |data| codec.encode(data))
+    }
+
+    /// The bytes `save_checkpoint_with_codec` would write, for sending a trie to a peer, which
+    /// can check them with `UntrustedTrie::from_bytes`.
+    pub fn checkpoint_bytes_with_codec<C: ValueCodec<T>>(&mut self, codec: &C) -> Vec<u8> {
+        let mut out = vec![];
+        self.write_checkpoint_to(&mut out, |data| codec.encode(data))
+            .expect("hash is too long");
+        out
+    }
+
+    fn write_checkpoint(&mut self, path: &Path, encode: impl Fn(&T) -> Vec<u8>) -> io::Result<()> {
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        let mut out = BufWriter::new(fs::File::create(&temporary)?);
+        self.write_checkpoint_to(&mut out, encode)?;
+        out.into_inner()?.sync_all()?;
+        fs::rename(temporary, path)
+    }
+
+    fn write_checkpoint_to<W: Write>(
+        &mut self,
+        out: &mut W,
+        encode: impl Fn(&T) -> Vec<u8>,
+    ) -> io::Result<()> {
+        let root = self.merkle_root();
+
+        // Breadth-first, so every child is written after its parent.
+        let mut order = vec![ROOT];
+        let mut position = 0;
+        while position < order.len() {
+            order.extend(self.node(order[position]).children().iter().flatten());
+            position += 1;
+        }
+        let mut renumbered = vec![NO_CHILD; self.nodes.len()];
+        for (new_index, old_index) in order.iter().enumerate() {
+            renumbered[*old_index as usize] = new_index as u32;
+        }
+
+        out.write_all(MAGIC)?;
+        out.write_all(&[VERSION])?;
+        out.write_all(&(N as u16).to_be_bytes())?;
+        out.write_all(&[self.eager_hashing as u8])?;
+        out.write_all(&(order.len() as u32).to_be_bytes())?;
+        write_hash(out, root.as_ref())?;
+        for index in order {
+            let node = self.node(index);
+            let flags = [
+                (node.get_data().is_some(), HAS_DATA),
+                (
+                    node.cached_data_hash(self.cache_generation).is_some(),
+                    HAS_DATA_HASH,
+                ),
+                // A leaf's root is its data hash, so it is only written once.
+                (
+                    !node.is_leaf() && node.cached_merkle_root(self.cache_generation).is_some(),
+                    HAS_MERKLE_ROOT,
+                ),
+            ]
+            .iter()
+            .filter(|(present, _)| *present)
+            .fold(0, |flags, (_, flag)| flags | flag);
+            out.write_all(&[flags])?;
+            for digit in 0..N {
+                let child = node.child(digit);
+                let child = child.map_or(NO_CHILD, |c| renumbered[c as usize]);
+                out.write_all(&child.to_be_bytes())?;
+            }
+            if let Some(data) = node.get_data() {
+                let data = encode(data);
+                out.write_all(&(data.len() as u32).to_be_bytes())?;
+                out.write_all(&data)?;
+            }
+            for hash in node.cached_hashes(self.cache_generation) {
+                write_hash(out, hash.as_ref())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a checkpoint written by `save_checkpoint`, then re-derives every hash from the
+    /// values and checks it against both the stored caches and the recorded root. Any mismatch
+    /// is reported as `InvalidData`.
+    pub fn load_checkpoint<P: AsRef<Path>>(path: P) -> io::Result<Self>
+    where
+        T: FromStr,
+        H: Default,
+    {
+        Self::read_checkpoint(path.as_ref(), |bytes| {
+            std::str::from_utf8(bytes).ok()?.parse().ok()
+        })
+    }
+
+    /// `load_checkpoint` for a checkpoint saved with `save_checkpoint_with_codec`.
+    pub fn load_checkpoint_with_codec<P, C>(path: P, codec: &C) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+        C: ValueCodec<T>,
+        H: Default,
+    {
+        Self::read_checkpoint(path.as_ref(), |bytes| codec.decode(bytes))
+    }
+
+    fn read_checkpoint(path: &Path, decode: impl Fn(&[u8]) -> Option<T>) -> io::Result<Self>
+    where
+        H: Default,
+    {
+        Self::decode_checkpoint(&fs::read(path)?, H::default(), decode)
+    }
+
+    pub(crate) fn decode_checkpoint(
+        bytes: &[u8],
+        hasher: H,
+        decode: impl Fn(&[u8]) -> Option<T>,
+    ) -> io::Result<Self> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(invalid("not a checkpoint file"));
+        }
+        if reader.u8()? != VERSION {
+            return Err(invalid("unsupported checkpoint version"));
+        }
+        if reader.u16()? as usize != N {
+            return Err(invalid("checkpoint was written for a different arity"));
+        }
+        let eager_hashing = reader.u8()? != 0;
+        let node_count = reader.u32()?;
+        // Each node takes at least its flags and children, which bounds what the count can
+        // make us allocate.
+        let max_nodes = reader.bytes.len() / (1 + 4 * N);
+        if node_count == 0 || node_count == NO_CHILD || node_count as usize > max_nodes {
+            return Err(invalid("bad node count"));
+        }
+        let recorded_root = reader.hash::<H>()?;
+
+        let mut trie = TrieNode::with_hasher(hasher);
+        trie.nodes.clear();
+        trie.eager_hashing = eager_hashing;
+        let mut referenced = vec![false; node_count as usize];
+        for index in 0..node_count {
+            let flags = reader.u8()?;
+            let mut node = Node::new(None);
+            for digit in 0..N {
+                let child = reader.u32()?;
+                if child == NO_CHILD {
+                    continue;
+                }
+                // Children always come after their parent, and each node has a single parent.
+                if child <= index || child >= node_count || referenced[child as usize] {
+                    return Err(invalid("checkpoint is not a tree"));
+                }
+                referenced[child as usize] = true;
+                node.set_child(digit, child);
+            }
+            if flags & HAS_DATA != 0 {
+                let len = reader.u32()? as usize;
+                let data = decode(reader.take(len)?).ok_or_else(|| invalid("unparseable value"))?;
+                node.replace_data(data);
+            }
+            if flags & HAS_DATA_HASH != 0 {
+                node.set_cached_data_hash(reader.hash::<H>()?, trie.cache_generation);
+            }
+            if flags & HAS_MERKLE_ROOT != 0 {
+                node.set_cached_merkle_root(reader.hash::<H>()?, trie.cache_generation);
+            }
+            trie.nodes.push(node);
+        }
+        if !reader.bytes.is_empty() {
+            return Err(invalid("trailing bytes after checkpoint"));
+        }
+
+        for index in (0..node_count).rev() {
+            trie.verify_cached_hashes_at(index)?;
+        }
+        if trie.current_root() != Some(&recorded_root) {
+            return Err(invalid("checkpoint root mismatch"));
+        }
+        Ok(trie)
+    }
+
+    // Recomputes the hashes of one node from its value and its (already verified) children.
+    fn verify_cached_hashes_at(&mut self, index: NodeIndex) -> io::Result<()> {
+        let generation = self.cache_generation;
+        let node = self.node_mut(index);
+        let stored_data_hash = node.cached_data_hash(generation).cloned();
+        let stored_merkle_root = node.cached_merkle_root(generation).cloned();
+        node.clear_cached_hashes();
+        let data_hash = self.data_hash_at(index);
+        let merkle_root = self.merkle_root_at(index);
+        if stored_data_hash.is_some_and(|stored| stored != data_hash)
+            || stored_merkle_root.is_some_and(|stored| stored != merkle_root)
+        {
+            return Err(invalid("checkpoint hash mismatch"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn checkpoint_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("{name}-{}.ckpt", std::process::id()))
+    }
+
+    #[test]
+    fn checkpoint_round_trip() {
+        let mut node: TrieNode<String> = TrieNode::new();
+        for key in [0, 1, 2, 6, 13, 1000, u32::MAX] {
+            node.insert(key, format!("value {key}"));
+        }
+        node.remove_subtree(0b1, 1);
+        let path = checkpoint_path("checkpoint_round_trip");
+        node.save_checkpoint(&path).unwrap();
+
+        let loaded: TrieNode<String> = TrieNode::load_checkpoint(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.current_root(), Some(&node.merkle_root()));
+        for key in [0, 2, 6, 1000] {
+            assert_eq!(
+                loaded.find_by_key(key).unwrap().get_data(),
+                Some(&format!("value {key}"))
+            );
+        }
+        assert_eq!(loaded.find_by_key(13), None);
+        assert_eq!(loaded.metrics().node_count, node.metrics().node_count);
+    }
+
+    #[test]
+    fn tampered_checkpoint_is_rejected() {
+        let mut node: TrieNode<u32> = TrieNode::new();
+        for key in 1..20 {
+            node.insert(key, key * 7);
+        }
+        let path = checkpoint_path("tampered_checkpoint_is_rejected");
+        node.save_checkpoint(&path).unwrap();
+        let original = fs::read(&path).unwrap();
+
+        let value = b"133";
+        let at = original
+            .windows(value.len())
+            .position(|window| window == value)
+            .unwrap();
+        let mut tampered = original.clone();
+        tampered[at + 2] = b'4';
+        fs::write(&path, &tampered).unwrap();
+        let error = TrieNode::<u32>::load_checkpoint(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        fs::write(&path, &original[..original.len() - 1]).unwrap();
+        assert!(TrieNode::<u32>::load_checkpoint(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
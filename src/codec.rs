@@ -0,0 +1,130 @@
+use std::str::FromStr;
+
+/// How a storage layer turns values into bytes and back. This is independent of
+/// `MerkleData::merkle_bytes`, which is what values commit to.
+pub trait ValueCodec<T> {
+    fn encode(&self, value: &T) -> Vec<u8>;
+
+    /// `None` if `bytes` were not written by `encode`.
+    fn decode(&self, bytes: &[u8]) -> Option<T>;
+}
+
+/// Values as their `ToString` rendering, parsed back with `FromStr`. The storage layers use
+/// this unless given another codec.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayCodec;
+
+impl<T: ToString + FromStr> ValueCodec<T> for DisplayCodec {
+    fn encode(&self, value: &T) -> Vec<u8> {
+        value.to_string().into_bytes()
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Option<T> {
+        std::str::from_utf8(bytes).ok()?.parse().ok()
+    }
+}
+
+/// Byte values as they are.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BytesCodec;
+
+impl ValueCodec<Vec<u8>> for BytesCodec {
+    fn encode(&self, value: &Vec<u8>) -> Vec<u8> {
+        value.clone()
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Option<Vec<u8>> {
+        Some(bytes.to_vec())
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BincodeCodec;
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize + serde::de::DeserializeOwned> ValueCodec<T> for BincodeCodec {
+    fn encode(&self, value: &T) -> Vec<u8> {
+        bincode::serialize(value).expect("value must be bincode-serializable")
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Option<T> {
+        bincode::deserialize(bytes).ok()
+    }
+}
+
+#[cfg(feature = "borsh")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BorshCodec;
+
+#[cfg(feature = "borsh")]
+impl<T: borsh::BorshSerialize + borsh::BorshDeserialize> ValueCodec<T> for BorshCodec {
+    fn encode(&self, value: &T) -> Vec<u8> {
+        borsh::to_vec(value).expect("value must be borsh-serializable")
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Option<T> {
+        borsh::from_slice(bytes).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::trie_node::trie_node::TrieNode;
+
+    #[test]
+    fn snapshots_round_trip_through_a_codec() {
+        let mut node: TrieNode<u32> = (0..20).map(|key| (key * 7, key)).collect();
+        let mut bytes = vec![];
+        node.export_snapshot(&mut bytes).unwrap();
+        let mut display = vec![];
+        node.export_snapshot_with_codec(&mut display, &DisplayCodec)
+            .unwrap();
+        assert_eq!(bytes, display);
+        assert!(ValueCodec::<u32>::decode(&DisplayCodec, b"x").is_none());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn bincode_codec_stores_structs() {
+        use crate::merkle_data::BincodeEncoded;
+
+        #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Account {
+            owner: String,
+            balance: u64,
+        }
+
+        let mut node: TrieNode<BincodeEncoded<Account>> = TrieNode::new();
+        for key in 0..10 {
+            let account = Account {
+                owner: format!("owner {key}"),
+                balance: key as u64 * 100,
+            };
+            node.insert(key, BincodeEncoded(account));
+        }
+        let mut bytes = vec![];
+        node.export_snapshot_with_codec(&mut bytes, &BincodeCodec)
+            .unwrap();
+        let mut imported: TrieNode<BincodeEncoded<Account>> =
+            TrieNode::import_snapshot_with_codec(bytes.as_slice(), &BincodeCodec).unwrap();
+        assert_eq!(imported.merkle_root(), node.merkle_root());
+        assert_eq!(
+            imported.find_by_key(3).unwrap().get_data().unwrap().0.owner,
+            "owner 3"
+        );
+    }
+
+    #[cfg(feature = "borsh")]
+    #[test]
+    fn borsh_codec_round_trips() {
+        use crate::merkle_data::BorshEncoded;
+
+        let value = BorshEncoded((7u32, "seven".to_string()));
+        let bytes = BorshCodec.encode(&value);
+        assert_eq!(bytes, borsh::to_vec(&value.0).unwrap());
+        assert_eq!(BorshCodec.decode(&bytes), Some(value));
+    }
+}
@@ -0,0 +1,324 @@
+use crate::hasher::{hashes_equal, MerkleHasher};
+use crate::merkle_data::MerkleData;
+use crate::proof::{MerkleProof, SUPPORTED_PROOF_VERSIONS};
+
+/// How an internal node's hash is formed from its data hash and its children's roots, given
+/// in digit order with the empty hash for missing children. Wrap a hasher in
+/// `CombinedHasher` to use a combiner in a trie.
+///
+/// Proofs are folded with the same combination step as the trie, so `MerkleProof::verify` and
+/// `verify_multiproof` accept proofs from a trie using a combiner when given the same
+/// `CombinedHasher`, and no other; verifiers outside this crate must reproduce the combiner.
+/// What a proof binds depends on the combiner: one that keeps children in digit order, as
+/// both below do, commits to where each child sits, so a proof also proves its key.
+pub trait NodeCombiner<H: MerkleHasher> {
+    fn combine(&self, hasher: &H, data: &H::Hash, children: &[H::Hash]) -> H::Hash;
+}
+
+/// The hasher's own `combine_children`: by default the hash of the data hash followed by
+/// every child root.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ConcatCombiner;
+
+impl<H: MerkleHasher> NodeCombiner<H> for ConcatCombiner {
+    fn combine(&self, hasher: &H, data: &H::Hash, children: &[H::Hash]) -> H::Hash {
+        hasher.combine_children(data, children)
+    }
+}
+
+/// Leaves empty children out, hashing a bitmap of the digits present, the data hash and the
+/// present children's roots. Sparse nodes hash fewer bytes. Proofs keep their shape, carrying
+/// the empty hash for empty siblings, which the verifier drops again.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OmitEmptyCombiner;
+
+impl<H: MerkleHasher> NodeCombiner<H> for OmitEmptyCombiner {
+    fn combine(&self, hasher: &H, data: &H::Hash, children: &[H::Hash]) -> H::Hash {
+        let empty = hasher.empty_hash();
+        let mut bitmap = vec![0u8; children.len().div_ceil(8)];
+        let mut concatenated = vec![];
+        for (digit, child) in children.iter().enumerate() {
+            if *child != empty {
+                bitmap[digit / 8] |= 1 << (digit % 8);
+                concatenated.extend_from_slice(child.as_ref());
+            }
+        }
+        let mut bytes = bitmap;
+        bytes.extend_from_slice(data.as_ref());
+        bytes.extend(concatenated);
+        hasher.hash(&bytes)
+    }
+}
+
+/// Hashes the data hash followed by the children's roots sorted by their bytes, so a node's
+/// hash doesn't depend on which digit each child sits under. For a binary trie this is the
+/// sorted-pair convention of OpenZeppelin's `MerkleProof` and most airdrop tools, and a proof
+/// can be checked with `MerkleProof::verify_commutative`, which needs no key or direction bits.
+///
+/// The price is that the root no longer binds keys to values: a proof for one key verifies for
+/// any other key as deep, and swapping two subtrees leaves the root unchanged. Use it when the
+/// value itself says what it is for, such as an airdrop claim naming its recipient.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SortedCombiner;
+
+impl<H: MerkleHasher> NodeCombiner<H> for SortedCombiner {
+    fn combine(&self, hasher: &H, data: &H::Hash, children: &[H::Hash]) -> H::Hash {
+        let mut sorted: Vec<&[u8]> = children.iter().map(AsRef::as_ref).collect();
+        sorted.sort_unstable();
+        let mut bytes = data.as_ref().to_vec();
+        for child in sorted {
+            bytes.extend_from_slice(child);
+        }
+        hasher.hash(&bytes)
+    }
+}
+
+/// Makes nodes without a value transparent. By default a value-less node contributes the empty
+/// hash as its data hash, and a node whose children are all empty still combines them, so two
+/// tries holding the same entries can have different roots when one keeps value-less or
+/// childless nodes the other never had, e.g. those `remove_subtree` leaves on the path to what
+/// it removed. Here a node whose children are all empty hashes as its data hash alone, as a leaf
+/// does, and a node without a value hashes its children's roots alone, so any node left with
+/// nothing under it hashes as the empty hash and the root depends only on the entries.
+///
+/// A value whose hash is the empty hash, such as an empty string's under most hashers, is
+/// indistinguishable from no value in either mode.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TransparentCombiner;
+
+impl<H: MerkleHasher> NodeCombiner<H> for TransparentCombiner {
+    fn combine(&self, hasher: &H, data: &H::Hash, children: &[H::Hash]) -> H::Hash {
+        let empty = hasher.empty_hash();
+        if children.iter().all(|child| *child == empty) {
+            return data.clone();
+        }
+        if *data != empty {
+            return hasher.combine_children(data, children);
+        }
+        let mut bytes = vec![];
+        for child in children {
+            bytes.extend_from_slice(child.as_ref());
+        }
+        hasher.hash(&bytes)
+    }
+}
+
+/// `H` with its internal nodes combined by `C`; values and the empty hash are hashed by `H`
+/// as before.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CombinedHasher<H, C> {
+    pub hasher: H,
+    pub combiner: C,
+}
+
+impl<H, C> CombinedHasher<H, C> {
+    pub fn new(hasher: H, combiner: C) -> Self {
+        CombinedHasher { hasher, combiner }
+    }
+}
+
+impl<H: MerkleHasher, C: NodeCombiner<H>> MerkleHasher for CombinedHasher<H, C> {
+    type Hash = H::Hash;
+
+    fn hash(&self, bytes: &[u8]) -> H::Hash {
+        self.hasher.hash(bytes)
+    }
+
+    fn hash_batch(&self, inputs: &[&[u8]]) -> Vec<H::Hash> {
+        self.hasher.hash_batch(inputs)
+    }
+
+    fn hash_from_bytes(bytes: &[u8]) -> Option<H::Hash> {
+        H::hash_from_bytes(bytes)
+    }
+
+    fn combine(&self, data: &H::Hash, left: &H::Hash, right: &H::Hash) -> H::Hash {
+        self.combine_children(data, &[left.clone(), right.clone()])
+    }
+
+    fn combine_children(&self, data: &H::Hash, children: &[H::Hash]) -> H::Hash {
+        self.combiner.combine(&self.hasher, data, children)
+    }
+
+    fn empty_hash(&self) -> H::Hash {
+        self.hasher.empty_hash()
+    }
+
+    fn max_hash_len(&self) -> usize {
+        self.hasher.max_hash_len()
+    }
+
+    fn heap_bytes(hash: &H::Hash) -> usize {
+        H::heap_bytes(hash)
+    }
+
+    fn hash_to_string(hash: &H::Hash) -> String {
+        H::hash_to_string(hash)
+    }
+
+    fn hash_from_string(string: &str) -> Option<H::Hash> {
+        H::hash_from_string(string)
+    }
+}
+
+impl<D: Clone + AsRef<[u8]>> MerkleProof<D> {
+    /// Checks a proof from a trie hashed with `SortedCombiner` without looking at its key: each
+    /// level's siblings are combined with the hash so far whatever digit it was under. The key
+    /// is neither needed nor checked, so this proves only that `value` is in the trie.
+    pub fn verify_commutative<H, V>(
+        &self,
+        hasher: &CombinedHasher<H, SortedCombiner>,
+        root: &D,
+        value: &V,
+    ) -> bool
+    where
+        H: MerkleHasher<Hash = D>,
+        V: MerkleData + ?Sized,
+    {
+        if !SUPPORTED_PROOF_VERSIONS.contains(&self.version)
+            || !self.arity.is_power_of_two()
+            || !(2..=256).contains(&self.arity)
+            || !(self.children_roots.is_empty() || self.children_roots.len() == self.arity)
+        {
+            return false;
+        }
+        let data_hash = hasher.hash(&value.merkle_bytes());
+        let mut hash = if self.children_roots.is_empty() {
+            data_hash
+        } else {
+            hasher.combine_children(&data_hash, &self.children_roots)
+        };
+        for level in &self.levels {
+            if level.siblings.len() != self.arity - 1 {
+                return false;
+            }
+            let mut children = level.siblings.clone();
+            children.push(hash);
+            hash = hasher.combine_children(&level.data_hash, &children);
+        }
+        hashes_equal(hash.as_ref(), root.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+    use crate::trie_node::trie_node::TrieNode;
+
+    #[test]
+    fn combiners_change_roots_and_proofs_follow() {
+        let entries = || (0..100).map(|key| (key * 3, key));
+        let mut plain: TrieNode<u32, StdMerkleHasher, 4> = entries().collect();
+        let mut concat: TrieNode<u32, CombinedHasher<StdMerkleHasher, ConcatCombiner>, 4> =
+            entries().collect();
+        assert_eq!(concat.merkle_root(), plain.merkle_root());
+
+        let hasher = CombinedHasher::new(StdMerkleHasher, OmitEmptyCombiner);
+        let mut omitting: TrieNode<u32, _, 4> = TrieNode::with_hasher(hasher);
+        omitting.insert_batch(entries());
+        let root = omitting.merkle_root();
+        assert_ne!(root, plain.merkle_root());
+        let proof = omitting.generate_proof(42).unwrap();
+        assert!(proof.verify(&hasher, &root, &14u32));
+        assert!(!proof.verify(&StdMerkleHasher, &root, &14u32));
+        let multiproof = omitting.generate_multiproof(&[3, 42]).unwrap();
+        assert!(crate::multiproof::verify_multiproof(
+            &hasher,
+            &root,
+            &[(3, 1u32), (42, 14)],
+            &multiproof
+        ));
+    }
+
+    #[test]
+    fn sorted_pairs_verify_without_directions() {
+        let hasher = CombinedHasher::new(StdMerkleHasher, SortedCombiner);
+        let mut trie: TrieNode<u32, _> = TrieNode::with_hasher(hasher);
+        trie.insert_batch((0..100).map(|key| (key * 3, key)));
+        let root = trie.merkle_root();
+        let mut plain: TrieNode<u32> = (0..100).map(|key| (key * 3, key)).collect();
+        assert_ne!(root, plain.merkle_root());
+
+        let mut proof = trie.generate_proof(42).unwrap();
+        assert!(proof.verify(&hasher, &root, &14u32));
+        assert!(proof.verify_commutative(&hasher, &root, &14u32));
+        assert!(!proof.verify_commutative(&hasher, &root, &15u32));
+        proof.key = 43;
+        assert!(proof.verify_commutative(&hasher, &root, &14u32));
+        proof.levels[0].siblings[0] = hasher.hash(b"forged");
+        assert!(!proof.verify_commutative(&hasher, &root, &14u32));
+
+        // Keys 2 and 3 sit under different children of the root, so swapping their values
+        // swaps those children, which the sorted root doesn't see.
+        let swapped = |a: u32, b: u32| {
+            let mut trie: TrieNode<u32, _> = TrieNode::with_hasher(hasher);
+            trie.insert_batch([(2, a), (3, b)]);
+            trie.merkle_root()
+        };
+        assert_eq!(swapped(5, 6), swapped(6, 5));
+    }
+
+    #[test]
+    fn transparent_roots_depend_only_on_entries() {
+        const ENTRIES: [(u32, u32); 3] = [(1, 1), (2, 2), (6, 6)];
+        fn fresh<C: NodeCombiner<StdMerkleHasher>>(
+            hasher: CombinedHasher<StdMerkleHasher, C>,
+        ) -> String {
+            let mut trie: TrieNode<u32, _> = TrieNode::with_hasher(hasher);
+            trie.insert_batch(ENTRIES);
+            trie.merkle_root()
+        }
+        // Keys 5 and 13 leave a value-less node under key 1 once removed.
+        fn pruned<C: NodeCombiner<StdMerkleHasher>>(
+            hasher: CombinedHasher<StdMerkleHasher, C>,
+        ) -> TrieNode<u32, CombinedHasher<StdMerkleHasher, C>> {
+            let mut trie = TrieNode::with_hasher(hasher);
+            trie.insert_batch(ENTRIES.into_iter().chain([(5, 5), (13, 13)]));
+            trie.remove_subtree(5, 3);
+            trie
+        }
+        let hasher = CombinedHasher::new(StdMerkleHasher, TransparentCombiner);
+        let plain = CombinedHasher::new(StdMerkleHasher, ConcatCombiner);
+        assert_ne!(pruned(plain).merkle_root(), fresh(plain));
+        let mut trie = pruned(hasher);
+        let root = trie.merkle_root();
+        assert_eq!(root, fresh(hasher));
+
+        let proof = trie.generate_proof(1).unwrap();
+        assert!(proof.verify(&hasher, &root, &1u32));
+        assert!(!proof.verify(&hasher, &root, &2u32));
+        assert!(trie
+            .generate_proof(6)
+            .unwrap()
+            .verify(&hasher, &root, &6u32));
+        assert_eq!(
+            TrieNode::<u32, _>::with_hasher(hasher).merkle_root(),
+            hasher.empty_hash()
+        );
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn sha256_vectors_for_both_modes() {
+        use crate::hasher::DigestHasher;
+
+        type Sha256 = DigestHasher<sha2::Sha256>;
+        fn root<C: NodeCombiner<Sha256>>(combiner: C) -> String {
+            let mut trie: TrieNode<String, _> =
+                TrieNode::with_hasher(CombinedHasher::new(Sha256::new(), combiner));
+            trie.insert(1, "foo".to_string());
+            trie.insert(6, "bar".to_string());
+            Sha256::hash_to_string(&trie.merkle_root())
+        }
+        assert_eq!(
+            root(ConcatCombiner),
+            "6ee39e2fb1775d9585b90883bec3892c1ad3b2ac9410f46ef0837881d59bb9a5"
+        );
+        assert_eq!(
+            root(TransparentCombiner),
+            "3bd5d0ac24d1d57fbc3f456f58de75627ecff9c440dd429ee0eb6ddfe4319f6d"
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use crate::hasher::MerkleHasher;
+
+// The canonical commitment, for implementations in other languages to reproduce. Every hash is
+// `H(encoding)` for an inner hash function `H`, over one of:
+//
+//   value: 0x00 | length u64 | value bytes
+//   empty: 0x01
+//   node:  0x02 | arity u16 | data hash | one hash per child digit, 0 to arity - 1
+//
+// Integers are big-endian, and every embedded hash is preceded by its length as a u64, so the
+// encoding stays unambiguous when hashes vary in length. Lengths are u64 so that no value or
+// hash is too long to encode. Values are hashed as their `MerkleData::merkle_bytes`.
+//
+// A node's data hash is the value hash of its data, or the empty hash if it has none. A node
+// without children hashes to its data hash; any other node hashes to the node encoding, with
+// the empty hash for missing children. The key `k` lives at the node reached by following the
+// base-`arity` digits of `k` from the root, least significant first; key 0 is the root itself.
+//
+// Keys can optionally be bound into the values (`key_bound::KeyBoundTrie`): the value under
+// `k` is then hashed as the value encoding of `k` as a u32 followed by the value's bytes, so a
+// proof binds its value to its key even for a verifier that never recomputes the path. Binding
+// keys changes every root, so both sides must agree on it.
+//
+// A key can also hold a list of values (`multi_value::MultiValue`), hashed as the value
+// encoding of the list's count as a u64 followed by, for each value in order, its length as a
+// u64 and its bytes.
+//
+// A value with a time to live (`expiry::Expiring`) is hashed as the value encoding of 0x00
+// followed by the value's bytes if it never expires, or of 0x01, its expiry time as a u64 and
+// then the value's bytes if it does.
+pub const VALUE_TAG: u8 = 0x00;
+pub const EMPTY_TAG: u8 = 0x01;
+pub const NODE_TAG: u8 = 0x02;
+
+pub fn encode_value(bytes: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(9 + bytes.len());
+    encoded.push(VALUE_TAG);
+    encoded.extend_from_slice(&(bytes.len() as u64).to_be_bytes());
+    encoded.extend_from_slice(bytes);
+    encoded
+}
+
+pub fn encode_empty() -> Vec<u8> {
+    vec![EMPTY_TAG]
+}
+
+pub fn encode_node<D: AsRef<[u8]>>(data_hash: &D, children: &[D]) -> Vec<u8> {
+    let mut encoded = vec![NODE_TAG];
+    encoded.extend_from_slice(&(children.len() as u16).to_be_bytes());
+    for hash in std::iter::once(data_hash).chain(children) {
+        let hash = hash.as_ref();
+        encoded.extend_from_slice(&(hash.len() as u64).to_be_bytes());
+        encoded.extend_from_slice(hash);
+    }
+    encoded
+}
+
+/// Commits with the canonical encoding above on top of `H`, so roots depend only on `H` and
+/// the trie's contents.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CanonicalHasher<H>(pub H);
+
+impl<H: MerkleHasher> MerkleHasher for CanonicalHasher<H> {
+    type Hash = H::Hash;
+
+    fn hash(&self, bytes: &[u8]) -> H::Hash {
+        self.0.hash(&encode_value(bytes))
+    }
+
+    fn hash_batch(&self, inputs: &[&[u8]]) -> Vec<H::Hash> {
+        let encoded: Vec<Vec<u8>> = inputs.iter().map(|input| encode_value(input)).collect();
+        let encoded: Vec<&[u8]> = encoded.iter().map(Vec::as_slice).collect();
+        self.0.hash_batch(&encoded)
+    }
+
+    fn hash_from_bytes(bytes: &[u8]) -> Option<H::Hash> {
+        H::hash_from_bytes(bytes)
+    }
+
+    fn combine(&self, data: &H::Hash, left: &H::Hash, right: &H::Hash) -> H::Hash {
+        self.combine_children(data, &[left.clone(), right.clone()])
+    }
+
+    fn combine_children(&self, data: &H::Hash, children: &[H::Hash]) -> H::Hash {
+        self.0.hash(&encode_node(data, children))
+    }
+
+    fn empty_hash(&self) -> H::Hash {
+        self.0.hash(&encode_empty())
+    }
+
+    fn max_hash_len(&self) -> usize {
+        self.0.max_hash_len()
+    }
+
+    fn heap_bytes(hash: &H::Hash) -> usize {
+        H::heap_bytes(hash)
+    }
+
+    fn hash_to_string(hash: &H::Hash) -> String {
+        H::hash_to_string(hash)
+    }
+
+    fn hash_from_string(string: &str) -> Option<H::Hash> {
+        H::hash_from_string(string)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn encodings_match_golden_bytes() {
+        let len = |len: u8| [0, 0, 0, 0, 0, 0, 0, len];
+        assert_eq!(encode_value(b"foo"), [&[0][..], &len(3), b"foo"].concat());
+        assert_eq!(encode_empty(), [1]);
+        let children = [vec![0xaa], vec![], vec![0xbb, 0xcc], vec![0xdd]];
+        let expected = [
+            &[2, 0, 4][..],
+            &len(2),
+            &[0x11, 0x22],
+            &len(1),
+            &[0xaa],
+            &len(0),
+            &len(2),
+            &[0xbb, 0xcc],
+            &len(1),
+            &[0xdd],
+        ];
+        assert_eq!(encode_node(&vec![0x11, 0x22], &children), expected.concat());
+    }
+
+    // 64-bit FNV-1a, big-endian: too weak for real use, but needs no feature, so the default
+    // tests check a whole root against the spec.
+    #[derive(Debug, Default, Clone, Copy)]
+    struct Fnv;
+
+    impl MerkleHasher for Fnv {
+        type Hash = [u8; 8];
+
+        fn hash(&self, bytes: &[u8]) -> [u8; 8] {
+            bytes
+                .iter()
+                .fold(0xcbf2_9ce4_8422_2325u64, |h, byte| {
+                    (h ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+                })
+                .to_be_bytes()
+        }
+
+        fn hash_from_bytes(bytes: &[u8]) -> Option<[u8; 8]> {
+            bytes.try_into().ok()
+        }
+    }
+
+    #[test]
+    fn fnv_golden_root() {
+        use crate::trie_node::trie_node::TrieNode;
+
+        let mut node: TrieNode<&str, CanonicalHasher<Fnv>> = TrieNode::new();
+        node.insert(1, "foo");
+        node.insert(2, "bar");
+        let root = node.merkle_root();
+        assert_eq!(
+            CanonicalHasher::<Fnv>::hash_to_string(&root),
+            "10a75100469f740e"
+        );
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn sha256_golden_roots() {
+        use crate::expiry::ExpiringTrie;
+        use crate::hasher::DigestHasher;
+        use crate::key_bound::KeyBoundTrie;
+        use crate::multi_value::MultiValue;
+        use crate::trie_node::trie_node::TrieNode;
+
+        type Sha256 = CanonicalHasher<DigestHasher<sha2::Sha256>>;
+        let mut node: TrieNode<String, Sha256> = TrieNode::new();
+        assert_eq!(
+            Sha256::hash_to_string(&node.merkle_root()),
+            "4bf5122f344554c53bde2ebb8cd2b7e3d1600ad631c385a5d7cce23c7785459a"
+        );
+        node.insert(1, "foo".to_string());
+        node.insert(2, "bar".to_string());
+        assert_eq!(
+            Sha256::hash_to_string(&node.merkle_root()),
+            "9657e828d80552951695f0f03097d3e09fa81b7b1f9d7330794110cc21191f0b"
+        );
+
+        let mut bound: KeyBoundTrie<String, Sha256> = KeyBoundTrie::default();
+        bound.insert(1, "foo".to_string());
+        bound.insert(2, "bar".to_string());
+        assert_eq!(
+            Sha256::hash_to_string(&bound.merkle_root()),
+            "18238fe60bfc72f6181af7bcbfc49233a71e96048ecd9bbfdde7acba49734052"
+        );
+
+        let mut multi: TrieNode<MultiValue<&str>, Sha256> = TrieNode::new();
+        multi.insert_multi(1, "foo");
+        multi.insert_multi(1, "baz");
+        multi.insert_multi(2, "bar");
+        assert_eq!(
+            Sha256::hash_to_string(&multi.merkle_root()),
+            "be72c73a700219fdabec9843765d629479709ae9bdb77bb821f84ce0d00f1943"
+        );
+
+        let mut expiring: ExpiringTrie<&str, Sha256, _> =
+            ExpiringTrie::with_clock(CanonicalHasher::default(), || 1000);
+        expiring.insert(1, "foo");
+        expiring.insert_with_ttl(2, "bar", 500);
+        assert_eq!(
+            Sha256::hash_to_string(&expiring.merkle_root()),
+            "9e4ba379d613c872e938518fa03f21f21f45b91f33a1f8734a62cc54f524f64c"
+        );
+    }
+}
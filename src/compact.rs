@@ -0,0 +1,73 @@
+use crate::trie_node::trie_node::{NodeIndex, TrieNode, ROOT};
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    /// Detaches every branch left without values below it, such as the chain of value-less
+    /// nodes `remove_subtree` leaves above what it removed, so the trie is shaped as if its
+    /// entries had been inserted afresh. Returns the number of nodes detached; their slots are
+    /// reused by later inserts.
+    ///
+    /// The root changes exactly when a value-less internal node had no values below it, since
+    /// such a node hashes its empty children rather than counting as absent; it then becomes
+    /// the root of the same entries inserted afresh. Dangling value-less leaves alone hash as
+    /// absent, so pruning them leaves the root as it was.
+    pub fn compact(&mut self) -> usize {
+        let (detached, _) = self.compact_at(ROOT);
+        self.rehash_if_eager();
+        detached
+    }
+
+    // Returns the nodes detached below `index` and whether the subtree holds any value.
+    fn compact_at(&mut self, index: NodeIndex) -> (usize, bool) {
+        let mut detached = 0;
+        let mut has_values = self.node(index).get_data().is_some();
+        for digit in 0..N {
+            let Some(child) = self.node(index).child(digit) else {
+                continue;
+            };
+            let (below, child_has_values) = self.compact_at(child);
+            detached += below;
+            if child_has_values {
+                has_values = true;
+            } else {
+                self.node_mut(index).take_child(digit);
+                self.free_subtrees.push(child);
+                detached += 1;
+            }
+        }
+        if detached > 0 {
+            self.node_mut(index).invalidate_merkle_root();
+        }
+        (detached, has_values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn compact_prunes_value_less_branches() {
+        let entries = || (0..64).map(|key| (key * 4 + 1, key)).chain([(2, 0)]);
+        let mut expected: TrieNode<u32> = entries().collect();
+        let mut node = expected.clone();
+        node.insert(4, 0);
+        node.merkle_root();
+
+        // A dangling value-less leaf hashes as absent.
+        node.remove_subtree(4, 3);
+        assert_eq!(node.merkle_root(), expected.merkle_root());
+        assert_eq!(node.compact(), 1);
+        assert_eq!(node.merkle_root(), expected.merkle_root());
+
+        // A value-less internal node with nothing below it does not.
+        node.insert(8, 0);
+        node.remove_subtree(8, 4);
+        assert_ne!(node.merkle_root(), expected.merkle_root());
+        assert_eq!(node.compact(), 2);
+        assert_eq!(node.merkle_root(), expected.merkle_root());
+        assert_eq!(node.metrics().node_count, expected.metrics().node_count);
+        assert_eq!(node.compact(), 0);
+    }
+}
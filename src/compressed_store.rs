@@ -0,0 +1,153 @@
+use std::{io, sync::Mutex};
+
+use zstd::bulk::{Compressor, Decompressor};
+
+use crate::async_store::{NodeId, NodeStore};
+
+// Record layout: a tag byte, then either the record itself (RAW) or its length as a u32
+// followed by a zstd frame (ZSTD). Records that don't shrink are kept raw.
+const RAW: u8 = 0;
+const ZSTD: u8 = 1;
+
+#[derive(Debug)]
+pub enum CompressionError<E> {
+    Backend(E),
+    Zstd(io::Error),
+    /// The record under this id isn't a valid compressed record, or needs another dictionary.
+    Corrupt(NodeId),
+}
+
+impl<E> From<E> for CompressionError<E> {
+    fn from(error: E) -> Self {
+        CompressionError::Backend(error)
+    }
+}
+
+/// Compresses each record with zstd before it reaches `S`. Node records are small, so most of
+/// the gain comes from a dictionary trained on existing records with `train_dictionary`; a
+/// store must be read with the dictionary it was written with.
+pub struct CompressedStore<S> {
+    inner: S,
+    compressor: Mutex<Compressor<'static>>,
+    decompressor: Mutex<Decompressor<'static>>,
+}
+
+impl<S: NodeStore> CompressedStore<S> {
+    pub fn new(inner: S, level: i32) -> io::Result<Self> {
+        Self::with_dictionary(inner, level, &[])
+    }
+
+    pub fn with_dictionary(inner: S, level: i32, dictionary: &[u8]) -> io::Result<Self> {
+        Ok(CompressedStore {
+            inner,
+            compressor: Mutex::new(Compressor::with_dictionary(level, dictionary)?),
+            decompressor: Mutex::new(Decompressor::with_dictionary(dictionary)?),
+        })
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+/// Trains a zstd dictionary of at most `max_bytes` on sample records, e.g. a few thousand
+/// records read back from an uncompressed store.
+pub fn train_dictionary<R: AsRef<[u8]>>(samples: &[R], max_bytes: usize) -> io::Result<Vec<u8>> {
+    zstd::dict::from_samples(samples, max_bytes)
+}
+
+impl<S: NodeStore> NodeStore for CompressedStore<S> {
+    type Error = CompressionError<S::Error>;
+
+    fn get(&self, id: NodeId) -> Result<Option<Vec<u8>>, Self::Error> {
+        let Some(record) = self.inner.get(id)? else {
+            return Ok(None);
+        };
+        let corrupt = || CompressionError::Corrupt(id);
+        match record.split_first() {
+            Some((&RAW, bytes)) => Ok(Some(bytes.to_vec())),
+            Some((&ZSTD, rest)) => {
+                let (len, frame) = rest.split_first_chunk::<4>().ok_or_else(corrupt)?;
+                let len = u32::from_be_bytes(*len) as usize;
+                let bytes = self
+                    .decompressor
+                    .lock()
+                    .unwrap()
+                    .decompress(frame, len)
+                    .map_err(|_| corrupt())?;
+                if bytes.len() != len {
+                    return Err(corrupt());
+                }
+                Ok(Some(bytes))
+            }
+            _ => Err(corrupt()),
+        }
+    }
+
+    fn put(&self, id: NodeId, bytes: Vec<u8>) -> Result<(), Self::Error> {
+        let frame = self
+            .compressor
+            .lock()
+            .unwrap()
+            .compress(&bytes)
+            .map_err(CompressionError::Zstd)?;
+        let record = match u32::try_from(bytes.len()) {
+            Ok(len) if frame.len() + 4 < bytes.len() => {
+                let mut record = vec![ZSTD];
+                record.extend_from_slice(&len.to_be_bytes());
+                record.extend_from_slice(&frame);
+                record
+            }
+            _ => {
+                let mut record = vec![RAW];
+                record.extend_from_slice(&bytes);
+                record
+            }
+        };
+        Ok(self.inner.put(id, record)?)
+    }
+
+    fn delete(&self, id: NodeId) -> Result<(), Self::Error> {
+        Ok(self.inner.delete(id)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::async_store::MemoryNodeStore;
+
+    #[test]
+    fn records_round_trip_and_shrink() {
+        let records: Vec<Vec<u8>> = (0..2000)
+            .map(|i| format!("{{\"account\": {i}, \"memo\": \"monthly transfer of funds\"}}"))
+            .map(String::into_bytes)
+            .collect();
+        let dictionary = train_dictionary(&records, 4096).unwrap();
+        let plain = CompressedStore::new(MemoryNodeStore::new(), 3).unwrap();
+        let trained =
+            CompressedStore::with_dictionary(MemoryNodeStore::new(), 3, &dictionary).unwrap();
+        let mut stored = [0, 0];
+        for (id, record) in records.iter().enumerate() {
+            for (store, stored) in [&plain, &trained].into_iter().zip(&mut stored) {
+                store.put(id as NodeId, record.clone()).unwrap();
+                assert_eq!(store.get(id as NodeId).unwrap().as_ref(), Some(record));
+                *stored += store.inner().get(id as NodeId).unwrap().unwrap().len();
+            }
+        }
+        let raw: usize = records.iter().map(Vec::len).sum();
+        assert!(stored[0] <= raw + records.len());
+        assert!(stored[1] * 3 < raw * 2);
+
+        let untrained = CompressedStore::new(trained.into_inner(), 3).unwrap();
+        assert!(matches!(
+            untrained.get(0),
+            Err(CompressionError::Corrupt(0))
+        ));
+    }
+}
@@ -0,0 +1,127 @@
+use crate::anchor::{chain_link, AnchorRecord};
+use crate::hasher::{hashes_equal, MerkleHasher};
+use crate::merkle_data::MerkleData;
+use crate::proof::MerkleProof;
+
+/// A proof against one of a `CrossRootProofBundle`'s roots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootClaim<D> {
+    /// The index of the root's record in the bundle.
+    pub record: usize,
+    pub proof: MerkleProof<D>,
+}
+
+/// Proofs against several roots of a trie over time, with the stretch of its `FileAnchor`
+/// chain that links those roots, so claims made at different times can be checked against a
+/// single trusted link in one call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrossRootProofBundle<D> {
+    /// The link of the record before the first in `records`, or the empty hash if they start
+    /// the chain.
+    pub previous_link: D,
+    /// Consecutive records of the chain, oldest first.
+    pub records: Vec<AnchorRecord<D>>,
+    pub claims: Vec<RootClaim<D>>,
+}
+
+impl<D: Clone + PartialEq + AsRef<[u8]>> CrossRootProofBundle<D> {
+    pub fn new(previous_link: D, records: Vec<AnchorRecord<D>>) -> Self {
+        CrossRootProofBundle {
+            previous_link,
+            records,
+            claims: vec![],
+        }
+    }
+
+    /// Adds a proof against `root`, claimed against its latest record. `false`, adding
+    /// nothing, if no record holds `root`.
+    pub fn add_proof(&mut self, root: &D, proof: MerkleProof<D>) -> bool {
+        let Some(record) = self.records.iter().rposition(|record| record.root == *root) else {
+            return false;
+        };
+        self.claims.push(RootClaim { record, proof });
+        true
+    }
+
+    /// Whether the records follow on from `previous_link` up to `latest_link`, which the
+    /// verifier must trust, e.g. because it was anchored, and each claim's proof shows
+    /// `values[i]`, in the order of the claims, under its record's root.
+    pub fn verify<H, V>(&self, hasher: &H, latest_link: &D, values: &[V]) -> bool
+    where
+        H: MerkleHasher<Hash = D>,
+        V: MerkleData,
+    {
+        if values.len() != self.claims.len() {
+            return false;
+        }
+        let mut link = self.previous_link.clone();
+        for record in &self.records {
+            link = chain_link(hasher, &link, record.timestamp, &record.root);
+            if !hashes_equal(link.as_ref(), record.link.as_ref()) {
+                return false;
+            }
+        }
+        if !hashes_equal(link.as_ref(), latest_link.as_ref()) {
+            return false;
+        }
+        self.claims.iter().zip(values).all(|(claim, value)| {
+            self.records
+                .get(claim.record)
+                .is_some_and(|record| claim.proof.verify(hasher, &record.root, value))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+
+    use super::*;
+    use crate::anchor::FileAnchor;
+    use crate::hasher::StdMerkleHasher;
+    use crate::root_history::RootHistory;
+    use crate::trie_node::trie_node::TrieNode;
+
+    #[test]
+    fn claims_at_several_roots_verify_together() {
+        let path = std::env::temp_dir().join(format!("cross-root-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut anchor = FileAnchor::open(&path, StdMerkleHasher).unwrap();
+        let mut history = RootHistory::with_snapshots(TrieNode::<u32>::new(), 4);
+        let mut roots = vec![];
+        for balance in [10, 20, 30] {
+            history.trie_mut().insert(1, balance);
+            history.trie_mut().insert(2, balance + 1);
+            roots.push(history.commit());
+            anchor
+                .anchor_at(roots.last().unwrap(), balance as u64)
+                .unwrap();
+        }
+        let records = FileAnchor::read(&path, &StdMerkleHasher).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        // Two records, following on from the first.
+        let mut bundle = CrossRootProofBundle::new(records[0].link.clone(), records[1..].to_vec());
+        for (root, key) in [(&roots[1], 1), (&roots[2], 2)] {
+            let proof = history.generate_proof_at(root, key).unwrap();
+            assert!(bundle.add_proof(root, proof));
+        }
+        assert!(!bundle.add_proof(&roots[0], bundle.claims[0].proof.clone()));
+        let latest = anchor.last_link();
+        assert!(bundle.verify(&StdMerkleHasher, latest, &[20u32, 31]));
+        assert!(!bundle.verify(&StdMerkleHasher, latest, &[20u32, 21]));
+        assert!(!bundle.verify(&StdMerkleHasher, latest, &[20u32]));
+        assert!(!bundle.verify(&StdMerkleHasher, &records[1].link, &[20u32, 31]));
+
+        // The roots can't be swapped for others, nor the claims moved between them.
+        let mut forged = bundle.clone();
+        forged.records[0].root = roots[2].clone();
+        assert!(!forged.verify(&StdMerkleHasher, latest, &[30u32, 31]));
+        let mut moved = bundle.clone();
+        moved.claims[0].record = 1;
+        assert!(!moved.verify(&StdMerkleHasher, latest, &[20u32, 31]));
+        moved.claims[0].record = 5;
+        assert!(!moved.verify(&StdMerkleHasher, latest, &[20u32, 31]));
+    }
+}
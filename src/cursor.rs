@@ -0,0 +1,176 @@
+use crate::error::TrieError;
+use crate::iter::{push_children, Stack};
+use crate::trie_node::trie_node::{TrieNode, ROOT};
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// A place in `TrieNode::iter`'s order that outlives the iterator: the last key handed out,
+/// and the root of the trie it was handed out from. Save it with `to_bytes` to pick a long
+/// iteration up again after a restart with `TrieNode::resume`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cursor<D> {
+    pub root: D,
+    /// `None` before the first entry.
+    pub last_key: Option<u32>,
+}
+
+// root len u16 | root | 0, or 1 | last key u32, integers big-endian.
+impl<D: AsRef<[u8]>> Cursor<D> {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let root = self.root.as_ref();
+        let mut bytes = (root.len() as u16).to_be_bytes().to_vec();
+        bytes.extend_from_slice(root);
+        match self.last_key {
+            Some(key) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&key.to_be_bytes());
+            }
+            None => bytes.push(0),
+        }
+        bytes
+    }
+
+    /// Decodes a cursor written by `to_bytes`; `None` if the bytes are malformed.
+    pub fn from_bytes<H: MerkleHasher<Hash = D>>(bytes: &[u8]) -> Option<Self> {
+        let len = u16::from_be_bytes(bytes.get(..2)?.try_into().ok()?) as usize;
+        let root = H::hash_from_bytes(bytes.get(2..2 + len)?)?;
+        let last_key = match bytes.get(2 + len..)? {
+            [0] => None,
+            [1, key @ ..] => Some(u32::from_be_bytes(key.try_into().ok()?)),
+            _ => return None,
+        };
+        Some(Cursor { root, last_key })
+    }
+}
+
+/// Iterator over `(key, value)` pairs in the order of `Iter`, which can hand out a `Cursor`
+/// for where it has got to.
+pub struct CursorIter<'a, T: MerkleData, H: MerkleHasher, const N: usize> {
+    trie: &'a TrieNode<T, H, N>,
+    stack: Stack,
+    root: H::Hash,
+    last_key: Option<u32>,
+}
+
+impl<'a, T: MerkleData, H: MerkleHasher, const N: usize> CursorIter<'a, T, H, N> {
+    /// The place just after the last entry handed out.
+    pub fn cursor(&self) -> Cursor<H::Hash> {
+        Cursor {
+            root: self.root.clone(),
+            last_key: self.last_key,
+        }
+    }
+}
+
+impl<'a, T: MerkleData, H: MerkleHasher, const N: usize> Iterator for CursorIter<'a, T, H, N> {
+    type Item = (u32, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (index, key, depth) = self.stack.pop()?;
+            let node = self.trie.node(index);
+            push_children(&mut self.stack, node, key, depth);
+            if let Some(data) = node.get_data() {
+                self.last_key = Some(key);
+                return Some((key, data));
+            }
+        }
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    /// `iter`, from the first entry, with cursors.
+    pub fn cursor_iter(&mut self) -> CursorIter<'_, T, H, N> {
+        CursorIter {
+            root: self.merkle_root(),
+            trie: self,
+            stack: vec![(ROOT, 0, 0)],
+            last_key: None,
+        }
+    }
+
+    /// Continues an iteration from `cursor`, with the entries after its last key. Fails with
+    /// `StaleCursor` if the trie's root isn't the cursor's, since entries may then have been
+    /// added or removed before the cursor and the iteration would miss or repeat them.
+    pub fn resume(
+        &mut self,
+        cursor: &Cursor<H::Hash>,
+    ) -> Result<CursorIter<'_, T, H, N>, TrieError> {
+        let root = self.merkle_root();
+        if root != cursor.root {
+            return Err(TrieError::StaleCursor);
+        }
+        let Some(last_key) = cursor.last_key else {
+            return Ok(self.cursor_iter());
+        };
+        // The stack `iter` has just after handing out `last_key`: the later siblings of each
+        // node on its path, and then its children.
+        let bits_per_digit = Self::BITS_PER_DIGIT;
+        let mut stack = vec![];
+        let mut index = Some(ROOT);
+        let mut key = 0;
+        for depth in 0..Self::key_depth(last_key) {
+            let node = self.node(index.unwrap());
+            let digit = Self::digit_at(last_key, depth);
+            for later in (digit + 1..N).rev() {
+                if let Some(child) = node.child(later) {
+                    stack.push((
+                        child,
+                        key | (later as u32) << (depth * bits_per_digit),
+                        depth + 1,
+                    ));
+                }
+            }
+            key |= (digit as u32) << (depth * bits_per_digit);
+            index = node.child(digit);
+            if index.is_none() {
+                break;
+            }
+        }
+        if let Some(index) = index {
+            push_children(&mut stack, self.node(index), key, Self::key_depth(last_key));
+        }
+        Ok(CursorIter {
+            trie: self,
+            stack,
+            root,
+            last_key: Some(last_key),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn cursors_resume_where_they_left_off() {
+        let mut trie: TrieNode<u32, StdMerkleHasher, 4> =
+            (0..300).map(|key| (key * 7, key)).collect();
+        let all: Vec<(u32, u32)> = trie.iter().map(|(key, value)| (key, *value)).collect();
+
+        let mut iter = trie.cursor_iter();
+        let mut seen: Vec<(u32, u32)> = iter.by_ref().take(100).map(|(k, v)| (k, *v)).collect();
+        let saved = iter.cursor().to_bytes();
+        let cursor = Cursor::from_bytes::<StdMerkleHasher>(&saved).unwrap();
+        assert_eq!(cursor.last_key, Some(seen[99].0));
+        seen.extend(trie.resume(&cursor).unwrap().map(|(k, v)| (k, *v)));
+        assert_eq!(seen, all);
+
+        let start = trie.cursor_iter().cursor();
+        assert_eq!(
+            Cursor::from_bytes::<StdMerkleHasher>(&start.to_bytes()),
+            Some(start.clone())
+        );
+        assert_eq!(trie.resume(&start).unwrap().count(), 300);
+        assert_eq!(
+            Cursor::from_bytes::<StdMerkleHasher>(&saved[..saved.len() - 1]),
+            None
+        );
+
+        trie.insert(1, 1);
+        assert_eq!(trie.resume(&cursor).err(), Some(TrieError::StaleCursor));
+    }
+}
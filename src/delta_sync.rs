@@ -0,0 +1,266 @@
+use std::{fmt::Debug, future::Future};
+
+use crate::state_sync::NodePosition;
+use crate::trie_node::trie_node::{NodeIndex, TrieNode, ROOT};
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// What a peer reveals about one of its nodes: whether it holds a value, the value's hash and
+/// the root of each child slot (`None` if empty).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeSummary<D> {
+    pub has_data: bool,
+    pub data_hash: D,
+    pub children: Vec<Option<D>>,
+}
+
+/// The requests a `DeltaSync` sends to the remote trie, answered there by `delta_summaries`
+/// and `delta_values`. Each request covers one level of the walk.
+pub trait DeltaTransport<T, D> {
+    type Error: Debug;
+
+    fn summaries(
+        &mut self,
+        positions: Vec<NodePosition>,
+    ) -> impl Future<Output = Result<Vec<Option<NodeSummary<D>>>, Self::Error>>;
+
+    fn values(
+        &mut self,
+        positions: Vec<NodePosition>,
+    ) -> impl Future<Output = Result<Vec<Option<T>>, Self::Error>>;
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeltaStats {
+    pub levels: u32,
+    pub nodes_compared: usize,
+    pub values_transferred: usize,
+    pub subtrees_removed: usize,
+    /// Whether the local root matched the remote one once the walk finished, which only fails
+    /// if the remote trie changed during the sync.
+    pub in_sync: bool,
+}
+
+/// Brings a local trie up to date with a remote one by walking both level by level, only
+/// descending into subtrees whose roots differ and only fetching values whose hashes differ.
+pub struct DeltaSync<X> {
+    transport: X,
+}
+
+impl<X> DeltaSync<X> {
+    pub fn new(transport: X) -> Self {
+        DeltaSync { transport }
+    }
+
+    pub fn into_transport(self) -> X {
+        self.transport
+    }
+
+    pub async fn pull<T, H, const N: usize>(
+        &mut self,
+        local: &mut TrieNode<T, H, N>,
+    ) -> Result<DeltaStats, X::Error>
+    where
+        T: MerkleData,
+        H: MerkleHasher,
+        X: DeltaTransport<T, H::Hash>,
+    {
+        let mut stats = DeltaStats::default();
+        let root = NodePosition { path: 0, depth: 0 };
+        let mut remote_root = None;
+        let mut frontier = vec![root];
+        while !frontier.is_empty() {
+            stats.levels += 1;
+            stats.nodes_compared += frontier.len();
+            let summaries = self.transport.summaries(frontier.clone()).await?;
+            let mut next = vec![];
+            let mut wanted = vec![];
+            for (position, summary) in frontier.into_iter().zip(summaries) {
+                let Some(summary) = summary else {
+                    if position.depth > 0 {
+                        local.remove_subtree(position.path, position.depth);
+                        stats.subtrees_removed += 1;
+                    }
+                    continue;
+                };
+                if position == root {
+                    remote_root = Some(summary.root(local.hasher()));
+                    if local.merkle_root() == *remote_root.as_ref().unwrap() {
+                        break;
+                    }
+                }
+                let index = local.create_path(position.path, position.depth);
+                if summary.has_data {
+                    if local.node(index).get_data().is_none()
+                        || local.data_hash_at(index) != summary.data_hash
+                    {
+                        wanted.push(position);
+                    }
+                } else {
+                    // `create_path` has already invalidated the ancestors.
+                    local.node_mut(index).take_data();
+                }
+                for (digit, remote_child) in summary.children.iter().enumerate().take(N) {
+                    let child = TrieNode::<T, H, N>::child_position(position, digit);
+                    match (remote_child, local.node(index).child(digit)) {
+                        (None, Some(_)) => {
+                            local.remove_subtree(child.path, child.depth);
+                            stats.subtrees_removed += 1;
+                        }
+                        (Some(remote), Some(local_child))
+                            if local.merkle_root_at(local_child) == *remote => {}
+                        (Some(_), _) => next.push(child),
+                        (None, None) => {}
+                    }
+                }
+            }
+            if !wanted.is_empty() {
+                let values = self.transport.values(wanted.clone()).await?;
+                for (position, value) in wanted.into_iter().zip(values) {
+                    if let Some(value) = value {
+                        let index = local.create_path(position.path, position.depth);
+                        local.node_mut(index).replace_data(value);
+                        local.note_key(position.path);
+                        stats.values_transferred += 1;
+                    }
+                }
+            }
+            frontier = next;
+        }
+        local.rehash_if_eager();
+        stats.in_sync = remote_root.is_some_and(|root| local.merkle_root() == root);
+        Ok(stats)
+    }
+}
+
+impl<D: Clone> NodeSummary<D> {
+    fn root<H: MerkleHasher<Hash = D>>(&self, hasher: &H) -> D {
+        if self.children.iter().all(Option::is_none) {
+            return self.data_hash.clone();
+        }
+        let roots: Vec<D> = self
+            .children
+            .iter()
+            .map(|child| child.clone().unwrap_or_else(|| hasher.empty_hash()))
+            .collect();
+        hasher.combine_children(&self.data_hash, &roots)
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    pub(crate) fn find_position(&self, position: NodePosition) -> Option<NodeIndex> {
+        let mut index = ROOT;
+        for depth in 0..position.depth {
+            index = self
+                .node(index)
+                .child(Self::digit_at(position.path, depth))?;
+        }
+        Some(index)
+    }
+
+    /// Answers `DeltaTransport::summaries` for a peer syncing from this trie.
+    pub fn delta_summaries(
+        &mut self,
+        positions: &[NodePosition],
+    ) -> Vec<Option<NodeSummary<H::Hash>>> {
+        positions
+            .iter()
+            .map(|position| {
+                let index = self.find_position(*position)?;
+                let children = (0..N)
+                    .map(|digit| {
+                        let child = self.node(index).child(digit)?;
+                        Some(self.merkle_root_at(child))
+                    })
+                    .collect();
+                Some(NodeSummary {
+                    has_data: self.node(index).get_data().is_some(),
+                    data_hash: self.data_hash_at(index),
+                    children,
+                })
+            })
+            .collect()
+    }
+
+    /// Answers `DeltaTransport::values` for a peer syncing from this trie.
+    pub fn delta_values(&self, positions: &[NodePosition]) -> Vec<Option<T>>
+    where
+        T: Clone,
+    {
+        positions
+            .iter()
+            .map(|position| {
+                let index = self.find_position(*position)?;
+                self.node(index).get_data().cloned()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    struct InProcess<'a> {
+        remote: &'a mut TrieNode<u32>,
+        values_sent: usize,
+    }
+
+    impl DeltaTransport<u32, String> for InProcess<'_> {
+        type Error = std::convert::Infallible;
+
+        async fn summaries(
+            &mut self,
+            positions: Vec<NodePosition>,
+        ) -> Result<Vec<Option<NodeSummary<String>>>, Self::Error> {
+            Ok(self.remote.delta_summaries(&positions))
+        }
+
+        async fn values(
+            &mut self,
+            positions: Vec<NodePosition>,
+        ) -> Result<Vec<Option<u32>>, Self::Error> {
+            self.values_sent += positions.len();
+            Ok(self.remote.delta_values(&positions))
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let mut context = std::task::Context::from_waker(std::task::Waker::noop());
+        loop {
+            if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+        }
+    }
+
+    #[test]
+    fn pull_transfers_only_changed_values() {
+        let mut local: TrieNode<u32> = (0..300).map(|key| (key, key)).collect();
+        let mut remote = local.clone();
+        remote.insert(17, 1700);
+        remote.insert(250, 2500);
+        remote.insert(1000, 10000);
+        remote.remove_subtree(0b1111, 4);
+        local.insert(5, 50);
+
+        let mut sync = DeltaSync::new(InProcess {
+            remote: &mut remote,
+            values_sent: 0,
+        });
+        let stats = block_on(sync.pull(&mut local)).unwrap();
+        assert!(stats.in_sync);
+        assert_eq!(stats.values_transferred, 4);
+        assert_eq!(sync.into_transport().values_sent, 4);
+        assert_eq!(local, remote);
+        assert_eq!(local.merkle_root(), remote.merkle_root());
+
+        let mut sync = DeltaSync::new(InProcess {
+            remote: &mut remote,
+            values_sent: 0,
+        });
+        let stats = block_on(sync.pull(&mut local)).unwrap();
+        assert_eq!((stats.levels, stats.values_transferred), (1, 0));
+    }
+}
@@ -0,0 +1,133 @@
+use crate::state_sync::NodePosition;
+use crate::trie_node::trie_node::{NodeIndex, TrieNode, ROOT};
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// How one key differs between two tries, from the first to the second.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyDiff<V> {
+    Added(u32, V),
+    Removed(u32, V),
+    Changed(u32, V, V),
+}
+
+impl<V> KeyDiff<V> {
+    pub fn key(&self) -> u32 {
+        match self {
+            KeyDiff::Added(key, _) | KeyDiff::Removed(key, _) | KeyDiff::Changed(key, _, _) => *key,
+        }
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    /// The keys added, removed or changed going from `self` to `other`, in ascending key order.
+    /// Subtrees with equal roots are skipped without being walked, and values are compared by
+    /// hash, so both tries must hash alike. Roots that aren't cached are computed on the fly
+    /// and not kept, so this is cheapest with both roots up to date.
+    pub fn diff<'a>(&'a self, other: &'a Self) -> Vec<KeyDiff<&'a T>> {
+        let mut diffs = vec![];
+        let root = NodePosition { path: 0, depth: 0 };
+        self.diff_at(Some(ROOT), other, Some(ROOT), root, &mut diffs);
+        diffs.sort_by_key(KeyDiff::key);
+        diffs
+    }
+
+    fn diff_at<'a>(
+        &'a self,
+        index: Option<NodeIndex>,
+        other: &'a Self,
+        other_index: Option<NodeIndex>,
+        position: NodePosition,
+        diffs: &mut Vec<KeyDiff<&'a T>>,
+    ) {
+        let (index, other_index) = match (index, other_index) {
+            (Some(index), Some(other_index)) => (index, other_index),
+            (Some(index), None) => {
+                return self.collect_at(index, position, &mut |key, data| {
+                    diffs.push(KeyDiff::Removed(key, data))
+                })
+            }
+            (None, Some(other_index)) => {
+                return other.collect_at(other_index, position, &mut |key, data| {
+                    diffs.push(KeyDiff::Added(key, data))
+                })
+            }
+            (None, None) => return,
+        };
+        if self.uncached_root_at(index) == other.uncached_root_at(other_index) {
+            return;
+        }
+        let key = position.path;
+        match (
+            self.node(index).get_data(),
+            other.node(other_index).get_data(),
+        ) {
+            (Some(data), Some(other_data)) => {
+                if self.uncached_data_hash_at(index) != other.uncached_data_hash_at(other_index) {
+                    diffs.push(KeyDiff::Changed(key, data, other_data));
+                }
+            }
+            (Some(data), None) => diffs.push(KeyDiff::Removed(key, data)),
+            (None, Some(other_data)) => diffs.push(KeyDiff::Added(key, other_data)),
+            (None, None) => {}
+        }
+        for digit in 0..N {
+            self.diff_at(
+                self.node(index).child(digit),
+                other,
+                other.node(other_index).child(digit),
+                Self::child_position(position, digit),
+                diffs,
+            );
+        }
+    }
+
+    fn collect_at<'a>(
+        &'a self,
+        index: NodeIndex,
+        position: NodePosition,
+        found: &mut impl FnMut(u32, &'a T),
+    ) {
+        let node = self.node(index);
+        if let Some(data) = node.get_data() {
+            found(position.path, data);
+        }
+        for digit in 0..N {
+            if let Some(child) = node.child(digit) {
+                self.collect_at(child, Self::child_position(position, digit), found);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn diff_reports_added_removed_and_changed_keys() {
+        let mut before: TrieNode<u32> = (0..100).map(|key| (key * 3, key)).collect();
+        let mut after = before.clone();
+        after.insert(33, 1000);
+        after.insert(301, 7);
+        after.insert(1 << 20, 8);
+        after.remove_subtree(6, 3);
+        before.merkle_root();
+        after.merkle_root();
+
+        // Every key whose lowest three digits are 110.
+        let removed: Vec<u32> = (0..100).filter(|key| key * 3 % 8 == 6).collect();
+        let mut expected: Vec<KeyDiff<&u32>> = removed
+            .iter()
+            .map(|key| KeyDiff::Removed(key * 3, key))
+            .collect();
+        expected.extend([
+            KeyDiff::Changed(33, &11, &1000),
+            KeyDiff::Added(301, &7),
+            KeyDiff::Added(1 << 20, &8),
+        ]);
+        expected.sort_by_key(KeyDiff::key);
+        assert_eq!(before.diff(&after), expected);
+        assert_eq!(before.diff(&before.clone()), vec![]);
+    }
+}
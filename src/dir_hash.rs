@@ -0,0 +1,81 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::embedded::StreamingHasher;
+use crate::hasher::MerkleHasher;
+use crate::str_trie::StrTrie;
+use crate::streamed::ContentDigest;
+
+/// A trie of every regular file under `dir`, keyed by its path relative to `dir` with `/`
+/// between components and holding its `ContentDigest`. Its root depends only on the files'
+/// paths and contents, so it can stand in for a checksum of the whole tree. Symlinks and empty
+/// directories are left out, and file names must be UTF-8.
+pub fn hash_directory<H>(hasher: &H, dir: &Path) -> io::Result<StrTrie<ContentDigest<H::Hash>, H>>
+where
+    H: MerkleHasher + StreamingHasher<Digest = <H as MerkleHasher>::Hash> + Clone,
+{
+    let mut trie = StrTrie::with_hasher(hasher.clone());
+    add_directory(hasher, dir, "", &mut trie)?;
+    Ok(trie)
+}
+
+fn add_directory<H>(
+    hasher: &H,
+    dir: &Path,
+    prefix: &str,
+    trie: &mut StrTrie<ContentDigest<H::Hash>, H>,
+) -> io::Result<()>
+where
+    H: MerkleHasher + StreamingHasher<Digest = <H as MerkleHasher>::Hash> + Clone,
+{
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().into_string().map_err(|name| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("file name {name:?} is not UTF-8"),
+            )
+        })?;
+        let key = format!("{prefix}{name}");
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            add_directory(hasher, &entry.path(), &format!("{key}/"), trie)?;
+        } else if file_type.is_file() {
+            let digest = ContentDigest::from_reader(hasher, fs::File::open(entry.path())?)?;
+            trie.insert(&key, digest);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "blake3"))]
+mod tests {
+
+    use super::*;
+    use crate::hasher::Blake3Hasher;
+
+    #[test]
+    fn directory_roots_follow_paths_and_contents() {
+        let dir = std::env::temp_dir().join(format!("dir-hash-{}", std::process::id()));
+        fs::create_dir_all(dir.join("docs/nested")).unwrap();
+        fs::write(dir.join("README"), "readme").unwrap();
+        fs::write(dir.join("docs/a.txt"), "a").unwrap();
+        fs::write(dir.join("docs/nested/b.txt"), "b").unwrap();
+        let hasher = Blake3Hasher::default();
+
+        let mut trie = hash_directory(&hasher, &dir).unwrap();
+        let hash = |bytes: &[u8]| ContentDigest::from_reader(&hasher, bytes).unwrap();
+        assert_eq!(trie.get("docs/nested/b.txt"), Some(&hash(b"b")));
+        assert_eq!(trie.get("docs"), None);
+        let root = trie.merkle_root();
+        assert_eq!(hash_directory(&hasher, &dir).unwrap().merkle_root(), root);
+
+        fs::write(dir.join("docs/a.txt"), "changed").unwrap();
+        assert_ne!(hash_directory(&hasher, &dir).unwrap().merkle_root(), root);
+        fs::write(dir.join("docs/a.txt"), "a").unwrap();
+        fs::rename(dir.join("docs/a.txt"), dir.join("docs/c.txt")).unwrap();
+        assert_ne!(hash_directory(&hasher, &dir).unwrap().merkle_root(), root);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
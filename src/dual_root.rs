@@ -0,0 +1,205 @@
+use crate::trie_node::trie_node::TrieNode;
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+// Bytes in a multiset accumulator; hashes up to this long are added as big-endian numbers.
+const ACCUMULATOR_BYTES: usize = 64;
+
+/// An order-independent hash of a multiset of values: the sum, modulo `2^512`, of each value's
+/// hash read as a big-endian number. Adding the same values in any order, or under any keys,
+/// gives the same digest, and a value can be taken out again by subtracting its hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MultisetHash {
+    sum: [u8; ACCUMULATOR_BYTES],
+}
+
+impl Default for MultisetHash {
+    fn default() -> Self {
+        MultisetHash {
+            sum: [0; ACCUMULATOR_BYTES],
+        }
+    }
+}
+
+impl MultisetHash {
+    /// The multiset hash of `values`.
+    pub fn from_values<'a, T, H, I>(hasher: &H, values: I) -> Self
+    where
+        T: MerkleData + ?Sized + 'a,
+        H: MerkleHasher,
+        I: IntoIterator<Item = &'a T>,
+    {
+        let mut multiset = MultisetHash::default();
+        for value in values {
+            multiset.add(hasher, value);
+        }
+        multiset
+    }
+
+    pub fn add<T: MerkleData + ?Sized, H: MerkleHasher>(&mut self, hasher: &H, value: &T) {
+        let hash = Self::padded_hash(hasher, value);
+        let mut carry = 0;
+        for (sum, byte) in self.sum.iter_mut().zip(hash).rev() {
+            let total = *sum as u16 + byte as u16 + carry;
+            *sum = total as u8;
+            carry = total >> 8;
+        }
+    }
+
+    /// Takes out a value added before.
+    pub fn remove<T: MerkleData + ?Sized, H: MerkleHasher>(&mut self, hasher: &H, value: &T) {
+        let hash = Self::padded_hash(hasher, value);
+        let mut borrow = 0;
+        for (sum, byte) in self.sum.iter_mut().zip(hash).rev() {
+            let total = *sum as i16 - byte as i16 - borrow;
+            *sum = total.rem_euclid(256) as u8;
+            borrow = (total < 0) as i16;
+        }
+    }
+
+    fn padded_hash<T: MerkleData + ?Sized, H: MerkleHasher>(
+        hasher: &H,
+        value: &T,
+    ) -> [u8; ACCUMULATOR_BYTES] {
+        let hash = hasher.hash(&value.merkle_bytes());
+        let hash = hash.as_ref();
+        assert!(
+            hash.len() <= ACCUMULATOR_BYTES,
+            "hashes are limited to {ACCUMULATOR_BYTES} bytes"
+        );
+        let mut padded = [0; ACCUMULATOR_BYTES];
+        padded[ACCUMULATOR_BYTES - hash.len()..].copy_from_slice(hash);
+        padded
+    }
+
+    /// The sum as a hash of `H`'s type: the hash of its bytes.
+    pub fn digest<H: MerkleHasher>(&self, hasher: &H) -> H::Hash {
+        hasher.hash(&self.sum)
+    }
+}
+
+/// Both commitments of a `DualRootTrie`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DualRoots<D> {
+    /// The multiset hash of the values alone, whatever their keys.
+    pub values: D,
+    /// The trie's merkle root, over values and their keys.
+    pub structure: D,
+}
+
+/// A trie that also commits to the multiset of its values, for auditors who only care whether
+/// the same values are present, not where. The multiset hash is kept up to date on every
+/// write, so `roots` only has to bring the merkle root up to date.
+pub struct DualRootTrie<T: MerkleData, H: MerkleHasher, const N: usize = 2> {
+    trie: TrieNode<T, H, N>,
+    values: MultisetHash,
+}
+
+impl<T: MerkleData + PartialEq, H: MerkleHasher, const N: usize> DualRootTrie<T, H, N> {
+    pub fn with_hasher(hasher: H) -> Self {
+        Self::from_trie(TrieNode::with_hasher(hasher))
+    }
+
+    /// Wraps a trie that may already hold values, hashing each of them once.
+    pub fn from_trie(trie: TrieNode<T, H, N>) -> Self {
+        let values = MultisetHash::from_values(trie.hasher(), trie.iter().map(|(_, value)| value));
+        DualRootTrie { trie, values }
+    }
+
+    pub fn trie(&self) -> &TrieNode<T, H, N> {
+        &self.trie
+    }
+
+    pub fn into_trie(self) -> TrieNode<T, H, N> {
+        self.trie
+    }
+
+    pub fn get(&self, key: u32) -> Option<&T> {
+        self.trie.get(key)
+    }
+
+    pub fn insert(&mut self, key: u32, data: T) {
+        if let Some(old) = self.trie.get(key) {
+            self.values.remove(self.trie.hasher(), old);
+        }
+        self.values.add(self.trie.hasher(), &data);
+        self.trie.insert(key, data);
+    }
+
+    /// `TrieNode::retain`, taking removed and changed values out of the multiset hash.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        H: Clone,
+        F: FnMut(u32, &mut T) -> bool,
+    {
+        let hasher = self.trie.hasher().clone();
+        let values = &mut self.values;
+        self.trie.retain(|key, value| {
+            values.remove(&hasher, value);
+            let keep = f(key, value);
+            if keep {
+                values.add(&hasher, value);
+            }
+            keep
+        });
+    }
+
+    pub fn roots(&mut self) -> DualRoots<H::Hash> {
+        DualRoots {
+            values: self.values.digest(self.trie.hasher()),
+            structure: self.trie.merkle_root(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn value_root_ignores_keys_and_order() {
+        let mut trie: DualRootTrie<u32, StdMerkleHasher> =
+            DualRootTrie::with_hasher(StdMerkleHasher);
+        for key in 0..100 {
+            trie.insert(key, key % 7);
+        }
+        let mut moved: DualRootTrie<u32, StdMerkleHasher> =
+            DualRootTrie::from_trie((0..100).rev().map(|key| (key + 1000, key % 7)).collect());
+        let roots = trie.roots();
+        assert_eq!(roots.values, moved.roots().values);
+        assert_ne!(roots.structure, moved.roots().structure);
+        assert_eq!(
+            roots.values,
+            MultisetHash::from_values(
+                &StdMerkleHasher,
+                (0..100).map(|key| key % 7).collect::<Vec<u32>>().iter()
+            )
+            .digest(&StdMerkleHasher)
+        );
+
+        // Overwrites and removals take the old values out again.
+        trie.insert(5, 100);
+        trie.insert(5, 5);
+        assert_eq!(trie.roots(), roots);
+        trie.retain(|key, value| {
+            *value += (key == 3) as u32;
+            key != 4
+        });
+        moved.retain(|key, value| {
+            *value += (key == 1003) as u32;
+            key != 1004
+        });
+        assert_eq!(trie.roots().values, moved.roots().values);
+        let mut expected: Vec<u32> = (0..100)
+            .filter(|key| *key != 4)
+            .map(|key| key % 7)
+            .collect();
+        expected[3] += 1;
+        assert_eq!(
+            trie.roots().values,
+            MultisetHash::from_values(&StdMerkleHasher, expected.iter()).digest(&StdMerkleHasher)
+        );
+        assert_ne!(trie.roots().values, roots.values);
+    }
+}
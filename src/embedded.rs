@@ -0,0 +1,93 @@
+// The allocation-free proof verifier, which lives in the `#![no_std]` crate
+// `merkle_proof_embedded` so that it can be built for targets without `std`, and its
+// `StreamingHasher` for the hashers here that support streaming.
+
+pub use merkle_proof_embedded::{verify_proof_bytes, StreamingHasher};
+
+pub(crate) use merkle_proof_embedded::constant_time_eq;
+
+#[cfg(feature = "digest")]
+impl<D: digest::Digest> StreamingHasher for crate::hasher::DigestHasher<D> {
+    type Digest = digest::Output<D>;
+    type State = D;
+
+    fn start(&self) -> D {
+        D::new()
+    }
+
+    fn update(&self, state: &mut D, bytes: &[u8]) {
+        state.update(bytes);
+    }
+
+    fn finish(&self, state: D) -> digest::Output<D> {
+        state.finalize()
+    }
+
+    fn digest_len(&self) -> usize {
+        <D as digest::Digest>::output_size()
+    }
+}
+
+#[cfg(feature = "blake3")]
+impl StreamingHasher for crate::hasher::Blake3Hasher {
+    type Digest = [u8; 32];
+    type State = blake3::Hasher;
+
+    fn start(&self) -> blake3::Hasher {
+        blake3::Hasher::new()
+    }
+
+    fn update(&self, state: &mut blake3::Hasher, bytes: &[u8]) {
+        state.update(bytes);
+    }
+
+    fn finish(&self, state: blake3::Hasher) -> [u8; 32] {
+        *state.finalize().as_bytes()
+    }
+
+    fn digest_len(&self) -> usize {
+        32
+    }
+}
+
+#[cfg(all(test, feature = "digest"))]
+mod tests {
+
+    use super::*;
+    use crate::hasher::DigestHasher;
+    use crate::trie_node::trie_node::TrieNode;
+
+    #[test]
+    fn verifies_encoded_proofs_like_merkle_proof() {
+        let hasher = DigestHasher::<sha2::Sha256>::new();
+        let mut node: TrieNode<u32, DigestHasher<sha2::Sha256>, 4> =
+            TrieNode::with_hasher(hasher.clone());
+        for key in [0, 1, 5, 6, 21, 300, 1000] {
+            node.insert(key, key * 10);
+        }
+        let root = node.merkle_root();
+        for key in [0, 1, 5, 21, 1000] {
+            let proof = node.generate_proof(key).unwrap().to_bytes();
+            let value = (key * 10).to_be_bytes();
+            assert!(verify_proof_bytes(&hasher, &proof, &root, key, &value));
+            assert!(!verify_proof_bytes(&hasher, &proof, &root, key, &[0]));
+            let mut old = node.generate_proof(key).unwrap();
+            old.version = 1;
+            assert!(verify_proof_bytes(
+                &hasher,
+                &old.to_bytes(),
+                &root,
+                key,
+                &value
+            ));
+            assert!(!verify_proof_bytes(&hasher, &proof, &root, key + 1, &value));
+            assert!(!verify_proof_bytes(
+                &hasher,
+                &proof[..proof.len() - 1],
+                &root,
+                key,
+                &value
+            ));
+        }
+    }
+}
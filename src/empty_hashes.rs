@@ -0,0 +1,102 @@
+use crate::trie_node::trie_node::TrieNode;
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// The roots of value-less subtrees, by height. Level 0 is `MerkleHasher::empty_hash`, which a
+/// missing value or child contributes; level `h + 1` is the root of a node with no value whose
+/// `arity` children are all at level `h`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmptyHashes<D> {
+    levels: Vec<D>,
+}
+
+impl<D: Clone> EmptyHashes<D> {
+    /// Levels 0 to `height`.
+    pub fn new<H: MerkleHasher<Hash = D>>(hasher: &H, arity: usize, height: usize) -> Self {
+        let mut levels = vec![hasher.empty_hash()];
+        for _ in 0..height {
+            let below = levels.last().unwrap().clone();
+            levels.push(hasher.combine_children(&levels[0], &vec![below; arity]));
+        }
+        EmptyHashes { levels }
+    }
+
+    /// Level 0, the hash of the empty string.
+    pub fn empty(&self) -> &D {
+        &self.levels[0]
+    }
+
+    pub fn level(&self, height: usize) -> Option<&D> {
+        self.levels.get(height)
+    }
+
+    pub fn height(&self) -> usize {
+        self.levels.len() - 1
+    }
+
+    pub fn as_slice(&self) -> &[D] {
+        &self.levels
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    /// The empty-subtree hashes for this trie's hasher and arity, up to the depth of the
+    /// deepest key. Computed on first use; clones of the trie copy them.
+    pub fn empty_hashes(&self) -> &EmptyHashes<H::Hash> {
+        self.empty_hashes.get_or_init(|| {
+            let height = u32::BITS.div_ceil(Self::BITS_PER_DIGIT) as usize;
+            EmptyHashes::new(&self.hasher, N, height)
+        })
+    }
+
+    pub(crate) fn empty_hash(&self) -> &H::Hash {
+        self.empty_hashes().empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn levels_combine_the_level_below() {
+        let hasher = StdMerkleHasher;
+        let empty_hashes = EmptyHashes::new(&hasher, 4, 3);
+        assert_eq!(empty_hashes.height(), 3);
+        assert_eq!(empty_hashes.empty(), &hasher.empty_hash());
+        let level_1 = hasher.combine_children(&hasher.empty_hash(), &vec![hasher.empty_hash(); 4]);
+        assert_eq!(empty_hashes.level(1), Some(&level_1));
+        assert_eq!(empty_hashes.level(4), None);
+
+        let node: TrieNode<u32, StdMerkleHasher, 16> = TrieNode::new();
+        assert_eq!(node.empty_hashes().height(), 8);
+    }
+
+    #[test]
+    fn cold_roots_hash_the_empty_string_once() {
+        #[derive(Default)]
+        struct CountEmpty(Cell<usize>);
+
+        impl MerkleHasher for CountEmpty {
+            type Hash = String;
+
+            fn hash(&self, bytes: &[u8]) -> String {
+                self.0.set(self.0.get() + bytes.is_empty() as usize);
+                StdMerkleHasher.hash(bytes)
+            }
+
+            fn hash_from_bytes(bytes: &[u8]) -> Option<String> {
+                StdMerkleHasher::hash_from_bytes(bytes)
+            }
+        }
+
+        let mut node: TrieNode<u32, CountEmpty> = (1..50).map(|key| (key * 9, key)).collect();
+        node.merkle_root();
+        node.insert(1000, 1);
+        node.merkle_root();
+        assert_eq!(node.hasher().0.get(), 1);
+    }
+}
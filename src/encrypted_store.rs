@@ -0,0 +1,137 @@
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+
+use crate::async_store::{NodeId, NodeStore};
+
+const NONCE_BYTES: usize = 24;
+
+#[derive(Debug)]
+pub enum EncryptionError<E> {
+    Backend(E),
+    /// The record under this id was not written with this key for this id, or was altered.
+    Undecryptable(NodeId),
+}
+
+impl<E> From<E> for EncryptionError<E> {
+    fn from(error: E) -> Self {
+        EncryptionError::Backend(error)
+    }
+}
+
+/// Encrypts every record with XChaCha20-Poly1305 before it reaches `S`. Tries hash values before
+/// they are stored, so roots and proofs are the same as over a plaintext store. Each record is a
+/// random nonce followed by the ciphertext, authenticated together with its id so records can't
+/// be swapped between ids.
+pub struct EncryptedStore<S> {
+    inner: S,
+    cipher: XChaCha20Poly1305,
+}
+
+impl<S: NodeStore> EncryptedStore<S> {
+    pub fn new(inner: S, key: &[u8; 32]) -> Self {
+        EncryptedStore {
+            inner,
+            cipher: XChaCha20Poly1305::new(key.into()),
+        }
+    }
+
+    /// The backend, holding only ciphertext.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: NodeStore> NodeStore for EncryptedStore<S> {
+    type Error = EncryptionError<S::Error>;
+
+    fn get(&self, id: NodeId) -> Result<Option<Vec<u8>>, Self::Error> {
+        let Some(record) = self.inner.get(id)? else {
+            return Ok(None);
+        };
+        let undecryptable = || EncryptionError::Undecryptable(id);
+        if record.len() < NONCE_BYTES {
+            return Err(undecryptable());
+        }
+        let (nonce, ciphertext) = record.split_at(NONCE_BYTES);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: &id.to_be_bytes(),
+        };
+        self.cipher
+            .decrypt(XNonce::from_slice(nonce), payload)
+            .map(Some)
+            .map_err(|_| undecryptable())
+    }
+
+    fn put(&self, id: NodeId, bytes: Vec<u8>) -> Result<(), Self::Error> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: &bytes,
+            aad: &id.to_be_bytes(),
+        };
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, payload)
+            .expect("records fit in a single message");
+        let mut record = nonce.to_vec();
+        record.extend_from_slice(&ciphertext);
+        Ok(self.inner.put(id, record)?)
+    }
+
+    fn delete(&self, id: NodeId) -> Result<(), Self::Error> {
+        Ok(self.inner.delete(id)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::async_store::{AsyncTrie, MemoryNodeStore};
+    use crate::hasher::StdMerkleHasher;
+    use crate::trie_node::trie_node::TrieNode;
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let mut context = std::task::Context::from_waker(std::task::Waker::noop());
+        loop {
+            if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+        }
+    }
+
+    #[test]
+    fn stored_records_are_encrypted() {
+        block_on(async {
+            let store = EncryptedStore::new(MemoryNodeStore::new(), &[7; 32]);
+            let mut trie: AsyncTrie<String, _, _> =
+                AsyncTrie::open(store, StdMerkleHasher).await.unwrap();
+            let mut expected: TrieNode<String> = TrieNode::new();
+            for key in 0..20 {
+                trie.insert(key, format!("secret {key}")).await.unwrap();
+                expected.insert(key, format!("secret {key}"));
+            }
+            assert_eq!(trie.merkle_root(), &expected.merkle_root());
+            let backend = trie.store().inner();
+            for id in 1..backend.len() as NodeId {
+                let record = backend.get(id).unwrap().unwrap();
+                assert!(!record.windows(6).any(|window| window == b"secret"));
+            }
+
+            let store = EncryptedStore::new(trie.into_store().into_inner(), &[8; 32]);
+            assert!(matches!(
+                AsyncTrie::<String, _, _>::open(store, StdMerkleHasher).await,
+                Err(crate::async_store::StoreError::Backend(
+                    EncryptionError::Undecryptable(_)
+                ))
+            ));
+        });
+    }
+}
@@ -0,0 +1,100 @@
+use std::fmt;
+
+/// Errors from trie operations that can fail on their input. Keys and prefixes are also shown
+/// as their paths, in binary, in the `Display` output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TrieError {
+    /// In a `SecureTrie`, `key` hashes to the same path as `existing`, which is already stored;
+    /// in `rebase_keys`, both map to the same new key.
+    KeyCollision { key: u32, existing: u32 },
+    /// `key` needs `depth` digits, more than the `max_depth` there are: a `FixedDepthTrie`'s
+    /// fixed depth, counting its marker digit, or a `LimitedTrie`'s maximum. From `attach`,
+    /// `key` is in the attached trie and both depths are within it; deeper, the key would be
+    /// shifted past a `u32`.
+    DepthExceeded {
+        key: u32,
+        depth: u32,
+        max_depth: u32,
+    },
+    /// The value for `key` commits to more than `limit` bytes.
+    ValueTooLarge { key: u32, size: usize, limit: usize },
+    /// Inserting `key` would take the trie past `limit` nodes.
+    TooManyNodes { key: u32, limit: usize },
+    /// An `IncrementalMerkleTree` of `depth` already holds `2^depth` leaves.
+    TreeFull { depth: u32 },
+    /// `key` was given after `previous` to something that needs its keys in order.
+    OutOfOrder { key: u32, previous: u32 },
+    /// Nothing can be attached under the prefix: a node is already there, or the attached
+    /// trie's root holds a value, which has no key unless the prefix ends in a non-zero digit.
+    PrefixUnavailable { prefix: u32, prefix_len: u32 },
+    /// A `Cursor` was taken from a trie with a different root, so where it points may have
+    /// moved.
+    StaleCursor,
+}
+
+impl fmt::Display for TrieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrieError::KeyCollision { key, existing } => write!(
+                f,
+                "key {key} (path {key:#b}) collides with key {existing} (path {existing:#b})"
+            ),
+            TrieError::DepthExceeded {
+                key,
+                depth,
+                max_depth,
+            } => write!(
+                f,
+                "key {key} (path {key:#b}) needs {depth} digits, more than the {max_depth} allowed"
+            ),
+            TrieError::ValueTooLarge { key, size, limit } => write!(
+                f,
+                "value for key {key} (path {key:#b}) commits to {size} bytes, more than the \
+                 {limit} allowed"
+            ),
+            TrieError::TooManyNodes { key, limit } => write!(
+                f,
+                "inserting key {key} (path {key:#b}) would take the trie past {limit} nodes"
+            ),
+            TrieError::TreeFull { depth } => {
+                write!(f, "tree of depth {depth} already holds 2^{depth} leaves")
+            }
+            TrieError::OutOfOrder { key, previous } => {
+                write!(f, "key {key} given after key {previous}, out of order")
+            }
+            TrieError::PrefixUnavailable { prefix, prefix_len } => write!(
+                f,
+                "can't attach under prefix {prefix:#b} of {prefix_len} digits: it is occupied \
+                 or can't hold the attached root's value"
+            ),
+            TrieError::StaleCursor => write!(f, "cursor taken from a trie with a different root"),
+        }
+    }
+}
+
+impl std::error::Error for TrieError {}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn messages_show_paths() {
+        let error = TrieError::DepthExceeded {
+            key: 16,
+            depth: 5,
+            max_depth: 4,
+        };
+        assert_eq!(
+            error.to_string(),
+            "key 16 (path 0b10000) needs 5 digits, more than the 4 allowed"
+        );
+        let error: Box<dyn std::error::Error> = Box::new(TrieError::PrefixUnavailable {
+            prefix: 0b1011,
+            prefix_len: 2,
+        });
+        assert!(error.to_string().contains("prefix 0b1011 of 2 digits"));
+    }
+}
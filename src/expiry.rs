@@ -0,0 +1,198 @@
+use std::borrow::Cow;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::hasher::MerkleHasher;
+use crate::merkle_data::MerkleData;
+use crate::proof::MerkleProof;
+use crate::trie_node::trie_node::TrieNode;
+
+/// Where an `ExpiringTrie` reads the time from, in whatever unit its expiry times are given
+/// in. Any `Fn() -> u64` is a clock, which suits tests and simulated time.
+pub trait Clock {
+    fn now(&self) -> u64;
+}
+
+/// Seconds since the Unix epoch.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs())
+    }
+}
+
+impl<F: Fn() -> u64> Clock for F {
+    fn now(&self) -> u64 {
+        self()
+    }
+}
+
+/// A value that is live until `expires_at`, or for good if that is `None`. It commits to the
+/// expiry as well, a presence byte and then a big-endian `u64`, ahead of the value's bytes,
+/// so a proof shows when its value ages out; see the commitment spec.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expiring<T> {
+    pub value: T,
+    pub expires_at: Option<u64>,
+}
+
+impl<T> Expiring<T> {
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+impl<T: MerkleData> MerkleData for Expiring<T> {
+    fn merkle_bytes(&self) -> Cow<'_, [u8]> {
+        let mut bytes = match self.expires_at {
+            Some(expires_at) => [&[1][..], &expires_at.to_be_bytes()].concat(),
+            None => vec![0],
+        };
+        bytes.extend_from_slice(&self.value.merkle_bytes());
+        Cow::Owned(bytes)
+    }
+}
+
+/// A trie whose values can be given a time to live, read from `C`. Expired values are hidden
+/// from reads straight away, but stay in the trie, and under its root, until
+/// `purge_expired` removes them.
+pub struct ExpiringTrie<T: MerkleData, H: MerkleHasher, C = SystemClock, const N: usize = 2> {
+    trie: TrieNode<Expiring<T>, H, N>,
+    clock: C,
+}
+
+impl<T: MerkleData + PartialEq, H: MerkleHasher + Default, const N: usize> Default
+    for ExpiringTrie<T, H, SystemClock, N>
+{
+    fn default() -> Self {
+        ExpiringTrie::with_clock(H::default(), SystemClock)
+    }
+}
+
+impl<T: MerkleData + PartialEq, H: MerkleHasher, C: Clock, const N: usize>
+    ExpiringTrie<T, H, C, N>
+{
+    pub fn with_clock(hasher: H, clock: C) -> Self {
+        ExpiringTrie {
+            trie: TrieNode::with_hasher(hasher),
+            clock,
+        }
+    }
+
+    pub fn trie(&self) -> &TrieNode<Expiring<T>, H, N> {
+        &self.trie
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Stores `value` under `key` for good.
+    pub fn insert(&mut self, key: u32, value: T) {
+        self.trie.insert(
+            key,
+            Expiring {
+                value,
+                expires_at: None,
+            },
+        );
+    }
+
+    /// Stores `value` under `key` until `ttl` from now.
+    pub fn insert_with_ttl(&mut self, key: u32, value: T, ttl: u64) {
+        let expires_at = Some(self.clock.now().saturating_add(ttl));
+        self.trie.insert(key, Expiring { value, expires_at });
+    }
+
+    pub fn get(&self, key: u32) -> Option<&T> {
+        let now = self.clock.now();
+        let entry = self.trie.get(key)?;
+        (!entry.is_expired(now)).then_some(&entry.value)
+    }
+
+    /// The live entries, in the trie's iteration order.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &T)> + '_ {
+        let now = self.clock.now();
+        self.trie
+            .iter()
+            .filter(move |(_, entry)| !entry.is_expired(now))
+            .map(|(key, entry)| (key, &entry.value))
+    }
+
+    /// Removes every value expired by `now`, invalidating the cached hashes on their paths
+    /// only. Returns the number removed.
+    pub fn purge_expired(&mut self, now: u64) -> usize {
+        let mut purged = 0;
+        self.trie.retain(|_, entry| {
+            let expired = entry.is_expired(now);
+            purged += expired as usize;
+            !expired
+        });
+        purged
+    }
+
+    pub fn merkle_root(&mut self) -> H::Hash {
+        self.trie.merkle_root()
+    }
+
+    /// Proves the value under `key` with its expiry, `Expiring { value, expires_at }`, which
+    /// the verifier can check against its own clock.
+    pub fn generate_proof(&mut self, key: u32) -> Option<MerkleProof<H::Hash>> {
+        self.trie.generate_proof(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn expired_values_are_hidden_then_purged() {
+        let now = Cell::new(100);
+        let mut sessions: ExpiringTrie<u32, StdMerkleHasher, _, 4> =
+            ExpiringTrie::with_clock(StdMerkleHasher, || now.get());
+        sessions.insert(1, 10);
+        sessions.insert_with_ttl(2, 20, 50);
+        sessions.insert_with_ttl(7, 70, 10);
+        sessions.insert_with_ttl(30, 300, 10);
+        let root = sessions.merkle_root();
+        let proof = sessions.generate_proof(7).unwrap();
+        let entry = Expiring {
+            value: 70u32,
+            expires_at: Some(110),
+        };
+        assert!(proof.verify(&StdMerkleHasher, &root, &entry));
+        let later = Expiring {
+            expires_at: Some(111),
+            ..entry
+        };
+        assert!(!proof.verify(&StdMerkleHasher, &root, &later));
+
+        now.set(110);
+        assert_eq!(sessions.get(7), None);
+        assert_eq!(sessions.get(2), Some(&20));
+        let mut live: Vec<u32> = sessions.iter().map(|(key, _)| key).collect();
+        live.sort();
+        assert_eq!(live, [1, 2]);
+        assert_eq!(sessions.merkle_root(), root);
+
+        // Purging detaches the paths to keys 7 and 30, leaving the root of a trie that never
+        // held them.
+        let untouched = sessions.trie().metrics().node_count;
+        assert_eq!(sessions.purge_expired(110), 2);
+        assert!(sessions.trie().metrics().node_count < untouched);
+        let mut expected: ExpiringTrie<u32, StdMerkleHasher, _, 4> =
+            ExpiringTrie::with_clock(StdMerkleHasher, || 100);
+        expected.insert(1, 10);
+        expected.insert_with_ttl(2, 20, 50);
+        assert_eq!(sessions.merkle_root(), expected.merkle_root());
+        assert_eq!(sessions.purge_expired(110), 0);
+    }
+}
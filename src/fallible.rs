@@ -0,0 +1,194 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::str::FromStr;
+use std::task::{Context, Poll, Waker};
+
+use crate::async_store::{AsyncTrie, NodeStore, StoreError};
+use crate::codec::{DisplayCodec, ValueCodec};
+use crate::trie_node::trie_node::TrieNode;
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// Lookups and inserts that may fail, for code that should work over any trie whether its nodes
+/// are in memory or in storage. `TrieNode` implements it with `Infallible` errors, so its own
+/// `find_by_key` and `insert` stay as they are, and a caller that holds a `TrieNode` never
+/// handles an error that cannot happen.
+pub trait TryTrie<T> {
+    type Error;
+
+    fn try_find_by_key(&self, key: u32) -> Result<Option<T>, Self::Error>;
+
+    fn try_insert(&mut self, key: u32, value: T) -> Result<(), Self::Error>;
+}
+
+impl<T, H, const N: usize> TryTrie<T> for TrieNode<T, H, N>
+where
+    T: MerkleData + PartialEq + Clone,
+    H: MerkleHasher,
+{
+    type Error = Infallible;
+
+    fn try_find_by_key(&self, key: u32) -> Result<Option<T>, Infallible> {
+        Ok(self.get(key).cloned())
+    }
+
+    fn try_insert(&mut self, key: u32, value: T) -> Result<(), Infallible> {
+        self.insert(key, value);
+        Ok(())
+    }
+}
+
+/// An `AsyncTrie` over a blocking `NodeStore`, driven synchronously. Every read and write goes to
+/// the store, so every operation returns the store's errors.
+pub struct StoredTrie<T, H: MerkleHasher, S, const N: usize = 2, C = DisplayCodec> {
+    trie: AsyncTrie<T, H, S, N, C>,
+}
+
+// A `NodeStore`'s futures are ready when they are created, and so are those of an `AsyncTrie`
+// awaiting only them.
+fn complete<F: Future>(future: F) -> F::Output {
+    let mut future = std::pin::pin!(future);
+    match future
+        .as_mut()
+        .poll(&mut Context::from_waker(Waker::noop()))
+    {
+        Poll::Ready(output) => output,
+        Poll::Pending => unreachable!("NodeStore futures complete immediately"),
+    }
+}
+
+impl<T, H, S, const N: usize> StoredTrie<T, H, S, N>
+where
+    T: MerkleData + ToString + FromStr,
+    H: MerkleHasher,
+    S: NodeStore + Sync,
+    S::Error: Send,
+{
+    /// Opens the trie kept in `store`, which may be empty.
+    pub fn open(store: S, hasher: H) -> Result<Self, StoreError<S::Error>> {
+        Self::open_with_codec(store, hasher, DisplayCodec)
+    }
+}
+
+impl<T, H, S, const N: usize, C> StoredTrie<T, H, S, N, C>
+where
+    T: MerkleData,
+    H: MerkleHasher,
+    S: NodeStore + Sync,
+    S::Error: Send,
+    C: ValueCodec<T>,
+{
+    pub fn open_with_codec(store: S, hasher: H, codec: C) -> Result<Self, StoreError<S::Error>> {
+        let trie = complete(AsyncTrie::open_with_codec(store, hasher, codec))?;
+        Ok(StoredTrie { trie })
+    }
+
+    pub fn merkle_root(&self) -> &H::Hash {
+        self.trie.merkle_root()
+    }
+
+    pub fn trie(&self) -> &AsyncTrie<T, H, S, N, C> {
+        &self.trie
+    }
+
+    pub fn into_trie(self) -> AsyncTrie<T, H, S, N, C> {
+        self.trie
+    }
+}
+
+impl<T, H, S, const N: usize, C> TryTrie<T> for StoredTrie<T, H, S, N, C>
+where
+    T: MerkleData,
+    H: MerkleHasher,
+    S: NodeStore + Sync,
+    S::Error: Send,
+    C: ValueCodec<T>,
+{
+    type Error = StoreError<S::Error>;
+
+    fn try_find_by_key(&self, key: u32) -> Result<Option<T>, Self::Error> {
+        complete(self.trie.get(key))
+    }
+
+    fn try_insert(&mut self, key: u32, value: T) -> Result<(), Self::Error> {
+        complete(self.trie.insert(key, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::*;
+    use crate::async_store::{MemoryNodeStore, NodeId};
+    use crate::hasher::StdMerkleHasher;
+
+    #[derive(Default)]
+    struct FlakyStore {
+        nodes: MemoryNodeStore,
+        down: AtomicBool,
+    }
+
+    impl FlakyStore {
+        fn check(&self) -> Result<(), &'static str> {
+            match self.down.load(Ordering::Relaxed) {
+                true => Err("store unavailable"),
+                false => Ok(()),
+            }
+        }
+    }
+
+    impl NodeStore for FlakyStore {
+        type Error = &'static str;
+
+        fn get(&self, id: NodeId) -> Result<Option<Vec<u8>>, Self::Error> {
+            self.check()?;
+            Ok(NodeStore::get(&self.nodes, id).unwrap())
+        }
+
+        fn put(&self, id: NodeId, bytes: Vec<u8>) -> Result<(), Self::Error> {
+            self.check()?;
+            NodeStore::put(&self.nodes, id, bytes).unwrap();
+            Ok(())
+        }
+
+        fn delete(&self, id: NodeId) -> Result<(), Self::Error> {
+            self.check()?;
+            NodeStore::delete(&self.nodes, id).unwrap();
+            Ok(())
+        }
+    }
+
+    fn fill<X: TryTrie<u32>>(trie: &mut X) -> Result<Option<u32>, X::Error> {
+        for key in 0..40 {
+            trie.try_insert(key * 5, key)?;
+        }
+        trie.try_find_by_key(35)
+    }
+
+    #[test]
+    fn the_same_code_runs_over_memory_and_storage() {
+        let mut memory: TrieNode<u32> = TrieNode::new();
+        let Ok(found) = fill(&mut memory);
+        assert_eq!(found, Some(7));
+
+        let mut stored: StoredTrie<u32, StdMerkleHasher, FlakyStore> =
+            StoredTrie::open(FlakyStore::default(), StdMerkleHasher).unwrap();
+        assert_eq!(fill(&mut stored).unwrap(), Some(7));
+        assert_eq!(stored.merkle_root(), &memory.merkle_root());
+        assert_eq!(stored.try_find_by_key(36).unwrap(), None);
+
+        stored.trie().store().down.store(true, Ordering::Relaxed);
+        assert!(matches!(
+            stored.try_find_by_key(35),
+            Err(StoreError::Backend("store unavailable"))
+        ));
+        assert!(matches!(
+            stored.try_insert(1, 1),
+            Err(StoreError::Backend("store unavailable"))
+        ));
+        stored.trie().store().down.store(false, Ordering::Relaxed);
+        assert_eq!(stored.merkle_root(), &memory.merkle_root());
+        assert_eq!(stored.try_find_by_key(1).unwrap(), None);
+    }
+}
@@ -0,0 +1,146 @@
+use crate::error::TrieError;
+use crate::proof::MerkleProof;
+use crate::trie_node::trie_node::TrieNode;
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// A trie in which every value sits at the same depth, so every proof has exactly `depth`
+/// levels, which suits verifiers with a fixed shape such as contracts and circuits. A key is
+/// stored with a marker digit of 1 above its `depth - 1` lowest digits, so keys must be below
+/// `N^(depth - 1)`. Proofs are for the stored key, see `stored_key`.
+pub struct FixedDepthTrie<T: MerkleData, H: MerkleHasher, const N: usize = 2> {
+    trie: TrieNode<T, H, N>,
+    depth: u32,
+}
+
+impl<T: MerkleData + PartialEq, H: MerkleHasher, const N: usize> FixedDepthTrie<T, H, N> {
+    /// `depth` is counted in digits, from 1 up to the most that fit in a `u32` key with the
+    /// marker digit: 32 in a binary trie, 8 with arity 16.
+    pub fn with_hasher(hasher: H, depth: u32) -> Self {
+        Self::from_trie(TrieNode::with_hasher(hasher), depth)
+    }
+
+    // `trie` must be empty.
+    pub(crate) fn from_trie(trie: TrieNode<T, H, N>, depth: u32) -> Self {
+        let bits_per_digit = N.trailing_zeros();
+        assert!(
+            depth >= 1 && (depth - 1) * bits_per_digit < u32::BITS,
+            "depth must be between 1 and {}",
+            (u32::BITS - 1) / bits_per_digit + 1
+        );
+        FixedDepthTrie { trie, depth }
+    }
+
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    pub fn trie(&self) -> &TrieNode<T, H, N> {
+        &self.trie
+    }
+
+    /// Where `key` is stored, or `None` if it doesn't fit in `depth - 1` digits.
+    pub fn stored_key(&self, key: u32) -> Option<u32> {
+        let marker = 1 << ((self.depth - 1) * N.trailing_zeros());
+        (key < marker).then_some(key | marker)
+    }
+
+    pub fn insert(&mut self, key: u32, data: T) -> Result<(), TrieError> {
+        let stored_key = self.stored_key(key).ok_or(TrieError::DepthExceeded {
+            key,
+            depth: TrieNode::<T, H, N>::key_depth(key) + 1,
+            max_depth: self.depth,
+        })?;
+        self.trie.insert(stored_key, data);
+        Ok(())
+    }
+
+    pub fn get(&self, key: u32) -> Option<&T> {
+        self.trie.find_by_key(self.stored_key(key)?)?.get_data()
+    }
+
+    pub fn merkle_root(&mut self) -> H::Hash {
+        self.trie.merkle_root()
+    }
+
+    /// A proof with exactly `depth` levels, each with `N - 1` siblings.
+    pub fn generate_proof(&mut self, key: u32) -> Option<MerkleProof<H::Hash>> {
+        let stored_key = self.stored_key(key)?;
+        self.trie.generate_proof(stored_key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &T)> + '_ {
+        let marker = 1 << ((self.depth - 1) * N.trailing_zeros());
+        self.trie
+            .iter()
+            .map(move |(key, data)| (key ^ marker, data))
+    }
+}
+
+impl<D: Clone + AsRef<[u8]>> MerkleProof<D> {
+    /// The key, that is the position, a proof from a `FixedDepthTrie` of `depth` digits is
+    /// for, read off the stored key it proves; `None` if it isn't laid out for that depth.
+    pub fn fixed_depth_key(&self, depth: u32) -> Option<u32> {
+        let shift = depth
+            .checked_sub(1)?
+            .checked_mul(self.arity.trailing_zeros())
+            .filter(|shift| *shift < u32::BITS)?;
+        (self.levels.len() == depth as usize && self.key >> shift == 1)
+            .then(|| self.key ^ 1 << shift)
+    }
+
+    /// Whether the proof shows `value` at `key` in a `FixedDepthTrie` of `depth` digits with
+    /// root `root`.
+    pub fn verify_fixed_depth<H, V>(
+        &self,
+        hasher: &H,
+        root: &D,
+        depth: u32,
+        key: u32,
+        value: &V,
+    ) -> bool
+    where
+        H: MerkleHasher<Hash = D>,
+        V: MerkleData + ?Sized,
+    {
+        self.fixed_depth_key(depth) == Some(key) && self.verify(hasher, root, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn every_proof_has_the_fixed_depth() {
+        let mut trie: FixedDepthTrie<u32, StdMerkleHasher, 4> =
+            FixedDepthTrie::with_hasher(StdMerkleHasher, 5);
+        for key in [0, 1, 5, 200, 255] {
+            trie.insert(key, key).unwrap();
+        }
+        assert_eq!(
+            trie.insert(256, 0),
+            Err(TrieError::DepthExceeded {
+                key: 256,
+                depth: 6,
+                max_depth: 5
+            })
+        );
+        let root = trie.merkle_root();
+        for key in [0, 1, 5, 200, 255] {
+            let proof = trie.generate_proof(key).unwrap();
+            assert_eq!(proof.levels.len(), 5);
+            assert!(proof.children_roots.is_empty());
+            assert!(proof.verify(&StdMerkleHasher, &root, &key));
+            assert_eq!(proof.fixed_depth_key(5), Some(key));
+            assert!(proof.verify_fixed_depth(&StdMerkleHasher, &root, 5, key, &key));
+            assert!(!proof.verify_fixed_depth(&StdMerkleHasher, &root, 5, key ^ 1, &key));
+            assert_eq!(proof.fixed_depth_key(4), None);
+        }
+        assert_eq!(trie.get(5), Some(&5));
+        let mut keys: Vec<u32> = trie.iter().map(|(key, _)| key).collect();
+        keys.sort();
+        assert_eq!(keys, [0, 1, 5, 200, 255]);
+    }
+}
@@ -0,0 +1,185 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::proof::MerkleProof;
+use crate::trie_node::trie_node::TrieNode;
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// What the top-level trie of a `Forest` stores for a namespace: its name and current root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamespaceRoot<D> {
+    pub name: String,
+    pub root: D,
+}
+
+impl<D: AsRef<[u8]>> MerkleData for NamespaceRoot<D> {
+    // name (u32 len + bytes) | root
+    fn merkle_bytes(&self) -> Cow<'_, [u8]> {
+        let mut bytes = (self.name.len() as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(self.name.as_bytes());
+        bytes.extend_from_slice(self.root.as_ref());
+        Cow::Owned(bytes)
+    }
+}
+
+/// Proof that a value is stored under a key in one namespace of a `Forest`: the key's proof
+/// within the namespace's trie, and the namespace root's proof within the top-level trie.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForestProof<D> {
+    pub namespace: String,
+    pub namespace_proof: MerkleProof<D>,
+    pub key_proof: MerkleProof<D>,
+}
+
+impl<D: Clone + PartialEq + AsRef<[u8]>> ForestProof<D> {
+    pub fn verify<H, V>(&self, hasher: &H, root: &D, value: &V) -> bool
+    where
+        H: MerkleHasher<Hash = D>,
+        V: MerkleData + ?Sized,
+    {
+        let Some(namespace_root) = self.key_proof.root_for(hasher, value) else {
+            return false;
+        };
+        let entry = NamespaceRoot {
+            name: self.namespace.clone(),
+            root: namespace_root,
+        };
+        self.namespace_proof.verify(hasher, root, &entry)
+    }
+}
+
+struct Namespace<T: MerkleData, H: MerkleHasher, const N: usize> {
+    name: String,
+    trie: TrieNode<T, H, N>,
+    // Whether the trie may have changed since its root was last written to the top level.
+    dirty: bool,
+}
+
+/// Many named tries under one commitment, e.g. one per tenant. Each namespace gets the next
+/// free key of a top-level trie, which stores the namespace's name and root; the forest's root
+/// is the top-level trie's root.
+pub struct Forest<T: MerkleData, H: MerkleHasher, const N: usize = 2> {
+    namespaces: Vec<Namespace<T, H, N>>,
+    ids: HashMap<String, u32>,
+    top: TrieNode<NamespaceRoot<H::Hash>, H, N>,
+}
+
+impl<T: MerkleData, H: MerkleHasher + Clone + Default, const N: usize> Default for Forest<T, H, N> {
+    fn default() -> Self {
+        Forest::with_hasher(H::default())
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher + Clone + Default, const N: usize> Forest<T, H, N> {
+    pub fn new() -> Self {
+        Forest::default()
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher + Clone, const N: usize> Forest<T, H, N> {
+    pub fn with_hasher(hasher: H) -> Self {
+        Forest {
+            namespaces: vec![],
+            ids: HashMap::new(),
+            top: TrieNode::with_hasher(hasher),
+        }
+    }
+
+    pub fn namespace(&self, name: &str) -> Option<&TrieNode<T, H, N>> {
+        Some(&self.namespaces[*self.ids.get(name)? as usize].trie)
+    }
+
+    /// The trie of namespace `name`, created empty if it doesn't exist yet.
+    pub fn namespace_mut(&mut self, name: &str) -> &mut TrieNode<T, H, N> {
+        let id = match self.ids.get(name) {
+            Some(id) => *id,
+            None => {
+                let id = self.namespaces.len() as u32;
+                self.namespaces.push(Namespace {
+                    name: name.to_string(),
+                    trie: TrieNode::with_hasher(self.top.hasher().clone()),
+                    dirty: true,
+                });
+                self.ids.insert(name.to_string(), id);
+                id
+            }
+        };
+        let namespace = &mut self.namespaces[id as usize];
+        namespace.dirty = true;
+        &mut namespace.trie
+    }
+
+    pub fn insert(&mut self, name: &str, key: u32, data: T)
+    where
+        T: PartialEq,
+    {
+        self.namespace_mut(name).insert(key, data);
+    }
+
+    /// Every namespace name, in the order they were created.
+    pub fn names(&self) -> impl Iterator<Item = &str> + '_ {
+        self.namespaces
+            .iter()
+            .map(|namespace| namespace.name.as_str())
+    }
+
+    /// The root committing to every namespace.
+    pub fn merkle_root(&mut self) -> H::Hash {
+        for (id, namespace) in self.namespaces.iter_mut().enumerate() {
+            if namespace.dirty {
+                let entry = NamespaceRoot {
+                    name: namespace.name.clone(),
+                    root: namespace.trie.merkle_root(),
+                };
+                self.top.insert(id as u32, entry);
+                namespace.dirty = false;
+            }
+        }
+        self.top.merkle_root()
+    }
+
+    /// Proves the value under `key` in namespace `name` against `merkle_root`; `None` if there
+    /// is no such namespace or value.
+    pub fn generate_proof(&mut self, name: &str, key: u32) -> Option<ForestProof<H::Hash>> {
+        let id = *self.ids.get(name)?;
+        self.merkle_root();
+        let key_proof = self.namespaces[id as usize].trie.generate_proof(key)?;
+        let namespace_proof = self.top.generate_proof(id)?;
+        Some(ForestProof {
+            namespace: name.to_string(),
+            namespace_proof,
+            key_proof,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn cross_namespace_proofs() {
+        let mut forest: Forest<u32, StdMerkleHasher> = Forest::new();
+        for key in 0..20 {
+            forest.insert("alice", key, key);
+            forest.insert("bob", key * 2, key + 100);
+        }
+        let root = forest.merkle_root();
+        let proof = forest.generate_proof("bob", 10).unwrap();
+        assert!(proof.verify(&StdMerkleHasher, &root, &105u32));
+        assert!(!proof.verify(&StdMerkleHasher, &root, &5u32));
+
+        let mut renamed = proof.clone();
+        renamed.namespace = "alice".to_string();
+        assert!(!renamed.verify(&StdMerkleHasher, &root, &105u32));
+        assert_eq!(forest.generate_proof("carol", 0), None);
+
+        forest.insert("alice", 3, 33);
+        let updated = forest.merkle_root();
+        assert_ne!(updated, root);
+        assert!(!proof.verify(&StdMerkleHasher, &updated, &105u32));
+        assert_eq!(forest.names().collect::<Vec<_>>(), ["alice", "bob"]);
+    }
+}
@@ -0,0 +1,141 @@
+// Generators for tries and proofs, so downstream crates can property-test code built on them.
+// Both kinds of trie generator insert a list of arbitrary entries, so the trie's shape is
+// whatever those keys give rise to.
+
+use crate::trie_node::trie_node::TrieNode;
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+#[cfg(feature = "arbitrary")]
+impl<'a, T, H, const N: usize> arbitrary::Arbitrary<'a> for TrieNode<T, H, N>
+where
+    T: MerkleData + PartialEq + arbitrary::Arbitrary<'a>,
+    H: MerkleHasher + Default,
+{
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        u.arbitrary_iter::<(u32, T)>()?.collect()
+    }
+}
+
+#[cfg(feature = "proptest")]
+pub use self::strategies::*;
+
+#[cfg(feature = "proptest")]
+mod strategies {
+    use std::fmt::Debug;
+
+    use proptest::arbitrary::{any, Arbitrary};
+    use proptest::collection::vec;
+    use proptest::sample::Index;
+    use proptest::strategy::{BoxedStrategy, Strategy};
+
+    use super::*;
+    use crate::proof::{MerkleProof, ProofLevel};
+
+    // Enough entries for several levels of branching, few enough to keep cases quick.
+    const MAX_ENTRIES: usize = 64;
+
+    impl<T, H, const N: usize> Arbitrary for TrieNode<T, H, N>
+    where
+        T: MerkleData + PartialEq + Arbitrary + 'static,
+        H: MerkleHasher + Default + Debug + 'static,
+        H::Hash: Debug,
+    {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<Self>;
+
+        fn arbitrary_with(_: ()) -> Self::Strategy {
+            vec(any::<(u32, T)>(), 0..MAX_ENTRIES)
+                .prop_map(|entries| entries.into_iter().collect())
+                .boxed()
+        }
+    }
+
+    /// A non-empty trie and one of the keys it holds, for properties about stored entries,
+    /// such as every proof verifying against the root.
+    pub fn trie_and_key<T, H, const N: usize>() -> impl Strategy<Value = (TrieNode<T, H, N>, u32)>
+    where
+        T: MerkleData + PartialEq + Arbitrary + 'static,
+        H: MerkleHasher + Default + Debug + 'static,
+        H::Hash: Debug,
+    {
+        (vec(any::<(u32, T)>(), 1..MAX_ENTRIES), any::<Index>()).prop_map(|(entries, chosen)| {
+            let key = chosen.get(&entries).0;
+            (entries.into_iter().collect(), key)
+        })
+    }
+
+    /// Proofs with arbitrary hashes, which almost never verify: for checking that code
+    /// handling untrusted proofs rejects them gracefully.
+    impl<D: Arbitrary + 'static> Arbitrary for MerkleProof<D> {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<Self>;
+
+        fn arbitrary_with(_: ()) -> Self::Strategy {
+            let level = (any::<D>(), vec(any::<D>(), 0..4), any::<u8>()).prop_map(
+                |(data_hash, siblings, position)| ProofLevel {
+                    data_hash,
+                    siblings,
+                    position,
+                },
+            );
+            (
+                any::<u8>(),
+                any::<u32>(),
+                any::<usize>(),
+                vec(any::<D>(), 0..4),
+                vec(level, 0..33),
+            )
+                .prop_map(
+                    |(version, key, arity, children_roots, levels)| MerkleProof {
+                        version,
+                        key,
+                        arity,
+                        children_roots,
+                        levels,
+                    },
+                )
+                .boxed()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[cfg(feature = "proptest")]
+    proptest::proptest! {
+        #[test]
+        fn stored_entries_always_prove(
+            (mut node, key) in trie_and_key::<u32, StdMerkleHasher, 4>(),
+            proof in proptest::arbitrary::any::<crate::proof::MerkleProof<String>>(),
+        ) {
+            let root = node.merkle_root();
+            let value = *node.get(key).unwrap();
+            let generated = node.generate_proof(key).unwrap();
+            proptest::prop_assert!(generated.verify(&StdMerkleHasher, &root, &value));
+            proptest::prop_assert!(!proof.verify(&StdMerkleHasher, &root, &value));
+        }
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn arbitrary_tries_hold_the_entries_they_were_built_from() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        let bytes: Vec<u8> = (0..=255).rev().collect();
+        let mut u = Unstructured::new(&bytes);
+        let mut node = TrieNode::<u32, StdMerkleHasher>::arbitrary(&mut u).unwrap();
+        let mut u = Unstructured::new(&bytes);
+        let entries: Vec<(u32, u32)> = u.arbitrary_iter().unwrap().map(Result::unwrap).collect();
+        assert!(!entries.is_empty());
+        let mut expected: TrieNode<u32> = entries.into_iter().collect();
+        assert_eq!(node.merkle_root(), expected.merkle_root());
+
+        let mut u = Unstructured::new(&bytes);
+        let proof = crate::proof::MerkleProof::<String>::arbitrary(&mut u).unwrap();
+        assert!(!proof.verify(&StdMerkleHasher, &node.merkle_root(), &0));
+    }
+}
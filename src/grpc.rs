@@ -0,0 +1,195 @@
+use std::{
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+};
+
+use tonic::{Request, Response, Status};
+
+use crate::trie_node::trie_node::TrieNode;
+use crate::{hasher::MerkleHasher, proof};
+
+pub mod proto {
+    tonic::include_proto!("merkle_trie.v1");
+}
+
+use proto::merkle_trie_server::{MerkleTrie, MerkleTrieServer};
+
+pub use proto::merkle_trie_client::MerkleTrieClient;
+
+impl proto::MerkleProof {
+    pub fn from_proof<D: AsRef<[u8]>>(proof: &proof::MerkleProof<D>) -> Self {
+        let bytes = |hashes: &[D]| hashes.iter().map(|hash| hash.as_ref().to_vec()).collect();
+        proto::MerkleProof {
+            version: proof.version as u32,
+            key: proof.key,
+            arity: proof.arity as u32,
+            children_roots: bytes(&proof.children_roots),
+            levels: proof
+                .levels
+                .iter()
+                .map(|level| proto::ProofLevel {
+                    data_hash: level.data_hash.as_ref().to_vec(),
+                    siblings: bytes(&level.siblings),
+                    position: level.position as u32,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn to_proof<H: MerkleHasher>(&self) -> Option<proof::MerkleProof<H::Hash>> {
+        let hashes = |bytes: &[Vec<u8>]| {
+            bytes
+                .iter()
+                .map(|hash| H::hash_from_bytes(hash))
+                .collect::<Option<Vec<_>>>()
+        };
+        let version: u8 = self.version.max(1).try_into().ok()?;
+        let proof = proof::MerkleProof {
+            version,
+            key: self.key,
+            arity: self.arity as usize,
+            children_roots: hashes(&self.children_roots)?,
+            levels: self
+                .levels
+                .iter()
+                .map(|level| {
+                    Some(proof::ProofLevel {
+                        data_hash: H::hash_from_bytes(&level.data_hash)?,
+                        siblings: hashes(&level.siblings)?,
+                        position: level.position.try_into().ok()?,
+                    })
+                })
+                .collect::<Option<Vec<_>>>()?,
+        };
+        Some(if version < 2 {
+            proof.with_key_positions()
+        } else {
+            proof
+        })
+    }
+}
+
+/// Implements the `MerkleTrie` gRPC service over a trie shared with the rest of the process.
+pub struct TrieService<H: MerkleHasher, const N: usize = 2> {
+    trie: Arc<Mutex<TrieNode<String, H, N>>>,
+    hasher: PhantomData<fn() -> H>,
+}
+
+impl<H, const N: usize> TrieService<H, N>
+where
+    H: MerkleHasher + Send + 'static,
+    H::Hash: Send,
+{
+    pub fn new(trie: Arc<Mutex<TrieNode<String, H, N>>>) -> Self {
+        TrieService {
+            trie,
+            hasher: PhantomData,
+        }
+    }
+
+    pub fn into_server(self) -> MerkleTrieServer<Self> {
+        MerkleTrieServer::new(self)
+    }
+}
+
+#[tonic::async_trait]
+impl<H, const N: usize> MerkleTrie for TrieService<H, N>
+where
+    H: MerkleHasher + Send + 'static,
+    H::Hash: Send,
+{
+    async fn get_root(
+        &self,
+        _request: Request<proto::GetRootRequest>,
+    ) -> Result<Response<proto::RootResponse>, Status> {
+        let root = self.trie.lock().unwrap().merkle_root();
+        Ok(Response::new(proto::RootResponse {
+            root: root.as_ref().to_vec(),
+        }))
+    }
+
+    async fn get_proof(
+        &self,
+        request: Request<proto::GetProofRequest>,
+    ) -> Result<Response<proto::GetProofResponse>, Status> {
+        let key = request.into_inner().key;
+        let mut trie = self.trie.lock().unwrap();
+        let proof = trie
+            .generate_proof(key)
+            .ok_or_else(|| Status::not_found(format!("no value under key {key}")))?;
+        let value = trie.find_by_key(key).unwrap().get_data().unwrap().clone();
+        Ok(Response::new(proto::GetProofResponse {
+            value,
+            root: trie.merkle_root().as_ref().to_vec(),
+            proof: Some(proto::MerkleProof::from_proof(&proof)),
+        }))
+    }
+
+    async fn batch_insert(
+        &self,
+        request: Request<proto::BatchInsertRequest>,
+    ) -> Result<Response<proto::RootResponse>, Status> {
+        let mut trie = self.trie.lock().unwrap();
+        for entry in request.into_inner().entries {
+            trie.insert(entry.key, entry.value);
+        }
+        Ok(Response::new(proto::RootResponse {
+            root: trie.merkle_root().as_ref().to_vec(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[tokio::test]
+    async fn batch_insert_and_prove_over_grpc() {
+        let trie: Arc<Mutex<TrieNode<String>>> = Arc::new(Mutex::new(TrieNode::new()));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(TrieService::new(trie.clone()).into_server())
+                .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+        );
+
+        let mut client = MerkleTrieClient::connect(format!("http://{address}"))
+            .await
+            .unwrap();
+        let entries = [(1, "foo"), (2, "bar")]
+            .map(|(key, value)| proto::Entry {
+                key,
+                value: value.to_string(),
+            })
+            .to_vec();
+        let root = client
+            .batch_insert(proto::BatchInsertRequest { entries })
+            .await
+            .unwrap()
+            .into_inner()
+            .root;
+        assert_eq!(root, b"13830055607334163982");
+
+        let response = client
+            .get_proof(proto::GetProofRequest { key: 2 })
+            .await
+            .unwrap()
+            .into_inner();
+        let proof = response
+            .proof
+            .unwrap()
+            .to_proof::<StdMerkleHasher>()
+            .unwrap();
+        let root = StdMerkleHasher::hash_from_bytes(&response.root).unwrap();
+        assert!(proof.verify(&StdMerkleHasher, &root, &response.value));
+
+        let missing = client
+            .get_proof(proto::GetProofRequest { key: 3 })
+            .await
+            .unwrap_err();
+        assert_eq!(missing.code(), tonic::Code::NotFound);
+    }
+}
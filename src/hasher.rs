@@ -0,0 +1,458 @@
+use std::{collections::hash_map::DefaultHasher, fmt::Debug, hash::Hasher};
+
+/// The hash function a trie commits with.
+///
+/// A node holding data `d` with children `l` and `r` hashes to `combine(hash(d), l, r)`, a
+/// childless node to `hash(d)`, and a missing child or missing data contributes `hash(b"")`.
+/// Tries with a wider arity combine all of a node's children through `combine_children`.
+pub trait MerkleHasher {
+    type Hash: Clone + PartialEq + Eq + Debug + AsRef<[u8]>;
+
+    fn hash(&self, bytes: &[u8]) -> Self::Hash;
+
+    /// Rebuilds a hash from its `as_ref()` bytes, as written by checkpoints. `None` if the bytes
+    /// can't be a hash of this type.
+    fn hash_from_bytes(bytes: &[u8]) -> Option<Self::Hash>;
+
+    /// Hashes many values at once; the trie hands every stale value hash to this in batches
+    /// before combining. Override it where hashing several inputs together is faster, e.g.
+    /// multi-buffer SIMD or a thread pool.
+    fn hash_batch(&self, inputs: &[&[u8]]) -> Vec<Self::Hash> {
+        inputs.iter().map(|input| self.hash(input)).collect()
+    }
+
+    fn combine(&self, data: &Self::Hash, left: &Self::Hash, right: &Self::Hash) -> Self::Hash {
+        let data = data.as_ref();
+        let left = left.as_ref();
+        let right = right.as_ref();
+        let mut concatenated = Vec::with_capacity(data.len() + left.len() + right.len());
+        concatenated.extend_from_slice(data);
+        concatenated.extend_from_slice(left);
+        concatenated.extend_from_slice(right);
+        self.hash(&concatenated)
+    }
+
+    fn combine_children(&self, data: &Self::Hash, children: &[Self::Hash]) -> Self::Hash {
+        if let [left, right] = children {
+            return self.combine(data, left, right);
+        }
+        let mut concatenated = data.as_ref().to_vec();
+        for child in children {
+            concatenated.extend_from_slice(child.as_ref());
+        }
+        self.hash(&concatenated)
+    }
+
+    fn empty_hash(&self) -> Self::Hash {
+        self.hash(b"")
+    }
+
+    /// The most bytes a hash's `as_ref()` can take, for sizing proofs. By default the length of
+    /// the empty hash, which is every hash's length for fixed-size digests.
+    fn max_hash_len(&self) -> usize {
+        self.empty_hash().as_ref().len()
+    }
+
+    /// Heap memory owned by a hash value, for memory accounting. Fixed-size digests own none.
+    fn heap_bytes(_hash: &Self::Hash) -> usize {
+        0
+    }
+
+    fn hash_to_string(hash: &Self::Hash) -> String {
+        crate::hex::encode(hash.as_ref())
+    }
+
+    fn hash_from_string(string: &str) -> Option<Self::Hash> {
+        Self::hash_from_bytes(&crate::hex::decode(string)?)
+    }
+}
+
+/// Compares two hashes in time that depends only on their lengths, so that a check guarding an
+/// authentication decision doesn't leak how much of a forged hash was right. Every verifier in
+/// the crate compares roots this way.
+pub fn hashes_equal(a: &[u8], b: &[u8]) -> bool {
+    crate::embedded::constant_time_eq(a, b)
+}
+
+/// The crate's original scheme: std's `DefaultHasher` over the bytes (terminated the way
+/// `str::hash` terminates them), rendered as a decimal string. Concatenating children therefore
+/// concatenates their decimal renderings.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StdMerkleHasher;
+
+impl MerkleHasher for StdMerkleHasher {
+    type Hash = String;
+
+    fn hash(&self, bytes: &[u8]) -> String {
+        let mut hashing = DefaultHasher::new();
+        hashing.write(bytes);
+        hashing.write_u8(0xff);
+        hashing.finish().to_string()
+    }
+
+    fn hash_from_bytes(bytes: &[u8]) -> Option<String> {
+        let decimal = std::str::from_utf8(bytes).ok()?;
+        let canonical = decimal.parse::<u64>().ok()?.to_string();
+        (canonical == decimal).then_some(canonical)
+    }
+
+    // The decimal digits of `u64::MAX`.
+    fn max_hash_len(&self) -> usize {
+        20
+    }
+
+    fn heap_bytes(hash: &String) -> usize {
+        hash.capacity()
+    }
+
+    fn hash_to_string(hash: &String) -> String {
+        hash.clone()
+    }
+
+    fn hash_from_string(string: &str) -> Option<String> {
+        Self::hash_from_bytes(string.as_bytes())
+    }
+}
+
+/// Adapts any RustCrypto `Digest` (SHA-2, SHA-3, RIPEMD, ...) without this crate depending on
+/// the concrete hash crates.
+#[cfg(feature = "digest")]
+pub struct DigestHasher<D>(std::marker::PhantomData<D>);
+
+#[cfg(feature = "digest")]
+impl<D> DigestHasher<D> {
+    pub fn new() -> Self {
+        DigestHasher(std::marker::PhantomData)
+    }
+}
+
+#[cfg(feature = "digest")]
+impl<D> Default for DigestHasher<D> {
+    fn default() -> Self {
+        DigestHasher::new()
+    }
+}
+
+#[cfg(feature = "digest")]
+impl<D> Clone for DigestHasher<D> {
+    fn clone(&self) -> Self {
+        DigestHasher::new()
+    }
+}
+
+#[cfg(feature = "digest")]
+impl<D> Debug for DigestHasher<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DigestHasher")
+    }
+}
+
+#[cfg(feature = "digest")]
+impl<D> PartialEq for DigestHasher<D> {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+#[cfg(feature = "digest")]
+impl<D: digest::Digest> MerkleHasher for DigestHasher<D> {
+    type Hash = digest::Output<D>;
+
+    fn hash(&self, bytes: &[u8]) -> Self::Hash {
+        D::digest(bytes)
+    }
+
+    fn hash_from_bytes(bytes: &[u8]) -> Option<Self::Hash> {
+        (bytes.len() == <D as digest::Digest>::output_size())
+            .then(|| digest::Output::<D>::clone_from_slice(bytes))
+    }
+
+    fn combine(&self, data: &Self::Hash, left: &Self::Hash, right: &Self::Hash) -> Self::Hash {
+        let mut hashing = D::new();
+        hashing.update(data);
+        hashing.update(left);
+        hashing.update(right);
+        hashing.finalize()
+    }
+}
+
+/// Blake3 over the raw bytes. With `hash_large_values_in_parallel` set, values of at least
+/// `parallel_threshold` bytes are hashed with blake3's internal multi-threading, which pays off
+/// for multi-megabyte leaves; smaller inputs stay on the calling thread.
+#[cfg(feature = "blake3")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Blake3Hasher {
+    pub hash_large_values_in_parallel: bool,
+    pub parallel_threshold: usize,
+}
+
+#[cfg(feature = "blake3")]
+impl Default for Blake3Hasher {
+    fn default() -> Self {
+        Blake3Hasher {
+            hash_large_values_in_parallel: false,
+            parallel_threshold: 128 * 1024,
+        }
+    }
+}
+
+#[cfg(feature = "blake3")]
+impl Blake3Hasher {
+    pub fn hash_large_values_in_parallel(mut self, enabled: bool) -> Self {
+        self.hash_large_values_in_parallel = enabled;
+        self
+    }
+}
+
+#[cfg(feature = "blake3")]
+impl MerkleHasher for Blake3Hasher {
+    type Hash = [u8; 32];
+
+    fn hash(&self, bytes: &[u8]) -> [u8; 32] {
+        let mut hashing = blake3::Hasher::new();
+        if self.hash_large_values_in_parallel && bytes.len() >= self.parallel_threshold {
+            hashing.update_rayon(bytes);
+        } else {
+            hashing.update(bytes);
+        }
+        *hashing.finalize().as_bytes()
+    }
+
+    fn hash_from_bytes(bytes: &[u8]) -> Option<[u8; 32]> {
+        bytes.try_into().ok()
+    }
+
+    /// Spreads batches over rayon's thread pool when the `rayon` feature is on.
+    #[cfg(feature = "rayon")]
+    fn hash_batch(&self, inputs: &[&[u8]]) -> Vec<[u8; 32]> {
+        use rayon::prelude::*;
+
+        inputs.par_iter().map(|input| self.hash(input)).collect()
+    }
+
+    fn combine(&self, data: &[u8; 32], left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        let mut hashing = blake3::Hasher::new();
+        hashing.update(data);
+        hashing.update(left);
+        hashing.update(right);
+        *hashing.finalize().as_bytes()
+    }
+}
+
+/// Mixes a secret key into every value hash, HMAC-style over `H` with a 64-byte block, so that a
+/// published root or proof doesn't let anyone without the key test guesses of low-entropy
+/// values. Interior combines stay unkeyed. Proofs verify only with a hasher built from the same
+/// key.
+#[derive(Clone)]
+pub struct KeyedHasher<H> {
+    inner: H,
+    inner_pad: Vec<u8>,
+    outer_pad: Vec<u8>,
+}
+
+impl<H: MerkleHasher> KeyedHasher<H> {
+    const BLOCK_BYTES: usize = 64;
+
+    pub fn new(inner: H, key: &[u8]) -> Self {
+        let mut block = if key.len() > Self::BLOCK_BYTES {
+            inner.hash(key).as_ref().to_vec()
+        } else {
+            key.to_vec()
+        };
+        block.resize(Self::BLOCK_BYTES, 0);
+        KeyedHasher {
+            inner_pad: block.iter().map(|byte| byte ^ 0x36).collect(),
+            outer_pad: block.iter().map(|byte| byte ^ 0x5c).collect(),
+            inner,
+        }
+    }
+}
+
+impl<H> Debug for KeyedHasher<H> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("KeyedHasher { .. }")
+    }
+}
+
+impl<H: MerkleHasher> MerkleHasher for KeyedHasher<H> {
+    type Hash = H::Hash;
+
+    fn hash(&self, bytes: &[u8]) -> H::Hash {
+        let mut keyed = self.inner_pad.clone();
+        keyed.extend_from_slice(bytes);
+        let mut outer = self.outer_pad.clone();
+        outer.extend_from_slice(self.inner.hash(&keyed).as_ref());
+        self.inner.hash(&outer)
+    }
+
+    fn hash_batch(&self, inputs: &[&[u8]]) -> Vec<H::Hash> {
+        let pad = |pad: &[u8], bytes: &[u8]| [pad, bytes].concat();
+        let keyed: Vec<Vec<u8>> = inputs
+            .iter()
+            .map(|input| pad(&self.inner_pad, input))
+            .collect();
+        let keyed: Vec<&[u8]> = keyed.iter().map(Vec::as_slice).collect();
+        let outer: Vec<Vec<u8>> = self
+            .inner
+            .hash_batch(&keyed)
+            .iter()
+            .map(|hash| pad(&self.outer_pad, hash.as_ref()))
+            .collect();
+        let outer: Vec<&[u8]> = outer.iter().map(Vec::as_slice).collect();
+        self.inner.hash_batch(&outer)
+    }
+
+    fn hash_from_bytes(bytes: &[u8]) -> Option<H::Hash> {
+        H::hash_from_bytes(bytes)
+    }
+
+    fn combine(&self, data: &H::Hash, left: &H::Hash, right: &H::Hash) -> H::Hash {
+        self.inner.combine(data, left, right)
+    }
+
+    fn combine_children(&self, data: &H::Hash, children: &[H::Hash]) -> H::Hash {
+        self.inner.combine_children(data, children)
+    }
+
+    fn empty_hash(&self) -> H::Hash {
+        self.inner.empty_hash()
+    }
+
+    fn max_hash_len(&self) -> usize {
+        self.inner.max_hash_len()
+    }
+
+    fn heap_bytes(hash: &H::Hash) -> usize {
+        H::heap_bytes(hash)
+    }
+
+    fn hash_to_string(hash: &H::Hash) -> String {
+        H::hash_to_string(hash)
+    }
+
+    fn hash_from_string(string: &str) -> Option<H::Hash> {
+        H::hash_from_string(string)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn std_hasher_matches_str_hashing() {
+        use std::hash::Hash;
+
+        let mut hashing = DefaultHasher::new();
+        "foo".hash(&mut hashing);
+        assert_eq!(StdMerkleHasher.hash(b"foo"), hashing.finish().to_string());
+        let combined =
+            StdMerkleHasher.combine(&"1".to_string(), &"2".to_string(), &"3".to_string());
+        assert_eq!(combined, StdMerkleHasher.hash(b"123"));
+    }
+
+    #[test]
+    fn hashes_equal_compares_whole_hashes() {
+        assert!(hashes_equal(b"abc", b"abc"));
+        assert!(!hashes_equal(b"abc", b"abd"));
+        assert!(!hashes_equal(b"abc", b"ab"));
+    }
+
+    #[test]
+    fn keyed_proofs_need_the_key() {
+        use crate::trie_node::trie_node::TrieNode;
+
+        let keyed = KeyedHasher::new(StdMerkleHasher, b"secret");
+        let mut node: TrieNode<u32, KeyedHasher<StdMerkleHasher>> =
+            TrieNode::with_hasher(keyed.clone());
+        let mut plain: TrieNode<u32> = TrieNode::new();
+        for key in 0..20 {
+            node.insert(key, key % 2);
+            plain.insert(key, key % 2);
+        }
+        let root = node.merkle_root();
+        assert_ne!(root, plain.merkle_root());
+
+        let proof = node.generate_proof(7).unwrap();
+        assert!(proof.verify(&keyed, &root, &1u32));
+        assert!(!proof.verify(&KeyedHasher::new(StdMerkleHasher, b"guess"), &root, &1u32));
+        assert!(!format!("{keyed:?}").contains("secret"));
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn sha256_trie_root() {
+        use crate::trie_node::trie_node::TrieNode;
+
+        let mut node: TrieNode<String, DigestHasher<sha2::Sha256>> = TrieNode::new();
+        node.insert(1, "foo".to_string());
+        node.insert(2, "bar".to_string());
+        let root = node.merkle_root();
+        assert_eq!(root.len(), 32);
+
+        let hasher = DigestHasher::<sha2::Sha256>::new();
+        let empty = hasher.empty_hash();
+        let left = hasher.combine(&empty, &empty, &hasher.hash(b"bar"));
+        let expected = hasher.combine(&empty, &left, &hasher.hash(b"foo"));
+        assert_eq!(root, expected);
+        assert_eq!(
+            DigestHasher::<sha2::Sha256>::hash_to_string(&empty),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[cfg(feature = "blake3")]
+    #[test]
+    fn blake3_parallel_hashing_matches_serial() {
+        let large_value = vec![7u8; 1024 * 1024];
+        let serial = Blake3Hasher::default();
+        let parallel = Blake3Hasher::default().hash_large_values_in_parallel(true);
+        assert_eq!(serial.hash(&large_value), parallel.hash(&large_value));
+        assert_eq!(
+            Blake3Hasher::hash_to_string(&serial.empty_hash()),
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+        );
+    }
+
+    #[test]
+    fn cold_roots_hash_values_in_batches() {
+        use crate::trie_node::trie_node::TrieNode;
+        use std::cell::RefCell;
+
+        #[derive(Default)]
+        struct Batches(RefCell<Vec<usize>>);
+
+        impl MerkleHasher for Batches {
+            type Hash = String;
+
+            fn hash(&self, bytes: &[u8]) -> String {
+                StdMerkleHasher.hash(bytes)
+            }
+
+            fn hash_from_bytes(bytes: &[u8]) -> Option<String> {
+                StdMerkleHasher::hash_from_bytes(bytes)
+            }
+
+            fn hash_batch(&self, inputs: &[&[u8]]) -> Vec<String> {
+                self.0.borrow_mut().push(inputs.len());
+                inputs.iter().map(|input| self.hash(input)).collect()
+            }
+        }
+
+        let mut node: TrieNode<u32, Batches> = (0..600).map(|key| (key, key)).collect();
+        let mut expected: TrieNode<u32> = (0..600).map(|key| (key, key)).collect();
+        assert_eq!(node.merkle_root(), expected.merkle_root());
+        assert_eq!(*node.hasher().0.borrow(), [256, 256, 88]);
+
+        node.insert(7, 8);
+        node.merkle_root();
+        assert_eq!(node.hasher().0.borrow().last(), Some(&1));
+
+        let keyed = KeyedHasher::new(StdMerkleHasher, b"key");
+        assert_eq!(
+            keyed.hash_batch(&[b"a", b"bc"]),
+            [keyed.hash(b"a"), keyed.hash(b"bc")]
+        );
+    }
+}
@@ -0,0 +1,33 @@
+pub fn encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+pub fn decode(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+pub fn decode_array<const LEN: usize>(hex: &str) -> Option<[u8; LEN]> {
+    decode(hex)?.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        assert_eq!(encode(&[0, 0xab, 0x1f]), "00ab1f");
+        assert_eq!(decode("00AB1f"), Some(vec![0, 0xab, 0x1f]));
+        assert_eq!(decode("abc"), None);
+        assert_eq!(decode("zz"), None);
+        assert_eq!(decode_array::<2>("0102"), Some([1, 2]));
+        assert_eq!(decode_array::<2>("01"), None);
+    }
+}
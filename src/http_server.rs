@@ -0,0 +1,274 @@
+use std::{
+    io,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::trie_node::trie_node::TrieNode;
+use crate::{
+    hasher::{MerkleHasher, StdMerkleHasher},
+    proof::{MerkleProof, ProofLevel},
+};
+
+pub type SharedTrie<H = StdMerkleHasher, const N: usize = 2> = Arc<Mutex<TrieNode<String, H, N>>>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofLevelBody {
+    pub data_hash: String,
+    pub siblings: Vec<String>,
+    /// Absent in format 1 bodies.
+    #[serde(default)]
+    pub position: u8,
+}
+
+/// A `MerkleProof` with every hash rendered by `MerkleHasher::hash_to_string`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofBody {
+    /// Absent in bodies from before proofs were versioned, which are all format 1.
+    #[serde(default = "first_proof_version")]
+    pub version: u8,
+    pub key: u32,
+    pub arity: usize,
+    pub children_roots: Vec<String>,
+    pub levels: Vec<ProofLevelBody>,
+}
+
+fn first_proof_version() -> u8 {
+    1
+}
+
+impl ProofBody {
+    pub fn from_proof<H: MerkleHasher>(proof: &MerkleProof<H::Hash>) -> Self {
+        let strings = |hashes: &[H::Hash]| hashes.iter().map(H::hash_to_string).collect();
+        ProofBody {
+            version: proof.version,
+            key: proof.key,
+            arity: proof.arity,
+            children_roots: strings(&proof.children_roots),
+            levels: proof
+                .levels
+                .iter()
+                .map(|level| ProofLevelBody {
+                    data_hash: H::hash_to_string(&level.data_hash),
+                    siblings: strings(&level.siblings),
+                    position: level.position,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn to_proof<H: MerkleHasher>(&self) -> Option<MerkleProof<H::Hash>> {
+        let hashes = |strings: &[String]| {
+            strings
+                .iter()
+                .map(|string| H::hash_from_string(string))
+                .collect::<Option<Vec<_>>>()
+        };
+        let proof = MerkleProof {
+            version: self.version,
+            key: self.key,
+            arity: self.arity,
+            children_roots: hashes(&self.children_roots)?,
+            levels: self
+                .levels
+                .iter()
+                .map(|level| {
+                    Some(ProofLevel {
+                        data_hash: H::hash_from_string(&level.data_hash)?,
+                        siblings: hashes(&level.siblings)?,
+                        position: level.position,
+                    })
+                })
+                .collect::<Option<Vec<_>>>()?,
+        };
+        Some(if self.version < 2 {
+            proof.with_key_positions()
+        } else {
+            proof
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RootResponse {
+    pub root: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofResponse {
+    pub key: u32,
+    pub value: String,
+    pub root: String,
+    pub proof: ProofBody,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InsertRequest {
+    pub key: u32,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifyRequest {
+    pub root: String,
+    pub value: String,
+    pub proof: ProofBody,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifyResponse {
+    pub valid: bool,
+}
+
+/// Routes for a verifiable key-value service over `trie`:
+///
+/// - `GET /root` returns the current root,
+/// - `GET /proof/{key}` returns the value under `key` with its proof, or 404,
+/// - `POST /insert` takes `{"key", "value"}` and returns the new root,
+/// - `POST /verify` takes `{"root", "value", "proof"}` and checks the proof without touching
+///   the trie.
+pub fn router<H, const N: usize>(trie: SharedTrie<H, N>) -> Router
+where
+    H: MerkleHasher + Send + 'static,
+    H::Hash: Send,
+{
+    Router::new()
+        .route("/root", get(root::<H, N>))
+        .route("/proof/{key}", get(proof::<H, N>))
+        .route("/insert", post(insert::<H, N>))
+        .route("/verify", post(verify::<H, N>))
+        .with_state(trie)
+}
+
+/// Serves `router(trie)` on `listener` until the server fails.
+pub async fn serve<H, const N: usize>(
+    listener: tokio::net::TcpListener,
+    trie: SharedTrie<H, N>,
+) -> io::Result<()>
+where
+    H: MerkleHasher + Send + 'static,
+    H::Hash: Send,
+{
+    axum::serve(listener, router(trie)).await
+}
+
+async fn root<H: MerkleHasher, const N: usize>(
+    State(trie): State<SharedTrie<H, N>>,
+) -> Json<RootResponse> {
+    let root = trie.lock().unwrap().merkle_root();
+    Json(RootResponse {
+        root: H::hash_to_string(&root),
+    })
+}
+
+async fn proof<H: MerkleHasher, const N: usize>(
+    State(trie): State<SharedTrie<H, N>>,
+    Path(key): Path<u32>,
+) -> Result<Json<ProofResponse>, StatusCode> {
+    let mut trie = trie.lock().unwrap();
+    let proof = trie.generate_proof(key).ok_or(StatusCode::NOT_FOUND)?;
+    let value = trie.find_by_key(key).unwrap().get_data().unwrap().clone();
+    Ok(Json(ProofResponse {
+        key,
+        value,
+        root: H::hash_to_string(&trie.merkle_root()),
+        proof: ProofBody::from_proof::<H>(&proof),
+    }))
+}
+
+async fn insert<H: MerkleHasher, const N: usize>(
+    State(trie): State<SharedTrie<H, N>>,
+    Json(request): Json<InsertRequest>,
+) -> Json<RootResponse> {
+    let mut trie = trie.lock().unwrap();
+    trie.insert(request.key, request.value);
+    Json(RootResponse {
+        root: H::hash_to_string(&trie.merkle_root()),
+    })
+}
+
+async fn verify<H: MerkleHasher, const N: usize>(
+    State(trie): State<SharedTrie<H, N>>,
+    Json(request): Json<VerifyRequest>,
+) -> Result<Json<VerifyResponse>, StatusCode> {
+    let root = H::hash_from_string(&request.root).ok_or(StatusCode::BAD_REQUEST)?;
+    let proof = request
+        .proof
+        .to_proof::<H>()
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    let trie = trie.lock().unwrap();
+    Ok(Json(VerifyResponse {
+        valid: proof.verify(trie.hasher(), &root, &request.value),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    async fn request(
+        address: std::net::SocketAddr,
+        method: &str,
+        path: &str,
+        body: &str,
+    ) -> String {
+        let mut stream = tokio::net::TcpStream::connect(address).await.unwrap();
+        let request = format!(
+            "{method} {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\
+             Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    fn body(response: &str) -> &str {
+        response.split_once("\r\n\r\n").unwrap().1
+    }
+
+    #[tokio::test]
+    async fn insert_prove_and_verify_over_http() {
+        let trie: SharedTrie = Arc::new(Mutex::new(TrieNode::new()));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, trie.clone()));
+
+        for (key, value) in [(1, "foo"), (2, "bar")] {
+            let insert = format!(r#"{{"key":{key},"value":"{value}"}}"#);
+            request(address, "POST", "/insert", &insert).await;
+        }
+        let root: RootResponse =
+            serde_json::from_str(body(&request(address, "GET", "/root", "").await)).unwrap();
+        assert_eq!(root.root, "13830055607334163982");
+
+        let response = request(address, "GET", "/proof/2", "").await;
+        let proof: ProofResponse = serde_json::from_str(body(&response)).unwrap();
+        assert_eq!(proof.value, "bar");
+        assert!(request(address, "GET", "/proof/3", "")
+            .await
+            .starts_with("HTTP/1.1 404"));
+
+        for (value, valid) in [("bar", true), ("forged", false)] {
+            let verify = serde_json::to_string(&VerifyRequest {
+                root: root.root.clone(),
+                value: value.to_string(),
+                proof: proof.proof.clone(),
+            })
+            .unwrap();
+            let response = request(address, "POST", "/verify", &verify).await;
+            let verdict: VerifyResponse = serde_json::from_str(body(&response)).unwrap();
+            assert_eq!(verdict.valid, valid);
+        }
+    }
+}
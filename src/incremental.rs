@@ -0,0 +1,243 @@
+use std::collections::BTreeMap;
+
+use crate::empty_hashes::EmptyHashes;
+use crate::error::TrieError;
+use crate::hasher::{hashes_equal, MerkleHasher};
+
+// The authentication path of a witnessed leaf, filled in as it becomes known: left siblings
+// when the leaf is appended, right siblings as later appends complete them.
+#[derive(Debug, Clone)]
+struct Tracked<D> {
+    siblings: Vec<Option<D>>,
+}
+
+/// An append-only binary Merkle tree of fixed depth over leaf commitments, as Zcash keeps its
+/// note commitments. Only the frontier is stored: for each level, the completed left node still
+/// waiting for its right sibling, so appending and computing the root take O(depth) hashes
+/// whatever the number of leaves. Unfilled leaves are `MerkleHasher::empty_hash`, and a node
+/// hashes like a value-less trie node with two children, so empty subtrees match
+/// `EmptyHashes` for arity 2.
+///
+/// Leaves appended with `append_witnessed` also keep their authentication path up to date
+/// through later appends, so a witness for them can be produced at any point.
+#[derive(Debug, Clone)]
+pub struct IncrementalMerkleTree<H: MerkleHasher> {
+    hasher: H,
+    depth: u32,
+    empty: EmptyHashes<H::Hash>,
+    // `frontier[level]` is set when the number of leaves has bit `level` set; the last entry is
+    // the root once the tree is full.
+    frontier: Vec<Option<H::Hash>>,
+    len: u64,
+    witnessed: BTreeMap<u64, Tracked<H::Hash>>,
+}
+
+impl<H: MerkleHasher> IncrementalMerkleTree<H> {
+    pub const MAX_DEPTH: u32 = 63;
+
+    /// An empty tree with room for `2^depth` leaves.
+    pub fn new(hasher: H, depth: u32) -> Self {
+        assert!(depth <= Self::MAX_DEPTH, "depth must be at most 63");
+        IncrementalMerkleTree {
+            empty: EmptyHashes::new(&hasher, 2, depth as usize),
+            hasher,
+            depth,
+            frontier: vec![None; depth as usize + 1],
+            len: 0,
+            witnessed: BTreeMap::new(),
+        }
+    }
+
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The completed left nodes on the path to the next leaf, by level, and then the root if
+    /// the tree is full.
+    pub fn frontier(&self) -> &[Option<H::Hash>] {
+        &self.frontier
+    }
+
+    fn node(&self, left: &H::Hash, right: &H::Hash) -> H::Hash {
+        self.hasher
+            .combine_children(self.empty.empty(), &[left.clone(), right.clone()])
+    }
+
+    /// Appends `leaf` and returns its position.
+    pub fn append(&mut self, leaf: H::Hash) -> Result<u64, TrieError> {
+        if self.len >> self.depth != 0 {
+            return Err(TrieError::TreeFull { depth: self.depth });
+        }
+        let position = self.len;
+        let mut node = leaf;
+        for level in 0..=self.depth as usize {
+            self.complete(level, position >> level, &node);
+            match self.frontier[level].take() {
+                Some(left) => node = self.node(&left, &node),
+                None => {
+                    self.frontier[level] = Some(node);
+                    break;
+                }
+            }
+        }
+        self.len += 1;
+        Ok(position)
+    }
+
+    /// Appends `leaf` and tracks its authentication path from here on; see `witness`.
+    pub fn append_witnessed(&mut self, leaf: H::Hash) -> Result<u64, TrieError> {
+        // The leaf's left siblings are exactly the frontier nodes it is about to combine with.
+        let siblings = (0..self.depth as usize)
+            .map(|level| {
+                (self.len >> level & 1 == 1)
+                    .then(|| self.frontier[level].clone())
+                    .flatten()
+            })
+            .collect();
+        let position = self.append(leaf)?;
+        self.witnessed.insert(position, Tracked { siblings });
+        Ok(position)
+    }
+
+    // The node at `index` on `level` has just been completed; it is the right sibling some
+    // witnessed leaves were waiting for.
+    fn complete(&mut self, level: usize, index: u64, node: &H::Hash) {
+        if index & 1 == 0 {
+            return;
+        }
+        let first = (index - 1) << level;
+        for (_, tracked) in self.witnessed.range_mut(first..first + (1 << level)) {
+            tracked.siblings[level] = Some(node.clone());
+        }
+    }
+
+    /// Stops tracking the leaf at `position`.
+    pub fn forget(&mut self, position: u64) -> bool {
+        self.witnessed.remove(&position).is_some()
+    }
+
+    pub fn root(&self) -> H::Hash {
+        match &self.frontier[self.depth as usize] {
+            Some(root) => root.clone(),
+            None => self.partial_root(self.depth as usize),
+        }
+    }
+
+    // The root of the subtree on `level` holding the next leaf, as far as it is filled.
+    fn partial_root(&self, level: usize) -> H::Hash {
+        let mut node = self.empty.empty().clone();
+        for below in 0..level {
+            node = match &self.frontier[below] {
+                Some(left) if self.len >> below & 1 == 1 => self.node(left, &node),
+                _ => self.node(&node, self.empty.level(below).unwrap()),
+            };
+        }
+        node
+    }
+
+    /// The authentication path of the leaf at `position` against the current root, if it was
+    /// appended with `append_witnessed` and not forgotten since.
+    pub fn witness(&self, position: u64) -> Option<MerklePath<H::Hash>> {
+        let tracked = self.witnessed.get(&position)?;
+        let siblings = (0..self.depth as usize)
+            .map(|level| match &tracked.siblings[level] {
+                Some(sibling) => sibling.clone(),
+                // A right sibling still being filled is the one holding the next leaf.
+                None if self.len > ((position >> level) + 1) << level => self.partial_root(level),
+                None => self.empty.level(level).unwrap().clone(),
+            })
+            .collect();
+        Some(MerklePath { position, siblings })
+    }
+}
+
+/// A leaf's position and its siblings from the leaf level up, as produced by
+/// `IncrementalMerkleTree::witness`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerklePath<D> {
+    pub position: u64,
+    pub siblings: Vec<D>,
+}
+
+impl<D: Clone + AsRef<[u8]>> MerklePath<D> {
+    pub fn root_for<H: MerkleHasher<Hash = D>>(&self, hasher: &H, leaf: &D) -> D {
+        let empty = hasher.empty_hash();
+        self.siblings
+            .iter()
+            .enumerate()
+            .fold(leaf.clone(), |node, (level, sibling)| {
+                let children = if self.position >> level & 1 == 0 {
+                    [node, sibling.clone()]
+                } else {
+                    [sibling.clone(), node]
+                };
+                hasher.combine_children(&empty, &children)
+            })
+    }
+
+    pub fn verify<H: MerkleHasher<Hash = D>>(&self, hasher: &H, leaf: &D, root: &D) -> bool {
+        hashes_equal(self.root_for(hasher, leaf).as_ref(), root.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    // The root of a tree of `depth` holding `leaves`, computed level by level.
+    fn full_root(depth: u32, leaves: &[String]) -> String {
+        let hasher = StdMerkleHasher;
+        let empty = hasher.empty_hash();
+        let mut level: Vec<String> = leaves.to_vec();
+        level.resize(1 << depth, empty.clone());
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| hasher.combine_children(&empty, pair))
+                .collect();
+        }
+        level.pop().unwrap()
+    }
+
+    #[test]
+    fn frontier_roots_and_witnesses_match_the_full_tree() {
+        let hasher = StdMerkleHasher;
+        let mut tree = IncrementalMerkleTree::new(hasher, 4);
+        assert_eq!(tree.root(), full_root(4, &[]));
+        let leaves: Vec<String> = (0..16u32)
+            .map(|leaf| hasher.hash(&leaf.to_be_bytes()))
+            .collect();
+        for (position, leaf) in leaves.iter().enumerate() {
+            let appended = if position % 3 == 0 {
+                tree.append_witnessed(leaf.clone())
+            } else {
+                tree.append(leaf.clone())
+            };
+            assert_eq!(appended, Ok(position as u64));
+            let root = tree.root();
+            assert_eq!(root, full_root(4, &leaves[..=position]));
+            for witnessed in (0..=position).step_by(3) {
+                let path = tree.witness(witnessed as u64).unwrap();
+                assert!(path.verify(&hasher, &leaves[witnessed], &root));
+                assert!(!path.verify(&hasher, &leaves[(witnessed + 1) % 16], &root));
+            }
+        }
+        assert_eq!(tree.witness(1), None);
+        assert!(tree.forget(3));
+        assert_eq!(tree.witness(3), None);
+        assert_eq!(
+            tree.append(leaves[0].clone()),
+            Err(TrieError::TreeFull { depth: 4 })
+        );
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::HashSet;
+use std::io::{self, Read};
+
+use serde_json::Value;
+
+use crate::checkpoint::invalid;
+use crate::codec::ValueCodec;
+use crate::hasher::MerkleHasher;
+use crate::merkle_data::MerkleData;
+use crate::multi_value::MultiValue;
+use crate::trie_node::trie_node::TrieNode;
+
+/// The column holding each record's key, a `u32` in decimal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyColumn(pub String);
+
+/// The columns making up each record's value, in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValueColumns(pub Vec<String>);
+
+impl ValueColumns {
+    /// The canonical encoding of a value made of `fields`, one per column: a lone column's text
+    /// as it is, or the fields as a `MultiValue` commits to them.
+    pub fn encode(&self, fields: &[&str]) -> Vec<u8> {
+        match fields {
+            [field] => field.as_bytes().to_vec(),
+            fields => MultiValue(fields.to_vec()).merkle_bytes().into_owned(),
+        }
+    }
+}
+
+impl<T: MerkleData + PartialEq, H: MerkleHasher + Default, const N: usize> TrieNode<T, H, N> {
+    /// Loads a table: JSON lines if its first non-blank line starts with `{`, CSV with a header
+    /// row otherwise. Each record's key is read from `key` and its value from `values`, which
+    /// `ValueColumns::encode` turns into bytes for `codec` to decode. Fields are taken as text:
+    /// CSV fields unquoted, JSON strings unescaped and other JSON values as serde_json writes
+    /// them, so the same table in either format loads to the same trie. The records are inserted
+    /// as one batch; a missing column, a repeated key or a value `codec` rejects fails the load,
+    /// naming the record, numbered from 1 without the CSV header.
+    pub fn from_reader<R: Read, C: ValueCodec<T>>(
+        mut reader: R,
+        key: KeyColumn,
+        values: ValueColumns,
+        codec: &C,
+    ) -> io::Result<Self> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        let mut columns = vec![key.0.as_str()];
+        columns.extend(values.0.iter().map(String::as_str));
+        let is_json = text
+            .lines()
+            .find(|line| !line.trim().is_empty())
+            .is_some_and(|line| line.trim_start().starts_with('{'));
+        let records = if is_json {
+            json_records(&text, &columns)?
+        } else {
+            csv_records(&text, &columns)?
+        };
+
+        let mut keys = HashSet::new();
+        let mut entries = Vec::with_capacity(records.len());
+        for (index, fields) in records.iter().enumerate() {
+            let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+            let key: u32 = fields[0]
+                .parse()
+                .map_err(|_| invalid_at(index, "key is not a u32"))?;
+            if !keys.insert(key) {
+                return Err(invalid_at(index, "key repeated"));
+            }
+            let value = codec
+                .decode(&values.encode(&fields[1..]))
+                .ok_or_else(|| invalid_at(index, "value can't be decoded"))?;
+            entries.push((key, value));
+        }
+        let mut trie = TrieNode::with_hasher(H::default());
+        trie.insert_batch(entries);
+        Ok(trie)
+    }
+}
+
+fn invalid_at(index: usize, reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("record {}: {reason}", index + 1),
+    )
+}
+
+// Each record's fields under `columns`, in order, from JSON objects one per line.
+fn json_records(text: &str, columns: &[&str]) -> io::Result<Vec<Vec<String>>> {
+    let lines = text.lines().filter(|line| !line.trim().is_empty());
+    lines
+        .enumerate()
+        .map(|(index, line)| {
+            let Ok(Value::Object(object)) = serde_json::from_str(line) else {
+                return Err(invalid_at(index, "not a JSON object"));
+            };
+            columns
+                .iter()
+                .map(|column| match object.get(*column) {
+                    None | Some(Value::Null) => Err(invalid_at(index, "column missing")),
+                    Some(Value::String(text)) => Ok(text.clone()),
+                    Some(value) => Ok(value.to_string()),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Each record's fields under `columns`, in order, from CSV whose first record names the columns.
+fn csv_records(text: &str, columns: &[&str]) -> io::Result<Vec<Vec<String>>> {
+    let mut records = csv_split(text)?.into_iter();
+    let header = records.next().ok_or_else(|| invalid("no header row"))?;
+    let positions = columns
+        .iter()
+        .map(|column| header.iter().position(|name| name == column))
+        .collect::<Option<Vec<usize>>>()
+        .ok_or_else(|| invalid("column missing from the header"))?;
+    records
+        .enumerate()
+        .map(|(index, record)| {
+            if record.len() != header.len() {
+                return Err(invalid_at(index, "wrong number of fields"));
+            }
+            Ok(positions.iter().map(|&at| record[at].clone()).collect())
+        })
+        .collect()
+}
+
+// RFC 4180 records: fields separated by commas and records by LF or CRLF, where a field in
+// double quotes may hold commas, line breaks and doubled quotes. Blank lines are skipped.
+fn csv_split(text: &str) -> io::Result<Vec<Vec<String>>> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let (mut in_quotes, mut was_quoted) = (false, false);
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                c => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() && !was_quoted => (in_quotes, was_quoted) = (true, true),
+            ',' => {
+                record.push(std::mem::take(&mut field));
+                was_quoted = false;
+            }
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                end_record(&mut records, &mut record, &mut field, was_quoted);
+                was_quoted = false;
+            }
+            _ if was_quoted => return Err(invalid("text after a closing quote")),
+            '"' => return Err(invalid("quote inside an unquoted field")),
+            c => field.push(c),
+        }
+    }
+    if in_quotes {
+        return Err(invalid("quoted field not closed"));
+    }
+    end_record(&mut records, &mut record, &mut field, was_quoted);
+    Ok(records)
+}
+
+fn end_record(
+    records: &mut Vec<Vec<String>>,
+    record: &mut Vec<String>,
+    field: &mut String,
+    was_quoted: bool,
+) {
+    if record.is_empty() && field.is_empty() && !was_quoted {
+        return;
+    }
+    record.push(std::mem::take(field));
+    records.push(std::mem::take(record));
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::codec::{BytesCodec, DisplayCodec};
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn csv_and_json_lines_load_the_same_trie() {
+        let csv = "id,name,note\r\n7,alice,\"says \"\"hi\"\", twice\"\n\n3,bob,\"two\nlines\"\n";
+        let json = "{\"id\": 7, \"name\": \"alice\", \"note\": \"says \\\"hi\\\", twice\"}\n\
+                    \n{\"name\": \"bob\", \"id\": \"3\", \"note\": \"two\\nlines\"}\n";
+        let load = |text: &str, values: &[&str]| {
+            TrieNode::<String, StdMerkleHasher, 4>::from_reader(
+                text.as_bytes(),
+                KeyColumn("id".to_string()),
+                ValueColumns(values.iter().map(|value| value.to_string()).collect()),
+                &DisplayCodec,
+            )
+        };
+        let mut from_csv = load(csv, &["note"]).unwrap();
+        assert_eq!(from_csv.get(7).unwrap(), "says \"hi\", twice");
+        assert_eq!(from_csv.get(3).unwrap(), "two\nlines");
+        let root = from_csv.merkle_root();
+        assert_eq!(load(json, &["note"]).unwrap().merkle_root(), root);
+
+        // Several columns commit as a `MultiValue` would.
+        let mut expected: TrieNode<MultiValue<&str>, StdMerkleHasher, 4> = TrieNode::new();
+        expected.insert(7, MultiValue(vec!["alice", "says \"hi\", twice"]));
+        expected.insert(3, MultiValue(vec!["bob", "two\nlines"]));
+        let root = expected.merkle_root();
+        let load_bytes = |text: &str| {
+            let values = ValueColumns(vec!["name".to_string(), "note".to_string()]);
+            let mut trie = TrieNode::<Vec<u8>, StdMerkleHasher, 4>::from_reader(
+                text.as_bytes(),
+                KeyColumn("id".to_string()),
+                values.clone(),
+                &BytesCodec,
+            )
+            .unwrap();
+            assert_eq!(trie.get(3), Some(&values.encode(&["bob", "two\nlines"])));
+            trie.merkle_root()
+        };
+        assert_eq!(load_bytes(csv), root);
+        assert_eq!(load_bytes(json), root);
+
+        let error = |text: &str| load(text, &["note"]).unwrap_err().to_string();
+        assert_eq!(error("id,note\n1,a\n1,b\n"), "record 2: key repeated");
+        assert_eq!(error("id,note\n1,a\nx,b\n"), "record 2: key is not a u32");
+        assert_eq!(
+            error("id,note\n1,a,b\n"),
+            "record 1: wrong number of fields"
+        );
+        assert_eq!(error("id,name\n1,a\n"), "column missing from the header");
+        assert_eq!(error("id,note\n1,\"a\n"), "quoted field not closed");
+        assert_eq!(
+            error("{\"id\": 1, \"note\": null}"),
+            "record 1: column missing"
+        );
+        assert_eq!(
+            error("{\"id\": 1, \"note\": 2}\n[1]"),
+            "record 2: not a JSON object"
+        );
+        assert!(load("", &["note"]).is_err());
+    }
+}
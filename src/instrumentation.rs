@@ -0,0 +1,219 @@
+// Hooks called from the trie's hot paths. Without the `metrics` and `tracing` features they
+// compile to nothing.
+
+#[cfg(feature = "tracing")]
+thread_local! {
+    // Nodes rehashed and served from cache on this thread, so a span can report the work done
+    // by the call it wraps.
+    static COUNTS: std::cell::Cell<(u64, u64)> = const { std::cell::Cell::new((0, 0)) };
+}
+
+pub(crate) fn record_insert() {
+    #[cfg(feature = "metrics")]
+    metrics::counter!("merkle_trie_inserts_total").increment(1);
+}
+
+pub(crate) fn record_cache_hit() {
+    #[cfg(feature = "metrics")]
+    metrics::counter!("merkle_trie_cache_hits_total").increment(1);
+    #[cfg(feature = "tracing")]
+    COUNTS.with(|counts| {
+        let (rehashed, cache_hits) = counts.get();
+        counts.set((rehashed, cache_hits + 1));
+    });
+}
+
+pub(crate) fn record_rehash() {
+    #[cfg(feature = "metrics")]
+    metrics::counter!("merkle_trie_nodes_rehashed_total").increment(1);
+    #[cfg(feature = "tracing")]
+    COUNTS.with(|counts| {
+        let (rehashed, cache_hits) = counts.get();
+        counts.set((rehashed + 1, cache_hits));
+    });
+}
+
+pub(crate) fn record_invalidations(_invalidated: usize) {
+    #[cfg(feature = "metrics")]
+    metrics::histogram!("merkle_trie_hashes_invalidated_per_mutation").record(_invalidated as f64);
+}
+
+// Arena slots, including detached ones awaiting reuse.
+pub(crate) fn record_node_count(_node_count: usize) {
+    #[cfg(feature = "metrics")]
+    metrics::gauge!("merkle_trie_nodes").set(_node_count as f64);
+}
+
+#[cfg(feature = "tracing")]
+fn traced<R>(span: tracing::Span, operation: impl FnOnce() -> R) -> R {
+    let _entered = span.enter();
+    let (rehashed_before, cache_hits_before) = COUNTS.with(|counts| counts.get());
+    let result = operation();
+    let (rehashed, cache_hits) = COUNTS.with(|counts| counts.get());
+    tracing::debug!(
+        rehashed = rehashed - rehashed_before,
+        cache_hits = cache_hits - cache_hits_before,
+        "done"
+    );
+    result
+}
+
+pub(crate) fn observe_root_recomputation<R>(recompute: impl FnOnce() -> R) -> R {
+    #[cfg(feature = "metrics")]
+    let started = std::time::Instant::now();
+    #[cfg(feature = "tracing")]
+    let root = traced(tracing::debug_span!("merkle_root"), recompute);
+    #[cfg(not(feature = "tracing"))]
+    let root = recompute();
+    #[cfg(feature = "metrics")]
+    metrics::histogram!("merkle_trie_root_recomputation_seconds")
+        .record(started.elapsed().as_secs_f64());
+    root
+}
+
+pub(crate) fn observe_batch_insert<R>(_len: usize, insert: impl FnOnce() -> R) -> R {
+    #[cfg(feature = "tracing")]
+    return traced(tracing::debug_span!("insert_batch", len = _len), insert);
+    #[cfg(not(feature = "tracing"))]
+    insert()
+}
+
+pub(crate) fn observe_proof_generation<R>(_key: u32, generate: impl FnOnce() -> R) -> R {
+    #[cfg(feature = "tracing")]
+    return traced(tracing::debug_span!("generate_proof", key = _key), generate);
+    #[cfg(not(feature = "tracing"))]
+    generate()
+}
+
+#[cfg(all(test, any(feature = "metrics", feature = "tracing")))]
+mod tests {
+
+    use crate::trie_node::trie_node::TrieNode;
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn trie_operations_emit_metrics() {
+        use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        metrics::with_local_recorder(&recorder, || {
+            let mut node: TrieNode<String> = TrieNode::new();
+            node.insert(1, "foo".to_string());
+            node.insert(2, "bar".to_string());
+            node.merkle_root();
+            node.insert(2, "baz".to_string());
+            node.merkle_root();
+            node.merkle_root();
+        });
+
+        let metrics: Vec<(String, DebugValue)> = snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| (key.key().name().to_string(), value))
+            .collect();
+        let value_of = |name: &str| {
+            metrics
+                .iter()
+                .find(|(metric, _)| metric == name)
+                .map(|(_, value)| value)
+                .unwrap()
+        };
+        assert_eq!(
+            value_of("merkle_trie_inserts_total"),
+            &DebugValue::Counter(3)
+        );
+        // The second recomputation reuses the root of the untouched key 1.
+        assert_eq!(
+            value_of("merkle_trie_nodes_rehashed_total"),
+            &DebugValue::Counter(7)
+        );
+        assert_eq!(
+            value_of("merkle_trie_cache_hits_total"),
+            &DebugValue::Counter(2)
+        );
+        assert_eq!(
+            value_of("merkle_trie_nodes"),
+            &DebugValue::Gauge(4.0.into())
+        );
+        let DebugValue::Histogram(timings) = value_of("merkle_trie_root_recomputation_seconds")
+        else {
+            panic!("expected a histogram");
+        };
+        assert_eq!(timings.len(), 2);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn spans_report_rehashed_and_cached_nodes() {
+        use std::sync::{Arc, Mutex};
+        use tracing::{field::Field, span, Event, Id, Metadata, Subscriber};
+
+        #[derive(Default)]
+        struct Counts {
+            rehashed: u64,
+            cache_hits: u64,
+        }
+
+        impl tracing::field::Visit for Counts {
+            fn record_u64(&mut self, field: &Field, value: u64) {
+                match field.name() {
+                    "rehashed" => self.rehashed = value,
+                    "cache_hits" => self.cache_hits = value,
+                    _ => {}
+                }
+            }
+
+            fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+        }
+
+        #[derive(Default, Clone)]
+        struct Recorder {
+            spans: Arc<Mutex<Vec<&'static str>>>,
+            events: Arc<Mutex<Vec<(u64, u64)>>>,
+        }
+
+        impl Subscriber for Recorder {
+            fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, span: &span::Attributes<'_>) -> Id {
+                let mut spans = self.spans.lock().unwrap();
+                spans.push(span.metadata().name());
+                Id::from_u64(spans.len() as u64)
+            }
+
+            fn record(&self, _span: &Id, _values: &span::Record<'_>) {}
+
+            fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+            fn event(&self, event: &Event<'_>) {
+                let mut counts = Counts::default();
+                event.record(&mut counts);
+                let counts = (counts.rehashed, counts.cache_hits);
+                self.events.lock().unwrap().push(counts);
+            }
+
+            fn enter(&self, _span: &Id) {}
+
+            fn exit(&self, _span: &Id) {}
+        }
+
+        let recorder = Recorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            let mut node: TrieNode<String> = TrieNode::new();
+            node.insert_batch([(1, "foo".to_string()), (2, "bar".to_string())]);
+            node.merkle_root();
+            node.insert(2, "baz".to_string());
+            node.generate_proof(1).unwrap();
+        });
+        assert_eq!(
+            *recorder.spans.lock().unwrap(),
+            ["insert_batch", "merkle_root", "generate_proof"]
+        );
+        // The proof for key 1 only needs its sibling subtree, which holds the changed key 2.
+        assert_eq!(*recorder.events.lock().unwrap(), [(0, 0), (4, 0), (2, 0)]);
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Mutex;
+
+use crate::hasher::MerkleHasher;
+
+/// Wraps `H` to remember the hash of every value of at least `min_len` bytes, so a value
+/// stored under many keys is hashed once however often it is inserted. Node combinations go
+/// straight to `H`, since they are rarely repeated. Shorter values aren't remembered either:
+/// looking them up costs about as much as hashing them.
+///
+/// Hashes are looked up by the value's bytes, never by a digest of them, so a collision can't
+/// give a value someone else's hash. That costs one copy of each distinct remembered value;
+/// `clear` frees them.
+pub struct InterningHasher<H: MerkleHasher> {
+    inner: H,
+    min_len: usize,
+    interned: Mutex<HashMap<Box<[u8]>, H::Hash>>,
+}
+
+impl<H: MerkleHasher> InterningHasher<H> {
+    pub fn new(inner: H, min_len: usize) -> Self {
+        InterningHasher {
+            inner,
+            min_len,
+            interned: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn inner(&self) -> &H {
+        &self.inner
+    }
+
+    /// The number of distinct values whose hashes are remembered.
+    pub fn interned(&self) -> usize {
+        self.interned.lock().unwrap().len()
+    }
+
+    pub fn clear(&self) {
+        self.interned.lock().unwrap().clear();
+    }
+}
+
+impl<H: MerkleHasher + Clone> Clone for InterningHasher<H> {
+    fn clone(&self) -> Self {
+        InterningHasher {
+            inner: self.inner.clone(),
+            min_len: self.min_len,
+            interned: Mutex::new(self.interned.lock().unwrap().clone()),
+        }
+    }
+}
+
+impl<H: MerkleHasher + Debug> Debug for InterningHasher<H> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InterningHasher")
+            .field("inner", &self.inner)
+            .field("min_len", &self.min_len)
+            .field("interned", &self.interned())
+            .finish()
+    }
+}
+
+impl<H: MerkleHasher> MerkleHasher for InterningHasher<H> {
+    type Hash = H::Hash;
+
+    fn hash(&self, bytes: &[u8]) -> H::Hash {
+        if bytes.len() < self.min_len {
+            return self.inner.hash(bytes);
+        }
+        if let Some(hash) = self.interned.lock().unwrap().get(bytes) {
+            return hash.clone();
+        }
+        let hash = self.inner.hash(bytes);
+        self.interned
+            .lock()
+            .unwrap()
+            .insert(bytes.into(), hash.clone());
+        hash
+    }
+
+    fn hash_batch(&self, inputs: &[&[u8]]) -> Vec<H::Hash> {
+        let mut hashes: Vec<Option<H::Hash>> = {
+            let interned = self.interned.lock().unwrap();
+            inputs
+                .iter()
+                .map(|input| interned.get(*input).cloned())
+                .collect()
+        };
+        // Each distinct missing value is hashed once, even if it repeats within the batch.
+        let mut missing: Vec<&[u8]> = inputs
+            .iter()
+            .zip(&hashes)
+            .filter(|(_, hash)| hash.is_none())
+            .map(|(input, _)| *input)
+            .collect();
+        missing.sort_unstable();
+        missing.dedup();
+        let computed = self.inner.hash_batch(&missing);
+        let mut interned = self.interned.lock().unwrap();
+        for (input, hash) in missing.iter().zip(&computed) {
+            if input.len() >= self.min_len {
+                interned.insert((*input).into(), hash.clone());
+            }
+        }
+        for (input, hash) in inputs.iter().zip(&mut hashes) {
+            if hash.is_none() {
+                let position = missing.binary_search(input).unwrap();
+                *hash = Some(computed[position].clone());
+            }
+        }
+        hashes.into_iter().map(Option::unwrap).collect()
+    }
+
+    fn hash_from_bytes(bytes: &[u8]) -> Option<H::Hash> {
+        H::hash_from_bytes(bytes)
+    }
+
+    fn combine(&self, data: &H::Hash, left: &H::Hash, right: &H::Hash) -> H::Hash {
+        self.inner.combine(data, left, right)
+    }
+
+    fn combine_children(&self, data: &H::Hash, children: &[H::Hash]) -> H::Hash {
+        self.inner.combine_children(data, children)
+    }
+
+    fn empty_hash(&self) -> H::Hash {
+        self.inner.empty_hash()
+    }
+
+    fn max_hash_len(&self) -> usize {
+        self.inner.max_hash_len()
+    }
+
+    fn heap_bytes(hash: &H::Hash) -> usize {
+        H::heap_bytes(hash)
+    }
+
+    fn hash_to_string(hash: &H::Hash) -> String {
+        H::hash_to_string(hash)
+    }
+
+    fn hash_from_string(string: &str) -> Option<H::Hash> {
+        H::hash_from_string(string)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+    use crate::trie_node::trie_node::TrieNode;
+
+    #[test]
+    fn repeated_values_are_hashed_once() {
+        // Counts the values of 1000 bytes or more it hashes.
+        #[derive(Default)]
+        struct CountLarge(AtomicUsize);
+
+        impl MerkleHasher for CountLarge {
+            type Hash = String;
+
+            fn hash(&self, bytes: &[u8]) -> String {
+                self.0
+                    .fetch_add((bytes.len() >= 1000) as usize, Ordering::Relaxed);
+                StdMerkleHasher.hash(bytes)
+            }
+
+            fn hash_from_bytes(bytes: &[u8]) -> Option<String> {
+                StdMerkleHasher::hash_from_bytes(bytes)
+            }
+        }
+
+        let large = |n: u32| format!("{n}").repeat(1000);
+        let entries = || (0..500).map(|key| (key * 3, large(key % 2)));
+        let hasher = InterningHasher::new(CountLarge::default(), 100);
+        let mut trie: TrieNode<String, _> = TrieNode::with_hasher(hasher);
+        trie.insert_batch(entries());
+        let mut plain: TrieNode<String> = entries().collect();
+        assert_eq!(trie.merkle_root(), plain.merkle_root());
+        trie.insert(1, large(1));
+        plain.insert(1, large(1));
+        assert_eq!(trie.merkle_root(), plain.merkle_root());
+        assert_eq!(trie.hasher().inner().0.load(Ordering::Relaxed), 2);
+        assert_eq!(trie.hasher().interned(), 2);
+
+        trie.hasher().clear();
+        trie.insert(1, large(0));
+        trie.merkle_root();
+        assert_eq!(trie.hasher().inner().0.load(Ordering::Relaxed), 3);
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::bloom::BloomFilter;
+use crate::proof::{MerkleProof, ProofLevel, PROOF_FORMAT_VERSION};
+use crate::trie_node::trie_node::{Node, NodeIndex, TrieNode, ROOT};
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+// Nodes still to visit, with their key and depth.
+pub(crate) type Stack = Vec<(NodeIndex, u32, u32)>;
+
+pub(crate) fn push_children<T, D, const N: usize>(
+    stack: &mut Stack,
+    node: &Node<T, D, N>,
+    key: u32,
+    depth: u32,
+) {
+    let bits_per_digit = N.trailing_zeros();
+    for (digit, child) in node.children().iter().enumerate().rev() {
+        if let Some(child) = child {
+            stack.push((
+                *child,
+                key | (digit as u32) << (depth * bits_per_digit),
+                depth + 1,
+            ));
+        }
+    }
+}
+
+/// Borrowing iterator over `(key, value)` pairs, depth first with digits in ascending order,
+/// so a key comes before every key below it.
+pub struct Iter<'a, T: MerkleData, H: MerkleHasher, const N: usize> {
+    trie: &'a TrieNode<T, H, N>,
+    stack: Stack,
+}
+
+impl<'a, T: MerkleData, H: MerkleHasher, const N: usize> Iterator for Iter<'a, T, H, N> {
+    type Item = (u32, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (index, key, depth) = self.stack.pop()?;
+            let node = self.trie.node(index);
+            push_children(&mut self.stack, node, key, depth);
+            if let Some(data) = node.get_data() {
+                return Some((key, data));
+            }
+        }
+    }
+}
+
+/// Consuming iterator over `(key, value)` pairs, in the same order as `Iter`.
+pub struct IntoIter<T: MerkleData, H: MerkleHasher, const N: usize> {
+    trie: TrieNode<T, H, N>,
+    stack: Stack,
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> Iterator for IntoIter<T, H, N> {
+    type Item = (u32, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (index, key, depth) = self.stack.pop()?;
+            let node = self.trie.node_mut(index);
+            let data = node.take_data();
+            push_children(&mut self.stack, self.trie.node(index), key, depth);
+            if let Some(data) = data {
+                return Some((key, data));
+            }
+        }
+    }
+}
+
+/// Iterator over `(key, value, proof)` triples, in the same order as `Iter`. Each node's data
+/// hash and children's roots are read once, when the walk enters it, and kept while the walk is
+/// below it, so a proof is assembled from them without walking down from the root again.
+pub struct ProvenIter<'a, T: MerkleData, H: MerkleHasher, const N: usize> {
+    trie: &'a TrieNode<T, H, N>,
+    stack: Stack,
+    // The internal nodes above the next node to visit, root first: each one's data hash and the
+    // roots of all its children.
+    ancestors: Vec<(H::Hash, Vec<H::Hash>)>,
+}
+
+impl<'a, T: MerkleData, H: MerkleHasher, const N: usize> Iterator for ProvenIter<'a, T, H, N> {
+    type Item = (u32, &'a T, MerkleProof<H::Hash>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (index, key, depth) = self.stack.pop()?;
+            self.ancestors.truncate(depth as usize);
+            let trie = self.trie;
+            let node = trie.node(index);
+            push_children(&mut self.stack, node, key, depth);
+            let children_roots = if node.is_leaf() {
+                vec![]
+            } else {
+                trie.shared_child_roots(index, None)
+            };
+            let proof = node.get_data().map(|data| {
+                let levels = self
+                    .ancestors
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .map(|(ancestor_depth, (data_hash, roots))| {
+                        let digit = TrieNode::<T, H, N>::digit_at(key, ancestor_depth as u32);
+                        let mut siblings = roots.clone();
+                        siblings.remove(digit);
+                        ProofLevel {
+                            data_hash: data_hash.clone(),
+                            siblings,
+                            position: digit as u8,
+                        }
+                    })
+                    .collect();
+                let proof = MerkleProof {
+                    version: PROOF_FORMAT_VERSION,
+                    key,
+                    arity: N,
+                    children_roots: children_roots.clone(),
+                    levels,
+                };
+                (data, proof)
+            });
+            if !node.is_leaf() {
+                let data_hash = trie.uncached_data_hash_at(index);
+                self.ancestors.push((data_hash, children_roots));
+            }
+            if let Some((data, proof)) = proof {
+                return Some((key, data, proof));
+            }
+        }
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    pub fn iter(&self) -> Iter<'_, T, H, N> {
+        Iter {
+            trie: self,
+            stack: vec![(ROOT, 0, 0)],
+        }
+    }
+
+    /// Folds every entry into an accumulator in `iter`'s order, walking the trie with an explicit
+    /// stack, so sums, counts and the like need neither recursion nor a collected `Vec`.
+    pub fn fold<A, F>(&self, init: A, mut f: F) -> A
+    where
+        F: FnMut(A, u32, &T) -> A,
+    {
+        self.iter().fold(init, |acc, (key, data)| f(acc, key, data))
+    }
+
+    /// `iter` with a proof for each entry, as `generate_proof` would give. Hashes are read from
+    /// the caches where they are kept and recomputed where they aren't; the root is brought up
+    /// to date first so that, under the default cache policy, all of them are.
+    pub fn iter_with_proofs(&mut self) -> ProvenIter<'_, T, H, N> {
+        self.merkle_root();
+        ProvenIter {
+            trie: self,
+            stack: vec![(ROOT, 0, 0)],
+            ancestors: vec![],
+        }
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher + Clone, const N: usize> TrieNode<T, H, N> {
+    /// Empties the trie, handing its entries out in the order of `iter`.
+    pub fn drain(&mut self) -> IntoIter<T, H, N> {
+        let mut emptied = TrieNode::with_hasher(self.hasher.clone());
+        emptied.eager_hashing = self.eager_hashing;
+        emptied.cache_policy = self.cache_policy;
+        emptied.bloom = self.bloom.as_ref().map(BloomFilter::emptied);
+        emptied.empty_hashes = self.empty_hashes.clone();
+        emptied.rehash_if_eager();
+        std::mem::replace(self, emptied).into_iter()
+    }
+}
+
+impl<'a, T: MerkleData, H: MerkleHasher, const N: usize> IntoIterator for &'a TrieNode<T, H, N> {
+    type Item = (u32, &'a T);
+    type IntoIter = Iter<'a, T, H, N>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> IntoIterator for TrieNode<T, H, N> {
+    type Item = (u32, T);
+    type IntoIter = IntoIter<T, H, N>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            trie: self,
+            stack: vec![(ROOT, 0, 0)],
+        }
+    }
+}
+
+impl<T: MerkleData + PartialEq, H: MerkleHasher, const N: usize> Extend<(u32, T)>
+    for TrieNode<T, H, N>
+{
+    fn extend<I: IntoIterator<Item = (u32, T)>>(&mut self, entries: I) {
+        self.insert_batch(entries);
+    }
+}
+
+impl<T: MerkleData + PartialEq, H: MerkleHasher + Default, const N: usize> FromIterator<(u32, T)>
+    for TrieNode<T, H, N>
+{
+    fn from_iter<I: IntoIterator<Item = (u32, T)>>(entries: I) -> Self {
+        let mut trie = TrieNode::new();
+        trie.insert_batch(entries);
+        trie
+    }
+}
+
+impl<T: MerkleData + PartialEq, H: MerkleHasher + Default, const N: usize> From<HashMap<u32, T>>
+    for TrieNode<T, H, N>
+{
+    fn from(map: HashMap<u32, T>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+impl<T: MerkleData + PartialEq, H: MerkleHasher + Default, const N: usize> From<BTreeMap<u32, T>>
+    for TrieNode<T, H, N>
+{
+    fn from(map: BTreeMap<u32, T>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> From<TrieNode<T, H, N>> for HashMap<u32, T> {
+    fn from(trie: TrieNode<T, H, N>) -> Self {
+        trie.into_iter().collect()
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> From<TrieNode<T, H, N>> for BTreeMap<u32, T> {
+    fn from(trie: TrieNode<T, H, N>) -> Self {
+        trie.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn collect_extend_and_iterate() {
+        let mut node: TrieNode<u32, crate::hasher::StdMerkleHasher, 4> =
+            (0..10).map(|key| (key * 5, key)).collect();
+        node.extend([(7, 70), (5, 10)]);
+        let mut expected: Vec<(u32, u32)> = (0..10).map(|key| (key * 5, key)).collect();
+        expected[1].1 = 10;
+        expected.push((7, 70));
+        expected.sort_unstable();
+
+        let mut borrowed: Vec<(u32, u32)> = node.iter().map(|(key, value)| (key, *value)).collect();
+        borrowed.sort_unstable();
+        assert_eq!(borrowed, expected);
+        assert_eq!((&node).into_iter().next(), Some((0, &0)));
+
+        let (count, sum, max) = node.fold((0, 0, 0), |(count, sum, max), key, value| {
+            (count + 1, sum + value, max.max(key))
+        });
+        assert_eq!((count, sum, max), (11, 124, 45));
+
+        let mut drained: Vec<(u32, u32)> = node.clone().drain().collect();
+        drained.sort_unstable();
+        assert_eq!(drained, expected);
+        let mut owned: Vec<(u32, u32)> = node.into_iter().collect();
+        owned.sort_unstable();
+        assert_eq!(owned, expected);
+
+        node = [(1, 1)].into_iter().collect();
+        assert_eq!(node.drain().collect::<Vec<_>>(), [(1, 1)]);
+        assert!(node.is_empty());
+    }
+
+    #[test]
+    fn proofs_from_the_walk_match_generated_ones() {
+        let mut node: TrieNode<u32, crate::hasher::StdMerkleHasher, 4> =
+            (0..300).map(|key| (key * 7, key)).collect();
+        node.insert(0, 1);
+        let root = node.merkle_root();
+        let proven: Vec<_> = node
+            .iter_with_proofs()
+            .map(|(key, value, proof)| (key, *value, proof))
+            .collect();
+        assert_eq!(proven.len(), 300);
+        for (key, value, proof) in proven {
+            assert!(proof.verify(&crate::hasher::StdMerkleHasher, &root, &value));
+            assert_eq!(node.generate_proof(key), Some(proof));
+        }
+    }
+
+    #[test]
+    fn convert_to_and_from_maps() {
+        let map: HashMap<u32, u32> = (0..50).map(|key| (key * 7, key)).collect();
+        let btree: BTreeMap<u32, u32> = map.clone().into_iter().collect();
+        let mut from_map: TrieNode<u32> = map.clone().into();
+        let mut from_btree = TrieNode::<u32>::from(btree.clone());
+        assert_eq!(from_map.merkle_root(), from_btree.merkle_root());
+
+        assert_eq!(HashMap::from(from_map), map);
+        assert_eq!(BTreeMap::from(from_btree), btree);
+    }
+}
@@ -0,0 +1,122 @@
+use std::borrow::Cow;
+
+use crate::hasher::{hashes_equal, MerkleHasher};
+use crate::merkle_data::MerkleData;
+use crate::proof::MerkleProof;
+use crate::trie_node::trie_node::TrieNode;
+use crate::verifier;
+
+/// A value bound to its key: it commits to the key, big-endian, followed by the value's
+/// bytes, so the same value hashes differently under every key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyBound<T> {
+    pub key: u32,
+    pub value: T,
+}
+
+impl<T: MerkleData> MerkleData for KeyBound<T> {
+    fn merkle_bytes(&self) -> Cow<'_, [u8]> {
+        let mut bytes = self.key.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&self.value.merkle_bytes());
+        Cow::Owned(bytes)
+    }
+}
+
+/// A trie that binds every value to its key, as `KeyBound` does, so a proof shows which key
+/// its value is under to a verifier that only folds the levels by position, without
+/// recomputing the path from the key. Roots differ from those of a plain trie with the same
+/// entries; see the commitment spec.
+pub struct KeyBoundTrie<T: MerkleData, H: MerkleHasher, const N: usize = 2> {
+    trie: TrieNode<KeyBound<T>, H, N>,
+}
+
+impl<T: MerkleData + PartialEq, H: MerkleHasher + Default, const N: usize> Default
+    for KeyBoundTrie<T, H, N>
+{
+    fn default() -> Self {
+        KeyBoundTrie::with_hasher(H::default())
+    }
+}
+
+impl<T: MerkleData + PartialEq, H: MerkleHasher, const N: usize> KeyBoundTrie<T, H, N> {
+    pub fn with_hasher(hasher: H) -> Self {
+        KeyBoundTrie {
+            trie: TrieNode::with_hasher(hasher),
+        }
+    }
+
+    pub fn trie(&self) -> &TrieNode<KeyBound<T>, H, N> {
+        &self.trie
+    }
+
+    pub fn insert(&mut self, key: u32, value: T) {
+        self.trie.insert(key, KeyBound { key, value });
+    }
+
+    pub fn get(&self, key: u32) -> Option<&T> {
+        Some(&self.trie.get(key)?.value)
+    }
+
+    pub fn merkle_root(&mut self) -> H::Hash {
+        self.trie.merkle_root()
+    }
+
+    pub fn generate_proof(&mut self, key: u32) -> Option<MerkleProof<H::Hash>> {
+        self.trie.generate_proof(key)
+    }
+}
+
+impl<D: Clone + AsRef<[u8]>> MerkleProof<D> {
+    /// Checks a proof from a `KeyBoundTrie` that `value` is under the proof's key, folding the
+    /// levels by their positions alone: the key is checked through the value's hash instead.
+    pub fn verify_key_bound<H, V>(&self, hasher: &H, root: &D, value: &V) -> bool
+    where
+        H: MerkleHasher<Hash = D>,
+        V: MerkleData + ?Sized,
+    {
+        let bound = KeyBound {
+            key: self.key,
+            value,
+        };
+        verifier::root_by_position(hasher, self, &bound.merkle_bytes())
+            .is_some_and(|computed| hashes_equal(computed.as_ref(), root.as_ref()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn proofs_bind_values_to_keys() {
+        let mut trie: KeyBoundTrie<u32, StdMerkleHasher, 4> = KeyBoundTrie::default();
+        for key in 0..100 {
+            trie.insert(key * 3, key);
+        }
+        assert_eq!(trie.get(30), Some(&10));
+        let root = trie.merkle_root();
+        let mut plain: TrieNode<u32, StdMerkleHasher, 4> =
+            (0..100).map(|key| (key * 3, key)).collect();
+        assert_ne!(root, plain.merkle_root());
+
+        let proof = trie.generate_proof(30).unwrap();
+        assert!(proof.verify_key_bound(&StdMerkleHasher, &root, &10u32));
+        assert!(proof.verify(
+            &StdMerkleHasher,
+            &root,
+            &KeyBound {
+                key: 30,
+                value: 10u32
+            }
+        ));
+        assert!(!proof.verify_key_bound(&StdMerkleHasher, &root, &11u32));
+
+        // A key on the same path by position is caught by the value's hash.
+        let mut moved = proof.clone();
+        moved.key = 30 + (1 << 20);
+        assert!(verifier::root_by_position(&StdMerkleHasher, &moved, b"").is_some());
+        assert!(!moved.verify_key_bound(&StdMerkleHasher, &root, &10u32));
+    }
+}
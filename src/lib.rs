@@ -1 +1,113 @@
+// Lets `#[derive(MerkleData)]`, which names this crate by path, be used inside it too.
+#[cfg(feature = "derive")]
+extern crate self as binary_tree_blockchain;
+
+pub mod anchor;
+pub mod append_log;
+pub mod arc_trie;
+pub mod async_store;
+pub mod audit;
+pub mod background;
+pub mod bit_path;
+#[cfg(feature = "bitcoin")]
+pub mod block_merkle;
+pub mod bloom;
+pub mod budget;
+pub mod builder;
+pub mod cache_budget;
+pub mod cache_policy;
+pub mod cached_store;
+pub mod checkpoint;
+pub mod codec;
+pub mod combiner;
+pub mod commitment_spec;
+pub mod compact;
+#[cfg(feature = "compression")]
+pub mod compressed_store;
+pub mod cross_root;
+pub mod cursor;
+pub mod delta_sync;
+pub mod diff;
+pub mod dir_hash;
+pub mod dual_root;
+pub mod embedded;
+pub mod empty_hashes;
+#[cfg(feature = "encryption")]
+pub mod encrypted_store;
+pub mod error;
+pub mod expiry;
+pub mod fallible;
+pub mod fixed_depth;
+pub mod forest;
+#[cfg(any(feature = "arbitrary", feature = "proptest"))]
+pub mod fuzzing;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod hasher;
+pub mod hex;
+#[cfg(feature = "http-server")]
+pub mod http_server;
+pub mod incremental;
+#[cfg(feature = "ingest")]
+pub mod ingest;
+mod instrumentation;
+pub mod intern;
+pub mod iter;
+pub mod key_bound;
+pub mod limits;
+pub mod mapped;
+pub mod merge;
+pub mod merkle_data;
+pub mod multi_value;
+#[cfg(feature = "multihash")]
+pub mod multihash;
+pub mod multiproof;
+pub mod namespace;
+pub mod nested;
+pub mod observer;
+pub mod ordered;
+pub mod overlay;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+pub mod persistent;
+#[cfg(feature = "poseidon")]
+pub mod poseidon;
+pub mod prehashed;
+pub mod proof;
+pub mod proof_size;
+pub mod reference;
+pub mod replication;
+#[cfg(feature = "rlp")]
+pub mod rlp;
+pub mod root_history;
+pub mod sampling;
+pub mod secure;
+pub mod set_commitment;
+pub mod sharded;
+pub mod shared;
+pub mod snapshot;
+pub mod split;
+pub mod state_sync;
+pub mod stats;
+pub mod str_trie;
+pub mod streamed;
+pub mod streaming;
+pub mod subtree_proof;
+pub mod sum_tree;
+pub mod swap;
+pub mod test_vectors;
+#[cfg(feature = "tokio")]
+pub mod tokio_trie;
+pub mod transform;
+pub mod transparency;
 pub mod trie_node;
+pub mod untrusted;
+pub mod validate;
+pub mod vector_commitment;
+pub mod verifiable_kv;
+pub mod verifier;
+pub mod verify_cost;
+pub mod visit;
+pub mod visualize;
+pub mod wal;
+pub mod warm;
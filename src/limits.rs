@@ -0,0 +1,165 @@
+use crate::error::TrieError;
+use crate::proof::MerkleProof;
+use crate::trie_node::trie_node::{TrieNode, ROOT};
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// Bounds on what a `LimitedTrie` accepts. The default bounds nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrieLimits {
+    /// Nodes reachable from the root, the root included.
+    pub max_nodes: usize,
+    /// In digits; key 0 is at depth 0.
+    pub max_depth: u32,
+    /// Length of a value's `merkle_bytes`.
+    pub max_value_bytes: usize,
+}
+
+impl Default for TrieLimits {
+    fn default() -> Self {
+        TrieLimits {
+            max_nodes: usize::MAX,
+            max_depth: u32::MAX,
+            max_value_bytes: usize::MAX,
+        }
+    }
+}
+
+/// A trie for untrusted input: inserts that would break its `TrieLimits` are refused with an
+/// error and leave the trie unchanged.
+pub struct LimitedTrie<T: MerkleData, H: MerkleHasher, const N: usize = 2> {
+    trie: TrieNode<T, H, N>,
+    limits: TrieLimits,
+    node_count: usize,
+}
+
+impl<T: MerkleData + PartialEq, H: MerkleHasher, const N: usize> LimitedTrie<T, H, N> {
+    pub fn with_hasher(hasher: H, limits: TrieLimits) -> Self {
+        Self::from_trie(TrieNode::with_hasher(hasher), limits)
+    }
+
+    // `trie` must be empty.
+    pub(crate) fn from_trie(trie: TrieNode<T, H, N>, limits: TrieLimits) -> Self {
+        LimitedTrie {
+            trie,
+            limits,
+            node_count: 1,
+        }
+    }
+
+    pub fn limits(&self) -> &TrieLimits {
+        &self.limits
+    }
+
+    pub fn trie(&self) -> &TrieNode<T, H, N> {
+        &self.trie
+    }
+
+    pub fn into_inner(self) -> TrieNode<T, H, N> {
+        self.trie
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.node_count
+    }
+
+    pub fn insert(&mut self, key: u32, data: T) -> Result<(), TrieError> {
+        let depth = TrieNode::<T, H, N>::key_depth(key);
+        if depth > self.limits.max_depth {
+            return Err(TrieError::DepthExceeded {
+                key,
+                depth,
+                max_depth: self.limits.max_depth,
+            });
+        }
+        let size = data.merkle_bytes().len();
+        if size > self.limits.max_value_bytes {
+            return Err(TrieError::ValueTooLarge {
+                key,
+                size,
+                limit: self.limits.max_value_bytes,
+            });
+        }
+        let mut index = ROOT;
+        let mut existing = 0;
+        while existing < depth {
+            let digit = TrieNode::<T, H, N>::digit_at(key, existing);
+            match self.trie.node(index).child(digit) {
+                Some(child) => index = child,
+                None => break,
+            }
+            existing += 1;
+        }
+        let added = (depth - existing) as usize;
+        if self.node_count.saturating_add(added) > self.limits.max_nodes {
+            return Err(TrieError::TooManyNodes {
+                key,
+                limit: self.limits.max_nodes,
+            });
+        }
+        self.trie.insert(key, data);
+        self.node_count += added;
+        Ok(())
+    }
+
+    /// Inserts entries in order until one is refused; the ones before it stay inserted.
+    pub fn insert_batch<I: IntoIterator<Item = (u32, T)>>(
+        &mut self,
+        entries: I,
+    ) -> Result<(), TrieError> {
+        entries
+            .into_iter()
+            .try_for_each(|(key, data)| self.insert(key, data))
+    }
+
+    pub fn merkle_root(&mut self) -> H::Hash {
+        self.trie.merkle_root()
+    }
+
+    pub fn generate_proof(&mut self, key: u32) -> Option<MerkleProof<H::Hash>> {
+        self.trie.generate_proof(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn limits_refuse_oversized_input() {
+        let limits = TrieLimits {
+            max_nodes: 7,
+            max_depth: 4,
+            max_value_bytes: 8,
+        };
+        let mut trie: LimitedTrie<String, StdMerkleHasher> =
+            LimitedTrie::with_hasher(StdMerkleHasher, limits);
+        assert_eq!(
+            trie.insert(16, "deep".to_string()),
+            Err(TrieError::DepthExceeded {
+                key: 16,
+                depth: 5,
+                max_depth: 4
+            })
+        );
+        assert_eq!(
+            trie.insert(1, "too long!".to_string()),
+            Err(TrieError::ValueTooLarge {
+                key: 1,
+                size: 9,
+                limit: 8
+            })
+        );
+        trie.insert(15, "a".to_string()).unwrap();
+        trie.insert(7, "b".to_string()).unwrap();
+        trie.insert(2, "c".to_string()).unwrap();
+        assert_eq!(trie.node_count(), 7);
+        assert_eq!(
+            trie.insert(4, "d".to_string()),
+            Err(TrieError::TooManyNodes { key: 4, limit: 7 })
+        );
+        trie.insert(3, "e".to_string()).unwrap();
+        assert_eq!(trie.node_count(), trie.trie().metrics().node_count);
+    }
+}
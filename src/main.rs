@@ -1,5 +1,318 @@
-mod trie_node;
+use std::{collections::HashMap, fs, process::ExitCode};
 
-fn main() {
-    println!("Hello, world!");
+use binary_tree_blockchain::diff::KeyDiff;
+use binary_tree_blockchain::hasher::{MerkleHasher, StdMerkleHasher};
+use binary_tree_blockchain::proof::MerkleProof;
+use binary_tree_blockchain::trie_node::trie_node::TrieNode;
+
+const USAGE: &str = "usage:
+  binary_tree_blockchain verify --root <hash> --key <key> --value <value> --proof <file> [--hasher <std|blake3>]
+  binary_tree_blockchain diff <a.mtrie> <b.mtrie> [--hasher <std|blake3>] [--arity <2|4|16|256>]
+  binary_tree_blockchain hash-dir <dir> [--hasher <blake3>]
+  binary_tree_blockchain load <file> --key <column> --values <column,...> [--hasher <std|blake3>] [--arity <2|4|16|256>]
+
+Hashes are written as the hasher renders them: decimal for std, hex otherwise. Proof files hold
+`MerkleProof::to_bytes`; values are taken as UTF-8 strings. `diff` prints one line per key that
+differs from a to b: `+ key value`, `- key value` or `~ key old -> new`. `hash-dir` prints the
+root of a trie of the files under a directory, keyed by relative path; it needs a hasher that
+can stream file contents, so only blake3. `load` prints the root of a trie built from a CSV or
+JSON-lines file with `TrieNode::from_reader`; it needs the ingest feature.";
+
+// Exits 0 on success, 1 when the check fails (or snapshots differ) and 2 on bad arguments or
+// unreadable input.
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(message) => {
+            eprintln!("error: {message}\n\n{USAGE}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<bool, String> {
+    let Some((command, args)) = args.split_first() else {
+        return Err("no command given".to_string());
+    };
+    match command.as_str() {
+        "verify" => {
+            let options = options(args, &["root", "key", "value", "proof", "hasher"])?;
+            match options.get("hasher").copied().unwrap_or("std") {
+                "std" => verify(&StdMerkleHasher, &options),
+                #[cfg(feature = "blake3")]
+                "blake3" => verify(
+                    &binary_tree_blockchain::hasher::Blake3Hasher::default(),
+                    &options,
+                ),
+                other => Err(format!("unknown hasher {other:?}")),
+            }
+        }
+        "diff" => {
+            let (paths, args) = args.split_at(args.len().min(2));
+            let [a, b] = paths else {
+                return Err("diff needs two snapshot files".to_string());
+            };
+            let options = options(args, &["hasher", "arity"])?;
+            let arity = options.get("arity").copied().unwrap_or("2");
+            match options.get("hasher").copied().unwrap_or("std") {
+                "std" => diff::<StdMerkleHasher>(arity, a, b),
+                #[cfg(feature = "blake3")]
+                "blake3" => diff::<binary_tree_blockchain::hasher::Blake3Hasher>(arity, a, b),
+                other => Err(format!("unknown hasher {other:?}")),
+            }
+        }
+        "hash-dir" => {
+            let Some((dir, args)) = args.split_first() else {
+                return Err("hash-dir needs a directory".to_string());
+            };
+            if !std::path::Path::new(dir).is_dir() {
+                return Err(format!("{dir} is not a directory"));
+            }
+            let options = options(args, &["hasher"])?;
+            match options.get("hasher").copied().unwrap_or("blake3") {
+                #[cfg(feature = "blake3")]
+                "blake3" => hash_dir(
+                    &binary_tree_blockchain::hasher::Blake3Hasher::default(),
+                    dir,
+                ),
+                other => Err(format!("hasher {other:?} cannot hash directories")),
+            }
+        }
+        #[cfg(feature = "ingest")]
+        "load" => {
+            let Some((path, args)) = args.split_first() else {
+                return Err("load needs a file".to_string());
+            };
+            let options = options(args, &["key", "values", "hasher", "arity"])?;
+            let arity = options.get("arity").copied().unwrap_or("2");
+            match options.get("hasher").copied().unwrap_or("std") {
+                "std" => load::<StdMerkleHasher>(arity, path, &options),
+                #[cfg(feature = "blake3")]
+                "blake3" => {
+                    load::<binary_tree_blockchain::hasher::Blake3Hasher>(arity, path, &options)
+                }
+                other => Err(format!("unknown hasher {other:?}")),
+            }
+        }
+        other => Err(format!("unknown command {other:?}")),
+    }
+}
+
+// `--name value` pairs, each name one of `allowed` and given at most once.
+fn options<'a>(args: &'a [String], allowed: &[&str]) -> Result<HashMap<&'a str, &'a str>, String> {
+    let mut options = HashMap::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let name = arg
+            .strip_prefix("--")
+            .filter(|name| allowed.contains(name))
+            .ok_or_else(|| format!("unexpected argument {arg:?}"))?;
+        let value = args
+            .next()
+            .ok_or_else(|| format!("--{name} needs a value"))?;
+        if options.insert(name, value.as_str()).is_some() {
+            return Err(format!("--{name} given twice"));
+        }
+    }
+    Ok(options)
+}
+
+fn required<'a>(options: &HashMap<&str, &'a str>, name: &str) -> Result<&'a str, String> {
+    options
+        .get(name)
+        .copied()
+        .ok_or_else(|| format!("--{name} is required"))
+}
+
+fn verify<H: MerkleHasher>(hasher: &H, options: &HashMap<&str, &str>) -> Result<bool, String> {
+    let root = H::hash_from_string(required(options, "root")?)
+        .ok_or_else(|| "--root is not a hash".to_string())?;
+    let key: u32 = required(options, "key")?
+        .parse()
+        .map_err(|_| "--key is not a u32".to_string())?;
+    let value = required(options, "value")?;
+    let path = required(options, "proof")?;
+    let bytes = fs::read(path).map_err(|error| format!("cannot read {path}: {error}"))?;
+    let proof = MerkleProof::from_bytes::<H>(&bytes)
+        .ok_or_else(|| format!("{path} does not hold a proof"))?;
+
+    let verified = proof.key == key && proof.verify(hasher, &root, value);
+    if verified {
+        println!("ok");
+    } else {
+        eprintln!("proof does not verify");
+    }
+    Ok(verified)
+}
+
+fn diff<H: MerkleHasher + Default>(arity: &str, a: &str, b: &str) -> Result<bool, String> {
+    match arity {
+        "2" => diff_snapshots::<H, 2>(a, b),
+        "4" => diff_snapshots::<H, 4>(a, b),
+        "16" => diff_snapshots::<H, 16>(a, b),
+        "256" => diff_snapshots::<H, 256>(a, b),
+        other => Err(format!("unsupported arity {other:?}")),
+    }
+}
+
+fn import<H: MerkleHasher + Default, const N: usize>(
+    path: &str,
+) -> Result<TrieNode<String, H, N>, String> {
+    let file = fs::File::open(path).map_err(|error| format!("cannot read {path}: {error}"))?;
+    TrieNode::import_snapshot(std::io::BufReader::new(file))
+        .map_err(|error| format!("cannot import {path}: {error}"))
+}
+
+fn diff_snapshots<H: MerkleHasher + Default, const N: usize>(
+    a: &str,
+    b: &str,
+) -> Result<bool, String> {
+    let (a, b) = (import::<H, N>(a)?, import::<H, N>(b)?);
+    let diffs = a.diff(&b);
+    for diff in &diffs {
+        match diff {
+            KeyDiff::Added(key, value) => println!("+ {key} {value}"),
+            KeyDiff::Removed(key, value) => println!("- {key} {value}"),
+            KeyDiff::Changed(key, old, new) => println!("~ {key} {old} -> {new}"),
+        }
+    }
+    Ok(diffs.is_empty())
+}
+
+#[cfg(feature = "blake3")]
+fn hash_dir<H>(hasher: &H, dir: &str) -> Result<bool, String>
+where
+    H: MerkleHasher
+        + binary_tree_blockchain::embedded::StreamingHasher<Digest = <H as MerkleHasher>::Hash>
+        + Clone,
+{
+    let mut trie = binary_tree_blockchain::dir_hash::hash_directory(hasher, dir.as_ref())
+        .map_err(|error| format!("cannot hash {dir}: {error}"))?;
+    println!("{}", H::hash_to_string(&trie.merkle_root()));
+    Ok(true)
+}
+
+#[cfg(feature = "ingest")]
+fn load<H: MerkleHasher + Default>(
+    arity: &str,
+    path: &str,
+    options: &HashMap<&str, &str>,
+) -> Result<bool, String> {
+    match arity {
+        "2" => load_table::<H, 2>(path, options),
+        "4" => load_table::<H, 4>(path, options),
+        "16" => load_table::<H, 16>(path, options),
+        "256" => load_table::<H, 256>(path, options),
+        other => Err(format!("unsupported arity {other:?}")),
+    }
+}
+
+#[cfg(feature = "ingest")]
+fn load_table<H: MerkleHasher + Default, const N: usize>(
+    path: &str,
+    options: &HashMap<&str, &str>,
+) -> Result<bool, String> {
+    use binary_tree_blockchain::codec::BytesCodec;
+    use binary_tree_blockchain::ingest::{KeyColumn, ValueColumns};
+
+    let key = KeyColumn(required(options, "key")?.to_string());
+    let values = ValueColumns(
+        required(options, "values")?
+            .split(',')
+            .map(str::to_string)
+            .collect(),
+    );
+    let file = fs::File::open(path).map_err(|error| format!("cannot read {path}: {error}"))?;
+    let mut trie: TrieNode<Vec<u8>, H, N> =
+        TrieNode::from_reader(std::io::BufReader::new(file), key, values, &BytesCodec)
+            .map_err(|error| format!("cannot load {path}: {error}"))?;
+    println!("{}", H::hash_to_string(&trie.merkle_root()));
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn verify_checks_proof_files() {
+        let mut node: TrieNode<String> = TrieNode::new();
+        node.insert(5, "foo".to_string());
+        node.insert(6, "bar".to_string());
+        let root = node.merkle_root();
+        let path = std::env::temp_dir().join(format!("cli-verify-{}.bin", std::process::id()));
+        fs::write(&path, node.generate_proof(5).unwrap().to_bytes()).unwrap();
+        let path = path.to_str().unwrap();
+
+        let verify = |key: &str, value: &str| {
+            run(&args(&[
+                "verify", "--root", &root, "--key", key, "--value", value, "--proof", path,
+            ]))
+        };
+        assert_eq!(verify("5", "foo"), Ok(true));
+        assert_eq!(verify("5", "bar"), Ok(false));
+        assert_eq!(verify("6", "foo"), Ok(false));
+        assert!(verify("x", "foo").is_err());
+        assert!(run(&args(&["verify", "--key", "5"])).is_err());
+        assert!(run(&args(&["prove"])).is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn diff_compares_snapshot_files() {
+        let write = |name: &str, entries: &[(u32, &str)]| {
+            let mut node: TrieNode<String> = entries
+                .iter()
+                .map(|(key, value)| (*key, value.to_string()))
+                .collect();
+            let path =
+                std::env::temp_dir().join(format!("cli-diff-{name}-{}.mtrie", std::process::id()));
+            node.export_snapshot(fs::File::create(&path).unwrap())
+                .unwrap();
+            path.to_str().unwrap().to_string()
+        };
+        let a = write("a", &[(1, "foo"), (2, "bar")]);
+        let b = write("b", &[(1, "foo"), (2, "baz"), (9, "new")]);
+        assert_eq!(run(&args(&["diff", &a, &a])), Ok(true));
+        assert_eq!(run(&args(&["diff", &a, &b])), Ok(false));
+        assert!(run(&args(&["diff", &a, &b, "--arity", "4"])).is_err());
+        assert!(run(&args(&["diff", &a])).is_err());
+        fs::remove_file(a).unwrap();
+        fs::remove_file(b).unwrap();
+    }
+
+    #[test]
+    fn hash_dir_needs_a_directory() {
+        assert!(run(&args(&["hash-dir"])).is_err());
+        assert!(run(&args(&["hash-dir", ".", "--hasher", "std"])).is_err());
+        assert!(run(&args(&["hash-dir", "/nonexistent-dir"])).is_err());
+        #[cfg(feature = "blake3")]
+        assert_eq!(run(&args(&["hash-dir", "src"])), Ok(true));
+    }
+
+    #[cfg(feature = "ingest")]
+    #[test]
+    fn load_reads_tables() {
+        let path = std::env::temp_dir().join(format!("cli-load-{}.csv", std::process::id()));
+        fs::write(&path, "id,name,note\n1,foo,a\n2,bar,b\n").unwrap();
+        let path = path.to_str().unwrap();
+        let load = |extra: &[&str]| {
+            let mut all = vec!["load", path, "--key", "id"];
+            all.extend_from_slice(extra);
+            run(&args(&all))
+        };
+        assert_eq!(load(&["--values", "name,note"]), Ok(true));
+        assert_eq!(load(&["--values", "name", "--arity", "16"]), Ok(true));
+        assert!(load(&["--values", "missing"]).is_err());
+        assert!(load(&[]).is_err());
+        assert!(run(&args(&["load"])).is_err());
+        fs::remove_file(path).unwrap();
+    }
 }
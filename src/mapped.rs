@@ -0,0 +1,292 @@
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::trie_node::trie_node::{NodeIndex, TrieNode, ROOT};
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+// Layout, all integers big-endian:
+//
+//   header: magic (8) | version u8 | arity u16
+//   node:   flags u8 | N child offsets u64 (0 = no child)
+//           | data hash (u8 len + bytes) | merkle root (u8 len + bytes)
+//           | value (u32 len + merkle bytes), if flags & HAS_DATA
+//
+//   trailer: root offset u64
+//
+// Nodes are written children first, so every offset points backwards and the file can be
+// streamed out in one pass.
+const MAGIC: &[u8; 8] = b"MRKLMMAP";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 8 + 1 + 2;
+const TRAILER_LEN: usize = 8;
+const HAS_DATA: u8 = 1;
+
+fn invalid(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    /// Writes the trie in a layout that `TrieView` reads in place, e.g. straight out of a
+    /// memory map. Values are stored as their merkle bytes. Hashes the cache policy or an
+    /// eviction dropped are recomputed from the children written before them.
+    pub fn write_mapped<W: Write>(&mut self, out: W) -> io::Result<()> {
+        self.merkle_root();
+        let mut out = BufWriter::new(out);
+        out.write_all(MAGIC)?;
+        out.write_all(&[VERSION])?;
+        out.write_all(&(N as u16).to_be_bytes())?;
+
+        let mut offset = HEADER_LEN as u64;
+        let mut offsets: Vec<u64> = vec![0; self.nodes.len()];
+        // The roots of written nodes whose parents are still to be written.
+        let mut roots: Vec<Option<H::Hash>> = vec![None; self.nodes.len()];
+        let mut record = vec![];
+        let mut stack: Vec<(NodeIndex, bool)> = vec![(ROOT, false)];
+        while let Some((index, children_written)) = stack.pop() {
+            if !children_written {
+                stack.push((index, true));
+                stack.extend(
+                    self.node(index)
+                        .children()
+                        .iter()
+                        .flatten()
+                        .map(|child| (*child, false)),
+                );
+                continue;
+            }
+
+            let data_hash = self.data_hash_at(index);
+            let node = self.node(index);
+            let child_roots: Vec<H::Hash> = node
+                .children()
+                .iter()
+                .map(|child| match child {
+                    Some(child) => roots[*child as usize]
+                        .take()
+                        .expect("children are written first"),
+                    None => self.empty_hash().clone(),
+                })
+                .collect();
+            let merkle_root = match node.cached_merkle_root(self.cache_generation) {
+                Some(root) => root.clone(),
+                None if node.is_leaf() => data_hash.clone(),
+                None => self.hasher.combine_children(&data_hash, &child_roots),
+            };
+
+            record.clear();
+            record.push(node.get_data().is_some() as u8 * HAS_DATA);
+            for digit in 0..N {
+                let child_offset = node.child(digit).map_or(0, |c| offsets[c as usize]);
+                record.extend_from_slice(&child_offset.to_be_bytes());
+            }
+            for hash in [&data_hash, &merkle_root] {
+                let hash = hash.as_ref();
+                let len = u8::try_from(hash.len()).map_err(|_| invalid("hash is too long"))?;
+                record.push(len);
+                record.extend_from_slice(hash);
+            }
+            if let Some(data) = node.get_data() {
+                let bytes = data.merkle_bytes();
+                let len = u32::try_from(bytes.len()).map_err(|_| invalid("value is too long"))?;
+                record.extend_from_slice(&len.to_be_bytes());
+                record.extend_from_slice(&bytes);
+            }
+            out.write_all(&record)?;
+            roots[index as usize] = Some(merkle_root);
+            offsets[index as usize] = offset;
+            offset += record.len() as u64;
+        }
+        out.write_all(&offsets[ROOT as usize].to_be_bytes())?;
+        out.flush()
+    }
+
+    pub fn write_mapped_file<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        self.write_mapped(fs::File::create(path)?)
+    }
+}
+
+/// A read-only trie traversed directly over the bytes written by `write_mapped`, without
+/// deserializing anything. Lookups only touch the pages on the key's path.
+#[derive(Debug, Clone, Copy)]
+pub struct TrieView<'a, const N: usize = 2> {
+    bytes: &'a [u8],
+    root: usize,
+}
+
+struct NodeRecord<'a> {
+    children: &'a [u8],
+    data_hash: &'a [u8],
+    merkle_root: &'a [u8],
+    data: Option<&'a [u8]>,
+}
+
+impl<'a, const N: usize> TrieView<'a, N> {
+    pub fn new(bytes: &'a [u8]) -> io::Result<Self> {
+        if bytes.len() < HEADER_LEN + TRAILER_LEN || &bytes[..8] != MAGIC {
+            return Err(invalid("not a mapped trie file"));
+        }
+        if bytes[8] != VERSION {
+            return Err(invalid("unsupported mapped trie version"));
+        }
+        if u16::from_be_bytes([bytes[9], bytes[10]]) as usize != N {
+            return Err(invalid("mapped trie was written for a different arity"));
+        }
+        let root = u64::from_be_bytes(bytes[bytes.len() - TRAILER_LEN..].try_into().unwrap());
+        let view = TrieView {
+            bytes,
+            root: usize::try_from(root).map_err(|_| invalid("root offset out of range"))?,
+        };
+        view.record(view.root)
+            .ok_or_else(|| invalid("root record out of range"))?;
+        Ok(view)
+    }
+
+    fn slice(&self, at: usize, len: usize) -> Option<&'a [u8]> {
+        self.bytes.get(at..at.checked_add(len)?)
+    }
+
+    fn record(&self, offset: usize) -> Option<NodeRecord<'a>> {
+        if offset < HEADER_LEN {
+            return None;
+        }
+        let flags = *self.bytes.get(offset)?;
+        let mut at = offset + 1;
+        let children = self.slice(at, N * 8)?;
+        at += N * 8;
+        let data_hash_len = *self.bytes.get(at)? as usize;
+        let data_hash = self.slice(at + 1, data_hash_len)?;
+        at += 1 + data_hash_len;
+        let merkle_root_len = *self.bytes.get(at)? as usize;
+        let merkle_root = self.slice(at + 1, merkle_root_len)?;
+        at += 1 + merkle_root_len;
+        let data = if flags & HAS_DATA != 0 {
+            let len = u32::from_be_bytes(self.slice(at, 4)?.try_into().unwrap()) as usize;
+            Some(self.slice(at + 4, len)?)
+        } else {
+            None
+        };
+        Some(NodeRecord {
+            children,
+            data_hash,
+            merkle_root,
+            data,
+        })
+    }
+
+    fn child_offset(record: &NodeRecord<'a>, digit: usize) -> Option<usize> {
+        let at = digit * 8;
+        let offset = u64::from_be_bytes(record.children[at..at + 8].try_into().unwrap());
+        (offset != 0)
+            .then(|| usize::try_from(offset).ok())
+            .flatten()
+    }
+
+    fn find(&self, key: u32) -> Option<NodeRecord<'a>> {
+        let bits_per_digit = N.trailing_zeros();
+        let depth = (u32::BITS - key.leading_zeros()).div_ceil(bits_per_digit);
+        let mut record = self.record(self.root)?;
+        for d in 0..depth {
+            let digit = ((key >> (d * bits_per_digit)) as usize) & (N - 1);
+            record = self.record(Self::child_offset(&record, digit)?)?;
+        }
+        Some(record)
+    }
+
+    pub fn merkle_root(&self) -> &'a [u8] {
+        self.record(self.root).unwrap().merkle_root
+    }
+
+    /// The merkle bytes of the value under `key`.
+    pub fn get(&self, key: u32) -> Option<&'a [u8]> {
+        self.find(key)?.data
+    }
+
+    pub fn data_hash(&self, key: u32) -> Option<&'a [u8]> {
+        Some(self.find(key)?.data_hash)
+    }
+
+    pub fn subtree_root(&self, key: u32) -> Option<&'a [u8]> {
+        Some(self.find(key)?.merkle_root)
+    }
+}
+
+/// A `TrieView` over a memory-mapped file, so opening is instant and the OS pages nodes in and
+/// out as lookups touch them.
+#[cfg(feature = "mmap")]
+pub struct MappedTrie<const N: usize = 2> {
+    map: memmap2::Mmap,
+}
+
+#[cfg(feature = "mmap")]
+impl<const N: usize> MappedTrie<N> {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = fs::File::open(path)?;
+        // SAFETY: the file is only read through bounds-checked slices. Truncating or rewriting
+        // it while mapped is undefined behaviour, as with any mmap; writers must emit a new
+        // file and rename it into place.
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        TrieView::<N>::new(&map)?;
+        Ok(MappedTrie { map })
+    }
+
+    pub fn view(&self) -> TrieView<'_, N> {
+        TrieView::new(&self.map).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    fn sample() -> TrieNode<String> {
+        let mut node = TrieNode::new();
+        for key in [0, 1, 2, 6, 13, 1000, u32::MAX] {
+            node.insert(key, format!("value {key}"));
+        }
+        node
+    }
+
+    #[test]
+    fn view_reads_values_and_hashes_in_place() {
+        let mut node = sample();
+        let mut bytes = vec![];
+        node.write_mapped(&mut bytes).unwrap();
+
+        let view: TrieView = TrieView::new(&bytes).unwrap();
+        assert_eq!(view.merkle_root(), node.merkle_root().as_bytes());
+        for key in [0, 1, 2, 6, 13, 1000, u32::MAX] {
+            assert_eq!(view.get(key), Some(format!("value {key}").as_bytes()));
+        }
+        assert_eq!(view.get(3), None);
+        assert_eq!(view.get(4), None);
+        let intermediate = node.find_by_key(0b10).unwrap();
+        assert_eq!(
+            view.data_hash(0b10),
+            intermediate.cached_data_hash(0).map(|hash| hash.as_bytes())
+        );
+
+        assert!(TrieView::<4>::new(&bytes).is_err());
+        assert!(TrieView::<2>::new(&bytes[..bytes.len() - 1]).is_err());
+        assert_eq!(
+            StdMerkleHasher::hash_from_bytes(view.merkle_root()),
+            Some(node.merkle_root())
+        );
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn mapped_file_lookup() {
+        let mut node = sample();
+        let path = std::env::temp_dir().join(format!("mapped_file_lookup-{}", std::process::id()));
+        node.write_mapped_file(&path).unwrap();
+        let mapped: MappedTrie = MappedTrie::open(&path).unwrap();
+        assert_eq!(mapped.view().get(1000), Some(&b"value 1000"[..]));
+        assert_eq!(mapped.view().merkle_root(), node.merkle_root().as_bytes());
+        fs::remove_file(&path).unwrap();
+    }
+}
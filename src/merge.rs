@@ -0,0 +1,153 @@
+use std::collections::{BTreeMap, HashSet};
+
+use crate::diff::KeyDiff;
+use crate::trie_node::trie_node::TrieNode;
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// A key both sides changed from `base`, in different ways. `None` means the key is absent
+/// on that side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeConflict<'a, T> {
+    pub key: u32,
+    pub base: Option<&'a T>,
+    pub ours: Option<&'a T>,
+    pub theirs: Option<&'a T>,
+}
+
+/// The result of `TrieNode::three_way_merge`. `merged` holds every clean merge, and each
+/// conflicting key as it is in `ours`, so resolving a conflict means writing the chosen value
+/// into `merged`.
+#[derive(Debug, Clone)]
+pub struct MergeOutcome<'a, T: MerkleData, H: MerkleHasher, const N: usize> {
+    pub merged: TrieNode<T, H, N>,
+    pub conflicts: Vec<MergeConflict<'a, T>>,
+}
+
+impl<'a, T: MerkleData, H: MerkleHasher, const N: usize> MergeOutcome<'a, T, H, N> {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+impl<T, H, const N: usize> TrieNode<T, H, N>
+where
+    T: MerkleData + PartialEq + Clone,
+    H: MerkleHasher + Clone,
+{
+    /// Merges the changes `ours` and `theirs` each made since their common ancestor `base`,
+    /// as `diff` finds them. A key changed on one side only takes that side's value, and one
+    /// changed the same way on both sides merges cleanly; a key changed differently on each
+    /// side is a conflict, in ascending key order, for the caller to resolve.
+    pub fn three_way_merge<'a>(
+        base: &'a Self,
+        ours: &'a Self,
+        theirs: &'a Self,
+    ) -> MergeOutcome<'a, T, H, N> {
+        let ours_changes: BTreeMap<u32, KeyDiff<&T>> = base
+            .diff(ours)
+            .into_iter()
+            .map(|diff| (diff.key(), diff))
+            .collect();
+        let mut merged = ours.clone();
+        let mut removed = HashSet::new();
+        let mut conflicts = vec![];
+        for theirs_change in base.diff(theirs) {
+            let key = theirs_change.key();
+            let (base_value, theirs_value) = sides(&theirs_change);
+            match ours_changes.get(&key) {
+                None => match theirs_value {
+                    Some(value) => merged.insert(key, value.clone()),
+                    None => {
+                        removed.insert(key);
+                    }
+                },
+                Some(ours_change) => {
+                    let (_, ours_value) = sides(ours_change);
+                    if ours_value != theirs_value {
+                        conflicts.push(MergeConflict {
+                            key,
+                            base: base_value,
+                            ours: ours_value,
+                            theirs: theirs_value,
+                        });
+                    }
+                }
+            }
+        }
+        if !removed.is_empty() {
+            merged.retain(|key, _| !removed.contains(&key));
+        }
+        MergeOutcome { merged, conflicts }
+    }
+}
+
+// A change's value in the base and on the changed side.
+fn sides<'a, T>(change: &KeyDiff<&'a T>) -> (Option<&'a T>, Option<&'a T>) {
+    match *change {
+        KeyDiff::Added(_, value) => (None, Some(value)),
+        KeyDiff::Removed(_, value) => (Some(value), None),
+        KeyDiff::Changed(_, before, after) => (Some(before), Some(after)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn merges_one_sided_changes_and_reports_the_rest() {
+        let base: TrieNode<u32> = (0..50).map(|key| (key, key)).collect();
+        let mut ours = base.clone();
+        let mut theirs = base.clone();
+        ours.insert(1, 100);
+        theirs.insert(2, 200);
+        ours.insert(3, 300);
+        theirs.insert(3, 300);
+        ours.insert(4, 400);
+        theirs.insert(4, 401);
+        ours.insert(60, 6);
+        theirs.insert(61, 6);
+        theirs.retain(|key, _| key != 49 && key != 5);
+        ours.insert(5, 500);
+
+        let outcome = TrieNode::three_way_merge(&base, &ours, &theirs);
+        assert_eq!(
+            outcome.conflicts,
+            vec![
+                MergeConflict {
+                    key: 4,
+                    base: Some(&4),
+                    ours: Some(&400),
+                    theirs: Some(&401),
+                },
+                MergeConflict {
+                    key: 5,
+                    base: Some(&5),
+                    ours: Some(&500),
+                    theirs: None,
+                },
+            ]
+        );
+        assert!(!outcome.is_clean());
+        let mut merged = outcome.merged;
+        let mut expected = base.clone();
+        for (key, value) in [
+            (1, 100),
+            (2, 200),
+            (3, 300),
+            (4, 400),
+            (5, 500),
+            (60, 6),
+            (61, 6),
+        ] {
+            expected.insert(key, value);
+        }
+        expected.retain(|key, _| key != 49);
+        assert_eq!(merged.merkle_root(), expected.merkle_root());
+
+        assert!(TrieNode::three_way_merge(&base, &ours, &ours).is_clean());
+        let mut unchanged = TrieNode::three_way_merge(&base, &base, &theirs).merged;
+        assert_eq!(unchanged.merkle_root(), theirs.clone().merkle_root());
+    }
+}
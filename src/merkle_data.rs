@@ -0,0 +1,208 @@
+use std::borrow::Cow;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// Bytes a value commits to when it is hashed into the trie.
+///
+/// Implemented for the standard string and byte containers (the `AsRef<[u8]>` family), which
+/// commit to their raw bytes, and for fixed-width integers, which commit to their big-endian
+/// encoding so that `12u32` and `"12"` no longer produce the same leaf hash. Structs can commit
+/// to a serde or borsh encoding through [`BincodeEncoded`] and [`BorshEncoded`], or, with the
+/// `derive` feature, to their fields' bytes through `#[derive(MerkleData)]`.
+pub trait MerkleData {
+    fn merkle_bytes(&self) -> Cow<'_, [u8]>;
+}
+
+#[cfg(feature = "derive")]
+pub use merkle_data_derive::MerkleData;
+
+/// Appends `field`'s bytes preceded by their length as a big-endian `u64`, which no field can
+/// overflow, as `#[derive(MerkleData)]` encodes each field.
+#[doc(hidden)]
+pub fn push_field<D: MerkleData + ?Sized>(bytes: &mut Vec<u8>, field: &D) {
+    let field = field.merkle_bytes();
+    bytes.extend_from_slice(&(field.len() as u64).to_be_bytes());
+    bytes.extend_from_slice(&field);
+}
+
+impl<D: MerkleData + ?Sized> MerkleData for &D {
+    fn merkle_bytes(&self) -> Cow<'_, [u8]> {
+        (**self).merkle_bytes()
+    }
+}
+
+impl<D: MerkleData + ?Sized> MerkleData for Box<D> {
+    fn merkle_bytes(&self) -> Cow<'_, [u8]> {
+        (**self).merkle_bytes()
+    }
+}
+
+// Shared values commit to what they point to, so sharing a value doesn't change any hash.
+impl<D: MerkleData + ?Sized> MerkleData for Arc<D> {
+    fn merkle_bytes(&self) -> Cow<'_, [u8]> {
+        (**self).merkle_bytes()
+    }
+}
+
+impl<D: MerkleData + ?Sized> MerkleData for Rc<D> {
+    fn merkle_bytes(&self) -> Cow<'_, [u8]> {
+        (**self).merkle_bytes()
+    }
+}
+
+impl MerkleData for str {
+    fn merkle_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.as_bytes())
+    }
+}
+
+impl MerkleData for String {
+    fn merkle_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.as_bytes())
+    }
+}
+
+impl MerkleData for [u8] {
+    fn merkle_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl MerkleData for Vec<u8> {
+    fn merkle_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self)
+    }
+}
+
+// Borrowed values let a trie of `Cow<'a, [u8]>` or `&'a [u8]` point straight into a loaded
+// buffer; the borrow lives in `T`, so the node types need no lifetime of their own.
+impl MerkleData for Cow<'_, [u8]> {
+    fn merkle_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.as_ref())
+    }
+}
+
+impl MerkleData for Cow<'_, str> {
+    fn merkle_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.as_bytes())
+    }
+}
+
+impl<const N: usize> MerkleData for [u8; N] {
+    fn merkle_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self)
+    }
+}
+
+macro_rules! impl_merkle_data_for_integers {
+    ($($integer:ty),*) => {
+        $(
+            impl MerkleData for $integer {
+                fn merkle_bytes(&self) -> Cow<'_, [u8]> {
+                    Cow::Owned(self.to_be_bytes().to_vec())
+                }
+            }
+        )*
+    };
+}
+
+impl_merkle_data_for_integers!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+/// Serializes as the wrapped value, so `BincodeCodec` stores the same bytes it commits to.
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct BincodeEncoded<S>(pub S);
+
+#[cfg(feature = "serde")]
+impl<S: serde::Serialize> MerkleData for BincodeEncoded<S> {
+    fn merkle_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(bincode::serialize(&self.0).expect("value must be bincode-serializable"))
+    }
+}
+
+#[cfg(feature = "borsh")]
+#[derive(Debug, Clone, PartialEq, Eq, borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct BorshEncoded<B>(pub B);
+
+#[cfg(feature = "borsh")]
+impl<B: borsh::BorshSerialize> MerkleData for BorshEncoded<B> {
+    fn merkle_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(borsh::to_vec(&self.0).expect("value must be borsh-serializable"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn strings_and_integers_commit_to_different_bytes() {
+        assert_eq!(&*"12".merkle_bytes(), b"12");
+        assert_eq!(&*"12".to_string().merkle_bytes(), b"12");
+        assert_eq!(&*12u32.merkle_bytes(), &[0, 0, 0, 12]);
+        assert_eq!(&*vec![1u8, 2].merkle_bytes(), &[1, 2]);
+        assert_eq!(&*(-1i16).merkle_bytes(), &[0xff, 0xff]);
+    }
+
+    #[test]
+    fn borrowed_values_hash_like_owned_values() {
+        use crate::trie_node::trie_node::TrieNode;
+
+        let buffer = b"foobarbaz".to_vec();
+        let mut borrowed: TrieNode<&[u8]> = TrieNode::new();
+        let mut cow: TrieNode<Cow<[u8]>> = TrieNode::new();
+        let mut owned: TrieNode<Vec<u8>> = TrieNode::new();
+        for (key, chunk) in buffer.chunks(3).enumerate() {
+            borrowed.insert(key as u32, chunk);
+            cow.insert(key as u32, Cow::Borrowed(chunk));
+            owned.insert(key as u32, chunk.to_vec());
+        }
+        assert_eq!(
+            borrowed.find_by_key(1).unwrap().get_data(),
+            Some(&&b"bar"[..])
+        );
+        assert_eq!(borrowed.merkle_root(), owned.merkle_root());
+        assert_eq!(cow.merkle_root(), owned.merkle_root());
+    }
+
+    #[cfg(feature = "borsh")]
+    #[test]
+    fn borsh_encoded_struct() {
+        #[derive(borsh::BorshSerialize)]
+        struct Account {
+            id: u32,
+            name: String,
+        }
+        let account = BorshEncoded(Account {
+            id: 1,
+            name: "a".to_string(),
+        });
+        assert_eq!(&*account.merkle_bytes(), &[1, 0, 0, 0, 1, 0, 0, 0, b'a']);
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn derived_structs_length_prefix_their_fields() {
+        #[derive(MerkleData)]
+        struct Transfer<A> {
+            from: A,
+            to: A,
+            amount: u16,
+        }
+        #[derive(MerkleData)]
+        struct Pair(String, String);
+
+        let transfer = Transfer {
+            from: "ab",
+            to: "c",
+            amount: 5,
+        };
+        let len = |len: u8| [0, 0, 0, 0, 0, 0, 0, len];
+        let expected = [&len(2)[..], b"ab", &len(1), b"c", &len(2), &[0, 5]];
+        assert_eq!(&*transfer.merkle_bytes(), expected.concat());
+        let pair = |a: &str, b: &str| Pair(a.to_string(), b.to_string()).merkle_bytes().to_vec();
+        assert_ne!(pair("ab", "c"), pair("a", "bc"));
+    }
+}
@@ -0,0 +1,69 @@
+use std::borrow::Cow;
+
+use crate::hasher::MerkleHasher;
+use crate::merkle_data::{push_field, MerkleData};
+use crate::trie_node::trie_node::TrieNode;
+
+/// Several values stored under one key, in the order they were added, e.g. a per-key event log.
+/// Commits to their count, a big-endian `u64`, followed by each value's bytes preceded by
+/// their length, so the hash covers the whole ordered list; see the commitment spec.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MultiValue<T>(pub Vec<T>);
+
+impl<T: MerkleData> MerkleData for MultiValue<T> {
+    fn merkle_bytes(&self) -> Cow<'_, [u8]> {
+        let mut bytes = (self.0.len() as u64).to_be_bytes().to_vec();
+        for value in &self.0 {
+            push_field(&mut bytes, value);
+        }
+        Cow::Owned(bytes)
+    }
+}
+
+impl<T: MerkleData + PartialEq, H: MerkleHasher, const N: usize> TrieNode<MultiValue<T>, H, N> {
+    /// Appends `value` to the values under `key`, in place if there already are some.
+    pub fn insert_multi(&mut self, key: u32, value: T) {
+        let mut value = Some(value);
+        self.update(key, |values| values.0.extend(value.take()));
+        if let Some(value) = value {
+            self.insert(key, MultiValue(vec![value]));
+        }
+    }
+
+    /// The values under `key`, oldest first; empty if there are none.
+    pub fn get_all(&self, key: u32) -> &[T] {
+        self.get(key).map_or(&[], |values| &values.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn leaves_commit_to_the_ordered_list() {
+        let mut events: TrieNode<MultiValue<String>, StdMerkleHasher, 4> = TrieNode::new();
+        for (key, event) in [(7, "opened"), (9, "opened"), (7, "renamed"), (7, "closed")] {
+            events.insert_multi(key, event.to_string());
+        }
+        assert_eq!(events.get_all(7), ["opened", "renamed", "closed"]);
+        assert_eq!(events.get_all(8), [] as [String; 0]);
+        let root = events.merkle_root();
+
+        let proof = events.generate_proof(7).unwrap();
+        let list = MultiValue(vec!["opened", "renamed", "closed"]);
+        assert!(proof.verify(&StdMerkleHasher, &root, &list));
+        let reordered = MultiValue(vec!["renamed", "opened", "closed"]);
+        assert!(!proof.verify(&StdMerkleHasher, &root, &reordered));
+
+        // Boundaries between values are committed to, not just their concatenation.
+        assert_ne!(
+            MultiValue(vec!["ab", "c"]).merkle_bytes(),
+            MultiValue(vec!["a", "bc"]).merkle_bytes()
+        );
+        events.insert_multi(9, "closed".to_string());
+        assert_ne!(events.merkle_root(), root);
+    }
+}
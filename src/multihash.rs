@@ -0,0 +1,117 @@
+use crate::trie_node::trie_node::TrieNode;
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// The multicodec for raw bytes. No IPLD codec describes a trie node, so this is the usual
+/// choice for a root referenced as an opaque digest.
+pub const RAW_CODEC: u64 = 0x55;
+
+/// A hasher with an entry in the multicodec table.
+pub trait MultihashCode: MerkleHasher {
+    const MULTIHASH_CODE: u64;
+}
+
+#[cfg(feature = "blake3")]
+impl MultihashCode for crate::hasher::Blake3Hasher {
+    const MULTIHASH_CODE: u64 = 0x1e;
+}
+
+#[cfg(feature = "digest")]
+impl MultihashCode for crate::hasher::DigestHasher<sha2::Sha256> {
+    const MULTIHASH_CODE: u64 = 0x12;
+}
+
+#[cfg(feature = "digest")]
+impl MultihashCode for crate::hasher::DigestHasher<sha2::Sha512> {
+    const MULTIHASH_CODE: u64 = 0x13;
+}
+
+// Unsigned LEB128, as multiformats encode every integer.
+fn push_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// `digest` as a multihash: the hasher's code, the digest length and the digest.
+pub fn multihash<H: MultihashCode>(digest: &H::Hash) -> Vec<u8> {
+    let digest = digest.as_ref();
+    let mut out = vec![];
+    push_varint(&mut out, H::MULTIHASH_CODE);
+    push_varint(&mut out, digest.len() as u64);
+    out.extend_from_slice(digest);
+    out
+}
+
+/// A version 1 CID for `digest` under `codec`, in its string form: multibase base32, lowercase
+/// and unpadded.
+pub fn cid<H: MultihashCode>(digest: &H::Hash, codec: u64) -> String {
+    let mut bytes = vec![];
+    push_varint(&mut bytes, 1);
+    push_varint(&mut bytes, codec);
+    bytes.extend(multihash::<H>(digest));
+    format!("b{}", base32(&bytes))
+}
+
+fn base32(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+    let mut out = String::with_capacity((bytes.len() * 8).div_ceil(5));
+    let (mut buffer, mut bits) = (0u32, 0);
+    for byte in bytes {
+        buffer = buffer << 8 | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(ALPHABET[(buffer >> bits) as usize & 31] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(ALPHABET[(buffer << (5 - bits)) as usize & 31] as char);
+    }
+    out
+}
+
+impl<T: MerkleData, H: MultihashCode, const N: usize> TrieNode<T, H, N> {
+    pub fn root_as_multihash(&mut self) -> Vec<u8> {
+        multihash::<H>(&self.merkle_root())
+    }
+
+    /// The root as a raw-codec CID, e.g. for pinning alongside IPFS content.
+    pub fn root_as_cid(&mut self) -> String {
+        cid::<H>(&self.merkle_root(), RAW_CODEC)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn varints_and_base32() {
+        let mut out = vec![];
+        push_varint(&mut out, 0x12);
+        push_varint(&mut out, 300);
+        assert_eq!(out, [0x12, 0xac, 0x02]);
+        assert_eq!(base32(b"foobar"), "mzxw6ytboi");
+        assert_eq!(base32(b""), "");
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn sha256_roots_as_cids() {
+        use crate::hasher::DigestHasher;
+
+        let mut node: TrieNode<u32, DigestHasher<sha2::Sha256>> =
+            TrieNode::with_hasher(DigestHasher::new());
+        node.insert(5, 50);
+        let root = node.merkle_root();
+        let multihash = node.root_as_multihash();
+        assert_eq!(multihash[..2], [0x12, 0x20]);
+        assert_eq!(multihash[2..], root[..]);
+        // Every raw sha2-256 CID starts this way.
+        assert!(node.root_as_cid().starts_with("bafkrei"));
+        assert_eq!(node.root_as_cid().len(), 59);
+    }
+}
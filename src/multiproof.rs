@@ -0,0 +1,213 @@
+use crate::hasher::{hashes_equal, MerkleHasher};
+use crate::merkle_data::MerkleData;
+use crate::trie_node::trie_node::{NodeIndex, TrieNode, ROOT};
+
+/// Proof for many keys at once. The nodes on the paths to the keys form a covering subtree,
+/// whose shape follows from the keys alone; the proof only carries the hashes the verifier
+/// cannot derive, in depth-first, digit order:
+///
+/// - `data_hashes`: the data hash of each covering node that is not one of the keys,
+/// - `siblings`: the root of each child slot hanging off the covering subtree, `None` if empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultiProof<D> {
+    pub arity: usize,
+    pub data_hashes: Vec<D>,
+    pub siblings: Vec<Option<D>>,
+}
+
+fn key_depth(key: u32, bits_per_digit: u32) -> u32 {
+    (u32::BITS - key.leading_zeros()).div_ceil(bits_per_digit)
+}
+
+// Splits the keys under the node at `depth` into the node's own key, if it is one of them, and
+// the keys under each of its children.
+fn split(keys: &[u32], depth: u32, arity: usize) -> (Option<u32>, Vec<Vec<u32>>) {
+    let bits_per_digit = arity.trailing_zeros();
+    let mut own = None;
+    let mut children = vec![vec![]; arity];
+    for key in keys {
+        if key_depth(*key, bits_per_digit) == depth {
+            own = Some(*key);
+        } else {
+            let digit = ((key >> (depth * bits_per_digit)) as usize) & (arity - 1);
+            children[digit].push(*key);
+        }
+    }
+    (own, children)
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    /// Proves the values under all of `keys` against the current root; `None` if any of them
+    /// holds no value.
+    pub fn generate_multiproof(&mut self, keys: &[u32]) -> Option<MultiProof<H::Hash>> {
+        let mut keys = keys.to_vec();
+        keys.sort_unstable();
+        keys.dedup();
+        let mut proof = MultiProof {
+            arity: N,
+            data_hashes: vec![],
+            siblings: vec![],
+        };
+        self.cover(ROOT, &keys, 0, &mut proof)?;
+        Some(proof)
+    }
+
+    fn cover(
+        &mut self,
+        index: NodeIndex,
+        keys: &[u32],
+        depth: u32,
+        proof: &mut MultiProof<H::Hash>,
+    ) -> Option<()> {
+        let (own, children) = split(keys, depth, N);
+        if own.is_some() {
+            self.node(index).get_data()?;
+        } else {
+            proof.data_hashes.push(self.data_hash_at(index));
+        }
+        for (digit, keys) in children.iter().enumerate() {
+            let child = self.node(index).child(digit);
+            if keys.is_empty() {
+                proof
+                    .siblings
+                    .push(child.map(|child| self.merkle_root_at(child)));
+            } else {
+                self.cover(child?, keys, depth + 1, proof)?;
+            }
+        }
+        Some(())
+    }
+}
+
+/// Checks that every `(key, value)` in `entries` is committed to by `root`, rebuilding the root
+/// once from the covering subtree instead of walking one path per entry. Fails on repeated keys.
+pub fn verify_multiproof<H, V>(
+    hasher: &H,
+    root: &H::Hash,
+    entries: &[(u32, V)],
+    proof: &MultiProof<H::Hash>,
+) -> bool
+where
+    H: MerkleHasher,
+    V: MerkleData,
+{
+    if !proof.arity.is_power_of_two() || proof.arity < 2 || proof.arity > 256 {
+        return false;
+    }
+    let mut entries: Vec<&(u32, V)> = entries.iter().collect();
+    entries.sort_unstable_by_key(|(key, _)| *key);
+    if entries.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+        return false;
+    }
+    let keys: Vec<u32> = entries.iter().map(|(key, _)| *key).collect();
+
+    let mut rebuild = Rebuild {
+        hasher,
+        arity: proof.arity,
+        entries: &entries,
+        data_hashes: proof.data_hashes.iter(),
+        siblings: proof.siblings.iter(),
+    };
+    let rebuilt = rebuild.subtree_root(&keys, 0);
+    rebuild.data_hashes.next().is_none()
+        && rebuild.siblings.next().is_none()
+        && rebuilt.is_some_and(|rebuilt| hashes_equal(rebuilt.as_ref(), root.as_ref()))
+}
+
+struct Rebuild<'a, H: MerkleHasher, V> {
+    hasher: &'a H,
+    arity: usize,
+    entries: &'a [&'a (u32, V)],
+    data_hashes: std::slice::Iter<'a, H::Hash>,
+    siblings: std::slice::Iter<'a, Option<H::Hash>>,
+}
+
+impl<H: MerkleHasher, V: MerkleData> Rebuild<'_, H, V> {
+    fn subtree_root(&mut self, keys: &[u32], depth: u32) -> Option<H::Hash> {
+        let (own, children) = split(keys, depth, self.arity);
+        let data_hash = match own {
+            Some(key) => {
+                let at = self
+                    .entries
+                    .binary_search_by_key(&key, |(key, _)| *key)
+                    .unwrap();
+                self.hasher.hash(&self.entries[at].1.merkle_bytes())
+            }
+            None => self.data_hashes.next()?.clone(),
+        };
+        let mut has_children = false;
+        let mut roots = Vec::with_capacity(self.arity);
+        for keys in &children {
+            let root = if keys.is_empty() {
+                self.siblings.next()?.clone()
+            } else {
+                Some(self.subtree_root(keys, depth + 1)?)
+            };
+            has_children |= root.is_some();
+            roots.push(root.unwrap_or_else(|| self.hasher.empty_hash()));
+        }
+        Some(if has_children {
+            self.hasher.combine_children(&data_hash, &roots)
+        } else {
+            data_hash
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn multiproof_verifies_all_entries_at_once() {
+        let mut node: TrieNode<u32> = TrieNode::new();
+        for key in 0..40 {
+            node.insert(key * 3, key);
+        }
+        let root = node.merkle_root();
+        let keys = [0, 3, 9, 12, 45, 117];
+        let proof = node.generate_multiproof(&keys).unwrap();
+        let entries: Vec<(u32, u32)> = keys.iter().map(|key| (*key, key / 3)).collect();
+        assert!(verify_multiproof(&StdMerkleHasher, &root, &entries, &proof));
+
+        let mut forged = entries.clone();
+        forged[2].1 = 7;
+        assert!(!verify_multiproof(&StdMerkleHasher, &root, &forged, &proof));
+        assert!(!verify_multiproof(
+            &StdMerkleHasher,
+            &root,
+            &entries[1..],
+            &proof
+        ));
+        let repeated = [entries.clone(), entries[..1].to_vec()].concat();
+        assert!(!verify_multiproof(
+            &StdMerkleHasher,
+            &root,
+            &repeated,
+            &proof
+        ));
+        assert_eq!(node.generate_multiproof(&[3, 4]), None);
+    }
+
+    #[test]
+    fn multiproof_for_wide_arity_and_no_keys() {
+        let mut node: TrieNode<u32, StdMerkleHasher, 16> = TrieNode::new();
+        for key in [1, 17, 0x1a3, 0x2a3, u32::MAX] {
+            node.insert(key, key);
+        }
+        let root = node.merkle_root();
+        let proof = node.generate_multiproof(&[0x1a3, 0x2a3]).unwrap();
+        let entries = [(0x2a3, 0x2a3u32), (0x1a3, 0x1a3)];
+        assert!(verify_multiproof(&StdMerkleHasher, &root, &entries, &proof));
+
+        let empty = node.generate_multiproof(&[]).unwrap();
+        assert!(verify_multiproof::<_, u32>(
+            &StdMerkleHasher,
+            &root,
+            &[],
+            &empty
+        ));
+    }
+}
@@ -0,0 +1,109 @@
+use crate::trie_node::trie_node::TrieNode;
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// Bits of a key that hold its namespace tag.
+pub const NAMESPACE_BITS: u32 = 8;
+/// The largest id a namespaced key can hold, in the bits left over by the tag.
+pub const MAX_ID: u32 = u32::MAX >> NAMESPACE_BITS;
+
+/// The trie key for `id` in `namespace`, or `None` if `id` is above `MAX_ID`. The tag takes
+/// the lowest bits, which the trie reads first, so each namespace is a subtree of its own: in
+/// a binary trie `remove_subtree(namespace, 8)` drops a whole table, and its keys never share
+/// nodes below the top eight levels with another table's.
+pub fn compose_key(namespace: u8, id: u32) -> Option<u32> {
+    (id <= MAX_ID).then_some(id << NAMESPACE_BITS | namespace as u32)
+}
+
+/// The namespace tag and id making up `key`.
+pub fn split_key(key: u32) -> (u8, u32) {
+    (key as u8, key >> NAMESPACE_BITS)
+}
+
+/// An id in one logical table of a trie that holds several, so that keys of different tables
+/// can't be mixed up. Declare one with `namespaced_key!`.
+pub trait NamespacedKey: Sized {
+    const NAMESPACE: u8;
+
+    /// `None` if `id` is above `MAX_ID`.
+    fn new(id: u32) -> Option<Self>;
+
+    fn id(&self) -> u32;
+
+    fn to_key(&self) -> u32 {
+        self.id() << NAMESPACE_BITS | Self::NAMESPACE as u32
+    }
+
+    /// `None` if `key` is in another namespace.
+    fn from_key(key: u32) -> Option<Self> {
+        let (namespace, id) = split_key(key);
+        (namespace == Self::NAMESPACE).then(|| Self::new(id))?
+    }
+}
+
+/// Declares a newtype over a `u32` id implementing `NamespacedKey` for the given tag:
+///
+/// ```
+/// binary_tree_blockchain::namespaced_key!(pub struct AccountId = 1);
+/// ```
+#[macro_export]
+macro_rules! namespaced_key {
+    ($(#[$meta:meta])* $vis:vis struct $name:ident = $namespace:expr) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        $vis struct $name(u32);
+
+        impl $crate::namespace::NamespacedKey for $name {
+            const NAMESPACE: u8 = $namespace;
+
+            fn new(id: u32) -> Option<Self> {
+                (id <= $crate::namespace::MAX_ID).then_some($name(id))
+            }
+
+            fn id(&self) -> u32 {
+                self.0
+            }
+        }
+    };
+}
+
+impl<T: MerkleData + PartialEq, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    pub fn insert_namespaced<K: NamespacedKey>(&mut self, key: K, data: T) {
+        self.insert(key.to_key(), data);
+    }
+
+    pub fn get_namespaced<K: NamespacedKey>(&self, key: K) -> Option<&T> {
+        self.get(key.to_key())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    namespaced_key!(struct AccountId = 1);
+    namespaced_key!(struct OrderId = 2);
+
+    #[test]
+    fn tables_share_a_trie_without_sharing_keys() {
+        assert_eq!(compose_key(3, 5), Some(5 << 8 | 3));
+        assert_eq!(compose_key(3, MAX_ID + 1), None);
+        assert_eq!(split_key(5 << 8 | 3), (3, 5));
+
+        let mut node: TrieNode<u32> = TrieNode::new();
+        for id in 0..100 {
+            node.insert_namespaced(AccountId::new(id).unwrap(), id);
+            node.insert_namespaced(OrderId::new(id).unwrap(), id + 1000);
+        }
+        assert_eq!(node.get_namespaced(AccountId::new(7).unwrap()), Some(&7));
+        assert_eq!(node.get_namespaced(OrderId::new(7).unwrap()), Some(&1007));
+        assert_eq!(AccountId::new(MAX_ID + 1), None);
+        let order = OrderId::new(MAX_ID).unwrap();
+        assert_eq!(OrderId::from_key(order.to_key()), Some(order));
+        assert_eq!(AccountId::from_key(order.to_key()), None);
+
+        assert!(node.remove_subtree(OrderId::NAMESPACE as u32, NAMESPACE_BITS));
+        assert_eq!(node.get_namespaced(OrderId::new(7).unwrap()), None);
+        assert_eq!(node.get_namespaced(AccountId::new(7).unwrap()), Some(&7));
+    }
+}
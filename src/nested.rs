@@ -0,0 +1,142 @@
+use std::borrow::Cow;
+
+use crate::proof::MerkleProof;
+use crate::trie_node::trie_node::{NodeIndex, TrieNode, ROOT};
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// A trie commits to its root, so tries can be stored as values in other tries, the way an
+/// account trie points at storage tries. The root is taken from the caches where they are
+/// filled; `update_nested` keeps them filled.
+impl<T: MerkleData, H: MerkleHasher, const N: usize> MerkleData for TrieNode<T, H, N> {
+    fn merkle_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(self.uncached_root_at(ROOT).as_ref().to_vec())
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    /// Runs `f` on the value under `key` in place and invalidates the cached hashes on its
+    /// path, whether or not `f` changed anything. `None` if no value is stored there.
+    pub fn update<R>(&mut self, key: u32, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        let mut path: Vec<NodeIndex> = vec![ROOT];
+        for depth in 0..Self::key_depth(key) {
+            let digit = Self::digit_at(key, depth);
+            path.push(self.node(*path.last().unwrap()).child(digit)?);
+        }
+        self.node(*path.last().unwrap()).get_data()?;
+        self.record_invalidation(&path);
+        let target = self.node_mut(*path.last().unwrap());
+        let result = f(target.data_mut()?);
+        target.clear_cached_hashes();
+        for index in path {
+            self.node_mut(index).invalidate_merkle_root();
+        }
+        self.rehash_if_eager();
+        Some(result)
+    }
+}
+
+impl<U, H, const M: usize, const N: usize> TrieNode<TrieNode<U, H, M>, H, N>
+where
+    U: MerkleData,
+    H: MerkleHasher,
+{
+    /// `update` for a nested trie: the inner trie's root is recomputed (and cached) before the
+    /// change propagates to the outer one.
+    pub fn update_nested<R>(
+        &mut self,
+        key: u32,
+        f: impl FnOnce(&mut TrieNode<U, H, M>) -> R,
+    ) -> Option<R> {
+        self.update(key, |inner| {
+            let result = f(inner);
+            inner.merkle_root();
+            result
+        })
+    }
+
+    /// Proves the value under `inner_key` of the trie stored under `outer_key`.
+    pub fn generate_nested_proof(
+        &mut self,
+        outer_key: u32,
+        inner_key: u32,
+    ) -> Option<NestedProof<H::Hash>> {
+        // Proving the inner key only fills the inner trie's caches; its root, and so every
+        // hash in the outer trie, is unchanged.
+        let mut inner = None;
+        self.with_inner_mut(outer_key, |trie| inner = trie.generate_proof(inner_key))?;
+        Some(NestedProof {
+            inner: inner?,
+            outer: self.generate_proof(outer_key)?,
+        })
+    }
+
+    fn with_inner_mut(&mut self, key: u32, f: impl FnOnce(&mut TrieNode<U, H, M>)) -> Option<()> {
+        let mut index = ROOT;
+        for depth in 0..Self::key_depth(key) {
+            index = self.node(index).child(Self::digit_at(key, depth))?;
+        }
+        f(self.node_mut(index).data_mut()?);
+        Some(())
+    }
+}
+
+/// Proof of a value in a trie nested in another: the value's proof in the inner trie and the
+/// inner trie's root's proof in the outer one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NestedProof<D> {
+    pub inner: MerkleProof<D>,
+    pub outer: MerkleProof<D>,
+}
+
+impl<D: Clone + PartialEq + AsRef<[u8]>> NestedProof<D> {
+    pub fn verify<H, V>(&self, hasher: &H, root: &D, value: &V) -> bool
+    where
+        H: MerkleHasher<Hash = D>,
+        V: MerkleData + ?Sized,
+    {
+        match self.inner.root_for(hasher, value) {
+            Some(inner_root) => self.outer.verify(hasher, root, inner_root.as_ref()),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn nested_updates_and_proofs() {
+        let mut outer: TrieNode<TrieNode<u32>, StdMerkleHasher, 4> = TrieNode::new();
+        for account in 0..10 {
+            let storage: TrieNode<u32> = (0..5).map(|slot| (slot, account * 10 + slot)).collect();
+            outer.insert(account, storage);
+        }
+        let root = outer.merkle_root();
+        let proof = outer.generate_nested_proof(7, 3).unwrap();
+        assert!(proof.verify(&StdMerkleHasher, &root, &73u32));
+        assert!(!proof.verify(&StdMerkleHasher, &root, &74u32));
+        assert_eq!(outer.current_root(), Some(&root));
+
+        outer.update_nested(7, |storage| storage.insert(3, 99));
+        assert_eq!(outer.current_root(), None);
+        let updated = outer.merkle_root();
+        assert_ne!(updated, root);
+        let proof = outer.generate_nested_proof(7, 3).unwrap();
+        assert!(proof.verify(&StdMerkleHasher, &updated, &99u32));
+
+        let mut expected: TrieNode<TrieNode<u32>, StdMerkleHasher, 4> = TrieNode::new();
+        for account in 0..10 {
+            let mut storage: TrieNode<u32> =
+                (0..5).map(|slot| (slot, account * 10 + slot)).collect();
+            if account == 7 {
+                storage.insert(3, 99);
+            }
+            expected.insert(account, storage);
+        }
+        assert_eq!(expected.merkle_root(), updated);
+        assert_eq!(outer.generate_nested_proof(7, 9), None);
+    }
+}
@@ -0,0 +1,123 @@
+use std::time::{Duration, Instant};
+
+use crate::trie_node::trie_node::TrieNode;
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// One change of an `ObservedTrie`'s root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootTransition<D> {
+    pub old_root: D,
+    pub new_root: D,
+    /// Mutations since the old root, as `TrieNode::invalidation_stats` counts them.
+    pub mutations: u64,
+    /// Time spent computing the new root; close to zero for an eagerly hashed trie, which did
+    /// the work as it was mutated.
+    pub duration: Duration,
+}
+
+/// Told of every new root, e.g. to publish it to a chain or a notarization service without
+/// polling the trie. Closures taking a `&RootTransition` are observers.
+pub trait RootObserver<D> {
+    fn root_changed(&mut self, transition: &RootTransition<D>);
+}
+
+impl<D, F: FnMut(&RootTransition<D>)> RootObserver<D> for F {
+    fn root_changed(&mut self, transition: &RootTransition<D>) {
+        self(transition)
+    }
+}
+
+/// A trie that reports each recomputation of its root to a `RootObserver`. The observer is
+/// called from `merkle_root` whenever the trie was mutated or its root invalidated since the
+/// last root it was told of, even if the new root turns out to equal the old one.
+pub struct ObservedTrie<T: MerkleData, H: MerkleHasher, O, const N: usize = 2> {
+    trie: TrieNode<T, H, N>,
+    observer: O,
+    root: H::Hash,
+    mutations: u64,
+}
+
+impl<T, H, O, const N: usize> ObservedTrie<T, H, O, N>
+where
+    T: MerkleData,
+    H: MerkleHasher,
+    O: RootObserver<H::Hash>,
+{
+    /// Computes the trie's current root, which the observer is not told of.
+    pub fn new(mut trie: TrieNode<T, H, N>, observer: O) -> Self {
+        ObservedTrie {
+            root: trie.merkle_root(),
+            mutations: trie.invalidation_stats().mutations,
+            trie,
+            observer,
+        }
+    }
+
+    pub fn trie(&self) -> &TrieNode<T, H, N> {
+        &self.trie
+    }
+
+    /// The trie, for mutation. Resetting its invalidation stats makes the next transition's
+    /// mutation count start from there.
+    pub fn trie_mut(&mut self) -> &mut TrieNode<T, H, N> {
+        &mut self.trie
+    }
+
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    pub fn into_parts(self) -> (TrieNode<T, H, N>, O) {
+        (self.trie, self.observer)
+    }
+
+    pub fn merkle_root(&mut self) -> H::Hash {
+        let mutations = self.trie.invalidation_stats().mutations;
+        if mutations == self.mutations && self.trie.current_root().is_some() {
+            return self.root.clone();
+        }
+        let started = Instant::now();
+        let new_root = self.trie.merkle_root();
+        let transition = RootTransition {
+            old_root: std::mem::replace(&mut self.root, new_root.clone()),
+            new_root,
+            mutations: mutations.saturating_sub(self.mutations),
+            duration: started.elapsed(),
+        };
+        self.mutations = mutations;
+        self.observer.root_changed(&transition);
+        transition.new_root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn observers_see_each_recomputed_root() {
+        let mut seen = vec![];
+        let mut trie = ObservedTrie::new(
+            TrieNode::<u32, StdMerkleHasher>::new(),
+            |transition: &RootTransition<_>| seen.push(transition.clone()),
+        );
+        let empty = trie.merkle_root();
+        for key in 0..3 {
+            trie.trie_mut().insert(key, key);
+        }
+        let first = trie.merkle_root();
+        assert_eq!(trie.merkle_root(), first);
+        trie.trie_mut().set_eager_hashing(true);
+        trie.trie_mut().insert(9, 9);
+        let second = trie.merkle_root();
+        drop(trie);
+
+        assert_eq!(seen.len(), 2);
+        assert_eq!((&seen[0].old_root, &seen[0].new_root), (&empty, &first));
+        assert_eq!(seen[0].mutations, 3);
+        assert_eq!((&seen[1].old_root, &seen[1].new_root), (&first, &second));
+        assert_eq!(seen[1].mutations, 1);
+    }
+}
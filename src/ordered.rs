@@ -0,0 +1,125 @@
+use std::ops::RangeInclusive;
+
+use crate::trie_node::trie_node::{TrieNode, ROOT};
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+// Digits are laid out least significant first, so a key's depth is its number of digits and
+// numeric order is by depth first. Within a depth, though, the most significant digit is the
+// deepest one, so the digits fixed on the way down can't rule a subtree out against a bound.
+// The queries below walk the trie a level at a time instead, visiting each node at most once,
+// and stop at the first level that settles the answer.
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    fn max_depth() -> u32 {
+        u32::BITS.div_ceil(Self::BITS_PER_DIGIT)
+    }
+
+    // The least key for which `accept` holds at the shallowest of `depths` holding one, or the
+    // greatest at the deepest if `greatest`.
+    fn extreme_key(
+        &self,
+        depths: RangeInclusive<u32>,
+        greatest: bool,
+        accept: impl Fn(u32) -> bool,
+    ) -> Option<u32> {
+        let mut best: Option<u32> = None;
+        let mut level = vec![(ROOT, 0u32)];
+        for depth in 0..=*depths.end() {
+            if depths.contains(&depth) {
+                let mut at_depth: Option<u32> = None;
+                for (index, path) in &level {
+                    if self.node(*index).get_data().is_some()
+                        && Self::key_depth(*path) == depth
+                        && accept(*path)
+                        && at_depth.is_none_or(|best| (*path > best) == greatest)
+                    {
+                        at_depth = Some(*path);
+                    }
+                }
+                if at_depth.is_some() {
+                    best = at_depth;
+                    if !greatest {
+                        break;
+                    }
+                }
+            }
+            level = level
+                .iter()
+                .flat_map(|(index, path)| {
+                    let children = self.node(*index).children().iter().enumerate();
+                    children.filter_map(move |(digit, child)| {
+                        let path = path | (digit as u32) << (depth * Self::BITS_PER_DIGIT);
+                        Some(((*child)?, path))
+                    })
+                })
+                .collect();
+            if level.is_empty() {
+                break;
+            }
+        }
+        best
+    }
+
+    fn least_key_from(&self, lower: u32) -> Option<u32> {
+        let depths = Self::key_depth(lower)..=Self::max_depth();
+        self.extreme_key(depths, false, |key| key >= lower)
+    }
+
+    fn greatest_key_to(&self, upper: u32) -> Option<u32> {
+        self.extreme_key(0..=Self::key_depth(upper), true, |key| key <= upper)
+    }
+
+    /// The smallest key holding a value.
+    pub fn first_key(&self) -> Option<u32> {
+        self.least_key_from(0)
+    }
+
+    /// The largest key holding a value.
+    pub fn last_key(&self) -> Option<u32> {
+        self.greatest_key_to(u32::MAX)
+    }
+
+    /// The smallest key above `key` holding a value.
+    pub fn next_key_after(&self, key: u32) -> Option<u32> {
+        self.least_key_from(key.checked_add(1)?)
+    }
+
+    /// The largest key below `key` holding a value.
+    pub fn prev_key_before(&self, key: u32) -> Option<u32> {
+        self.greatest_key_to(key.checked_sub(1)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn ordered_queries_match_a_sorted_scan() {
+        let keys = [0, 3, 4, 9, 17, 64, 65, 1000, 4097, u32::MAX];
+        let node: TrieNode<u32, StdMerkleHasher, 4> = keys.iter().map(|key| (*key, 1)).collect();
+        assert_eq!(node.first_key(), Some(0));
+        assert_eq!(node.last_key(), Some(u32::MAX));
+        for probe in [0, 1, 3, 5, 16, 64, 999, 1000, 5000, u32::MAX - 1, u32::MAX] {
+            let next = keys.iter().copied().find(|key| *key > probe);
+            let prev = keys.iter().copied().rev().find(|key| *key < probe);
+            assert_eq!(node.next_key_after(probe), next, "after {probe}");
+            assert_eq!(node.prev_key_before(probe), prev, "before {probe}");
+        }
+
+        let keys: Vec<u32> = (1..3000).map(|key| key * 7919 % 70_001).collect();
+        let node: TrieNode<u32> = keys.iter().map(|key| (*key, 1)).collect();
+        let mut sorted = keys.clone();
+        sorted.sort();
+        for probe in (0..72_000).step_by(997) {
+            let next = sorted.iter().copied().find(|key| *key > probe);
+            let prev = sorted.iter().copied().rev().find(|key| *key < probe);
+            assert_eq!(node.next_key_after(probe), next, "after {probe}");
+            assert_eq!(node.prev_key_before(probe), prev, "before {probe}");
+        }
+
+        let empty: TrieNode<u32> = TrieNode::new();
+        assert_eq!((empty.first_key(), empty.last_key()), (None, None));
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::BTreeMap;
+
+use crate::trie_node::trie_node::{NodeIndex, TrieNode, ROOT};
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// Speculative writes on top of a trie that is only read, e.g. a block being built on top of
+/// confirmed state. Writes go to the top of a stack of layers, each of which can be discarded
+/// or merged into the one below; `into_writes` hands the result back for
+/// `TrieNode::insert_batch` once the base can be mutated again.
+pub struct TrieOverlay<'a, T: MerkleData, H: MerkleHasher, const N: usize = 2> {
+    base: &'a TrieNode<T, H, N>,
+    layers: Vec<BTreeMap<u32, T>>,
+}
+
+impl<'a, T: MerkleData, H: MerkleHasher, const N: usize> TrieOverlay<'a, T, H, N> {
+    pub fn new(base: &'a TrieNode<T, H, N>) -> Self {
+        TrieOverlay {
+            base,
+            layers: vec![BTreeMap::new()],
+        }
+    }
+
+    pub fn base(&self) -> &'a TrieNode<T, H, N> {
+        self.base
+    }
+
+    pub fn insert(&mut self, key: u32, data: T) {
+        self.layers.last_mut().unwrap().insert(key, data);
+    }
+
+    pub fn get(&self, key: u32) -> Option<&T>
+    where
+        T: PartialEq,
+    {
+        self.layers
+            .iter()
+            .rev()
+            .find_map(|layer| layer.get(&key))
+            .or_else(|| self.base.find_by_key(key)?.get_data())
+    }
+
+    /// Starts a new layer on top of the current one.
+    pub fn push_layer(&mut self) {
+        self.layers.push(BTreeMap::new());
+    }
+
+    /// Drops the writes of the top layer; the bottom layer is emptied rather than removed.
+    pub fn discard_layer(&mut self) {
+        if self.layers.len() > 1 {
+            self.layers.pop();
+        } else {
+            self.layers[0].clear();
+        }
+    }
+
+    /// Folds the top layer into the one below it.
+    pub fn merge_layer(&mut self) {
+        if self.layers.len() > 1 {
+            let top = self.layers.pop().unwrap();
+            self.layers.last_mut().unwrap().extend(top);
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// Every write across the layers, the topmost winning, in ascending key order.
+    pub fn into_writes(mut self) -> Vec<(u32, T)> {
+        while self.layers.len() > 1 {
+            self.merge_layer();
+        }
+        self.layers.pop().unwrap().into_iter().collect()
+    }
+
+    /// The root the base would have with every write applied. Subtrees without writes use the
+    /// base's cached roots where it has them; nothing is cached in either direction.
+    pub fn merkle_root(&self) -> H::Hash {
+        let mut writes: BTreeMap<u32, &T> = BTreeMap::new();
+        for layer in &self.layers {
+            writes.extend(layer.iter().map(|(key, data)| (*key, data)));
+        }
+        let writes: Vec<(u32, &T)> = writes.into_iter().collect();
+        self.combined_root(0, Some(ROOT), &writes)
+            .expect("the root always exists")
+    }
+
+    // The combined root of the node at `depth` whose base counterpart is `index`, given the
+    // writes that land in its subtree. `None` if the node exists in neither.
+    fn combined_root(
+        &self,
+        depth: u32,
+        index: Option<NodeIndex>,
+        writes: &[(u32, &T)],
+    ) -> Option<H::Hash> {
+        let hasher = self.base.hasher();
+        if writes.is_empty() {
+            return index.map(|index| self.base.uncached_root_at(index));
+        }
+        let data_hash = match writes
+            .iter()
+            .find(|(key, _)| TrieNode::<T, H, N>::key_depth(*key) == depth)
+        {
+            Some((_, data)) => hasher.hash(&data.merkle_bytes()),
+            None => match index {
+                Some(index) => self.base.uncached_data_hash_at(index),
+                None => self.base.empty_hash().clone(),
+            },
+        };
+        let mut has_children = false;
+        let mut roots = Vec::with_capacity(N);
+        for digit in 0..N {
+            let below: Vec<(u32, &T)> = writes
+                .iter()
+                .filter(|(key, _)| {
+                    TrieNode::<T, H, N>::key_depth(*key) > depth
+                        && TrieNode::<T, H, N>::digit_at(*key, depth) == digit
+                })
+                .copied()
+                .collect();
+            let child = index.and_then(|index| self.base.node(index).child(digit));
+            let root = self.combined_root(depth + 1, child, &below);
+            has_children |= root.is_some();
+            roots.push(root.unwrap_or_else(|| self.base.empty_hash().clone()));
+        }
+        Some(if has_children {
+            hasher.combine_children(&data_hash, &roots)
+        } else {
+            data_hash
+        })
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    pub(crate) fn uncached_data_hash_at(&self, index: NodeIndex) -> H::Hash {
+        let node = self.node(index);
+        if let Some(hash) = node.cached_data_hash(self.cache_generation) {
+            return hash.clone();
+        }
+        match node.get_data() {
+            Some(data) => self.hasher.hash(&data.merkle_bytes()),
+            None => self.empty_hash().clone(),
+        }
+    }
+
+    // `merkle_root_at` for a shared borrow: uses the caches but doesn't fill them.
+    pub(crate) fn uncached_root_at(&self, index: NodeIndex) -> H::Hash {
+        let node = self.node(index);
+        if let Some(hash) = node.cached_merkle_root(self.cache_generation) {
+            return hash.clone();
+        }
+        let data_hash = self.uncached_data_hash_at(index);
+        if node.is_leaf() {
+            return data_hash;
+        }
+        let roots: Vec<H::Hash> = node
+            .children()
+            .iter()
+            .map(|child| match child {
+                Some(child) => self.uncached_root_at(*child),
+                None => self.empty_hash().clone(),
+            })
+            .collect();
+        self.hasher.combine_children(&data_hash, &roots)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn stacked_overlays_match_applying_writes() {
+        let mut base: TrieNode<u32> = (0..50).map(|key| (key * 3, key)).collect();
+        let base_root = base.merkle_root();
+
+        let mut overlay = TrieOverlay::new(&base);
+        overlay.insert(3, 100);
+        overlay.insert(1000, 7);
+        overlay.push_layer();
+        overlay.insert(3, 200);
+        overlay.insert(4, 4);
+        assert_eq!(overlay.get(3), Some(&200));
+        assert_eq!(overlay.get(6), Some(&2));
+
+        let mut expected = base.clone();
+        expected.insert_batch([(3, 100), (1000, 7), (3, 200), (4, 4)]);
+        assert_eq!(overlay.merkle_root(), expected.merkle_root());
+
+        overlay.discard_layer();
+        assert_eq!(overlay.get(3), Some(&100));
+        let mut expected = base.clone();
+        expected.insert_batch([(3, 100), (1000, 7)]);
+        assert_eq!(overlay.merkle_root(), expected.merkle_root());
+        assert_eq!(base.current_root(), Some(&base_root));
+
+        let writes = overlay.into_writes();
+        base.insert_batch(writes);
+        assert_eq!(base, expected);
+    }
+}
@@ -0,0 +1,216 @@
+use rayon::prelude::*;
+
+use crate::bit_path::BitPath;
+use crate::trie_node::trie_node::{NodeIndex, TrieNode, ROOT};
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+// A node's data hash and, unless it is a leaf, its root.
+type NodeHashes<D> = (NodeIndex, D, Option<D>);
+
+impl<T, H, const N: usize> TrieNode<T, H, N>
+where
+    T: MerkleData + PartialEq + Send,
+    H: MerkleHasher + Clone + Send + Sync,
+    H::Hash: Send,
+{
+    /// `insert_batch` with the work split by the lowest `partition_depth` digits of each key,
+    /// which pick the subtree the key lands in. Each of the `N^partition_depth` subtrees is
+    /// built and hashed on rayon's thread pool, then grafted in, leaving only the levels above
+    /// them to combine. Subtrees the trie already has, and keys above the partition depth, are
+    /// inserted serially. Later entries win over earlier ones for the same key, as in
+    /// `insert_batch`.
+    pub fn insert_batch_parallel<I>(&mut self, entries: I, partition_depth: u32)
+    where
+        I: IntoIterator<Item = (u32, T)>,
+    {
+        let shift = partition_depth * Self::BITS_PER_DIGIT;
+        assert!(
+            partition_depth >= 1 && shift <= 16,
+            "partition depth must be between 1 and {}",
+            16 / Self::BITS_PER_DIGIT
+        );
+        let mut partitions: Vec<Vec<(u32, T)>> = (0..1usize << shift).map(|_| vec![]).collect();
+        let mut shallow = vec![];
+        for (key, data) in entries {
+            if Self::key_depth(key) < partition_depth {
+                shallow.push((key, data));
+            } else {
+                partitions[(key & ((1 << shift) - 1)) as usize].push((key, data));
+            }
+        }
+
+        // Subtrees the trie already has take their entries the ordinary way.
+        let mut serial = vec![];
+        let mut fresh = vec![];
+        for (prefix, entries) in partitions.into_iter().enumerate() {
+            if entries.is_empty() {
+                continue;
+            }
+            if self.has_position(prefix as u32, partition_depth) {
+                serial.extend(entries);
+            } else {
+                fresh.push((prefix as u32, entries));
+            }
+        }
+
+        let hasher = self.hasher.clone();
+        let generation = self.cache_generation;
+        let built: Vec<(u32, TrieNode<T, H, N>, Vec<u32>)> = fresh
+            .into_par_iter()
+            .map(|(prefix, entries)| {
+                let mut subtree = TrieNode::with_hasher(hasher.clone());
+                // So that the hashes cached here are still current once grafted.
+                subtree.cache_generation = generation;
+                let mut keys = Vec::with_capacity(entries.len());
+                for (key, data) in entries {
+                    // Relative to the subtree, a key loses the digits that led to it.
+                    subtree.insert(key >> shift, data);
+                    keys.push(key);
+                }
+                subtree.merkle_root();
+                (prefix, subtree, keys)
+            })
+            .collect();
+
+        let eager_hashing = std::mem::replace(&mut self.eager_hashing, false);
+        for (prefix, subtree, keys) in built {
+            self.graft(prefix, partition_depth, subtree);
+            for key in keys {
+                self.note_key(key);
+            }
+        }
+        self.insert_batch(serial.into_iter().chain(shallow));
+        self.eager_hashing = eager_hashing;
+        self.rehash_if_eager();
+    }
+
+    fn has_position(&self, path: u32, depth: u32) -> bool {
+        let mut index = ROOT;
+        for d in 0..depth {
+            match self.node(index).child(Self::digit_at(path, d)) {
+                Some(child) => index = child,
+                None => return false,
+            }
+        }
+        true
+    }
+
+    // Appends the nodes of `subtree`, a trie only ever inserted into, to the arena with their
+    // hashes, as the node at (`path`, `depth`), which must not exist yet.
+    fn graft(&mut self, path: u32, depth: u32, subtree: TrieNode<T, H, N>) {
+        let offset = self.nodes.len() as u32;
+        for mut node in subtree.nodes {
+            node.offset_children(offset);
+            self.nodes.push(node);
+        }
+        let parent = self.create_path(path, depth - 1);
+        self.node_mut(parent)
+            .set_child(Self::digit_at(path, depth - 1), ROOT + offset);
+    }
+}
+
+impl<T, H, const N: usize> TrieNode<T, H, N>
+where
+    T: MerkleData + Sync,
+    H: MerkleHasher + Sync,
+    H::Hash: Send + Sync,
+{
+    /// `warm_cache` with each subtree hashed on rayon's thread pool. The hashes are cached once
+    /// every subtree is done, so prefixes should not overlap or the shared nodes are hashed
+    /// twice.
+    pub fn warm_cache_parallel<I: IntoIterator<Item = BitPath>>(&mut self, prefixes: I) -> usize {
+        let indexes = self.prefix_indexes(prefixes);
+        let trie = &*self;
+        let hashed: Vec<_> = indexes
+            .par_iter()
+            .map(|index| {
+                let mut hashed = vec![];
+                trie.uncached_hashes(*index, &mut hashed);
+                hashed
+            })
+            .collect();
+        for hashed in hashed {
+            self.cache_hashes(hashed);
+        }
+        indexes.len()
+    }
+
+    // The root of the subtree at `index`, appending the hashes of each node under it that
+    // weren't cached, without caching them. Unlike `merkle_root_at` this only reads the trie, so
+    // subtrees can be hashed on several threads and their hashes cached afterwards.
+    fn uncached_hashes(&self, index: NodeIndex, hashed: &mut Vec<NodeHashes<H::Hash>>) -> H::Hash {
+        let node = self.node(index);
+        if let Some(root) = node.cached_merkle_root(self.cache_generation) {
+            return root.clone();
+        }
+        let data_hash = match node.cached_data_hash(self.cache_generation) {
+            Some(data_hash) => data_hash.clone(),
+            None => match node.get_data() {
+                Some(data) => self.hasher.hash(&data.merkle_bytes()),
+                None => self.empty_hash().clone(),
+            },
+        };
+        if node.is_leaf() {
+            hashed.push((index, data_hash.clone(), None));
+            return data_hash;
+        }
+        let children: Vec<H::Hash> = node
+            .children()
+            .iter()
+            .map(|child| match child {
+                Some(child) => self.uncached_hashes(*child, hashed),
+                None => self.empty_hash().clone(),
+            })
+            .collect();
+        let root = self.hasher.combine_children(&data_hash, &children);
+        hashed.push((index, data_hash, Some(root.clone())));
+        root
+    }
+
+    fn cache_hashes(&mut self, hashed: Vec<NodeHashes<H::Hash>>) {
+        for (index, data_hash, root) in hashed {
+            self.cache_data_hash(index, data_hash);
+            if let Some(root) = root {
+                self.cache_merkle_root(index, root);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn parallel_batches_match_serial_ones() {
+        let entries: Vec<(u32, u32)> = (0..2000)
+            .map(|i| (i * 7919 % 100_000, i))
+            .chain([(3, 1), (5, 2), (3, 9)])
+            .collect();
+        let mut expected: TrieNode<u32> = TrieNode::new();
+        expected.insert_batch(entries.clone());
+
+        let mut node: TrieNode<u32> = TrieNode::new();
+        node.insert_batch_parallel(entries.clone(), 3);
+        assert_eq!(node.merkle_root(), expected.merkle_root());
+        assert_eq!(node, expected);
+
+        // Again on top of existing subtrees.
+        let mut node: TrieNode<u32> = (0..50).map(|key| (key * 8, key)).collect();
+        let mut expected = node.clone();
+        expected.insert_batch(entries.clone());
+        node.insert_batch_parallel(entries, 3);
+        assert_eq!(node.merkle_root(), expected.merkle_root());
+
+        node.invalidate_all();
+        let prefixes = (0..8).map(|prefix| BitPath::new(prefix, 3));
+        assert_eq!(node.warm_cache_parallel(prefixes), 8);
+        assert!(node
+            .find_by_path(&BitPath::new(5, 3))
+            .unwrap()
+            .cached_merkle_root(node.cache_generation)
+            .is_some());
+        assert_eq!(node.merkle_root(), expected.merkle_root());
+    }
+}
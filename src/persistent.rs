@@ -0,0 +1,397 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::async_store::{NodeId, NodeStore, StoreError, StoredNode, META_ID};
+use crate::codec::{DisplayCodec, ValueCodec};
+use crate::trie_node::trie_node::{NodeIndex, TrieNode, ROOT};
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+// A record in the store, kept under the root of the subtree it holds.
+#[derive(Debug)]
+struct Flushed {
+    id: NodeId,
+    // Links to the record from other records, plus one from the metadata if it is the root.
+    links: usize,
+    // The roots of the children it links to.
+    children: Vec<Vec<u8>>,
+}
+
+/// An in-memory trie persisted to a `NodeStore`, in the record format of `AsyncTrie`. Records
+/// are known by the root of the subtree they hold, so `flush` stops at the first node on each
+/// path whose subtree is already stored and writes only the nodes above it, each under a fresh
+/// id; equal subtrees share a record. The metadata record is rewritten to commit them, and the
+/// records nothing links to any more are deleted afterwards, so a flush that fails part way
+/// leaves the previous one readable.
+pub struct PersistentTrie<T: MerkleData, H: MerkleHasher, S, const N: usize = 2, C = DisplayCodec> {
+    trie: TrieNode<T, H, N>,
+    store: S,
+    codec: C,
+    next_id: NodeId,
+    flushed: HashMap<Vec<u8>, Flushed>,
+    // The root of the trie as of the last flush.
+    root: Option<Vec<u8>>,
+}
+
+impl<T, H, S, const N: usize> PersistentTrie<T, H, S, N>
+where
+    T: MerkleData + ToString + FromStr,
+    H: MerkleHasher,
+    S: NodeStore,
+{
+    /// Opens the trie kept in `store`, which may be empty.
+    pub fn open(store: S, hasher: H) -> Result<Self, StoreError<S::Error>> {
+        Self::open_with_codec(store, hasher, DisplayCodec)
+    }
+}
+
+impl<T, H, S, const N: usize, C> PersistentTrie<T, H, S, N, C>
+where
+    T: MerkleData,
+    H: MerkleHasher,
+    S: NodeStore,
+    C: ValueCodec<T>,
+{
+    /// Opens the trie kept in `store`, storing values through `codec`. The whole trie is
+    /// loaded and rehashed, and every hash the store holds is checked against the one
+    /// recomputed, so a store that was corrupted or tampered with is reported as `Corrupt`
+    /// rather than giving roots and proofs that don't match its values.
+    pub fn open_with_codec(store: S, hasher: H, codec: C) -> Result<Self, StoreError<S::Error>> {
+        let mut persistent = PersistentTrie {
+            trie: TrieNode::with_hasher(hasher),
+            store,
+            codec,
+            next_id: 0,
+            flushed: HashMap::new(),
+            root: None,
+        };
+        // next_id u64 | root id u64
+        if let Some(meta) = persistent.store.get(META_ID)? {
+            let meta: [u8; 16] = meta
+                .try_into()
+                .map_err(|_| StoreError::Corrupt("malformed metadata"))?;
+            let (next_id, root_id) = meta.split_at(8);
+            persistent.next_id = NodeId::from_be_bytes(next_id.try_into().unwrap());
+            let root = persistent.load(NodeId::from_be_bytes(root_id.try_into().unwrap()), ROOT)?;
+            persistent.link(root.as_ref());
+            persistent.root = Some(root.as_ref().to_vec());
+        }
+        Ok(persistent)
+    }
+
+    // Loads the subtree under record `id` into `index`, returning its recomputed root.
+    fn load(&mut self, id: NodeId, index: NodeIndex) -> Result<H::Hash, StoreError<S::Error>> {
+        let bytes = self
+            .store
+            .get(id)?
+            .ok_or(StoreError::Corrupt("dangling child link"))?;
+        let stored = StoredNode::<H::Hash, N>::decode::<H>(&bytes)
+            .ok_or(StoreError::Corrupt("malformed node"))?;
+        if stored.spilled.is_some() {
+            return Err(StoreError::Corrupt("value spilled to a blob"));
+        }
+        if let Some(data) = &stored.data {
+            let value = self
+                .codec
+                .decode(data)
+                .ok_or(StoreError::Corrupt("unparseable value"))?;
+            self.trie.node_mut(index).replace_data(value);
+        }
+        if self.trie.data_hash_at(index) != stored.data_hash {
+            return Err(StoreError::Corrupt("data hash doesn't match the value"));
+        }
+        let mut children = vec![];
+        for (digit, child) in stored.children.iter().enumerate() {
+            if let Some((child_id, child_root)) = child {
+                let child = self.trie.push_node(None);
+                if self.load(*child_id, child)? != *child_root {
+                    return Err(StoreError::Corrupt("child root doesn't match the child"));
+                }
+                self.trie.node_mut(index).set_child(digit, child);
+                children.push(child_root.as_ref().to_vec());
+            }
+        }
+        let root = stored.merkle_root(self.trie.hasher());
+        self.trie.cache_merkle_root(index, root.clone());
+        // A record linked from several others is loaded once for each, but its own links are
+        // counted once.
+        if !self.flushed.contains_key(root.as_ref()) {
+            self.record(root.as_ref(), id, children);
+        }
+        Ok(root)
+    }
+
+    // Keeps record `id` as the one holding `root`, counting its links to `children`.
+    fn record(&mut self, root: &[u8], id: NodeId, children: Vec<Vec<u8>>) {
+        for child in &children {
+            self.link(child);
+        }
+        let flushed = Flushed {
+            id,
+            links: 0,
+            children,
+        };
+        self.flushed.insert(root.to_vec(), flushed);
+    }
+
+    fn link(&mut self, root: &[u8]) {
+        self.flushed.get_mut(root).unwrap().links += 1;
+    }
+
+    // Drops a link to the record holding `root`, deleting it once none are left, along with
+    // whatever only it linked to.
+    fn unlink(&mut self, root: &[u8]) -> Result<(), StoreError<S::Error>> {
+        let flushed = self.flushed.get_mut(root).unwrap();
+        flushed.links -= 1;
+        if flushed.links == 0 {
+            let flushed = self.flushed.remove(root).unwrap();
+            self.store.delete(flushed.id)?;
+            for child in &flushed.children {
+                self.unlink(child)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn trie(&self) -> &TrieNode<T, H, N> {
+        &self.trie
+    }
+
+    /// The trie to modify; changes reach the store on the next `flush`.
+    pub fn trie_mut(&mut self) -> &mut TrieNode<T, H, N> {
+        &mut self.trie
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    /// Writes the nodes changed since the last flush and commits them. Returns the number of
+    /// nodes written.
+    pub fn flush(&mut self) -> Result<usize, StoreError<S::Error>> {
+        let root = self.trie.merkle_root();
+        let mut written = 0;
+        let (root_id, _) = self.write_subtree(ROOT, &mut written)?;
+
+        let mut meta = self.next_id.to_be_bytes().to_vec();
+        meta.extend_from_slice(&root_id.to_be_bytes());
+        self.store.put(META_ID, meta)?;
+
+        self.link(root.as_ref());
+        if let Some(previous) = self.root.replace(root.as_ref().to_vec()) {
+            self.unlink(&previous)?;
+        }
+        Ok(written)
+    }
+
+    // Children first, so a parent's record can link to its children's ids. A subtree that is
+    // already stored is linked to as it is, without visiting the nodes below it.
+    fn write_subtree(
+        &mut self,
+        index: NodeIndex,
+        written: &mut usize,
+    ) -> Result<(NodeId, H::Hash), StoreError<S::Error>> {
+        let root = self.trie.merkle_root_at(index);
+        if let Some(flushed) = self.flushed.get(root.as_ref()) {
+            return Ok((flushed.id, root));
+        }
+        let children = self.trie.node(index).children().to_vec();
+        let mut links: [Option<(NodeId, H::Hash)>; N] = std::array::from_fn(|_| None);
+        for (link, child) in links.iter_mut().zip(&children) {
+            if let Some(child) = child {
+                *link = Some(self.write_subtree(*child, written)?);
+            }
+        }
+
+        let stored = StoredNode {
+            data: self
+                .trie
+                .node(index)
+                .get_data()
+                .map(|data| self.codec.encode(data)),
+            spilled: None,
+            data_hash: self.trie.data_hash_at(index),
+            children: links,
+        };
+        let id = self.next_id;
+        self.next_id += 1;
+        self.store.put(id, stored.encode())?;
+        *written += 1;
+        let child_roots = stored
+            .children
+            .iter()
+            .flatten()
+            .map(|(_, root)| root.as_ref().to_vec())
+            .collect();
+        self.record(root.as_ref(), id, child_roots);
+        Ok((id, root))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::cell::Cell;
+
+    use crate::async_store::MemoryNodeStore;
+    use crate::hasher::StdMerkleHasher;
+
+    // Counts hash calls, to tell which nodes a flush visits.
+    #[derive(Default)]
+    struct Counting(Cell<usize>);
+
+    impl MerkleHasher for Counting {
+        type Hash = <StdMerkleHasher as MerkleHasher>::Hash;
+
+        fn hash(&self, bytes: &[u8]) -> Self::Hash {
+            self.0.set(self.0.get() + 1);
+            StdMerkleHasher.hash(bytes)
+        }
+
+        fn hash_from_bytes(bytes: &[u8]) -> Option<Self::Hash> {
+            StdMerkleHasher::hash_from_bytes(bytes)
+        }
+    }
+
+    #[test]
+    fn flush_writes_only_changed_nodes() {
+        let mut persistent: PersistentTrie<u32, StdMerkleHasher, MemoryNodeStore> =
+            PersistentTrie::open(MemoryNodeStore::new(), StdMerkleHasher).unwrap();
+        persistent
+            .trie_mut()
+            .insert_batch((0..64).map(|key| (key, key * 10)));
+        let node_count = persistent.trie().metrics().node_count;
+        assert_eq!(persistent.flush().unwrap(), node_count);
+        assert_eq!(persistent.flush().unwrap(), 0);
+
+        // Key 45 sits at depth 6, so its path holds 7 nodes.
+        persistent.trie_mut().insert(45, 1);
+        assert_eq!(persistent.flush().unwrap(), 7);
+        persistent.trie_mut().insert(200, 2);
+        assert_eq!(persistent.flush().unwrap(), 9);
+        // Only 3 of those nodes are new; the records the other 13 replace are gone, and the
+        // metadata record makes one more.
+        assert_eq!(persistent.store().len(), node_count + 3 + 1);
+
+        let root = persistent.trie_mut().merkle_root();
+        let mut reopened: PersistentTrie<u32, StdMerkleHasher, MemoryNodeStore> =
+            PersistentTrie::open(persistent.into_store(), StdMerkleHasher).unwrap();
+        assert_eq!(reopened.trie().current_root(), Some(&root));
+        assert_eq!(
+            reopened.trie().find_by_key(45).unwrap().get_data(),
+            Some(&1)
+        );
+        assert_eq!(reopened.flush().unwrap(), 0);
+        reopened.trie_mut().insert(3, 3);
+        assert_eq!(reopened.flush().unwrap(), 3);
+        let mut expected: TrieNode<u32> = (0..64).map(|key| (key, key * 10)).collect();
+        expected.insert_batch([(45, 1), (200, 2), (3, 3)]);
+        assert_eq!(reopened.trie_mut().merkle_root(), expected.merkle_root());
+    }
+
+    #[test]
+    fn flush_visits_only_the_changed_paths() {
+        let mut persistent: PersistentTrie<u32, Counting, MemoryNodeStore> =
+            PersistentTrie::open(MemoryNodeStore::new(), Counting::default()).unwrap();
+        persistent
+            .trie_mut()
+            .insert_batch((0..1024).map(|key| (key, key)));
+        persistent.flush().unwrap();
+
+        // With the leaves' hashes dropped, visiting any leaf means hashing it again.
+        let trie = persistent.trie_mut();
+        for index in 0..trie.nodes.len() as NodeIndex {
+            if trie.node(index).is_leaf() {
+                trie.node_mut(index).clear_cached_hashes();
+            }
+        }
+        trie.insert(45, 1);
+        trie.merkle_root();
+        trie.hasher().0.set(0);
+        // Key 45 sits at depth 6, so its path holds 7 nodes.
+        assert_eq!(persistent.flush().unwrap(), 7);
+        assert_eq!(persistent.trie().hasher().0.get(), 0);
+
+        let root = persistent.trie_mut().merkle_root();
+        let reopened: PersistentTrie<u32, Counting, MemoryNodeStore> =
+            PersistentTrie::open(persistent.into_store(), Counting::default()).unwrap();
+        assert_eq!(reopened.trie().current_root(), Some(&root));
+    }
+
+    #[test]
+    fn equal_subtrees_share_a_record_until_neither_needs_it() {
+        let mut persistent: PersistentTrie<u32, StdMerkleHasher, MemoryNodeStore> =
+            PersistentTrie::open(MemoryNodeStore::new(), StdMerkleHasher).unwrap();
+        // Of the 5 nodes, keys 2 and 3 are leaves holding the same value, so one record serves
+        // both.
+        persistent.trie_mut().insert_batch([(1, 5), (2, 7), (3, 7)]);
+        assert_eq!(persistent.flush().unwrap(), 4);
+        assert_eq!(persistent.store().len(), 4 + 1);
+
+        // Key 3 still needs the old leaf, and key 2's new one is written.
+        persistent.trie_mut().insert(2, 8);
+        assert_eq!(persistent.flush().unwrap(), 3);
+        assert_eq!(persistent.store().len(), 5 + 1);
+        // Now key 3 links to key 2's leaf, and the old one is gone.
+        persistent.trie_mut().insert(3, 8);
+        assert_eq!(persistent.flush().unwrap(), 2);
+        assert_eq!(persistent.store().len(), 4 + 1);
+
+        let mut expected: TrieNode<u32> = TrieNode::new();
+        expected.insert_batch([(1, 5), (2, 8), (3, 8)]);
+        let mut reopened: PersistentTrie<u32, StdMerkleHasher, MemoryNodeStore> =
+            PersistentTrie::open(persistent.into_store(), StdMerkleHasher).unwrap();
+        assert_eq!(reopened.trie_mut().merkle_root(), expected.merkle_root());
+        assert_eq!(reopened.trie(), &expected);
+    }
+
+    #[test]
+    fn open_rejects_hashes_that_dont_match_the_values() {
+        let flushed = || {
+            let mut persistent: PersistentTrie<u32, StdMerkleHasher, MemoryNodeStore> =
+                PersistentTrie::open(MemoryNodeStore::new(), StdMerkleHasher).unwrap();
+            persistent
+                .trie_mut()
+                .insert_batch((0..16).map(|key| (key, key)));
+            persistent.flush().unwrap();
+            persistent.into_store()
+        };
+        let open = |store| PersistentTrie::<u32, StdMerkleHasher, _>::open(store, StdMerkleHasher);
+        let decode = |bytes: &[u8]| StoredNode::<String, 2>::decode::<StdMerkleHasher>(bytes);
+
+        let store = flushed();
+        let mut forged = decode(&store.get(5).unwrap().unwrap()).unwrap();
+        forged.data_hash = StdMerkleHasher.hash(b"forged");
+        store.put(5, forged.encode()).unwrap();
+        let Err(StoreError::Corrupt(reason)) = open(store) else {
+            panic!("forged data hash accepted");
+        };
+        assert_eq!(reason, "data hash doesn't match the value");
+
+        // A forged root in a parent's link to the record is caught as well.
+        let store = flushed();
+        let mut parent = (0..20)
+            .filter_map(|id| {
+                let stored = decode(&store.get(id).unwrap()?)?;
+                stored
+                    .children
+                    .iter()
+                    .any(|child| matches!(child, Some((5, _))))
+                    .then_some((id, stored))
+            })
+            .next()
+            .unwrap();
+        for (_, root) in parent.1.children.iter_mut().flatten() {
+            *root = StdMerkleHasher.hash(b"forged");
+        }
+        store.put(parent.0, parent.1.encode()).unwrap();
+        let Err(StoreError::Corrupt(reason)) = open(store) else {
+            panic!("forged child root accepted");
+        };
+        assert_eq!(reason, "child root doesn't match the child");
+    }
+}
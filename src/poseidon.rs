@@ -0,0 +1,246 @@
+use std::{fmt::Debug, marker::PhantomData};
+
+use crate::hasher::MerkleHasher;
+
+/// A prime field whose elements fit in a `u64`.
+pub trait PrimeField: Debug + Clone + Copy + PartialEq + Eq + Default {
+    const MODULUS: u64;
+    /// How many input bytes can be packed into one element without ever reaching the modulus.
+    const BYTES_PER_ELEMENT: usize;
+}
+
+/// p = 2^64 - 2^32 + 1, used by Plonky2 and friends.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Goldilocks;
+
+impl PrimeField for Goldilocks {
+    const MODULUS: u64 = 0xffff_ffff_0000_0001;
+    const BYTES_PER_ELEMENT: usize = 7;
+}
+
+/// p = 2^31 - 2^27 + 1, used by RISC Zero and Plonky3.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BabyBear;
+
+impl PrimeField for BabyBear {
+    const MODULUS: u64 = 0x7800_0001;
+    const BYTES_PER_ELEMENT: usize = 3;
+}
+
+const WIDTH: usize = 4;
+const RATE: usize = 3;
+const FULL_ROUNDS: usize = 8;
+const PARTIAL_ROUNDS: usize = 56;
+// x^7 is a permutation of both fields above since gcd(7, p - 1) = 1.
+const SBOX_EXPONENT: u64 = 7;
+const NODE_DOMAIN: u64 = 1 << 32;
+
+fn add<F: PrimeField>(a: u64, b: u64) -> u64 {
+    ((a as u128 + b as u128) % F::MODULUS as u128) as u64
+}
+
+fn mul<F: PrimeField>(a: u64, b: u64) -> u64 {
+    ((a as u128 * b as u128) % F::MODULUS as u128) as u64
+}
+
+fn pow<F: PrimeField>(mut base: u64, mut exponent: u64) -> u64 {
+    let mut result = 1;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mul::<F>(result, base);
+        }
+        base = mul::<F>(base, base);
+        exponent >>= 1;
+    }
+    result
+}
+
+fn inverse<F: PrimeField>(value: u64) -> u64 {
+    pow::<F>(value, F::MODULUS - 2)
+}
+
+/// A Poseidon-style hash over `F`: width 4 (rate 3, capacity 1), x^7 S-box, 8 full and 56
+/// partial rounds, a Cauchy MDS matrix and round constants drawn from SplitMix64 seeded with
+/// the modulus. The parameters are self-generated, so roots interoperate with circuits that
+/// instantiate the same constants rather than with a standardized Poseidon parameter set.
+///
+/// Hashes are field elements, encoded big-endian. Leaf data is packed into elements of
+/// `F::BYTES_PER_ELEMENT` bytes with its byte length in the capacity slot; node combination is a
+/// single permutation over `(data, left, right)`, which keeps in-circuit verification cheap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoseidonHasher<F: PrimeField> {
+    round_constants: Vec<[u64; WIDTH]>,
+    mds: [[u64; WIDTH]; WIDTH],
+    field: PhantomData<F>,
+}
+
+impl<F: PrimeField> Default for PoseidonHasher<F> {
+    fn default() -> Self {
+        PoseidonHasher::new()
+    }
+}
+
+impl<F: PrimeField> PoseidonHasher<F> {
+    pub fn new() -> Self {
+        let mut seed = F::MODULUS;
+        let mut next_element = || loop {
+            seed = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = seed;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^= z >> 31;
+            let candidate = z >> F::MODULUS.leading_zeros();
+            if candidate < F::MODULUS {
+                return candidate;
+            }
+        };
+        let round_constants = (0..FULL_ROUNDS + PARTIAL_ROUNDS)
+            .map(|_| {
+                [
+                    next_element(),
+                    next_element(),
+                    next_element(),
+                    next_element(),
+                ]
+            })
+            .collect();
+
+        let mut mds = [[0; WIDTH]; WIDTH];
+        for (i, row) in mds.iter_mut().enumerate() {
+            for (j, entry) in row.iter_mut().enumerate() {
+                *entry = inverse::<F>((i + WIDTH + j) as u64);
+            }
+        }
+
+        PoseidonHasher {
+            round_constants,
+            mds,
+            field: PhantomData,
+        }
+    }
+
+    pub fn permute(&self, state: &mut [u64; WIDTH]) {
+        for (round, constants) in self.round_constants.iter().enumerate() {
+            for (element, constant) in state.iter_mut().zip(constants) {
+                *element = add::<F>(*element, *constant);
+            }
+            let partial_rounds = FULL_ROUNDS / 2..FULL_ROUNDS / 2 + PARTIAL_ROUNDS;
+            let is_full_round = !partial_rounds.contains(&round);
+            if is_full_round {
+                for element in state.iter_mut() {
+                    *element = pow::<F>(*element, SBOX_EXPONENT);
+                }
+            } else {
+                state[0] = pow::<F>(state[0], SBOX_EXPONENT);
+            }
+            let mut mixed = [0; WIDTH];
+            for (row, output) in self.mds.iter().zip(mixed.iter_mut()) {
+                for (entry, element) in row.iter().zip(state.iter()) {
+                    *output = add::<F>(*output, mul::<F>(*entry, *element));
+                }
+            }
+            *state = mixed;
+        }
+    }
+
+    pub fn element_of(hash: &[u8; 8]) -> u64 {
+        u64::from_be_bytes(*hash) % F::MODULUS
+    }
+}
+
+impl<F: PrimeField> MerkleHasher for PoseidonHasher<F> {
+    type Hash = [u8; 8];
+
+    fn hash(&self, bytes: &[u8]) -> [u8; 8] {
+        let mut state = [bytes.len() as u64 % F::MODULUS, 0, 0, 0];
+        let elements: Vec<u64> = bytes
+            .chunks(F::BYTES_PER_ELEMENT)
+            .map(|chunk| chunk.iter().fold(0, |acc, byte| (acc << 8) | *byte as u64))
+            .collect();
+        if elements.is_empty() {
+            self.permute(&mut state);
+        }
+        for block in elements.chunks(RATE) {
+            for (slot, element) in state[1..].iter_mut().zip(block) {
+                *slot = add::<F>(*slot, *element);
+            }
+            self.permute(&mut state);
+        }
+        state[1].to_be_bytes()
+    }
+
+    fn hash_from_bytes(bytes: &[u8]) -> Option<[u8; 8]> {
+        let hash: [u8; 8] = bytes.try_into().ok()?;
+        (u64::from_be_bytes(hash) < F::MODULUS).then_some(hash)
+    }
+
+    fn combine(&self, data: &[u8; 8], left: &[u8; 8], right: &[u8; 8]) -> [u8; 8] {
+        let mut state = [
+            NODE_DOMAIN,
+            Self::element_of(data),
+            Self::element_of(left),
+            Self::element_of(right),
+        ];
+        self.permute(&mut state);
+        state[1].to_be_bytes()
+    }
+
+    fn combine_children(&self, data: &[u8; 8], children: &[[u8; 8]]) -> [u8; 8] {
+        if let [left, right] = children {
+            return self.combine(data, left, right);
+        }
+        let mut state = [NODE_DOMAIN + children.len() as u64, 0, 0, 0];
+        let elements: Vec<u64> = std::iter::once(data)
+            .chain(children)
+            .map(Self::element_of)
+            .collect();
+        for block in elements.chunks(RATE) {
+            for (slot, element) in state[1..].iter_mut().zip(block) {
+                *slot = add::<F>(*slot, *element);
+            }
+            self.permute(&mut state);
+        }
+        state[1].to_be_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::trie_node::trie_node::TrieNode;
+
+    #[test]
+    fn hashes_are_field_elements() {
+        let goldilocks = PoseidonHasher::<Goldilocks>::new();
+        let baby_bear = PoseidonHasher::<BabyBear>::new();
+        for input in [
+            &b""[..],
+            b"foo",
+            b"a much longer value spanning several blocks",
+        ] {
+            let hash = goldilocks.hash(input);
+            assert!(u64::from_be_bytes(hash) < Goldilocks::MODULUS);
+            assert!(u64::from_be_bytes(baby_bear.hash(input)) < BabyBear::MODULUS);
+        }
+        assert_ne!(goldilocks.hash(b""), goldilocks.hash(b"\0"));
+        let (a, b) = (goldilocks.hash(b"a"), goldilocks.hash(b"b"));
+        assert_ne!(
+            goldilocks.combine(&a, &a, &b),
+            goldilocks.combine(&a, &b, &a)
+        );
+    }
+
+    #[test]
+    fn poseidon_trie_root() {
+        let mut node: TrieNode<String, PoseidonHasher<Goldilocks>> = TrieNode::new();
+        node.insert(1, "foo".to_string());
+        node.insert(2, "bar".to_string());
+
+        let hasher = PoseidonHasher::<Goldilocks>::new();
+        let empty = hasher.empty_hash();
+        let left = hasher.combine(&empty, &empty, &hasher.hash(b"bar"));
+        let expected = hasher.combine(&empty, &left, &hasher.hash(b"foo"));
+        assert_eq!(node.merkle_root(), expected);
+    }
+}
@@ -0,0 +1,236 @@
+use std::borrow::Cow;
+
+use crate::proof::MerkleProof;
+use crate::trie_node::trie_node::TrieNode;
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// A value stored with its digest, or the digest alone. It commits to the digest's bytes,
+/// which `PrehashedHasher` takes as the value's hash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prehashed<T, D> {
+    pub digest: D,
+    pub value: Option<T>,
+}
+
+impl<T, D: AsRef<[u8]>> MerkleData for Prehashed<T, D> {
+    fn merkle_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.digest.as_ref())
+    }
+}
+
+/// `H`, except that the bytes of one of its own hashes hash to that hash, so that values can
+/// commit to digests computed elsewhere. Any other bytes are hashed by `H` as before, and
+/// internal nodes and the empty hash are unchanged, so a trie of digests has the root of the
+/// same trie of plaintext values under `H`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PrehashedHasher<H>(pub H);
+
+impl<H: MerkleHasher> MerkleHasher for PrehashedHasher<H> {
+    type Hash = H::Hash;
+
+    fn hash(&self, bytes: &[u8]) -> H::Hash {
+        H::hash_from_bytes(bytes).unwrap_or_else(|| self.0.hash(bytes))
+    }
+
+    fn hash_from_bytes(bytes: &[u8]) -> Option<H::Hash> {
+        H::hash_from_bytes(bytes)
+    }
+
+    fn combine(&self, data: &H::Hash, left: &H::Hash, right: &H::Hash) -> H::Hash {
+        self.0.combine(data, left, right)
+    }
+
+    fn combine_children(&self, data: &H::Hash, children: &[H::Hash]) -> H::Hash {
+        self.0.combine_children(data, children)
+    }
+
+    fn empty_hash(&self) -> H::Hash {
+        self.0.empty_hash()
+    }
+
+    fn max_hash_len(&self) -> usize {
+        self.0.max_hash_len()
+    }
+
+    fn heap_bytes(hash: &H::Hash) -> usize {
+        H::heap_bytes(hash)
+    }
+
+    fn hash_to_string(hash: &H::Hash) -> String {
+        H::hash_to_string(hash)
+    }
+
+    fn hash_from_string(string: &str) -> Option<H::Hash> {
+        H::hash_from_string(string)
+    }
+}
+
+/// A trie whose values can be inserted as digests, for callers that hash values elsewhere or
+/// can't share them. Its root and proofs are those of a `TrieNode<T, H, N>` holding the
+/// plaintext values, so a proof verifies under `H` against the plaintext, or under
+/// `PrehashedHasher<H>` against the digest alone.
+pub struct PrehashedTrie<T, H: MerkleHasher, const N: usize = 2> {
+    trie: TrieNode<Prehashed<T, H::Hash>, PrehashedHasher<H>, N>,
+}
+
+impl<T: PartialEq, H: MerkleHasher + Default, const N: usize> Default for PrehashedTrie<T, H, N> {
+    fn default() -> Self {
+        PrehashedTrie::with_hasher(H::default())
+    }
+}
+
+impl<T: PartialEq, H: MerkleHasher, const N: usize> PrehashedTrie<T, H, N> {
+    pub fn with_hasher(hasher: H) -> Self {
+        PrehashedTrie {
+            trie: TrieNode::with_hasher(PrehashedHasher(hasher)),
+        }
+    }
+
+    pub fn trie(&self) -> &TrieNode<Prehashed<T, H::Hash>, PrehashedHasher<H>, N> {
+        &self.trie
+    }
+
+    /// Hashes `value` as a plain trie would and stores it with its digest.
+    pub fn insert(&mut self, key: u32, value: T)
+    where
+        T: MerkleData,
+    {
+        let digest = self.trie.hasher().0.hash(&value.merkle_bytes());
+        self.insert_hashed(key, digest, Some(value));
+    }
+
+    /// Stores `digest` as the hash of the value under `key`, with the value if the caller has
+    /// it. The value isn't checked against the digest.
+    pub fn insert_hashed(&mut self, key: u32, digest: H::Hash, maybe_value: Option<T>) {
+        self.trie.insert(
+            key,
+            Prehashed {
+                digest,
+                value: maybe_value,
+            },
+        );
+    }
+
+    /// The value under `key`, if one was stored with its digest.
+    pub fn get(&self, key: u32) -> Option<&T> {
+        self.trie.get(key)?.value.as_ref()
+    }
+
+    pub fn digest(&self, key: u32) -> Option<&H::Hash> {
+        Some(&self.trie.get(key)?.digest)
+    }
+
+    pub fn merkle_root(&mut self) -> H::Hash {
+        self.trie.merkle_root()
+    }
+
+    /// Proves the digest under `key`, whether or not its value is known.
+    pub fn generate_proof(&mut self, key: u32) -> Option<MerkleProof<H::Hash>> {
+        self.trie.generate_proof(key)
+    }
+
+    /// A copy with the values for which `redact` holds dropped, leaving their digests.
+    pub fn redacted<F>(&self, mut redact: F) -> Self
+    where
+        T: Clone,
+        H: Clone,
+        F: FnMut(u32, &T) -> bool,
+    {
+        let trie = self.trie.rebuild(
+            self.trie.hasher().clone(),
+            |key, entry| {
+                Some(Prehashed {
+                    digest: entry.digest.clone(),
+                    value: entry.value.clone().filter(|value| !redact(key, value)),
+                })
+            },
+            false,
+        );
+        PrehashedTrie { trie }
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher + Clone, const N: usize> TrieNode<T, H, N> {
+    /// A copy to share with a verifier who may see only some of the values: those for which
+    /// `redact` holds are replaced by their digests. Every node is kept, so the copy has the
+    /// same root, and every entry in it, redacted or not, can still be proven.
+    pub fn redacted<F>(&self, mut redact: F) -> PrehashedTrie<T, H, N>
+    where
+        T: Clone + PartialEq,
+        F: FnMut(u32, &T) -> bool,
+    {
+        let trie = self.rebuild(
+            PrehashedHasher(self.hasher().clone()),
+            |key, value| {
+                Some(Prehashed {
+                    digest: self.hasher().hash(&value.merkle_bytes()),
+                    value: (!redact(key, value)).then(|| value.clone()),
+                })
+            },
+            false,
+        );
+        PrehashedTrie { trie }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn digests_stand_in_for_values() {
+        let mut plain: TrieNode<u32, StdMerkleHasher, 4> =
+            (0..50).map(|key| (key * 3, key)).collect();
+        let mut prehashed: PrehashedTrie<u32, StdMerkleHasher, 4> = PrehashedTrie::default();
+        for key in 0..50 {
+            if key % 2 == 0 {
+                prehashed.insert(key * 3, key);
+            } else {
+                let digest = StdMerkleHasher.hash(&key.merkle_bytes());
+                prehashed.insert_hashed(key * 3, digest, None);
+            }
+        }
+        let root = prehashed.merkle_root();
+        assert_eq!(root, plain.merkle_root());
+        assert_eq!(prehashed.get(6), Some(&2));
+        assert_eq!(prehashed.get(9), None);
+        assert_eq!(
+            prehashed.digest(9),
+            Some(&StdMerkleHasher.hash(&3u32.merkle_bytes()))
+        );
+
+        // A withheld value is still provable, by its digest or by the plaintext.
+        let proof = prehashed.generate_proof(9).unwrap();
+        let digest = prehashed.digest(9).unwrap().clone();
+        assert!(proof.verify(&PrehashedHasher(StdMerkleHasher), &root, &digest));
+        assert!(proof.verify(&StdMerkleHasher, &root, &3u32));
+        assert!(!proof.verify(&StdMerkleHasher, &root, &4u32));
+        assert_eq!(plain.generate_proof(9), Some(proof));
+    }
+
+    #[test]
+    fn redacted_copies_keep_the_root() {
+        let mut node: TrieNode<u32, StdMerkleHasher, 4> =
+            (0..100).map(|key| (key * 3, key)).collect();
+        node.remove_subtree(0b1011, 2);
+        let root = node.merkle_root();
+        let mut redacted = node.redacted(|key, _| key % 2 == 0);
+        assert_eq!(redacted.merkle_root(), root);
+        assert_eq!(redacted.get(6), None);
+        assert_eq!(redacted.get(9), Some(&3));
+
+        let proof = redacted.generate_proof(9).unwrap();
+        assert!(proof.verify(&StdMerkleHasher, &root, &3u32));
+        let digest = redacted.digest(6).unwrap().clone();
+        let proof = redacted.generate_proof(6).unwrap();
+        assert!(proof.verify(&PrehashedHasher(StdMerkleHasher), &root, &digest));
+
+        let mut further = redacted.redacted(|_, value| *value > 10);
+        assert_eq!(further.merkle_root(), root);
+        assert_eq!(further.get(9), Some(&3));
+        assert_eq!(further.get(33), None);
+        assert!(further.digest(33).is_some());
+    }
+}
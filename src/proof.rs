@@ -0,0 +1,608 @@
+use crate::hasher::{hashes_equal, MerkleHasher};
+use crate::trie_node::trie_node::{NodeIndex, TrieNode, ROOT};
+use crate::{instrumentation, merkle_data::MerkleData, verifier};
+
+/// One ancestor of the proven node: its data hash, the merkle roots of its other children in
+/// digit order with the child on the path left out, and the digit that child is under. In a
+/// binary trie the position is the direction: 0 when the path goes left, 1 when it goes right.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct ProofLevel<D> {
+    pub data_hash: D,
+    pub siblings: Vec<D>,
+    pub position: u8,
+}
+
+/// The proof format `generate_proof` produces. A format fixes both the byte encoding and how a
+/// proof is folded into a root; verifiers keep accepting every format in
+/// `SUPPORTED_PROOF_VERSIONS`, so proofs handed out by an older release stay checkable.
+///
+/// Format 2 added each level's position. Format 1 left it to be worked out from the key, and
+/// decoding a format 1 proof does just that.
+pub const PROOF_FORMAT_VERSION: u8 = 2;
+
+pub const SUPPORTED_PROOF_VERSIONS: &[u8] = &[1, 2];
+
+/// The newest format both sides understand, given the versions a peer supports.
+pub fn negotiate_proof_version(peer_versions: &[u8]) -> Option<u8> {
+    SUPPORTED_PROOF_VERSIONS
+        .iter()
+        .rev()
+        .find(|version| peer_versions.contains(version))
+        .copied()
+}
+
+/// Proof that a value is stored under `key`. A keyed node can have descendants, so the proof
+/// carries the roots of the node's own children (empty if it has none) along with one level per
+/// ancestor, nearest first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct MerkleProof<D> {
+    /// The format the proof was produced in; see `PROOF_FORMAT_VERSION`.
+    pub version: u8,
+    pub key: u32,
+    pub arity: usize,
+    pub children_roots: Vec<D>,
+    pub levels: Vec<ProofLevel<D>>,
+}
+
+impl<D: Clone> MerkleProof<D> {
+    /// The root this proof commits `value` to, or `None` if the proof is malformed for its key
+    /// and arity.
+    pub fn root_for<H, V>(&self, hasher: &H, value: &V) -> Option<D>
+    where
+        H: MerkleHasher<Hash = D>,
+        V: MerkleData + ?Sized,
+    {
+        self.root_at_depth(hasher, value, 0)
+    }
+
+    // The root of the subtree at `top` digits deep on the path to the key that this proof
+    // commits `value` to. Only the levels below `top` are folded in.
+    pub(crate) fn root_at_depth<H, V>(&self, hasher: &H, value: &V, top: u32) -> Option<D>
+    where
+        H: MerkleHasher<Hash = D>,
+        V: MerkleData + ?Sized,
+    {
+        verifier::root_at_depth(hasher, self, &value.merkle_bytes(), top)
+    }
+
+    pub fn verify<H, V>(&self, hasher: &H, root: &D, value: &V) -> bool
+    where
+        H: MerkleHasher<Hash = D>,
+        V: MerkleData + ?Sized,
+        D: AsRef<[u8]>,
+    {
+        self.root_for(hasher, value)
+            .is_some_and(|computed| hashes_equal(computed.as_ref(), root.as_ref()))
+    }
+
+    /// The root this proof commits `value` to, going up by each level's position without
+    /// looking at the key, for verifiers that don't know how keys map to paths. Such a root
+    /// shows only that `value` is stored at the path the positions spell out; `verify` also
+    /// checks that the path is the key's.
+    pub fn root_by_position<H, V>(&self, hasher: &H, value: &V) -> Option<D>
+    where
+        H: MerkleHasher<Hash = D>,
+        V: MerkleData + ?Sized,
+    {
+        verifier::root_by_position(hasher, self, &value.merkle_bytes())
+    }
+
+    pub fn verify_by_position<H, V>(&self, hasher: &H, root: &D, value: &V) -> bool
+    where
+        H: MerkleHasher<Hash = D>,
+        V: MerkleData + ?Sized,
+        D: AsRef<[u8]>,
+    {
+        self.root_by_position(hasher, value)
+            .is_some_and(|computed| hashes_equal(computed.as_ref(), root.as_ref()))
+    }
+}
+
+impl<D> MerkleProof<D> {
+    /// Sets each level's position to the key's digit there, for proofs read from a form that
+    /// doesn't carry positions. Levels beyond the key's depth get position 0, and proofs with
+    /// an unusable arity are left alone; neither verifies.
+    pub fn with_key_positions(mut self) -> Self {
+        if !self.arity.is_power_of_two() || self.arity < 2 || self.arity > 256 {
+            return self;
+        }
+        let bits_per_digit = self.arity.trailing_zeros();
+        let count = self.levels.len() as u32;
+        for (level, ancestor_depth) in self.levels.iter_mut().zip((0..count).rev()) {
+            let digit = self
+                .key
+                .checked_shr(ancestor_depth * bits_per_digit)
+                .unwrap_or(0);
+            level.position = (digit as usize & (self.arity - 1)) as u8;
+        }
+        self
+    }
+}
+
+/// A value together with its key and the proof for it, to be handed around as one piece.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+pub struct ProvenEntry<T, D> {
+    pub key: u32,
+    pub value: T,
+    pub proof: MerkleProof<D>,
+}
+
+impl<T: MerkleData, D: Clone + AsRef<[u8]>> ProvenEntry<T, D> {
+    /// Whether `value` is stored under `key` in the trie with the given root. A proof for a
+    /// different key never verifies.
+    pub fn verify<H: MerkleHasher<Hash = D>>(&self, hasher: &H, root: &D) -> bool {
+        self.proof.key == self.key && self.proof.verify(hasher, root, &self.value)
+    }
+}
+
+// Format 2, all integers big-endian:
+//
+//   version u8 | key u32 | arity u16 | children root count u16 | children roots
+//   | level count u8 | per level: position u8 | data hash | sibling count u16 | siblings
+//
+// with every hash as u16 len + bytes. Format 1 is the same without the positions.
+impl<D: AsRef<[u8]>> MerkleProof<D> {
+    /// Encodes the proof in its own format version.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.version];
+        let push_hashes = |bytes: &mut Vec<u8>, hashes: &[D]| {
+            bytes.extend_from_slice(&(hashes.len() as u16).to_be_bytes());
+            for hash in hashes {
+                push_hash(bytes, hash.as_ref());
+            }
+        };
+        bytes.extend_from_slice(&self.key.to_be_bytes());
+        bytes.extend_from_slice(&(self.arity as u16).to_be_bytes());
+        push_hashes(&mut bytes, &self.children_roots);
+        bytes.push(self.levels.len() as u8);
+        for level in &self.levels {
+            if self.version >= 2 {
+                bytes.push(level.position);
+            }
+            push_hash(&mut bytes, level.data_hash.as_ref());
+            push_hashes(&mut bytes, &level.siblings);
+        }
+        bytes
+    }
+
+    /// Decodes a proof written by `to_bytes` in any supported format version; `None` if the
+    /// version is unknown or the bytes are malformed.
+    pub fn from_bytes<H: MerkleHasher<Hash = D>>(bytes: &[u8]) -> Option<Self> {
+        let (&version, mut bytes) = bytes.split_first()?;
+        let proof = match version {
+            1 => Self::decode::<H>(&mut bytes, version)?.with_key_positions(),
+            2 => Self::decode::<H>(&mut bytes, version)?,
+            _ => return None,
+        };
+        bytes.is_empty().then_some(proof)
+    }
+
+    fn decode<H: MerkleHasher<Hash = D>>(bytes: &mut &[u8], version: u8) -> Option<Self> {
+        fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+            let taken = bytes.get(..len)?;
+            *bytes = &bytes[len..];
+            Some(taken)
+        }
+        fn take_u16(bytes: &mut &[u8]) -> Option<u16> {
+            Some(u16::from_be_bytes(take(bytes, 2)?.try_into().ok()?))
+        }
+        fn take_hash<H: MerkleHasher>(bytes: &mut &[u8]) -> Option<H::Hash> {
+            let len = take_u16(bytes)? as usize;
+            H::hash_from_bytes(take(bytes, len)?)
+        }
+        fn take_hashes<H: MerkleHasher>(bytes: &mut &[u8]) -> Option<Vec<H::Hash>> {
+            let count = take_u16(bytes)?;
+            (0..count).map(|_| take_hash::<H>(bytes)).collect()
+        }
+
+        let key = u32::from_be_bytes(take(bytes, 4)?.try_into().ok()?);
+        let arity = take_u16(bytes)? as usize;
+        let children_roots = take_hashes::<H>(bytes)?;
+        let level_count = take(bytes, 1)?[0];
+        let levels = (0..level_count)
+            .map(|_| {
+                let position = if version >= 2 { take(bytes, 1)?[0] } else { 0 };
+                Some(ProofLevel {
+                    data_hash: take_hash::<H>(bytes)?,
+                    siblings: take_hashes::<H>(bytes)?,
+                    position,
+                })
+            })
+            .collect::<Option<_>>()?;
+        Some(MerkleProof {
+            version,
+            key,
+            arity,
+            children_roots,
+            levels,
+        })
+    }
+}
+
+fn push_hash(bytes: &mut Vec<u8>, hash: &[u8]) {
+    bytes.extend_from_slice(&(hash.len() as u16).to_be_bytes());
+    bytes.extend_from_slice(hash);
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    /// Proves the value under `key` against the current root; `None` if no value is stored
+    /// there. Computes (and caches) whatever subtree roots the proof needs.
+    pub fn generate_proof(&mut self, key: u32) -> Option<MerkleProof<H::Hash>> {
+        instrumentation::observe_proof_generation(key, || self.prove(key))
+    }
+
+    fn prove(&mut self, key: u32) -> Option<MerkleProof<H::Hash>> {
+        let depth = Self::key_depth(key);
+        let mut path: Vec<NodeIndex> = vec![ROOT];
+        for d in 0..depth {
+            let digit = Self::digit_at(key, d);
+            path.push(self.node(*path.last().unwrap()).child(digit)?);
+        }
+        let target = path.pop().unwrap();
+        self.node(target).get_data()?;
+
+        let children_roots = if self.node(target).is_leaf() {
+            vec![]
+        } else {
+            self.child_roots(target, None)
+        };
+        let mut levels = Vec::with_capacity(path.len());
+        for (ancestor_depth, index) in path.into_iter().enumerate().rev() {
+            let digit = Self::digit_at(key, ancestor_depth as u32);
+            levels.push(ProofLevel {
+                data_hash: self.data_hash_at(index),
+                siblings: self.child_roots(index, Some(digit)),
+                position: digit as u8,
+            });
+        }
+        Some(MerkleProof {
+            version: PROOF_FORMAT_VERSION,
+            key,
+            arity: N,
+            children_roots,
+            levels,
+        })
+    }
+
+    /// `generate_proof`, bundled with the key and a copy of the value.
+    pub fn generate_proven_entry(&mut self, key: u32) -> Option<ProvenEntry<T, H::Hash>>
+    where
+        T: Clone + PartialEq,
+    {
+        let proof = self.generate_proof(key)?;
+        let value = self.find_by_key(key)?.get_data()?.clone();
+        Some(ProvenEntry { key, value, proof })
+    }
+
+    /// The value under `key` and its proof, as `get` and `generate_proof` give, from a single
+    /// walk down to the key: each level is proved as the walk passes through it.
+    pub fn get_with_proof(&mut self, key: u32) -> Option<(&T, MerkleProof<H::Hash>)> {
+        let mut levels = Vec::with_capacity(Self::key_depth(key) as usize);
+        let mut index = ROOT;
+        for depth in 0..Self::key_depth(key) {
+            let digit = Self::digit_at(key, depth);
+            let child = self.node(index).child(digit)?;
+            levels.push(ProofLevel {
+                data_hash: self.data_hash_at(index),
+                siblings: self.child_roots(index, Some(digit)),
+                position: digit as u8,
+            });
+            index = child;
+        }
+        self.node(index).get_data()?;
+        let children_roots = if self.node(index).is_leaf() {
+            vec![]
+        } else {
+            self.child_roots(index, None)
+        };
+        levels.reverse();
+        let proof = MerkleProof {
+            version: PROOF_FORMAT_VERSION,
+            key,
+            arity: N,
+            children_roots,
+            levels,
+        };
+        Some((self.node(index).get_data()?, proof))
+    }
+
+    // The roots of every child slot of the internal node `index`, except `skip`.
+    pub(crate) fn child_roots(&mut self, index: NodeIndex, skip: Option<usize>) -> Vec<H::Hash> {
+        (0..N)
+            .filter(|digit| Some(*digit) != skip)
+            .map(|digit| match self.node(index).child(digit) {
+                Some(child) => self.merkle_root_at(child),
+                None => self.empty_hash().clone(),
+            })
+            .collect()
+    }
+
+    // `child_roots` for a shared borrow: roots missing from the caches are recomputed but not
+    // cached.
+    pub(crate) fn shared_child_roots(&self, index: NodeIndex, skip: Option<usize>) -> Vec<H::Hash> {
+        (0..N)
+            .filter(|digit| Some(*digit) != skip)
+            .map(|digit| match self.node(index).child(digit) {
+                Some(child) => self.uncached_root_at(child),
+                None => self.empty_hash().clone(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<T, H, const N: usize> TrieNode<T, H, N>
+where
+    T: MerkleData + Sync,
+    H: MerkleHasher + Sync,
+    H::Hash: Send + Sync,
+{
+    /// Proves each of `keys`, in order, on rayon's thread pool. The root is brought up to date
+    /// first. Keys are then split by digit level by level, so the data hash and child roots of a
+    /// node on the path of several keys are worked out once for all of them, and the subtrees
+    /// under different digits are proved as separate rayon tasks.
+    pub fn generate_proofs_parallel(&mut self, keys: &[u32]) -> Vec<Option<MerkleProof<H::Hash>>> {
+        self.merkle_root();
+        let mut proofs: Vec<_> = keys.iter().map(|_| None).collect();
+        let keys = keys.iter().copied().enumerate().collect();
+        for (at, proof) in self.prove_below(ROOT, 0, None, keys) {
+            proofs[at] = Some(proof);
+        }
+        proofs
+    }
+
+    // The proofs of those of `keys`, each paired with its place in the request, found at or
+    // below the node `index` at `depth`. `above` is the chain of levels from its parent up to
+    // the root. Hashes missing from the caches are recomputed but not cached.
+    fn prove_below(
+        &self,
+        index: NodeIndex,
+        depth: u32,
+        above: Option<&SharedLevel<'_, H::Hash>>,
+        keys: Vec<(usize, u32)>,
+    ) -> Vec<(usize, MerkleProof<H::Hash>)> {
+        use rayon::prelude::*;
+
+        let node = self.node(index);
+        let roots = if node.is_leaf() {
+            vec![]
+        } else {
+            self.shared_child_roots(index, None)
+        };
+        let mut proofs = vec![];
+        let mut groups: Vec<Vec<(usize, u32)>> = vec![vec![]; N];
+        for (at, key) in keys {
+            if Self::key_depth(key) > depth {
+                groups[Self::digit_at(key, depth)].push((at, key));
+            } else if node.get_data().is_some() {
+                proofs.push((at, Self::shared_proof(key, depth, roots.clone(), above)));
+            }
+        }
+        if groups.iter().all(Vec::is_empty) {
+            return proofs;
+        }
+
+        let level = SharedLevel {
+            data_hash: self.uncached_data_hash_at(index),
+            roots,
+            above,
+        };
+        let below: Vec<_> = groups
+            .into_par_iter()
+            .enumerate()
+            .filter(|(_, group)| !group.is_empty())
+            .filter_map(|(digit, group)| {
+                let child = node.child(digit)?;
+                Some(self.prove_below(child, depth + 1, Some(&level), group))
+            })
+            .flatten()
+            .collect();
+        proofs.extend(below);
+        proofs
+    }
+
+    fn shared_proof(
+        key: u32,
+        depth: u32,
+        children_roots: Vec<H::Hash>,
+        mut above: Option<&SharedLevel<'_, H::Hash>>,
+    ) -> MerkleProof<H::Hash> {
+        let mut levels = vec![];
+        let mut ancestor_depth = depth;
+        while let Some(level) = above {
+            ancestor_depth -= 1;
+            let digit = Self::digit_at(key, ancestor_depth);
+            let mut siblings = level.roots.clone();
+            siblings.remove(digit);
+            levels.push(ProofLevel {
+                data_hash: level.data_hash.clone(),
+                siblings,
+                position: digit as u8,
+            });
+            above = level.above;
+        }
+        MerkleProof {
+            version: PROOF_FORMAT_VERSION,
+            key,
+            arity: N,
+            children_roots,
+            levels,
+        }
+    }
+}
+
+// A node passed on the way down in `prove_below`: its data hash, the roots of all its children
+// and the node above it.
+#[cfg(feature = "rayon")]
+struct SharedLevel<'a, D> {
+    data_hash: D,
+    roots: Vec<D>,
+    above: Option<&'a SharedLevel<'a, D>>,
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn proofs_verify_against_root() {
+        let mut node: TrieNode<String> = TrieNode::new();
+        for key in [0, 1, 2, 3, 6, 13, 1000] {
+            node.insert(key, format!("value {key}"));
+        }
+        let root = node.merkle_root();
+        for key in [0, 1, 2, 3, 6, 13, 1000] {
+            let proof = node.generate_proof(key).unwrap();
+            assert!(proof.verify(&StdMerkleHasher, &root, &format!("value {key}")));
+            assert!(!proof.verify(&StdMerkleHasher, &root, "forged"));
+        }
+        assert_eq!(node.generate_proof(4), None);
+        assert_eq!(node.generate_proof(5), None);
+
+        let mut proof = node.generate_proof(6).unwrap();
+        proof.key = 4;
+        assert!(!proof.verify(&StdMerkleHasher, &root, "value 6"));
+    }
+
+    #[test]
+    fn positions_verify_without_the_key() {
+        let mut node: TrieNode<u32, StdMerkleHasher, 4> =
+            (0..50).map(|key| (key * 3, key)).collect();
+        let root = node.merkle_root();
+        let mut proof = node.generate_proof(42).unwrap();
+        // 42 is 222 in base 4, read from its lowest digit; levels go from the parent up.
+        let positions: Vec<u8> = proof.levels.iter().map(|level| level.position).collect();
+        assert_eq!(positions, [2, 2, 2]);
+        assert!(proof.verify_by_position(&StdMerkleHasher, &root, &14u32));
+
+        proof.key = 0;
+        assert!(proof.verify_by_position(&StdMerkleHasher, &root, &14u32));
+        assert!(!proof.verify(&StdMerkleHasher, &root, &14u32));
+        proof.key = 42;
+        proof.levels[1].position = 1;
+        assert!(!proof.verify_by_position(&StdMerkleHasher, &root, &14u32));
+        assert!(!proof.verify(&StdMerkleHasher, &root, &14u32));
+        proof.levels[1].position = 4;
+        assert_eq!(proof.root_by_position(&StdMerkleHasher, &14u32), None);
+        assert_eq!(proof.with_key_positions(), node.generate_proof(42).unwrap());
+    }
+
+    #[test]
+    fn proven_entries_verify_on_their_own() {
+        let mut node: TrieNode<u32> = (0..50).map(|key| (key * 3, key)).collect();
+        let root = node.merkle_root();
+        let entry = node.generate_proven_entry(42).unwrap();
+        assert_eq!(entry.value, 14);
+        assert!(entry.verify(&StdMerkleHasher, &root));
+
+        let mut moved = entry.clone();
+        moved.key = 45;
+        assert!(!moved.verify(&StdMerkleHasher, &root));
+        assert_eq!(node.generate_proven_entry(43), None);
+
+        let proof = node.generate_proof(42).unwrap();
+        assert_eq!(node.get_with_proof(42), Some((&14, proof)));
+        node.insert(1 << 20, 7);
+        let proof = node.generate_proof(0).unwrap();
+        assert_eq!(node.get_with_proof(0), Some((&0, proof)));
+        assert_eq!(node.get_with_proof(43), None);
+        assert_eq!(node.get_with_proof(1 << 21), None);
+
+        #[cfg(feature = "serde")]
+        {
+            let bytes = bincode::serialize(&entry).unwrap();
+            let decoded: ProvenEntry<u32, String> = bincode::deserialize(&bytes).unwrap();
+            assert_eq!(decoded, entry);
+        }
+        #[cfg(feature = "borsh")]
+        {
+            let bytes = borsh::to_vec(&entry).unwrap();
+            let decoded: ProvenEntry<u32, String> = borsh::from_slice(&bytes).unwrap();
+            assert_eq!(decoded, entry);
+        }
+    }
+
+    #[test]
+    fn proofs_round_trip_through_their_format_version() {
+        let mut node: TrieNode<u32> = (0..50).map(|key| (key * 3, key)).collect();
+        let root = node.merkle_root();
+        let proof = node.generate_proof(42).unwrap();
+        let bytes = proof.to_bytes();
+        assert_eq!(bytes[0], 2);
+        let decoded = MerkleProof::from_bytes::<StdMerkleHasher>(&bytes).unwrap();
+        assert_eq!(decoded, proof);
+        assert!(decoded.verify(&StdMerkleHasher, &root, &14u32));
+
+        let mut future = bytes.clone();
+        future[0] = 200;
+        assert_eq!(MerkleProof::from_bytes::<StdMerkleHasher>(&future), None);
+        assert_eq!(
+            MerkleProof::from_bytes::<StdMerkleHasher>(&bytes[..bytes.len() - 1]),
+            None
+        );
+        let mut unknown = proof.clone();
+        unknown.version = 200;
+        assert!(!unknown.verify(&StdMerkleHasher, &root, &14u32));
+
+        // A format 1 proof is the format 2 one without positions, which decoding restores.
+        let mut old = proof.clone();
+        old.version = 1;
+        let old_bytes = old.to_bytes();
+        assert_eq!(old_bytes.len(), bytes.len() - proof.levels.len());
+        assert_eq!(
+            MerkleProof::from_bytes::<StdMerkleHasher>(&old_bytes),
+            Some(old)
+        );
+
+        assert_eq!(negotiate_proof_version(&[1, 7]), Some(1));
+        assert_eq!(negotiate_proof_version(&[1, 2]), Some(2));
+        assert_eq!(negotiate_proof_version(&[7]), None);
+    }
+
+    #[test]
+    fn wide_arity_proofs() {
+        let mut node: TrieNode<u32, StdMerkleHasher, 16> = TrieNode::new();
+        for key in [1, 17, 0x1a3, u32::MAX] {
+            node.insert(key, key);
+        }
+        let root = node.merkle_root();
+        let proof = node.generate_proof(0x1a3).unwrap();
+        assert_eq!(proof.levels.len(), 3);
+        assert!(proof.levels.iter().all(|level| level.siblings.len() == 15));
+        assert!(proof.verify(&StdMerkleHasher, &root, &0x1a3u32));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_proofs_match_serial_ones() {
+        let mut node: TrieNode<u32> = TrieNode::new();
+        for key in (0..500).map(|k| k * 7) {
+            node.insert(key, key);
+        }
+        // Out of order and repeated keys come back in the order asked for.
+        let keys: Vec<u32> = (0..600).rev().chain([7, 0, 7]).collect();
+        let parallel = node.generate_proofs_parallel(&keys);
+        for (key, proof) in keys.iter().zip(parallel) {
+            assert_eq!(proof, node.generate_proof(*key));
+        }
+    }
+}
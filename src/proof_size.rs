@@ -0,0 +1,84 @@
+use crate::trie_node::trie_node::{TrieNode, ROOT};
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// The length of `MerkleProof::to_bytes` in the current format for a key `depth` digits deep,
+/// with every hash `hash_len` bytes; `with_children` if the proven node has children.
+pub fn encoded_proof_size(arity: usize, depth: u32, with_children: bool, hash_len: usize) -> usize {
+    let hash = 2 + hash_len;
+    let children = if with_children { arity * hash } else { 0 };
+    // position, data hash, sibling count and siblings.
+    let level = 1 + hash + 2 + (arity - 1) * hash;
+    1 + 4 + 2 + 2 + children + 1 + depth as usize * level
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    /// How many bytes `generate_proof(key).to_bytes()` would take, found from the trie's shape
+    /// without hashing anything; `None` if no value is stored under `key`. Exact for hashers
+    /// with fixed-size hashes, and an upper bound otherwise.
+    pub fn proof_size_for(&self, key: u32) -> Option<usize> {
+        let mut index = ROOT;
+        for depth in 0..Self::key_depth(key) {
+            index = self.node(index).child(Self::digit_at(key, depth))?;
+        }
+        let node = self.node(index);
+        node.get_data()?;
+        Some(encoded_proof_size(
+            N,
+            Self::key_depth(key),
+            !node.is_leaf(),
+            self.hasher().max_hash_len(),
+        ))
+    }
+
+    /// The most bytes a proof from a trie of this arity and hasher can take, whatever it holds:
+    /// that of a key as deep as a `u32` key can be.
+    pub fn max_proof_size(&self) -> usize {
+        let depth = u32::BITS.div_ceil(Self::BITS_PER_DIGIT);
+        let hash_len = self.hasher().max_hash_len();
+        // Only a key one digit shallower can have children, and a level outweighs them.
+        encoded_proof_size(N, depth, false, hash_len).max(encoded_proof_size(
+            N,
+            depth - 1,
+            true,
+            hash_len,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn sizes_bound_the_proofs() {
+        let mut trie: TrieNode<u32, StdMerkleHasher, 4> =
+            (0..200).map(|key| (key * 5, key)).collect();
+        trie.insert(u32::MAX, 0);
+        for key in (0..1000).chain([u32::MAX]) {
+            let proof = trie.generate_proof(key).map(|proof| proof.to_bytes().len());
+            match trie.proof_size_for(key) {
+                Some(size) => assert!(proof.unwrap() <= size && size <= trie.max_proof_size()),
+                None => assert_eq!(proof, None),
+            }
+        }
+
+        #[cfg(feature = "blake3")]
+        {
+            use crate::hasher::Blake3Hasher;
+            let mut trie: TrieNode<u32, Blake3Hasher, 16> =
+                TrieNode::with_hasher(Blake3Hasher::default());
+            trie.insert_batch((0..200).map(|key| (key * 5, key)));
+            trie.insert(u32::MAX, 0);
+            for key in [0, 5, 995, u32::MAX] {
+                let proof = trie.generate_proof(key).unwrap().to_bytes();
+                assert_eq!(trie.proof_size_for(key), Some(proof.len()));
+            }
+            assert_eq!(
+                trie.max_proof_size(),
+                trie.generate_proof(u32::MAX).unwrap().to_bytes().len()
+            );
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::BTreeMap;
+
+use crate::trie_node::trie_node::TrieNode;
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// The root of a trie of arity `n` holding `entries`, recomputed from scratch by following the
+/// commitment's definition as directly as possible: no arena, no caches, and every key
+/// rescanned at every node. Meant as an oracle for tests, not for real use, since it takes
+/// time quadratic in the number of entries.
+///
+/// A node exists at every prefix of every key's path; it holds the value of the key whose
+/// path it ends, if any. A node without children hashes to its data hash, any other to its
+/// data hash combined with its children's roots, where a missing child is the empty hash.
+pub fn reference_root<T: MerkleData, H: MerkleHasher>(
+    hasher: &H,
+    n: usize,
+    entries: &BTreeMap<u32, T>,
+) -> H::Hash {
+    assert!(n.is_power_of_two() && (2..=256).contains(&n));
+    let bits = n.trailing_zeros();
+    let depth_of = |key: u32| (u32::BITS - key.leading_zeros()).div_ceil(bits);
+    let keys: Vec<u32> = entries.keys().copied().collect();
+    reference_root_at(hasher, n, entries, &depth_of, &keys, 0, 0)
+}
+
+fn reference_root_at<T: MerkleData, H: MerkleHasher>(
+    hasher: &H,
+    n: usize,
+    entries: &BTreeMap<u32, T>,
+    depth_of: &impl Fn(u32) -> u32,
+    keys: &[u32],
+    path: u32,
+    depth: u32,
+) -> H::Hash {
+    let bits = n.trailing_zeros();
+    let data_hash = match keys
+        .iter()
+        .find(|key| **key == path && depth_of(**key) == depth)
+    {
+        Some(key) => hasher.hash(&entries[key].merkle_bytes()),
+        None => hasher.empty_hash(),
+    };
+    let mut has_children = false;
+    let children: Vec<H::Hash> = (0..n as u32)
+        .map(|digit| {
+            let below: Vec<u32> = keys
+                .iter()
+                .copied()
+                .filter(|key| {
+                    depth_of(*key) > depth && (key >> (depth * bits)) & (n as u32 - 1) == digit
+                })
+                .collect();
+            if below.is_empty() {
+                return hasher.empty_hash();
+            }
+            has_children = true;
+            let child = path | digit << (depth * bits);
+            reference_root_at(hasher, n, entries, depth_of, &below, child, depth + 1)
+        })
+        .collect();
+    if has_children {
+        hasher.combine_children(&data_hash, &children)
+    } else {
+        data_hash
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    /// In debug builds, panics unless the root matches `reference_root` over the trie's
+    /// entries, which catches a cache a mutation failed to invalidate; does nothing in release
+    /// builds. The reference knows only the entries, so a trie still holding the value-less
+    /// branches `remove_subtree` leaves behind needs `compact` first.
+    pub fn assert_matches_reference(&mut self) {
+        if cfg!(debug_assertions) {
+            let entries: BTreeMap<u32, &T> = self.iter().collect();
+            let expected = reference_root(self.hasher(), N, &entries);
+            assert_eq!(
+                self.merkle_root(),
+                expected,
+                "the trie's root differs from the reference root of its entries"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn reference_agrees_with_the_trie() {
+        let entries: BTreeMap<u32, u32> = (0..200).map(|key| (key * 7, key)).collect();
+        let mut node: TrieNode<u32, StdMerkleHasher, 4> = entries.clone().into_iter().collect();
+        assert_eq!(
+            reference_root(&StdMerkleHasher, 4, &entries),
+            node.merkle_root()
+        );
+        node.insert(3, 3);
+        node.retain(|key, _| key % 2 == 1);
+        node.assert_matches_reference();
+
+        let empty = BTreeMap::<u32, u32>::new();
+        assert_eq!(
+            reference_root(&StdMerkleHasher, 2, &empty),
+            TrieNode::<u32>::new().merkle_root()
+        );
+
+        // A stale cache left by a buggy wrapper is caught.
+        let corrupt = node.hasher().hash(b"corrupt");
+        node.cache_merkle_root(crate::trie_node::trie_node::ROOT, corrupt);
+        let caught = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            node.assert_matches_reference()
+        }));
+        assert_eq!(caught.is_err(), cfg!(debug_assertions));
+    }
+}
@@ -0,0 +1,333 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::hasher::{hashes_equal, MerkleHasher};
+use crate::merkle_data::MerkleData;
+use crate::proof::MerkleProof;
+use crate::state_sync::NodePosition;
+use crate::trie_node::trie_node::TrieNode;
+
+/// A node a commit changed, as a replica needs it: its value and the root of each child
+/// slot (`None` if empty).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangedNode<T, D> {
+    pub position: NodePosition,
+    pub value: Option<T>,
+    pub children: Vec<Option<D>>,
+}
+
+/// What a `Primary` emits on commit: the roots before and after, and every node the commit
+/// changed, parents before children.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootDelta<T, D> {
+    pub previous_root: D,
+    pub root: D,
+    pub nodes: Vec<ChangedNode<T, D>>,
+}
+
+/// Why a replica refused a delta. It is left as it was.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeltaError {
+    /// The delta follows a root other than the replica's, so one was missed or replayed.
+    WrongBase,
+    /// The nodes don't form a delta: a position is repeated or out of range, the root node is
+    /// missing, or a node isn't linked from its parent in the delta.
+    Malformed,
+    /// The nodes don't hash to the announced root, or link to a subtree the replica doesn't
+    /// hold.
+    RootMismatch,
+}
+
+/// The writing side of a replicated trie: it records the paths each change touches, and
+/// `commit` turns them into a `RootDelta` for the replicas.
+pub struct Primary<T: MerkleData, H: MerkleHasher, const N: usize = 2> {
+    trie: TrieNode<T, H, N>,
+    // (depth, path) of every node changed since the last commit, so parents sort first.
+    touched: BTreeSet<(u32, u32)>,
+    committed: H::Hash,
+}
+
+impl<T: MerkleData + Clone + PartialEq, H: MerkleHasher + Default, const N: usize> Default
+    for Primary<T, H, N>
+{
+    fn default() -> Self {
+        Primary::with_hasher(H::default())
+    }
+}
+
+impl<T: MerkleData + Clone + PartialEq, H: MerkleHasher, const N: usize> Primary<T, H, N> {
+    pub fn with_hasher(hasher: H) -> Self {
+        let mut trie = TrieNode::with_hasher(hasher);
+        let committed = trie.merkle_root();
+        Primary {
+            trie,
+            touched: BTreeSet::new(),
+            committed,
+        }
+    }
+
+    pub fn trie(&self) -> &TrieNode<T, H, N> {
+        &self.trie
+    }
+
+    // Records the nodes on the path to the first `depth` digits of `path`.
+    fn touch(&mut self, path: u32, depth: u32) {
+        let bits_per_digit = TrieNode::<T, H, N>::BITS_PER_DIGIT;
+        for depth in 0..=depth {
+            let mask = 1u32
+                .checked_shl(depth * bits_per_digit)
+                .map_or(u32::MAX, |bit| bit - 1);
+            self.touched.insert((depth, path & mask));
+        }
+    }
+
+    pub fn insert(&mut self, key: u32, value: T) {
+        self.touch(key, TrieNode::<T, H, N>::key_depth(key));
+        self.trie.insert(key, value);
+    }
+
+    /// As `TrieNode::remove_subtree`.
+    pub fn remove_subtree(&mut self, prefix: u32, prefix_len: u32) -> bool {
+        if prefix_len > 0 {
+            self.touch(prefix, prefix_len - 1);
+        } else {
+            self.touch(0, 0);
+        }
+        self.trie.remove_subtree(prefix, prefix_len)
+    }
+
+    /// The changes since the last commit, with the root they lead to.
+    pub fn commit(&mut self) -> RootDelta<T, H::Hash> {
+        let root = self.trie.merkle_root();
+        let mut nodes = vec![];
+        for (depth, path) in std::mem::take(&mut self.touched) {
+            let position = NodePosition { path, depth };
+            // Gone since it was touched; its parent, also touched, shows the empty slot.
+            let Some(index) = self.trie.find_position(position) else {
+                continue;
+            };
+            let summary = self.trie.delta_summaries(&[position]).pop().flatten();
+            nodes.push(ChangedNode {
+                position,
+                value: self.trie.node(index).get_data().cloned(),
+                children: summary.map_or(vec![], |summary| summary.children),
+            });
+        }
+        let previous_root = std::mem::replace(&mut self.committed, root.clone());
+        RootDelta {
+            previous_root,
+            root,
+            nodes,
+        }
+    }
+}
+
+/// A read replica of a `Primary`'s trie, kept up to date by its deltas. Each delta is checked
+/// to hash to its announced root before any of it is applied, so the replica only ever holds
+/// a trie the primary committed to, and its reads and proofs can be served as the primary's.
+pub struct Replica<T: MerkleData, H: MerkleHasher, const N: usize = 2> {
+    trie: TrieNode<T, H, N>,
+    root: H::Hash,
+}
+
+impl<T: MerkleData + PartialEq, H: MerkleHasher + Default, const N: usize> Default
+    for Replica<T, H, N>
+{
+    fn default() -> Self {
+        Replica::with_hasher(H::default())
+    }
+}
+
+impl<T: MerkleData + PartialEq, H: MerkleHasher, const N: usize> Replica<T, H, N> {
+    /// An empty replica, to be fed a primary's deltas from its first commit on.
+    pub fn with_hasher(hasher: H) -> Self {
+        let mut trie = TrieNode::with_hasher(hasher);
+        let root = trie.merkle_root();
+        Replica { trie, root }
+    }
+
+    pub fn trie(&self) -> &TrieNode<T, H, N> {
+        &self.trie
+    }
+
+    pub fn root(&self) -> &H::Hash {
+        &self.root
+    }
+
+    pub fn get(&self, key: u32) -> Option<&T> {
+        self.trie.get(key)
+    }
+
+    pub fn generate_proof(&mut self, key: u32) -> Option<MerkleProof<H::Hash>> {
+        self.trie.generate_proof(key)
+    }
+
+    /// Checks `delta` against its announced root and applies it.
+    pub fn apply(&mut self, delta: RootDelta<T, H::Hash>) -> Result<(), DeltaError> {
+        if !hashes_equal(delta.previous_root.as_ref(), self.root.as_ref()) {
+            return Err(DeltaError::WrongBase);
+        }
+        self.check(&delta)?;
+        for node in delta.nodes {
+            let position = node.position;
+            let index = self.trie.create_path(position.path, position.depth);
+            match node.value {
+                Some(value) => {
+                    self.trie.node_mut(index).replace_data(value);
+                    self.trie.note_key(position.path);
+                }
+                None => {
+                    self.trie.node_mut(index).take_data();
+                }
+            }
+            for (digit, child) in node.children.iter().enumerate() {
+                if child.is_none() && self.trie.node(index).child(digit).is_some() {
+                    let child = TrieNode::<T, H, N>::child_position(position, digit);
+                    self.trie.remove_subtree(child.path, child.depth);
+                }
+            }
+        }
+        self.trie.rehash_if_eager();
+        debug_assert!(self.trie.merkle_root() == delta.root);
+        self.root = delta.root;
+        Ok(())
+    }
+
+    // Recomputes the root the delta leads to, children first, from its nodes and the roots of
+    // the replica's subtrees it leaves alone.
+    fn check(&mut self, delta: &RootDelta<T, H::Hash>) -> Result<(), DeltaError> {
+        if delta.nodes.is_empty() {
+            return match hashes_equal(delta.root.as_ref(), self.root.as_ref()) {
+                true => Ok(()),
+                false => Err(DeltaError::RootMismatch),
+            };
+        }
+        let bits_per_digit = TrieNode::<T, H, N>::BITS_PER_DIGIT;
+        let in_range = |position: NodePosition| {
+            position
+                .depth
+                .checked_mul(bits_per_digit)
+                .filter(|bits| *bits <= u32::BITS)
+                .is_some_and(|bits| position.path.checked_shr(bits).unwrap_or(0) == 0)
+        };
+        let mut positions = HashMap::new();
+        for (at, node) in delta.nodes.iter().enumerate() {
+            let position = node.position;
+            if !in_range(position) {
+                return Err(DeltaError::Malformed);
+            }
+            let linked = match position.depth {
+                0 => at == 0,
+                depth => {
+                    let parent = NodePosition {
+                        path: position.path & ((1 << ((depth - 1) * bits_per_digit)) - 1),
+                        depth: depth - 1,
+                    };
+                    let digit = TrieNode::<T, H, N>::digit_at(position.path, depth - 1);
+                    positions.get(&parent).is_some_and(|parent: &usize| {
+                        delta.nodes[*parent].children[digit].is_some()
+                    })
+                }
+            };
+            let children_fit = node.children.len() == N
+                && (position.depth * bits_per_digit < u32::BITS
+                    || node.children.iter().all(Option::is_none));
+            if !linked || !children_fit || positions.insert(position, at).is_some() {
+                return Err(DeltaError::Malformed);
+            }
+        }
+
+        let empty = self.trie.empty_hash().clone();
+        let mut roots: HashMap<NodePosition, H::Hash> = HashMap::new();
+        for node in delta.nodes.iter().rev() {
+            let mut children = Vec::with_capacity(N);
+            for (digit, listed) in node.children.iter().enumerate() {
+                let Some(listed) = listed else {
+                    children.push(empty.clone());
+                    continue;
+                };
+                let child = TrieNode::<T, H, N>::child_position(node.position, digit);
+                let actual = match roots.get(&child) {
+                    Some(root) => Some(root.clone()),
+                    None => self
+                        .trie
+                        .find_position(child)
+                        .map(|index| self.trie.merkle_root_at(index)),
+                };
+                if !actual.is_some_and(|actual| hashes_equal(actual.as_ref(), listed.as_ref())) {
+                    return Err(DeltaError::RootMismatch);
+                }
+                children.push(listed.clone());
+            }
+            let data_hash = match &node.value {
+                Some(value) => self.trie.hasher().hash(&value.merkle_bytes()),
+                None => empty.clone(),
+            };
+            let root = match node.children.iter().all(Option::is_none) {
+                true => data_hash,
+                false => self.trie.hasher().combine_children(&data_hash, &children),
+            };
+            roots.insert(node.position, root);
+        }
+        let root = &roots[&NodePosition { path: 0, depth: 0 }];
+        match hashes_equal(root.as_ref(), delta.root.as_ref()) {
+            true => Ok(()),
+            false => Err(DeltaError::RootMismatch),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn replicas_follow_the_primary_through_checked_deltas() {
+        let mut primary: Primary<u32, StdMerkleHasher, 4> = Primary::default();
+        let mut replica: Replica<u32, StdMerkleHasher, 4> = Replica::default();
+        for key in 0..300 {
+            primary.insert(key * 3, key);
+        }
+        let snapshot = primary.commit();
+        assert_eq!(snapshot.nodes.len(), primary.trie().metrics().node_count);
+        replica.apply(snapshot.clone()).unwrap();
+        assert_eq!(replica.root(), &snapshot.root);
+        assert_eq!(replica.get(30), Some(&10));
+
+        primary.insert(30, 1000);
+        primary.insert(2000, 1);
+        primary.remove_subtree(0b1011, 2);
+        let delta = primary.commit();
+        // Only the paths to the changes, sharing the root: 4 nodes down to key 30, 7 down to
+        // key 2000 and 2 down to the removed subtree's parent.
+        assert_eq!(delta.nodes.len(), 11);
+
+        // A tampered value, a dropped root node and a replay are all refused, leaving the
+        // replica as it was.
+        let mut tampered = delta.clone();
+        let changed = tampered
+            .nodes
+            .iter_mut()
+            .find(|node| node.value == Some(1000));
+        changed.unwrap().value = Some(999);
+        assert_eq!(replica.apply(tampered), Err(DeltaError::RootMismatch));
+        let mut rootless = delta.clone();
+        rootless.nodes.remove(0);
+        assert_eq!(replica.apply(rootless), Err(DeltaError::Malformed));
+        assert_eq!(replica.get(30), Some(&10));
+        assert_eq!(replica.apply(snapshot), Err(DeltaError::WrongBase));
+
+        replica.apply(delta.clone()).unwrap();
+        assert_eq!(replica.trie(), primary.trie());
+        assert_eq!(replica.get(30), Some(&1000));
+        assert_eq!(replica.get(11), None);
+        let proof = replica.generate_proof(2000).unwrap();
+        assert!(proof.verify(&StdMerkleHasher, &delta.root, &1u32));
+
+        let unchanged = primary.commit();
+        assert!(unchanged.nodes.is_empty());
+        replica.apply(unchanged).unwrap();
+        assert_eq!(replica.root(), &delta.root);
+    }
+}
@@ -0,0 +1,330 @@
+use crate::codec::ValueCodec;
+use crate::hasher::MerkleHasher;
+use crate::proof::{MerkleProof, ProofLevel};
+use crate::trie_node::trie_node::{Node, NodeIndex};
+
+/// A value in Ethereum's recursive length prefix encoding: a byte string or a list of values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rlp {
+    Bytes(Vec<u8>),
+    List(Vec<Rlp>),
+}
+
+impl Rlp {
+    /// An integer as its big-endian bytes without leading zeros, so zero is the empty string.
+    pub fn uint(value: u64) -> Self {
+        let bytes = value.to_be_bytes();
+        let start = bytes.iter().position(|byte| *byte != 0).unwrap_or(8);
+        Rlp::Bytes(bytes[start..].to_vec())
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Rlp::Bytes(bytes) => Some(bytes),
+            Rlp::List(_) => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Rlp]> {
+        match self {
+            Rlp::Bytes(_) => None,
+            Rlp::List(items) => Some(items),
+        }
+    }
+
+    /// `None` unless this is an integer as `uint` writes it.
+    pub fn as_uint(&self) -> Option<u64> {
+        let bytes = self.as_bytes()?;
+        if bytes.len() > 8 || bytes.first() == Some(&0) {
+            return None;
+        }
+        Some(
+            bytes
+                .iter()
+                .fold(0, |value, byte| value << 8 | *byte as u64),
+        )
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = vec![];
+        self.encode_into(&mut out);
+        out
+    }
+
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        match self {
+            Rlp::Bytes(bytes) if bytes.len() == 1 && bytes[0] < 0x80 => out.push(bytes[0]),
+            Rlp::Bytes(bytes) => {
+                push_header(out, 0x80, bytes.len());
+                out.extend_from_slice(bytes);
+            }
+            Rlp::List(items) => {
+                let mut payload = vec![];
+                for item in items {
+                    item.encode_into(&mut payload);
+                }
+                push_header(out, 0xc0, payload.len());
+                out.extend(payload);
+            }
+        }
+    }
+
+    /// Decodes exactly one value; `None` if `bytes` are malformed, not in canonical form or
+    /// have anything after the value.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let (item, rest) = Self::decode_prefix(bytes)?;
+        rest.is_empty().then_some(item)
+    }
+
+    fn decode_prefix(bytes: &[u8]) -> Option<(Self, &[u8])> {
+        let (&first, rest) = bytes.split_first()?;
+        if first < 0x80 {
+            return Some((Rlp::Bytes(vec![first]), rest));
+        }
+        let (list, offset) = if first < 0xc0 {
+            (false, first - 0x80)
+        } else {
+            (true, first - 0xc0)
+        };
+        let (len, rest) = if offset <= 55 {
+            (offset as usize, rest)
+        } else {
+            let len_bytes = rest.get(..(offset - 55) as usize)?;
+            if len_bytes.len() > 8 || len_bytes[0] == 0 {
+                return None;
+            }
+            let len = len_bytes
+                .iter()
+                .fold(0u64, |len, byte| len << 8 | *byte as u64);
+            if len <= 55 {
+                return None;
+            }
+            (usize::try_from(len).ok()?, &rest[len_bytes.len()..])
+        };
+        let payload = rest.get(..len)?;
+        let rest = &rest[len..];
+        if !list {
+            if len == 1 && payload[0] < 0x80 {
+                return None;
+            }
+            return Some((Rlp::Bytes(payload.to_vec()), rest));
+        }
+        let mut items = vec![];
+        let mut payload = payload;
+        while !payload.is_empty() {
+            let (item, remaining) = Self::decode_prefix(payload)?;
+            items.push(item);
+            payload = remaining;
+        }
+        Some((Rlp::List(items), rest))
+    }
+}
+
+fn push_header(out: &mut Vec<u8>, offset: u8, len: usize) {
+    if len <= 55 {
+        out.push(offset + len as u8);
+    } else {
+        let Rlp::Bytes(len_bytes) = Rlp::uint(len as u64) else {
+            unreachable!()
+        };
+        out.push(offset + 55 + len_bytes.len() as u8);
+        out.extend(len_bytes);
+    }
+}
+
+fn hashes<D: AsRef<[u8]>>(hashes: &[D]) -> Rlp {
+    Rlp::List(
+        hashes
+            .iter()
+            .map(|hash| Rlp::Bytes(hash.as_ref().to_vec()))
+            .collect(),
+    )
+}
+
+fn hashes_from<H: MerkleHasher>(items: &Rlp) -> Option<Vec<H::Hash>> {
+    items
+        .as_list()?
+        .iter()
+        .map(|item| H::hash_from_bytes(item.as_bytes()?))
+        .collect()
+}
+
+// A proof is the list [version, key, arity, [children roots], [[data hash, [siblings],
+// position], ..]], where format 1 proofs leave out the positions.
+impl<D: AsRef<[u8]>> MerkleProof<D> {
+    pub fn to_rlp(&self) -> Vec<u8> {
+        let levels = self
+            .levels
+            .iter()
+            .map(|level| {
+                let mut items = vec![
+                    Rlp::Bytes(level.data_hash.as_ref().to_vec()),
+                    hashes(&level.siblings),
+                ];
+                if self.version >= 2 {
+                    items.push(Rlp::uint(level.position as u64));
+                }
+                Rlp::List(items)
+            })
+            .collect();
+        Rlp::List(vec![
+            Rlp::uint(self.version as u64),
+            Rlp::uint(self.key as u64),
+            Rlp::uint(self.arity as u64),
+            hashes(&self.children_roots),
+            Rlp::List(levels),
+        ])
+        .encode()
+    }
+
+    /// Decodes a proof written by `to_rlp`; `None` if the bytes are malformed.
+    pub fn from_rlp<H: MerkleHasher<Hash = D>>(bytes: &[u8]) -> Option<Self> {
+        let item = Rlp::decode(bytes)?;
+        let [version, key, arity, children_roots, levels] = item.as_list()? else {
+            return None;
+        };
+        let version = u8::try_from(version.as_uint()?).ok()?;
+        let levels = levels
+            .as_list()?
+            .iter()
+            .map(|level| {
+                let (data_hash, siblings, position) = match level.as_list()? {
+                    [data_hash, siblings] if version < 2 => (data_hash, siblings, 0),
+                    [data_hash, siblings, position] if version >= 2 => {
+                        (data_hash, siblings, u8::try_from(position.as_uint()?).ok()?)
+                    }
+                    _ => return None,
+                };
+                Some(ProofLevel {
+                    data_hash: H::hash_from_bytes(data_hash.as_bytes()?)?,
+                    siblings: hashes_from::<H>(siblings)?,
+                    position,
+                })
+            })
+            .collect::<Option<_>>()?;
+        let proof = MerkleProof {
+            version,
+            key: u32::try_from(key.as_uint()?).ok()?,
+            arity: usize::try_from(arity.as_uint()?).ok()?,
+            children_roots: hashes_from::<H>(children_roots)?,
+            levels,
+        };
+        Some(if version < 2 {
+            proof.with_key_positions()
+        } else {
+            proof
+        })
+    }
+}
+
+// A leaf is the list [value] and an internal node [value, [child, ..]], where a missing value
+// is the empty list and a missing child the empty string, which no child index encodes to since
+// index 0 is the root. Cached hashes are left out.
+impl<T, D, const N: usize> Node<T, D, N> {
+    pub fn to_rlp<C: ValueCodec<T>>(&self, codec: &C) -> Vec<u8> {
+        let value = match self.get_data() {
+            Some(value) => Rlp::Bytes(codec.encode(value)),
+            None => Rlp::List(vec![]),
+        };
+        let mut items = vec![value];
+        if !self.is_leaf() {
+            let children = self
+                .children()
+                .iter()
+                .map(|child| match child {
+                    Some(child) => Rlp::uint(*child as u64),
+                    None => Rlp::Bytes(vec![]),
+                })
+                .collect();
+            items.push(Rlp::List(children));
+        }
+        Rlp::List(items).encode()
+    }
+
+    /// Decodes a node written by `to_rlp`, with nothing cached; `None` if the bytes are
+    /// malformed or the node does not have `N` children.
+    pub fn from_rlp<C: ValueCodec<T>>(bytes: &[u8], codec: &C) -> Option<Self> {
+        let item = Rlp::decode(bytes)?;
+        let (value, children) = match item.as_list()? {
+            [value] => (value, None),
+            [value, children] => (value, Some(children.as_list()?)),
+            _ => return None,
+        };
+        let maybe_data = match value {
+            Rlp::Bytes(bytes) => Some(codec.decode(bytes)?),
+            Rlp::List(items) if items.is_empty() => None,
+            Rlp::List(_) => return None,
+        };
+        let Some(children) = children else {
+            return Some(Node::new(maybe_data));
+        };
+        let children: Vec<Option<NodeIndex>> = children
+            .iter()
+            .map(|child| match child.as_bytes()? {
+                [] => Some(None),
+                _ => Some(Some(NodeIndex::try_from(child.as_uint()?).ok()?)),
+            })
+            .collect::<Option<_>>()?;
+        Some(Node::Internal {
+            maybe_data,
+            maybe_cached_data_hash: None,
+            maybe_cached_merkle_root: None,
+            generation: 0,
+            children: Box::new(children.try_into().ok()?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::codec::DisplayCodec;
+    use crate::hasher::StdMerkleHasher;
+    use crate::trie_node::trie_node::TrieNode;
+
+    #[test]
+    fn encodings_match_the_spec_and_round_trip() {
+        let bytes = |bytes: &[u8]| Rlp::Bytes(bytes.to_vec());
+        assert_eq!(bytes(b"dog").encode(), b"\x83dog");
+        assert_eq!(
+            Rlp::List(vec![bytes(b"cat"), bytes(b"dog")]).encode(),
+            b"\xc8\x83cat\x83dog"
+        );
+        assert_eq!(bytes(b"").encode(), [0x80]);
+        assert_eq!(Rlp::List(vec![]).encode(), [0xc0]);
+        assert_eq!(Rlp::uint(0).encode(), [0x80]);
+        assert_eq!(Rlp::uint(15).encode(), [0x0f]);
+        assert_eq!(Rlp::uint(1024).encode(), [0x82, 0x04, 0x00]);
+        let long = bytes(&[b'a'; 56]);
+        assert_eq!(long.encode()[..2], [0xb8, 56]);
+        assert_eq!(Rlp::decode(&long.encode()), Some(long));
+        // Non-canonical forms are rejected.
+        assert_eq!(Rlp::decode(&[0x81, 0x05]), None);
+        assert_eq!(Rlp::decode(&[0xb8, 0x05, 1, 2, 3, 4, 5]), None);
+        assert_eq!(Rlp::decode(&[0x83, b'd', b'o']), None);
+        assert_eq!(Rlp::decode(b"\x83dog\x00"), None);
+
+        let mut node: TrieNode<u32> = (0..50).map(|key| (key * 3, key)).collect();
+        node.merkle_root();
+        let proof = node.generate_proof(42).unwrap();
+        let encoded = proof.to_rlp();
+        assert_eq!(
+            MerkleProof::from_rlp::<StdMerkleHasher>(&encoded),
+            Some(proof)
+        );
+        assert_eq!(
+            MerkleProof::from_rlp::<StdMerkleHasher>(&encoded[1..]),
+            None
+        );
+
+        for key in [0, 42] {
+            let original = node.find_by_key(key).unwrap();
+            let decoded: Node<u32> =
+                Node::from_rlp(&original.to_rlp(&DisplayCodec), &DisplayCodec).unwrap();
+            assert_eq!(decoded.get_data(), original.get_data());
+            assert_eq!(decoded.is_leaf(), original.is_leaf());
+            assert_eq!(decoded.children(), original.children());
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::VecDeque;
+
+use crate::hasher::{hashes_equal, MerkleHasher};
+use crate::merkle_data::MerkleData;
+use crate::proof::MerkleProof;
+use crate::trie_node::trie_node::TrieNode;
+
+/// A trie that remembers its last few committed roots, for clients that are an update or two
+/// behind. With snapshots on, a copy of the trie is kept for each of those roots so proofs can
+/// still be generated against them.
+pub struct RootHistory<T: MerkleData, H: MerkleHasher, const N: usize = 2> {
+    trie: TrieNode<T, H, N>,
+    capacity: usize,
+    // Oldest first.
+    history: VecDeque<Committed<T, H, N>>,
+    snapshot: Option<SnapshotFn<T, H, N>>,
+}
+
+// `TrieNode::clone`, captured where the bounds for it hold.
+type SnapshotFn<T, H, const N: usize> = fn(&TrieNode<T, H, N>) -> TrieNode<T, H, N>;
+
+struct Committed<T: MerkleData, H: MerkleHasher, const N: usize> {
+    root: H::Hash,
+    // The trie as of `root`, when snapshots are on.
+    snapshot: Option<TrieNode<T, H, N>>,
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> RootHistory<T, H, N> {
+    /// Keeps the last `capacity` committed roots. The trie's current root is committed first.
+    pub fn new(trie: TrieNode<T, H, N>, capacity: usize) -> Self {
+        let mut history = RootHistory {
+            trie,
+            capacity,
+            history: VecDeque::new(),
+            snapshot: None,
+        };
+        history.commit();
+        history
+    }
+
+    /// `new`, also keeping a snapshot of the trie for every remembered root.
+    pub fn with_snapshots(trie: TrieNode<T, H, N>, capacity: usize) -> Self
+    where
+        T: Clone,
+        H: Clone,
+    {
+        let mut history = RootHistory {
+            trie,
+            capacity,
+            history: VecDeque::new(),
+            snapshot: Some(TrieNode::clone),
+        };
+        history.commit();
+        history
+    }
+
+    pub fn trie(&self) -> &TrieNode<T, H, N> {
+        &self.trie
+    }
+
+    /// The trie, for mutation. Changes are only remembered once they are committed.
+    pub fn trie_mut(&mut self) -> &mut TrieNode<T, H, N> {
+        &mut self.trie
+    }
+
+    /// Records the current root, evicting the oldest once more than `capacity` are kept.
+    /// Committing an unchanged root does nothing.
+    pub fn commit(&mut self) -> H::Hash {
+        let root = self.trie.merkle_root();
+        if self.history.back().map(|last| &last.root) != Some(&root) {
+            self.history.push_back(Committed {
+                root: root.clone(),
+                snapshot: self.snapshot.map(|snapshot| snapshot(&self.trie)),
+            });
+            while self.history.len() > self.capacity.max(1) {
+                self.history.pop_front();
+            }
+        }
+        root
+    }
+
+    /// The remembered roots, oldest first; the last one is the latest commit.
+    pub fn recent_roots(&self) -> impl Iterator<Item = &H::Hash> + '_ {
+        self.history.iter().map(|committed| &committed.root)
+    }
+
+    /// Whether `root` is one of the remembered roots.
+    pub fn verify_against_recent(&self, root: &H::Hash) -> bool {
+        self.history
+            .iter()
+            .any(|committed| hashes_equal(committed.root.as_ref(), root.as_ref()))
+    }
+
+    /// A proof of the value `key` held when the trie's root was `root`. `None` if that root is
+    /// no longer remembered, snapshots are off, or there was no value under `key`.
+    pub fn generate_proof_at(&mut self, root: &H::Hash, key: u32) -> Option<MerkleProof<H::Hash>> {
+        self.history
+            .iter_mut()
+            .rev()
+            .find(|committed| committed.root == *root)?
+            .snapshot
+            .as_mut()?
+            .generate_proof(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn proofs_against_recent_roots() {
+        let mut history = RootHistory::with_snapshots(TrieNode::<u32>::new(), 2);
+        history.trie_mut().insert(1, 10);
+        let first = history.commit();
+        history.trie_mut().insert(1, 11);
+        let second = history.commit();
+        history.commit();
+        history.trie_mut().insert(2, 20);
+        let third = history.commit();
+
+        assert!(!history.verify_against_recent(&first));
+        assert!(history.verify_against_recent(&second));
+        assert_eq!(
+            history.recent_roots().collect::<Vec<_>>(),
+            [&second, &third]
+        );
+        let proof = history.generate_proof_at(&second, 1).unwrap();
+        assert!(proof.verify(&StdMerkleHasher, &second, &11u32));
+        assert_eq!(history.generate_proof_at(&second, 2), None);
+        assert_eq!(history.generate_proof_at(&first, 1), None);
+
+        let mut plain = RootHistory::new(TrieNode::<u32>::new(), 2);
+        let root = plain.commit();
+        assert!(plain.verify_against_recent(&root));
+        assert_eq!(plain.generate_proof_at(&root, 0), None);
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::HashSet;
+
+use crate::hasher::MerkleHasher;
+use crate::merkle_data::MerkleData;
+use crate::proof::ProvenEntry;
+use crate::trie_node::trie_node::TrieNode;
+
+/// The entries sampled from a trie with root `root` under the public `seed`, for random audits
+/// and proofs of custody: `k` distinct ranks among its `len` entries in ascending key order, or
+/// all of them if `k >= len`, in the order drawn. The `i`-th draw is `hash(root || seed || i)`,
+/// with `i` a big-endian u64, folded to a u64 with FNV-1a and reduced modulo `len`; draws
+/// repeating an earlier rank are skipped. Since the root is an input, the sample can't be known
+/// before the trie is committed to.
+pub fn sample_ranks<H: MerkleHasher>(
+    hasher: &H,
+    root: &H::Hash,
+    seed: &[u8],
+    k: usize,
+    len: usize,
+) -> Vec<usize> {
+    let k = k.min(len);
+    let mut drawn = HashSet::new();
+    let mut ranks = vec![];
+    let mut bytes = root.as_ref().to_vec();
+    bytes.extend_from_slice(seed);
+    let prefix = bytes.len();
+    let mut draw = 0u64;
+    while ranks.len() < k {
+        bytes.truncate(prefix);
+        bytes.extend_from_slice(&draw.to_be_bytes());
+        let folded = hasher
+            .hash(&bytes)
+            .as_ref()
+            .iter()
+            .fold(0xcbf2_9ce4_8422_2325u64, |h, byte| {
+                (h ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+            });
+        let rank = (folded % len as u64) as usize;
+        if drawn.insert(rank) {
+            ranks.push(rank);
+        }
+        draw += 1;
+    }
+    ranks
+}
+
+/// Whether `entries` are exactly the `k` entries `sample_ranks` picks under `seed` from a trie
+/// with root `root` holding `keys`, given in ascending order, each proven against the root.
+pub fn verify_samples<T, H>(
+    hasher: &H,
+    root: &H::Hash,
+    seed: &[u8],
+    k: usize,
+    keys: &[u32],
+    entries: &[ProvenEntry<T, H::Hash>],
+) -> bool
+where
+    T: MerkleData,
+    H: MerkleHasher,
+{
+    let ranks = sample_ranks(hasher, root, seed, k, keys.len());
+    ranks.len() == entries.len()
+        && ranks
+            .iter()
+            .zip(entries)
+            .all(|(rank, entry)| entry.key == keys[*rank] && entry.verify(hasher, root))
+}
+
+impl<T: MerkleData + Clone + PartialEq, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    /// Proofs for the entries `sample_ranks` picks under `seed` from this trie's current root,
+    /// in the order drawn.
+    pub fn sample_proofs(&mut self, seed: &[u8], k: usize) -> Vec<ProvenEntry<T, H::Hash>> {
+        let mut keys: Vec<u32> = self.iter().map(|(key, _)| key).collect();
+        keys.sort_unstable();
+        let root = self.merkle_root();
+        sample_ranks(&self.hasher, &root, seed, k, keys.len())
+            .into_iter()
+            .filter_map(|rank| self.generate_proven_entry(keys[rank]))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn samples_are_fixed_by_root_and_seed() {
+        let mut trie: TrieNode<u32, StdMerkleHasher, 4> =
+            (0..200).map(|key| (key * 3, key)).collect();
+        let keys: Vec<u32> = (0..200).map(|key| key * 3).collect();
+        let root = trie.merkle_root();
+        let samples = trie.sample_proofs(b"block 17", 10);
+        assert_eq!(samples.len(), 10);
+        let sampled: HashSet<u32> = samples.iter().map(|entry| entry.key).collect();
+        assert_eq!(sampled.len(), 10);
+        assert_eq!(trie.sample_proofs(b"block 17", 10), samples);
+        assert_ne!(trie.sample_proofs(b"block 18", 10), samples);
+        assert!(verify_samples(
+            &StdMerkleHasher,
+            &root,
+            b"block 17",
+            10,
+            &keys,
+            &samples
+        ));
+        assert!(!verify_samples(
+            &StdMerkleHasher,
+            &root,
+            b"block 18",
+            10,
+            &keys,
+            &samples
+        ));
+        assert!(!verify_samples(
+            &StdMerkleHasher,
+            &root,
+            b"block 17",
+            10,
+            &keys,
+            &samples[1..]
+        ));
+
+        // Another root draws another sample.
+        trie.insert(0, 1);
+        assert_ne!(trie.sample_proofs(b"block 17", 10)[0].key, samples[0].key);
+        assert_eq!(trie.sample_proofs(b"block 17", 500).len(), 200);
+        assert!(TrieNode::<u32>::new().sample_proofs(b"", 3).is_empty());
+    }
+}
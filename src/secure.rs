@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+
+use crate::error::TrieError;
+use crate::proof::MerkleProof;
+use crate::trie_node::trie_node::TrieNode;
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// The path `SecureTrie` stores `key` under: the hash of its big-endian bytes, folded to 31 bits
+/// with FNV-1a, with the top bit set so that every key has the full number of digits. That
+/// leaves 31 bits, so distinct keys can share a path; see `SecureTrie` for what that limits.
+pub fn secure_key<H: MerkleHasher>(hasher: &H, key: u32) -> u32 {
+    let hash = hasher.hash(&key.to_be_bytes());
+    let folded = hash
+        .as_ref()
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325u64, |h, byte| {
+            (h ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+        });
+    (folded ^ (folded >> 32)) as u32 | 1 << 31
+}
+
+/// A trie that stores each value under `secure_key` of its key rather than the key itself, as
+/// geth's secure trie does. Every value sits at the same depth, so no choice of keys can make
+/// paths deep or lopsided. Proofs are for the hashed key; the original keys are kept in a side
+/// table for iteration.
+///
+/// Paths are 31-bit hashes, so by the birthday bound two keys are likely to share one once a
+/// few tens of thousands are stored: inserting 0, 1, 2, ... with `StdMerkleHasher` first
+/// collides at key 69763. `insert` refuses the second key with `KeyCollision`, so a secure trie
+/// suits key sets well below that size; larger ones belong in a plain `TrieNode`.
+pub struct SecureTrie<T: MerkleData, H: MerkleHasher, const N: usize = 2> {
+    trie: TrieNode<T, H, N>,
+    // Hashed key -> original key.
+    preimages: HashMap<u32, u32>,
+}
+
+impl<T: MerkleData + PartialEq, H: MerkleHasher + Default, const N: usize> Default
+    for SecureTrie<T, H, N>
+{
+    fn default() -> Self {
+        SecureTrie::with_hasher(H::default())
+    }
+}
+
+impl<T: MerkleData + PartialEq, H: MerkleHasher, const N: usize> SecureTrie<T, H, N> {
+    pub fn with_hasher(hasher: H) -> Self {
+        Self::from_trie(TrieNode::with_hasher(hasher))
+    }
+
+    // `trie` must be empty.
+    pub(crate) fn from_trie(trie: TrieNode<T, H, N>) -> Self {
+        SecureTrie {
+            trie,
+            preimages: HashMap::new(),
+        }
+    }
+
+    pub fn trie(&self) -> &TrieNode<T, H, N> {
+        &self.trie
+    }
+
+    pub fn secure_key(&self, key: u32) -> u32 {
+        secure_key(self.trie.hasher(), key)
+    }
+
+    /// The original key stored under `secure_key`, if any.
+    pub fn preimage(&self, secure_key: u32) -> Option<u32> {
+        self.preimages.get(&secure_key).copied()
+    }
+
+    /// Fails without changing anything if another key already hashes to the same path.
+    pub fn insert(&mut self, key: u32, data: T) -> Result<(), TrieError> {
+        let secure_key = self.secure_key(key);
+        match self.preimages.insert(secure_key, key) {
+            Some(existing) if existing != key => {
+                self.preimages.insert(secure_key, existing);
+                Err(TrieError::KeyCollision { key, existing })
+            }
+            _ => {
+                self.trie.insert(secure_key, data);
+                Ok(())
+            }
+        }
+    }
+
+    /// The value under `key`; `None` for a key whose path another key holds.
+    pub fn get(&self, key: u32) -> Option<&T> {
+        let secure_key = self.secure_key(key);
+        if self.preimage(secure_key) != Some(key) {
+            return None;
+        }
+        self.trie.find_by_key(secure_key)?.get_data()
+    }
+
+    pub fn merkle_root(&mut self) -> H::Hash {
+        self.trie.merkle_root()
+    }
+
+    /// Proves the value under `key`. The proof's key is `secure_key(key)`, which a verifier
+    /// should check before trusting it.
+    pub fn generate_proof(&mut self, key: u32) -> Option<MerkleProof<H::Hash>> {
+        let secure_key = self.secure_key(key);
+        if self.preimage(secure_key) != Some(key) {
+            return None;
+        }
+        self.trie.generate_proof(secure_key)
+    }
+
+    /// `(key, value)` pairs with the original keys, in the order of the hashed keys.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &T)> + '_ {
+        self.trie
+            .iter()
+            .map(|(secure_key, data)| (self.preimages[&secure_key], data))
+    }
+
+    pub fn len(&self) -> usize {
+        self.preimages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.preimages.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn secure_keys_sit_at_full_depth() {
+        let mut trie: SecureTrie<u32, StdMerkleHasher, 16> = SecureTrie::default();
+        for key in 0..100 {
+            trie.insert(key, key * 2).unwrap();
+        }
+        assert_eq!(trie.get(7), Some(&14));
+        assert_eq!(trie.get(100), None);
+        let root = trie.merkle_root();
+        for key in [0, 1, 99] {
+            let proof = trie.generate_proof(key).unwrap();
+            assert_eq!(proof.levels.len(), 8);
+            assert_eq!(proof.key, secure_key(&StdMerkleHasher, key));
+            assert!(proof.verify(&StdMerkleHasher, &root, &(key * 2)));
+        }
+        let mut keys: Vec<u32> = trie.iter().map(|(key, _)| key).collect();
+        keys.sort();
+        assert_eq!(keys, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn colliding_keys_are_refused() {
+        let (key, existing) = (69763, 38631);
+        let hasher = StdMerkleHasher;
+        assert_eq!(secure_key(&hasher, key), secure_key(&hasher, existing));
+
+        let mut trie: SecureTrie<u32, StdMerkleHasher> = SecureTrie::default();
+        trie.insert(existing, 1).unwrap();
+        let root = trie.merkle_root();
+        assert_eq!(
+            trie.insert(key, 2),
+            Err(TrieError::KeyCollision { key, existing })
+        );
+        assert_eq!(trie.get(existing), Some(&1));
+        assert_eq!(trie.get(key), None);
+        assert_eq!(trie.generate_proof(key), None);
+        assert_eq!(trie.preimage(trie.secure_key(key)), Some(existing));
+        assert_eq!(trie.len(), 1);
+        assert_eq!(trie.merkle_root(), root);
+        // Writing the same key again is not a collision.
+        trie.insert(existing, 3).unwrap();
+        assert_eq!(trie.get(existing), Some(&3));
+    }
+}
@@ -0,0 +1,218 @@
+use std::borrow::Cow;
+
+use crate::hasher::MerkleHasher;
+use crate::merkle_data::{push_field, MerkleData};
+use crate::proof::MerkleProof;
+use crate::secure::secure_key;
+use crate::trie_node::trie_node::{TrieNode, ROOT};
+
+/// The pairs stored at one position of a `SetCommitment`: every pair whose key hashes there,
+/// once per time it was inserted, sorted by key and then by value bytes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Bucket {
+    entries: Vec<(u32, Vec<u8>)>,
+}
+
+impl Bucket {
+    /// `(key, value bytes)` pairs, in sorted order.
+    pub fn entries(&self) -> impl Iterator<Item = (u32, &[u8])> + '_ {
+        self.entries
+            .iter()
+            .map(|(key, value)| (*key, value.as_slice()))
+    }
+
+    /// How many times the pair is in the bucket.
+    pub fn count<V: MerkleData + ?Sized>(&self, key: u32, value: &V) -> usize {
+        let value = value.merkle_bytes();
+        self.entries
+            .iter()
+            .filter(|(k, v)| *k == key && v[..] == value[..])
+            .count()
+    }
+}
+
+// Each pair as its big-endian key and its length-prefixed value bytes, in the bucket's order.
+impl MerkleData for Bucket {
+    fn merkle_bytes(&self) -> Cow<'_, [u8]> {
+        let mut bytes = vec![];
+        for (key, value) in &self.entries {
+            bytes.extend_from_slice(&key.to_be_bytes());
+            push_field(&mut bytes, value.as_slice());
+        }
+        Cow::Owned(bytes)
+    }
+}
+
+/// A commitment to a multiset of `(key, value)` pairs whose root depends on the pairs alone,
+/// never on the order they were inserted and removed in. Pairs are kept in sorted `Bucket`s,
+/// one at `secure_key` of each pair's key, so every bucket sits at full depth and the trie's
+/// shape is fixed by which positions are occupied. Removing a bucket's last pair prunes the
+/// nodes that led only to it, leaving the trie as if the pair had never been inserted.
+///
+/// Keys that hash to the same position share a bucket rather than colliding. Proofs are for a
+/// whole bucket, at `secure_key` of the key, and verify against its `merkle_bytes`.
+pub struct SetCommitment<H: MerkleHasher, const N: usize = 2> {
+    trie: TrieNode<Bucket, H, N>,
+    len: usize,
+}
+
+impl<H: MerkleHasher + Default, const N: usize> Default for SetCommitment<H, N> {
+    fn default() -> Self {
+        SetCommitment::with_hasher(H::default())
+    }
+}
+
+impl<H: MerkleHasher, const N: usize> SetCommitment<H, N> {
+    pub fn with_hasher(hasher: H) -> Self {
+        SetCommitment {
+            trie: TrieNode::with_hasher(hasher),
+            len: 0,
+        }
+    }
+
+    pub fn trie(&self) -> &TrieNode<Bucket, H, N> {
+        &self.trie
+    }
+
+    pub fn secure_key(&self, key: u32) -> u32 {
+        secure_key(self.trie.hasher(), key)
+    }
+
+    /// The bucket holding `key`'s pairs, if there are any.
+    pub fn bucket(&self, key: u32) -> Option<&Bucket> {
+        self.trie.get(self.secure_key(key))
+    }
+
+    /// Adds one occurrence of the pair.
+    pub fn insert<V: MerkleData + ?Sized>(&mut self, key: u32, value: &V) {
+        let position = self.secure_key(key);
+        let mut bucket = self.trie.get(position).cloned().unwrap_or_default();
+        let entry = (key, value.merkle_bytes().into_owned());
+        let index = bucket
+            .entries
+            .partition_point(|existing| *existing <= entry);
+        bucket.entries.insert(index, entry);
+        self.trie.insert(position, bucket);
+        self.len += 1;
+    }
+
+    /// Removes one occurrence of the pair; `false` if there was none.
+    pub fn remove<V: MerkleData + ?Sized>(&mut self, key: u32, value: &V) -> bool {
+        let position = self.secure_key(key);
+        let Some(mut bucket) = self.trie.get(position).cloned() else {
+            return false;
+        };
+        let entry = (key, value.merkle_bytes().into_owned());
+        let Ok(index) = bucket.entries.binary_search(&entry) else {
+            return false;
+        };
+        bucket.entries.remove(index);
+        if bucket.entries.is_empty() {
+            self.prune(position);
+        } else {
+            self.trie.insert(position, bucket);
+        }
+        self.len -= 1;
+        true
+    }
+
+    // Removes the bucket at `position` with the chain of nodes above it that lead nowhere else.
+    fn prune(&mut self, position: u32) {
+        let mut cut = 1;
+        let mut index = ROOT;
+        for depth in 0..TrieNode::<Bucket, H, N>::key_depth(position) {
+            let node = self.trie.node(index);
+            if node.children().iter().flatten().count() > 1 {
+                cut = depth + 1;
+            }
+            match node.child(TrieNode::<Bucket, H, N>::digit_at(position, depth)) {
+                Some(child) => index = child,
+                None => return,
+            }
+        }
+        self.trie.remove_subtree(position, cut);
+    }
+
+    pub fn count<V: MerkleData + ?Sized>(&self, key: u32, value: &V) -> usize {
+        self.bucket(key)
+            .map_or(0, |bucket| bucket.count(key, value))
+    }
+
+    pub fn contains<V: MerkleData + ?Sized>(&self, key: u32, value: &V) -> bool {
+        self.count(key, value) > 0
+    }
+
+    pub fn merkle_root(&mut self) -> H::Hash {
+        self.trie.merkle_root()
+    }
+
+    /// Proves the bucket holding `key`'s pairs. The proof's key is `secure_key(key)`, which a
+    /// verifier should check before trusting it.
+    pub fn generate_proof(&mut self, key: u32) -> Option<MerkleProof<H::Hash>> {
+        let position = self.secure_key(key);
+        self.trie.generate_proof(position)
+    }
+
+    /// The number of pairs, counting repeats.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn roots_depend_only_on_the_pairs() {
+        let pairs: Vec<(u32, u32)> = (0..60).map(|i| (i % 20, i / 20)).collect();
+        let mut forward: SetCommitment<StdMerkleHasher, 4> = SetCommitment::default();
+        for (key, value) in &pairs {
+            forward.insert(*key, value);
+        }
+        let root = forward.merkle_root();
+
+        // Reversed, with extra pairs inserted and removed along the way.
+        let mut shuffled: SetCommitment<StdMerkleHasher, 4> = SetCommitment::default();
+        for (key, value) in pairs.iter().rev() {
+            shuffled.insert(key + 1000, value);
+            shuffled.insert(*key, value);
+            shuffled.insert(*key, &99u32);
+        }
+        for (key, value) in &pairs {
+            assert!(shuffled.remove(key + 1000, value));
+            shuffled.remove(*key, &99u32);
+        }
+        assert!(!shuffled.remove(5, &99u32));
+        assert_eq!(shuffled.len(), 60);
+        assert_eq!(shuffled.merkle_root(), root);
+
+        // A repeated pair is counted.
+        forward.insert(3, &1u32);
+        assert_eq!(forward.count(3, &1u32), 2);
+        assert_ne!(forward.merkle_root(), root);
+        assert!(forward.remove(3, &1u32));
+        assert_eq!(forward.merkle_root(), root);
+
+        let bucket = forward.bucket(3).unwrap().clone();
+        assert_eq!(bucket.entries().count(), 3);
+        let proof = forward.generate_proof(3).unwrap();
+        assert_eq!(proof.key, forward.secure_key(3));
+        assert!(proof.verify(&StdMerkleHasher, &root, &bucket));
+
+        for (key, value) in &pairs {
+            assert!(shuffled.remove(*key, value));
+        }
+        assert!(shuffled.is_empty());
+        assert_eq!(
+            shuffled.merkle_root(),
+            SetCommitment::<StdMerkleHasher, 4>::default().merkle_root()
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::bit_path::BitPath;
+use crate::trie_node::trie_node::TrieNode;
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// A trie shared between threads, locked per subtree rather than as a whole. Keys are split by
+/// their lowest `lock_depth` digits, each value of which picks one of `N^lock_depth` shards with
+/// a lock of its own, so writers to different subtrees never wait on each other. A deeper lock
+/// depth spreads prefix-skewed writes over more shards, at the cost of more locks and more
+/// levels to combine into the root. Keys too short to reach the lock depth share one more lock.
+///
+/// The root is that of a single `TrieNode` holding every entry.
+pub struct ShardedTrie<T: MerkleData, H: MerkleHasher, const N: usize = 2> {
+    hasher: H,
+    lock_depth: u32,
+    // Keys above the lock depth, which sit on the levels the shards hang from.
+    shallow: Mutex<BTreeMap<u32, T>>,
+    shards: Vec<Mutex<TrieNode<T, H, N>>>,
+}
+
+impl<T, H, const N: usize> ShardedTrie<T, H, N>
+where
+    T: MerkleData + PartialEq,
+    H: MerkleHasher + Clone,
+{
+    /// `lock_depth` is in digits, so a binary trie with lock depth 4 has 16 shards. At most 16
+    /// bits' worth of digits are allowed.
+    pub fn with_hasher(hasher: H, lock_depth: u32) -> Self {
+        let shift = lock_depth * TrieNode::<T, H, N>::BITS_PER_DIGIT;
+        assert!(
+            shift <= 16,
+            "lock depth must be at most {}",
+            16 / TrieNode::<T, H, N>::BITS_PER_DIGIT
+        );
+        ShardedTrie {
+            shards: (0..1 << shift)
+                .map(|_| Mutex::new(TrieNode::with_hasher(hasher.clone())))
+                .collect(),
+            hasher,
+            lock_depth,
+            shallow: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn lock_depth(&self) -> u32 {
+        self.lock_depth
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    fn shard(&self, key: u32) -> Option<MutexGuard<'_, TrieNode<T, H, N>>> {
+        (TrieNode::<T, H, N>::key_depth(key) >= self.lock_depth).then(|| {
+            self.shards[key as usize & (self.shards.len() - 1)]
+                .lock()
+                .unwrap()
+        })
+    }
+
+    pub fn insert(&self, key: u32, data: T) {
+        match self.shard(key) {
+            Some(mut shard) => shard.insert(key, data),
+            None => {
+                self.shallow.lock().unwrap().insert(key, data);
+            }
+        }
+    }
+
+    pub fn get(&self, key: u32) -> Option<T>
+    where
+        T: Clone,
+    {
+        match self.shard(key) {
+            Some(shard) => shard.get(key).cloned(),
+            None => self.shallow.lock().unwrap().get(&key).cloned(),
+        }
+    }
+
+    /// Locks every shard, in order, to hash a consistent state. Each shard keeps its hashes
+    /// cached, so only the shards written to since the last call are rehashed.
+    pub fn merkle_root(&self) -> H::Hash {
+        let shallow = self.shallow.lock().unwrap();
+        let mut shards: Vec<_> = self
+            .shards
+            .iter()
+            .map(|shard| shard.lock().unwrap())
+            .collect();
+        self.root_at(&shallow, &mut shards, 0, 0)
+            .unwrap_or_else(|| self.hasher.empty_hash())
+    }
+
+    // The root of the subtree at `path`, or `None` if the trie has no node there.
+    fn root_at(
+        &self,
+        shallow: &BTreeMap<u32, T>,
+        shards: &mut [MutexGuard<'_, TrieNode<T, H, N>>],
+        path: u32,
+        depth: u32,
+    ) -> Option<H::Hash> {
+        let bits_per_digit = TrieNode::<T, H, N>::BITS_PER_DIGIT;
+        if depth == self.lock_depth {
+            let shard = &mut shards[path as usize];
+            let index = shard.index_by_path(&BitPath::new(path as u64, depth * bits_per_digit))?;
+            return Some(shard.merkle_root_at(index));
+        }
+        let data = (TrieNode::<T, H, N>::key_depth(path) == depth)
+            .then(|| shallow.get(&path))
+            .flatten();
+        let children: Vec<Option<H::Hash>> = (0..N as u32)
+            .map(|digit| {
+                let child = path | digit << (depth * bits_per_digit);
+                self.root_at(shallow, shards, child, depth + 1)
+            })
+            .collect();
+        let data_hash = match data {
+            Some(data) => self.hasher.hash(&data.merkle_bytes()),
+            None => self.hasher.empty_hash(),
+        };
+        if children.iter().all(Option::is_none) {
+            return data.is_some().then_some(data_hash);
+        }
+        let children: Vec<H::Hash> = children
+            .into_iter()
+            .map(|child| child.unwrap_or_else(|| self.hasher.empty_hash()))
+            .collect();
+        Some(self.hasher.combine_children(&data_hash, &children))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn shards_combine_into_the_plain_root() {
+        for lock_depth in [0, 1, 3] {
+            let trie: ShardedTrie<u32, StdMerkleHasher, 4> =
+                ShardedTrie::with_hasher(StdMerkleHasher, lock_depth);
+            assert_eq!(trie.shard_count(), 1 << (2 * lock_depth));
+            assert_eq!(trie.merkle_root(), StdMerkleHasher.empty_hash());
+            std::thread::scope(|scope| {
+                for thread in 0..4 {
+                    let trie = &trie;
+                    scope.spawn(move || {
+                        for key in (thread..500).step_by(4) {
+                            trie.insert(key * 3, key);
+                        }
+                    });
+                }
+            });
+            let mut expected: TrieNode<u32, StdMerkleHasher, 4> =
+                (0..500).map(|key| (key * 3, key)).collect();
+            assert_eq!(trie.merkle_root(), expected.merkle_root());
+            assert_eq!(trie.get(30), Some(10));
+            assert_eq!(trie.get(31), None);
+
+            trie.insert(3, 0);
+            trie.insert(1 << 20, 1);
+            expected.insert(3, 0);
+            expected.insert(1 << 20, 1);
+            assert_eq!(trie.merkle_root(), expected.merkle_root());
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::sync::Arc;
+
+use crate::trie_node::trie_node::TrieNode;
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// Hands out one `Arc` per distinct value, so a value stored under many keys of a
+/// `TrieNode<Arc<T>>` is held in memory once. Pair it with `InterningHasher` to also hash it
+/// once.
+#[derive(Debug)]
+pub struct ValuePool<T> {
+    values: HashSet<Arc<T>>,
+}
+
+impl<T> Default for ValuePool<T> {
+    fn default() -> Self {
+        ValuePool {
+            values: HashSet::new(),
+        }
+    }
+}
+
+impl<T: Hash + Eq> ValuePool<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The pool's `Arc` for a value equal to `value`, adding `value` if there is none.
+    pub fn share(&mut self, value: T) -> Arc<T> {
+        if let Some(shared) = self.values.get(&value) {
+            return Arc::clone(shared);
+        }
+        let shared = Arc::new(value);
+        self.values.insert(Arc::clone(&shared));
+        shared
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Drops the values nothing but the pool holds any more, returning how many.
+    pub fn prune(&mut self) -> usize {
+        let before = self.values.len();
+        self.values.retain(|value| Arc::strong_count(value) > 1);
+        before - self.values.len()
+    }
+}
+
+impl<T, H, const N: usize> TrieNode<Arc<T>, H, N>
+where
+    T: MerkleData + PartialEq,
+    H: MerkleHasher,
+{
+    /// Stores `value` under `key` without copying it, sharing it with every other holder.
+    pub fn insert_shared(&mut self, key: u32, value: Arc<T>) {
+        self.insert(key, value)
+    }
+
+    /// The value under `key`, as another handle on the shared value.
+    pub fn get_shared(&self, key: u32) -> Option<Arc<T>> {
+        self.get(key).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn shared_values_are_stored_once() {
+        let mut pool = ValuePool::new();
+        let mut trie: TrieNode<Arc<String>> = TrieNode::new();
+        for key in 0..100 {
+            trie.insert_shared(key, pool.share(format!("{}", key % 2).repeat(1000)));
+        }
+        assert_eq!(pool.len(), 2);
+        let (a, b) = (trie.get_shared(0).unwrap(), trie.get_shared(2).unwrap());
+        assert!(Arc::ptr_eq(&a, &b));
+
+        let mut plain: TrieNode<String> = (0..100)
+            .map(|key| (key, format!("{}", key % 2).repeat(1000)))
+            .collect();
+        assert_eq!(trie.merkle_root(), plain.merkle_root());
+
+        drop((a, b));
+        trie.retain(|key, _| key % 2 == 1);
+        assert_eq!(pool.prune(), 1);
+        assert_eq!(pool.len(), 1);
+    }
+}
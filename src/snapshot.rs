@@ -0,0 +1,231 @@
+use std::{
+    io::{self, Read, Write},
+    str::FromStr,
+};
+
+use crate::checkpoint::{invalid, write_hash, Reader};
+use crate::codec::ValueCodec;
+use crate::trie_node::trie_node::{Node, TrieNode, ROOT};
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+// The `.mtrie` snapshot format, all integers big-endian:
+//
+//   header:  magic (8) | version u8 | arity u16 | algorithm id (u16 len + bytes)
+//            | root (u16 len + bytes) | node count u32
+//   node:    N child indices u32 (NO_CHILD = none) | has value u8
+//            | value (u32 len + codec bytes, `ToString` rendering by default), if present
+//   trailer: checksum (u16 len + bytes)
+//
+// Nodes are listed breadth-first from the root, so each child comes after its parent. The
+// algorithm id is the hash of ALGORITHM_PROBE, which tells hash functions (and keys, for keyed
+// hashers) apart without naming them. The checksum is the hash of everything before it. Unlike
+// a checkpoint, a snapshot carries no cached hashes: the importer recomputes them all.
+const MAGIC: &[u8; 8] = b"MTRIESNP";
+const VERSION: u8 = 1;
+const NO_CHILD: u32 = u32::MAX;
+const ALGORITHM_PROBE: &[u8] = b"mtrie algorithm id";
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    /// Writes a self-contained, verifiable `.mtrie` snapshot of the trie to `out`.
+    pub fn export_snapshot<W: Write>(&mut self, out: W) -> io::Result<()>
+    where
+        T: ToString,
+    {
+        self.write_snapshot(out, |data| data.to_string().into_bytes())
+    }
+
+    /// `export_snapshot`, with values written by `codec`.
+    pub fn export_snapshot_with_codec<W, C>(&mut self, out: W, codec: &C) -> io::Result<()>
+    where
+        W: Write,
+        C: ValueCodec<T>,
+    {
+        self.write_snapshot(out, |data| codec.encode(data))
+    }
+
+    fn write_snapshot<W: Write>(
+        &mut self,
+        mut out: W,
+        encode: impl Fn(&T) -> Vec<u8>,
+    ) -> io::Result<()> {
+        let root = self.merkle_root();
+        let mut order = vec![ROOT];
+        let mut position = 0;
+        while position < order.len() {
+            order.extend(self.node(order[position]).children().iter().flatten());
+            position += 1;
+        }
+        let mut renumbered = vec![NO_CHILD; self.nodes.len()];
+        for (new_index, old_index) in order.iter().enumerate() {
+            renumbered[*old_index as usize] = new_index as u32;
+        }
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&(N as u16).to_be_bytes());
+        write_hash(&mut bytes, self.hasher.hash(ALGORITHM_PROBE).as_ref())?;
+        write_hash(&mut bytes, root.as_ref())?;
+        bytes.extend_from_slice(&(order.len() as u32).to_be_bytes());
+        for index in order {
+            let node = self.node(index);
+            for digit in 0..N {
+                let child = node.child(digit);
+                let child = child.map_or(NO_CHILD, |c| renumbered[c as usize]);
+                bytes.extend_from_slice(&child.to_be_bytes());
+            }
+            match node.get_data() {
+                Some(data) => {
+                    let data = encode(data);
+                    bytes.push(1);
+                    bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+                    bytes.extend_from_slice(&data);
+                }
+                None => bytes.push(0),
+            }
+        }
+        let checksum = self.hasher.hash(&bytes);
+        write_hash(&mut bytes, checksum.as_ref())?;
+        out.write_all(&bytes)?;
+        out.flush()
+    }
+
+    /// Reads a snapshot written by `export_snapshot` with the same hasher, rebuilding the trie
+    /// and checking the recomputed root against the one in the header. Any mismatch is
+    /// reported as `InvalidData`.
+    pub fn import_snapshot<R: Read>(input: R) -> io::Result<Self>
+    where
+        T: FromStr,
+        H: Default,
+    {
+        Self::read_snapshot(input, |bytes| std::str::from_utf8(bytes).ok()?.parse().ok())
+    }
+
+    /// `import_snapshot` for a snapshot written with `export_snapshot_with_codec`.
+    pub fn import_snapshot_with_codec<R, C>(input: R, codec: &C) -> io::Result<Self>
+    where
+        R: Read,
+        C: ValueCodec<T>,
+        H: Default,
+    {
+        Self::read_snapshot(input, |bytes| codec.decode(bytes))
+    }
+
+    fn read_snapshot<R: Read>(mut input: R, decode: impl Fn(&[u8]) -> Option<T>) -> io::Result<Self>
+    where
+        H: Default,
+    {
+        let mut bytes = vec![];
+        input.read_to_end(&mut bytes)?;
+        let mut trie = TrieNode::with_hasher(H::default());
+        let mut reader = Reader { bytes: &bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(invalid("not a snapshot file"));
+        }
+        if reader.u8()? != VERSION {
+            return Err(invalid("unsupported snapshot version"));
+        }
+        if reader.u16()? as usize != N {
+            return Err(invalid("snapshot was written for a different arity"));
+        }
+        if reader.hash::<H>()? != trie.hasher.hash(ALGORITHM_PROBE) {
+            return Err(invalid(
+                "snapshot was written with a different hash algorithm",
+            ));
+        }
+        let recorded_root = reader.hash::<H>()?;
+        let node_count = reader.u32()?;
+        // Each node takes at least its children and value flag, which bounds what the count can
+        // make us allocate.
+        let max_nodes = reader.bytes.len() / (N * 4 + 1);
+        if node_count == 0 || node_count == NO_CHILD || node_count as usize > max_nodes {
+            return Err(invalid("bad node count"));
+        }
+
+        trie.nodes.clear();
+        let mut referenced = vec![false; node_count as usize];
+        for index in 0..node_count {
+            let mut node = Node::new(None);
+            for digit in 0..N {
+                let child = reader.u32()?;
+                if child == NO_CHILD {
+                    continue;
+                }
+                if child <= index || child >= node_count || referenced[child as usize] {
+                    return Err(invalid("snapshot is not a tree"));
+                }
+                referenced[child as usize] = true;
+                node.set_child(digit, child);
+            }
+            if reader.u8()? != 0 {
+                let len = reader.u32()? as usize;
+                let data = decode(reader.take(len)?).ok_or_else(|| invalid("unparseable value"))?;
+                node.replace_data(data);
+            }
+            trie.nodes.push(node);
+        }
+        let body_len = bytes.len() - reader.bytes.len();
+        if reader.hash::<H>()? != trie.hasher.hash(&bytes[..body_len]) {
+            return Err(invalid("snapshot checksum mismatch"));
+        }
+        if !reader.bytes.is_empty() {
+            return Err(invalid("trailing bytes after snapshot"));
+        }
+        if trie.merkle_root() != recorded_root {
+            return Err(invalid("snapshot root mismatch"));
+        }
+        Ok(trie)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::commitment_spec::CanonicalHasher;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn snapshot_round_trip_and_integrity() {
+        let mut node: TrieNode<String> = (0..100).map(|key| (key * 7, format!("v{key}"))).collect();
+        node.remove_subtree(0b01, 2);
+        let mut snapshot = vec![];
+        node.export_snapshot(&mut snapshot).unwrap();
+
+        let mut imported: TrieNode<String> = TrieNode::import_snapshot(&snapshot[..]).unwrap();
+        assert_eq!(imported.merkle_root(), node.merkle_root());
+        assert_eq!(imported, node);
+
+        let mut corrupted = snapshot.clone();
+        let last = corrupted.len() - 30;
+        corrupted[last] ^= 1;
+        assert!(TrieNode::<String>::import_snapshot(&corrupted[..]).is_err());
+
+        // Same hash type, different commitment scheme.
+        let error =
+            TrieNode::<String, CanonicalHasher<StdMerkleHasher>>::import_snapshot(&snapshot[..])
+                .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "snapshot was written with a different hash algorithm"
+        );
+    }
+
+    #[test]
+    fn node_count_is_bounded_by_the_bytes_left() {
+        let mut node: TrieNode<String> = (0..100).map(|key| (key * 7, format!("v{key}"))).collect();
+        let mut snapshot = vec![];
+        node.export_snapshot(&mut snapshot).unwrap();
+        let hash_len = node.hasher().hash(ALGORITHM_PROBE).len();
+        let count_at = MAGIC.len() + 3 + 2 * (2 + hash_len);
+        let import = |bytes: &[u8]| {
+            TrieNode::<String>::import_snapshot(bytes)
+                .unwrap_err()
+                .to_string()
+        };
+
+        let mut inflated = snapshot.clone();
+        inflated[count_at..count_at + 4].copy_from_slice(&(NO_CHILD - 1).to_be_bytes());
+        assert_eq!(import(&inflated), "bad node count");
+        assert_eq!(import(&snapshot[..count_at + 40]), "bad node count");
+    }
+}
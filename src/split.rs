@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+
+use crate::bit_path::BitPath;
+use crate::error::TrieError;
+use crate::trie_node::trie_node::{Node, NodeIndex, TrieNode, ROOT};
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+impl<T: MerkleData, H: MerkleHasher + Clone, const N: usize> TrieNode<T, H, N> {
+    /// Detaches the subtree under the lowest `prefix_len` digits of `prefix`, as
+    /// `remove_subtree` does, and returns it as a trie of its own, e.g. to move a tenant that
+    /// outgrew a shared trie to one of its own. The new trie's keys are the original ones with
+    /// the prefix's digits shifted off, and its root is the subtree's root. Nodes are moved
+    /// rather than copied, with their cached hashes, so hashes cached before the split need no
+    /// recomputing on either side except on the original's path to the prefix. `None` if the
+    /// trie holds nothing under the prefix.
+    pub fn split_off(&mut self, prefix: u32, prefix_len: u32) -> Option<TrieNode<T, H, N>> {
+        assert!(
+            Self::prefix_fits(prefix_len),
+            "prefix must fit in a u32 key"
+        );
+        let mut path = vec![];
+        let mut root = ROOT;
+        for depth in 0..prefix_len {
+            path.push(root);
+            root = self.node(root).child(Self::digit_at(prefix, depth))?;
+        }
+        if root == ROOT && self.is_empty() {
+            return None;
+        }
+        if let Some(&parent) = path.last() {
+            self.node_mut(parent)
+                .take_child(Self::digit_at(prefix, prefix_len - 1));
+            self.record_invalidation(&path);
+            for index in &path {
+                self.node_mut(*index).invalidate_merkle_root();
+            }
+        }
+
+        // Breadth first, so the subtree's root lands on the new trie's root.
+        let mut moved = vec![root];
+        let mut next = 0;
+        while next < moved.len() {
+            moved.extend(self.node(moved[next]).children().iter().flatten());
+            next += 1;
+        }
+        let renumbered: HashMap<NodeIndex, NodeIndex> = moved
+            .iter()
+            .enumerate()
+            .map(|(new, old)| (*old, new as NodeIndex))
+            .collect();
+        let mut out = TrieNode::with_hasher(self.hasher.clone());
+        out.nodes = moved
+            .iter()
+            .map(|index| {
+                let mut node = std::mem::replace(self.node_mut(*index), Node::new(None));
+                node.map_children(|child| renumbered[&child]);
+                node
+            })
+            .collect();
+        // Every moved slot is now an empty leaf, free for reuse; the root stays in place.
+        self.free_subtrees
+            .extend(moved.into_iter().filter(|index| *index != ROOT));
+        out.cache_generation = self.cache_generation;
+        out.eager_hashing = self.eager_hashing;
+        out.cache_policy = self.cache_policy;
+        self.rehash_if_eager();
+        out.rehash_if_eager();
+        Some(out)
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    /// Grafts `subtrie` under the lowest `prefix_len` digits of `prefix`, the inverse of
+    /// `split_off`: its keys gain the prefix's digits. The position must be vacant, or hold
+    /// only an empty node such as `remove_subtree` leaves, and every key of `subtrie` must
+    /// still fit in a `u32` once shifted. Nodes are moved with their cached hashes, so only the
+    /// path above the prefix needs rehashing.
+    pub fn attach(
+        &mut self,
+        prefix: u32,
+        prefix_len: u32,
+        subtrie: TrieNode<T, H, N>,
+    ) -> Result<(), TrieError> {
+        assert!(
+            Self::prefix_fits(prefix_len),
+            "prefix must fit in a u32 key"
+        );
+        let shift = prefix_len * Self::BITS_PER_DIGIT;
+        let unavailable = TrieError::PrefixUnavailable { prefix, prefix_len };
+        let path = BitPath::new(prefix as u64, shift);
+        if let Some(index) = self.index_by_path(&path) {
+            let node = self.node(index);
+            if node.get_data().is_some() || !node.is_leaf() {
+                return Err(unavailable);
+            }
+        }
+        let root_has_data = subtrie.node(ROOT).get_data().is_some();
+        if root_has_data && prefix_len > 0 && Self::digit_at(prefix, prefix_len - 1) == 0 {
+            return Err(unavailable);
+        }
+        let mut keys = vec![];
+        for (key, _) in subtrie.iter() {
+            if (key as u64) << shift > u32::MAX as u64 {
+                return Err(TrieError::DepthExceeded {
+                    key,
+                    depth: Self::key_depth(key),
+                    max_depth: (u32::BITS - shift).div_ceil(Self::BITS_PER_DIGIT),
+                });
+            }
+            keys.push(prefix | key << shift);
+        }
+
+        let (target, dirtied) = self.create_counting(&path);
+        self.invalidations.record(dirtied);
+        // The subtrie's root replaces the empty node at the target and the rest are appended.
+        let offset = self.nodes.len() as NodeIndex - 1;
+        let renumber = |index: NodeIndex| match index {
+            ROOT => target,
+            index => index + offset,
+        };
+        let (from, to) = (subtrie.cache_generation, self.cache_generation);
+        for (index, mut node) in subtrie.nodes.into_iter().enumerate() {
+            node.map_children(renumber);
+            node.restamp(from, to);
+            if index as NodeIndex == ROOT {
+                *self.node_mut(target) = node;
+            } else {
+                self.nodes.push(node);
+            }
+        }
+        self.free_subtrees
+            .extend(subtrie.free_subtrees.into_iter().map(renumber));
+        for key in keys {
+            self.note_key(key);
+        }
+        self.rehash_if_eager();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn split_off_moves_a_subtree_with_its_caches() {
+        let entries = |keys: std::ops::Range<u32>| keys.map(|key| (key * 3, key));
+        let mut node: TrieNode<u32, StdMerkleHasher, 4> = entries(0..300).collect();
+        node.merkle_root();
+        // Keys ending in base-4 digits 3 then 2.
+        let mut split = node.split_off(0b1011, 2).unwrap();
+        assert!(split.current_root().is_some());
+        assert_eq!(node.current_root(), None);
+
+        let in_prefix = |key: &(u32, u32)| key.0 & 0b1111 == 0b1011;
+        let mut expected: TrieNode<u32, StdMerkleHasher, 4> = entries(0..300)
+            .filter(in_prefix)
+            .map(|(key, value)| (key >> 4, value))
+            .collect();
+        assert_eq!(split.merkle_root(), expected.merkle_root());
+        assert_eq!(split.get(1), Some(&9));
+        let mut rest: TrieNode<u32, StdMerkleHasher, 4> =
+            entries(0..300).filter(|entry| !in_prefix(entry)).collect();
+        assert_eq!(node.merkle_root(), rest.merkle_root());
+
+        // Freed slots are reused.
+        let nodes = node.nodes.len();
+        node.insert(11, 1);
+        assert_eq!(node.nodes.len(), nodes);
+        rest.insert(11, 1);
+        assert_eq!(node.merkle_root(), rest.merkle_root());
+        assert_eq!(node.split_off(0b1011, 4), None);
+
+        let mut all = node.split_off(0, 0).unwrap();
+        assert!(node.is_empty());
+        assert_eq!(all.merkle_root(), rest.merkle_root());
+        assert_eq!(node.split_off(0, 0), None);
+    }
+
+    #[test]
+    fn attach_reverses_split_off() {
+        let mut node: TrieNode<u32, StdMerkleHasher, 4> =
+            (0..300).map(|key| (key * 3, key)).collect();
+        let root = node.merkle_root();
+        let split = node.split_off(0b1011, 2).unwrap();
+        node.merkle_root();
+        assert_eq!(
+            node.attach(0b11, 1, split.clone()),
+            Err(TrieError::PrefixUnavailable {
+                prefix: 0b11,
+                prefix_len: 1
+            })
+        );
+        assert_eq!(
+            node.attach(0b1000, 2, split.clone()),
+            Err(TrieError::PrefixUnavailable {
+                prefix: 0b1000,
+                prefix_len: 2
+            })
+        );
+        assert!(matches!(
+            node.attach(0b01 << 28, 15, split.clone()),
+            Err(TrieError::DepthExceeded { max_depth: 1, .. })
+        ));
+
+        let rehashed = node.invalidation_stats().mutations;
+        node.attach(0b1011, 2, split).unwrap();
+        assert_eq!(node.invalidation_stats().mutations, rehashed + 1);
+        assert_eq!(node.merkle_root(), root);
+        assert_eq!(node.get(27), Some(&9));
+
+        // Into a trie of another cache generation, whose stale caches are dropped.
+        let mut other: TrieNode<u32, StdMerkleHasher, 4> = TrieNode::new();
+        other.invalidate_all();
+        let mut moved = node.split_off(0b10, 1).unwrap();
+        moved.merkle_root();
+        moved.insert(0b1, 7);
+        other.attach(0b10, 1, moved).unwrap();
+        let mut expected: TrieNode<u32, StdMerkleHasher, 4> = (0..300)
+            .map(|key| (key * 3, key))
+            .filter(|(key, _)| key & 0b11 == 0b10)
+            .chain([(0b110, 7)])
+            .collect();
+        assert_eq!(other.merkle_root(), expected.merkle_root());
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+
+use crate::trie_node::trie_node::{NodeIndex, TrieNode, ROOT};
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData, proof::ProofLevel};
+
+/// Where a node sits: the first `depth` digits of `path`, least significant first. Unlike a
+/// key, this also names the value-less nodes whose last digit is 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+pub struct NodePosition {
+    pub path: u32,
+    pub depth: u32,
+}
+
+/// A piece of a trie for state sync: the nodes of the subtrie at `prefix`, down to some depth,
+/// the roots of the subtries cut off below that depth, and the proof levels linking the
+/// subtrie's root to the trie's root, nearest ancestor first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateChunk<T, D> {
+    pub prefix: NodePosition,
+    pub nodes: Vec<(NodePosition, Option<T>)>,
+    pub boundary: Vec<(NodePosition, D)>,
+    pub levels: Vec<ProofLevel<D>>,
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    pub(crate) fn child_position(position: NodePosition, digit: usize) -> NodePosition {
+        NodePosition {
+            path: position.path | (digit as u32) << (position.depth * Self::BITS_PER_DIGIT),
+            depth: position.depth + 1,
+        }
+    }
+
+    /// Exports the subtrie under the first `prefix_len` digits of `prefix`, including `levels`
+    /// levels of nodes and cutting off the rest at their roots. `None` if there is no node at
+    /// the prefix. Exporting every prefix of one length, plus the top `prefix_len` levels from
+    /// the root, covers the whole trie.
+    pub fn export_chunk(
+        &mut self,
+        prefix: u32,
+        prefix_len: u32,
+        levels: u32,
+    ) -> Option<StateChunk<T, H::Hash>>
+    where
+        T: Clone,
+    {
+        let mut path: Vec<NodeIndex> = vec![ROOT];
+        for depth in 0..prefix_len {
+            let digit = Self::digit_at(prefix, depth);
+            path.push(self.node(*path.last().unwrap()).child(digit)?);
+        }
+        let prefix_position = NodePosition {
+            path: prefix & prefix_mask(prefix_len, Self::BITS_PER_DIGIT),
+            depth: prefix_len,
+        };
+        let cut_off = prefix_len.saturating_add(levels);
+
+        let mut chunk = StateChunk {
+            prefix: prefix_position,
+            nodes: vec![],
+            boundary: vec![],
+            levels: vec![],
+        };
+        let mut stack = vec![(path.pop().unwrap(), prefix_position)];
+        while let Some((index, position)) = stack.pop() {
+            if position.depth >= cut_off {
+                chunk.boundary.push((position, self.merkle_root_at(index)));
+                continue;
+            }
+            let node = self.node(index);
+            chunk.nodes.push((position, node.get_data().cloned()));
+            for digit in 0..N {
+                if let Some(child) = node.child(digit) {
+                    stack.push((child, Self::child_position(position, digit)));
+                }
+            }
+        }
+        for (depth, index) in path.into_iter().enumerate().rev() {
+            let digit = Self::digit_at(prefix, depth as u32);
+            chunk.levels.push(ProofLevel {
+                data_hash: self.data_hash_at(index),
+                siblings: self.child_roots(index, Some(digit)),
+                position: digit as u8,
+            });
+        }
+        Some(chunk)
+    }
+
+    /// Checks `chunk` against the trusted `root` and, if it holds, adds its nodes to this trie.
+    /// Chunks can be imported in any order; once all of them are in, the trie's root is `root`.
+    pub fn import_chunk(&mut self, root: &H::Hash, chunk: StateChunk<T, H::Hash>) -> bool {
+        if self.chunk_root(&chunk).as_ref() != Some(root) {
+            return false;
+        }
+        for (position, data) in chunk.nodes {
+            let index = self.create_path(position.path, position.depth);
+            if let Some(data) = data {
+                self.node_mut(index).replace_data(data);
+                self.note_key(position.path);
+            }
+        }
+        self.rehash_if_eager();
+        true
+    }
+
+    // The trie root `chunk` commits to, or `None` if it is malformed.
+    fn chunk_root(&self, chunk: &StateChunk<T, H::Hash>) -> Option<H::Hash> {
+        let bits_per_digit = Self::BITS_PER_DIGIT;
+        let well_formed = |position: &NodePosition| {
+            position.depth >= chunk.prefix.depth
+                && position.depth * bits_per_digit <= u32::BITS
+                && position.path & !prefix_mask(position.depth, bits_per_digit) == 0
+                && position.path & prefix_mask(chunk.prefix.depth, bits_per_digit)
+                    == chunk.prefix.path
+        };
+        let nodes: HashMap<NodePosition, Option<&T>> = chunk
+            .nodes
+            .iter()
+            .map(|(position, data)| (*position, data.as_ref()))
+            .collect();
+        let boundary: HashMap<NodePosition, &H::Hash> = chunk
+            .boundary
+            .iter()
+            .map(|(position, root)| (*position, root))
+            .collect();
+        if nodes.len() != chunk.nodes.len()
+            || boundary.len() != chunk.boundary.len()
+            || nodes.keys().any(|position| boundary.contains_key(position))
+            || !nodes.keys().chain(boundary.keys()).all(well_formed)
+            || chunk.levels.len() != chunk.prefix.depth as usize
+        {
+            return None;
+        }
+
+        let mut reached = 0;
+        let mut hash = self.subtree_root(chunk.prefix, &nodes, &boundary, &mut reached)?;
+        if reached != nodes.len() + boundary.len() {
+            return None;
+        }
+        for (level, depth) in chunk.levels.iter().zip((0..chunk.prefix.depth).rev()) {
+            if level.siblings.len() != N - 1 {
+                return None;
+            }
+            let mut children = level.siblings.clone();
+            children.insert(Self::digit_at(chunk.prefix.path, depth), hash);
+            hash = self.hasher.combine_children(&level.data_hash, &children);
+        }
+        Some(hash)
+    }
+
+    fn subtree_root(
+        &self,
+        position: NodePosition,
+        nodes: &HashMap<NodePosition, Option<&T>>,
+        boundary: &HashMap<NodePosition, &H::Hash>,
+        reached: &mut usize,
+    ) -> Option<H::Hash> {
+        if let Some(root) = boundary.get(&position) {
+            *reached += 1;
+            return Some((*root).clone());
+        }
+        let data = nodes.get(&position)?;
+        *reached += 1;
+        let data_hash = match data {
+            Some(data) => self.hasher.hash(&data.merkle_bytes()),
+            None => self.empty_hash().clone(),
+        };
+        let mut has_children = false;
+        let mut roots = Vec::with_capacity(N);
+        for digit in 0..N {
+            let root = if position.depth * Self::BITS_PER_DIGIT < u32::BITS {
+                let child = Self::child_position(position, digit);
+                self.subtree_root(child, nodes, boundary, reached)
+            } else {
+                None
+            };
+            has_children |= root.is_some();
+            roots.push(root.unwrap_or_else(|| self.empty_hash().clone()));
+        }
+        Some(if has_children {
+            self.hasher.combine_children(&data_hash, &roots)
+        } else {
+            data_hash
+        })
+    }
+}
+
+pub(crate) fn prefix_mask(depth: u32, bits_per_digit: u32) -> u32 {
+    depth
+        .checked_mul(bits_per_digit)
+        .and_then(|bits| u32::MAX.checked_shl(bits))
+        .map_or(u32::MAX, |high| !high)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn trie_is_rebuilt_from_verified_chunks() {
+        let mut source: TrieNode<u32> = (0..200).map(|key| (key * 37, key)).collect();
+        source.insert(u32::MAX, 1);
+        let root = source.merkle_root();
+
+        let mut chunks = vec![source.export_chunk(0, 0, 3).unwrap()];
+        for prefix in 0..8 {
+            chunks.push(source.export_chunk(prefix, 3, u32::MAX).unwrap());
+        }
+        let mut synced: TrieNode<u32> = TrieNode::new();
+        for chunk in chunks.into_iter().rev() {
+            assert!(synced.import_chunk(&root, chunk));
+        }
+        assert_eq!(synced.merkle_root(), root);
+        assert_eq!(synced.find_by_key(37 * 150).unwrap().get_data(), Some(&150));
+    }
+
+    #[test]
+    fn tampered_chunks_are_rejected() {
+        let mut source: TrieNode<u32> = (0..50).map(|key| (key * 3, key)).collect();
+        let root = source.merkle_root();
+        let chunk = source.export_chunk(0b10, 2, u32::MAX).unwrap();
+
+        let mut target: TrieNode<u32> = TrieNode::new();
+        let mut forged = chunk.clone();
+        let (_, data) = forged
+            .nodes
+            .iter_mut()
+            .find(|(_, data)| data.is_some())
+            .unwrap();
+        *data = Some(1000);
+        assert!(!target.import_chunk(&root, forged));
+
+        let mut extra = chunk.clone();
+        extra.nodes.push((
+            NodePosition {
+                path: 0b01,
+                depth: 2,
+            },
+            Some(1),
+        ));
+        assert!(!target.import_chunk(&root, extra));
+
+        let empty_root = target.merkle_root();
+        assert!(!target.import_chunk(&empty_root, chunk.clone()));
+        assert_eq!(target.merkle_root(), empty_root);
+        assert!(target.import_chunk(&root, chunk));
+    }
+}
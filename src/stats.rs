@@ -0,0 +1,484 @@
+use std::mem::size_of;
+use std::time::{Duration, Instant};
+
+use crate::bit_path::BitPath;
+use crate::bloom::BloomFilter;
+use crate::state_sync::NodePosition;
+use crate::trie_node::trie_node::{Node, NodeIndex, TrieNode, ROOT};
+use crate::{hasher::MerkleHasher, instrumentation, merkle_data::MerkleData};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TrieMetrics {
+    pub node_count: usize,
+    pub leaf_count: usize,
+    pub max_depth: usize,
+    pub estimated_heap_bytes: usize,
+}
+
+/// How a trie's nodes are spread out, for spotting key patterns that produce long chains.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ShapeStats {
+    /// Nodes at each depth, the root's depth being 0.
+    pub nodes_per_depth: Vec<usize>,
+    /// Nodes holding a value at each depth.
+    pub values_per_depth: Vec<usize>,
+    /// The mean depth of the nodes holding a value, i.e. the proof length; 0 without values.
+    pub average_path_length: f64,
+    /// `branch_occupancy[c]` internal nodes have exactly `c` children.
+    pub branch_occupancy: Vec<usize>,
+}
+
+/// How evenly the nodes at one depth split their values between their children. A node's skew
+/// is the share of the values below it held by its fullest child: `1 / N` when they are spread
+/// evenly, 1 when they all sit under one child.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LevelBalance {
+    /// Nodes at this depth with at least two values below them; the others can't be skewed.
+    pub nodes: usize,
+    pub mean_skew: f64,
+    pub max_skew: f64,
+}
+
+/// Skew at each depth, from `balance_report`. With random keys, or keys hashed as `SecureTrie`
+/// does, the top levels, which hold most values, stay near `1 / N`; deeper nodes split only a
+/// few values each, so their skew is noisier. Skew close to 1 at every depth means the keys
+/// share long runs of digits and the trie is degenerating into a list, which makes proofs long
+/// and writes slow.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BalanceReport {
+    /// Indexed by depth, the root's being 0.
+    pub levels: Vec<LevelBalance>,
+}
+
+impl BalanceReport {
+    /// The mean skew over every node counted at any depth; 0 if there are none.
+    pub fn mean_skew(&self) -> f64 {
+        let nodes: usize = self.levels.iter().map(|level| level.nodes).sum();
+        if nodes == 0 {
+            return 0.0;
+        }
+        let total: f64 = self
+            .levels
+            .iter()
+            .map(|level| level.mean_skew * level.nodes as f64)
+            .sum();
+        total / nodes as f64
+    }
+}
+
+/// Lookups served by a node cache such as `CachedStore`, counted since it was created.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub cached_nodes: usize,
+}
+
+impl CacheStats {
+    /// The fraction of lookups served from memory, or 0 before the first lookup.
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+/// How many cached roots each mutation invalidated: the number of nodes on its path that will
+/// need rehashing, counted over `insert`, `update`, `remove_subtree` and mutable indexing since
+/// the stats were last reset. An average close to the trie's depth means writes keep landing
+/// on freshly hashed paths, so caching saves little for them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidationStats {
+    pub mutations: u64,
+    pub hashes_invalidated: u64,
+    /// The fewest invalidated by one mutation; 0 before the first.
+    pub min: usize,
+    pub max: usize,
+}
+
+impl InvalidationStats {
+    /// The mean invalidated per mutation, or 0 before the first mutation.
+    pub fn average(&self) -> f64 {
+        if self.mutations == 0 {
+            0.0
+        } else {
+            self.hashes_invalidated as f64 / self.mutations as f64
+        }
+    }
+
+    pub(crate) fn record(&mut self, invalidated: usize) {
+        instrumentation::record_invalidations(invalidated);
+        self.min = if self.mutations == 0 {
+            invalidated
+        } else {
+            self.min.min(invalidated)
+        };
+        self.max = self.max.max(invalidated);
+        self.mutations += 1;
+        self.hashes_invalidated += invalidated as u64;
+    }
+}
+
+/// Where the time of one root computation went, from `merkle_root_profiled`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RootProfile {
+    /// Nodes reached, which is the rehashed ones plus the cached ones that stopped the walk.
+    pub nodes_visited: usize,
+    pub nodes_rehashed: usize,
+    pub cache_hits: usize,
+    /// Input to the hasher: value bytes plus the hashes fed to each combine.
+    pub bytes_hashed: usize,
+    /// Time spent on the nodes at each depth, not counting their descendants.
+    pub time_per_depth: Vec<Duration>,
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    /// `merkle_root`, along with a breakdown of the work it took. Nodes are hashed one at a
+    /// time, without `MerkleHasher::hash_batch`, so that each can be timed; the caches end up
+    /// as `merkle_root` leaves them.
+    pub fn merkle_root_profiled(&mut self) -> (H::Hash, RootProfile) {
+        let mut profile = RootProfile::default();
+        let root = self.profile_at(ROOT, 0, &mut profile);
+        (root, profile)
+    }
+
+    fn profile_at(&mut self, index: NodeIndex, depth: usize, profile: &mut RootProfile) -> H::Hash {
+        profile.nodes_visited += 1;
+        if let Some(root) = self.node(index).cached_merkle_root(self.cache_generation) {
+            profile.cache_hits += 1;
+            return root.clone();
+        }
+        profile.nodes_rehashed += 1;
+        let started = Instant::now();
+        let mut below = Duration::ZERO;
+
+        let data_hash = match self.node(index).cached_data_hash(self.cache_generation) {
+            Some(hash) => hash.clone(),
+            None => {
+                let hash = match self.node(index).get_data() {
+                    Some(data) => {
+                        let bytes = data.merkle_bytes();
+                        profile.bytes_hashed += bytes.len();
+                        self.hasher.hash(&bytes)
+                    }
+                    None => self.empty_hash().clone(),
+                };
+                self.cache_data_hash(index, hash.clone());
+                hash
+            }
+        };
+        let root = if self.node(index).is_leaf() {
+            data_hash
+        } else {
+            let children: Vec<Option<NodeIndex>> = self.node(index).children().to_vec();
+            let mut roots = Vec::with_capacity(N);
+            for child in children {
+                roots.push(match child {
+                    Some(child) => {
+                        let child_started = Instant::now();
+                        let root = self.profile_at(child, depth + 1, profile);
+                        below += child_started.elapsed();
+                        root
+                    }
+                    None => self.empty_hash().clone(),
+                });
+            }
+            profile.bytes_hashed += data_hash.as_ref().len()
+                + roots.iter().map(|root| root.as_ref().len()).sum::<usize>();
+            let root = self.hasher.combine_children(&data_hash, &roots);
+            self.cache_merkle_root(index, root.clone());
+            root
+        };
+
+        if profile.time_per_depth.len() <= depth {
+            profile.time_per_depth.resize(depth + 1, Duration::ZERO);
+        }
+        profile.time_per_depth[depth] += started.elapsed().saturating_sub(below);
+        root
+    }
+
+    pub fn invalidation_stats(&self) -> &InvalidationStats {
+        &self.invalidations
+    }
+
+    pub fn reset_invalidation_stats(&mut self) {
+        self.invalidations = InvalidationStats::default();
+    }
+
+    /// Counts are taken over every reachable node, including the root and the
+    /// data-less intermediate nodes created on the way to a key. The heap estimate
+    /// covers the node arena and cached hash strings, but not heap memory owned
+    /// by the stored values themselves.
+    pub fn metrics(&self) -> TrieMetrics {
+        let mut metrics = TrieMetrics {
+            estimated_heap_bytes: self.nodes.capacity() * size_of::<Node<T, H::Hash, N>>()
+                + self.bloom.as_ref().map_or(0, BloomFilter::heap_bytes),
+            ..TrieMetrics::default()
+        };
+        let mut stack: Vec<(NodeIndex, usize)> = vec![(ROOT, 0)];
+        while let Some((index, depth)) = stack.pop() {
+            let node = self.node(index);
+            metrics.node_count += 1;
+            metrics.max_depth = metrics.max_depth.max(depth);
+            for cached_hash in node.cached_hashes(self.cache_generation) {
+                metrics.estimated_heap_bytes += H::heap_bytes(cached_hash);
+            }
+
+            if node.is_leaf() {
+                metrics.leaf_count += 1;
+            } else {
+                metrics.estimated_heap_bytes += size_of::<[Option<NodeIndex>; N]>();
+            }
+            for child in node.children().iter().flatten() {
+                stack.push((*child, depth + 1));
+            }
+        }
+        metrics
+    }
+
+    /// Depth distribution, average path length and branch occupancy of every reachable node.
+    pub fn shape_stats(&self) -> ShapeStats {
+        let mut stats = ShapeStats {
+            branch_occupancy: vec![0; N + 1],
+            ..ShapeStats::default()
+        };
+        let mut total_depth = 0;
+        let mut stack: Vec<(NodeIndex, usize)> = vec![(ROOT, 0)];
+        while let Some((index, depth)) = stack.pop() {
+            let node = self.node(index);
+            if stats.nodes_per_depth.len() <= depth {
+                stats.nodes_per_depth.resize(depth + 1, 0);
+                stats.values_per_depth.resize(depth + 1, 0);
+            }
+            stats.nodes_per_depth[depth] += 1;
+            if node.get_data().is_some() {
+                stats.values_per_depth[depth] += 1;
+                total_depth += depth;
+            }
+            if !node.is_leaf() {
+                let children = node.children().iter().flatten().count();
+                stats.branch_occupancy[children] += 1;
+            }
+            for child in node.children().iter().flatten() {
+                stack.push((*child, depth + 1));
+            }
+        }
+        let values: usize = stats.values_per_depth.iter().sum();
+        if values > 0 {
+            stats.average_path_length = total_depth as f64 / values as f64;
+        }
+        stats
+    }
+
+    /// The number of values below each child of the node at `position`, in digit order; `None`
+    /// if there is no node there.
+    pub fn child_populations(&self, position: NodePosition) -> Option<Vec<usize>> {
+        let path = BitPath::new(position.path as u64, position.depth * Self::BITS_PER_DIGIT);
+        let index = self.index_by_path(&path)?;
+        Some(
+            self.node(index)
+                .children()
+                .iter()
+                .map(|child| child.map_or(0, |child| self.iter_at(child).count()))
+                .collect(),
+        )
+    }
+
+    // The values in the subtree at `index`.
+    fn iter_at(&self, index: NodeIndex) -> impl Iterator<Item = &T> + '_ {
+        let mut stack = vec![index];
+        std::iter::from_fn(move || loop {
+            let node = self.node(stack.pop()?);
+            stack.extend(node.children().iter().flatten());
+            if let Some(data) = node.get_data() {
+                return Some(data);
+            }
+        })
+    }
+
+    /// The skew of every node's children, summarised per depth.
+    pub fn balance_report(&self) -> BalanceReport {
+        // Every node in depth-first order with its depth, so each comes before its children
+        // and the values below them can be totalled from the end.
+        let mut order: Vec<(NodeIndex, usize)> = vec![];
+        let mut stack = vec![(ROOT, 0)];
+        while let Some((index, depth)) = stack.pop() {
+            order.push((index, depth));
+            for child in self.node(index).children().iter().flatten() {
+                stack.push((*child, depth + 1));
+            }
+        }
+        let mut values_below = vec![0; self.nodes.len()];
+        let mut report = BalanceReport::default();
+        for (index, depth) in order.into_iter().rev() {
+            let node = self.node(index);
+            let populations: Vec<usize> = node
+                .children()
+                .iter()
+                .map(|child| child.map_or(0, |child| values_below[child as usize]))
+                .collect();
+            let below: usize = populations.iter().sum();
+            values_below[index as usize] = below + node.get_data().is_some() as usize;
+            if below < 2 {
+                continue;
+            }
+            let skew = *populations.iter().max().unwrap() as f64 / below as f64;
+            if report.levels.len() <= depth {
+                report.levels.resize(depth + 1, LevelBalance::default());
+            }
+            let level = &mut report.levels[depth];
+            level.mean_skew =
+                (level.mean_skew * level.nodes as f64 + skew) / (level.nodes + 1) as f64;
+            level.nodes += 1;
+            level.max_skew = level.max_skew.max(skew);
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn metrics_of_empty_trie() {
+        let node: TrieNode<String> = TrieNode::new();
+        let metrics = node.metrics();
+        assert_eq!(metrics.node_count, 1);
+        assert_eq!(metrics.leaf_count, 1);
+        assert_eq!(metrics.max_depth, 0);
+        assert_eq!(metrics.estimated_heap_bytes, size_of::<Node<String>>());
+    }
+
+    #[test]
+    fn shape_stats_of_a_chain() {
+        let mut node: TrieNode<u32> = TrieNode::new();
+        node.insert(0b1000, 1);
+        node.insert(0b1001, 2);
+        let stats = node.shape_stats();
+        assert_eq!(stats.nodes_per_depth, [1, 2, 2, 2, 2]);
+        assert_eq!(stats.values_per_depth, [0, 0, 0, 0, 2]);
+        assert_eq!(stats.average_path_length, 4.0);
+        // Digits are read least significant first, so the keys split at the root into two
+        // chains of single-child nodes.
+        assert_eq!(stats.branch_occupancy, [0, 6, 1]);
+    }
+
+    #[test]
+    fn balance_reports_tell_chains_from_spread_keys() {
+        let spread: TrieNode<u32> = (1..256).map(|key| (key, key)).collect();
+        assert_eq!(
+            spread.child_populations(NodePosition { path: 0, depth: 0 }),
+            Some(vec![127, 128])
+        );
+        let report = spread.balance_report();
+        assert_eq!(report.levels[0].nodes, 1);
+        assert!(report.levels[..4].iter().all(|level| level.max_skew < 0.52));
+
+        // Each key leaves the shared run of zeros one digit later.
+        let chain: TrieNode<u32> = (0..31).map(|bit| (1 << bit, bit)).collect();
+        let report = chain.balance_report();
+        assert!(report.mean_skew() > 0.8);
+        assert_eq!(report.levels[0].max_skew, 30.0 / 31.0);
+        assert_eq!(
+            chain.child_populations(NodePosition { path: 0, depth: 1 }),
+            Some(vec![29, 1])
+        );
+        assert_eq!(
+            chain.child_populations(NodePosition { path: 3, depth: 2 }),
+            None
+        );
+        assert_eq!(TrieNode::<u32>::new().balance_report().mean_skew(), 0.0);
+    }
+
+    #[test]
+    fn profiled_roots_count_the_work_done() {
+        let mut node: TrieNode<u32> = [(1, 1), (2, 2), (3, 3)].into_iter().collect();
+        let (root, profile) = node.merkle_root_profiled();
+        assert_eq!(node.current_root(), Some(&root));
+        assert_eq!(profile.nodes_rehashed, 5);
+        assert_eq!(profile.cache_hits, 0);
+        assert_eq!(profile.time_per_depth.len(), 3);
+
+        // Key 3's path is rehashed; the root's other child, the parent of key 2, is cached.
+        node.insert(3, 4);
+        let (root, profile) = node.merkle_root_profiled();
+        let mut expected: TrieNode<u32> = [(1, 1), (2, 2), (3, 4)].into_iter().collect();
+        assert_eq!(root, expected.merkle_root());
+        assert_eq!(
+            (
+                profile.nodes_visited,
+                profile.nodes_rehashed,
+                profile.cache_hits
+            ),
+            (4, 3, 1)
+        );
+        // The new value's 4 bytes, then two combines of three hashes each.
+        assert!(profile.bytes_hashed > 4 + 6);
+    }
+
+    #[test]
+    fn metrics_count_intermediate_nodes_and_cached_roots() {
+        let mut node: TrieNode<String> = TrieNode::new();
+        node.insert(4, "foo".to_string());
+        node.insert(2, "bar".to_string());
+        let metrics = node.metrics();
+        assert_eq!(metrics.node_count, 5);
+        assert_eq!(metrics.leaf_count, 2);
+        assert_eq!(metrics.max_depth, 3);
+
+        let uncached_bytes = metrics.estimated_heap_bytes;
+        assert_eq!(
+            uncached_bytes,
+            node.nodes.capacity() * size_of::<Node<String>>()
+                + 3 * size_of::<[Option<NodeIndex>; 2]>()
+        );
+        node.merkle_root();
+        assert!(node.metrics().estimated_heap_bytes > uncached_bytes);
+    }
+
+    #[test]
+    fn invalidations_count_the_cached_roots_on_each_path() {
+        let mut node: TrieNode<u32> = [(1, 1), (2, 2), (3, 3)].into_iter().collect();
+        // Nothing was cached while building.
+        assert_eq!(node.invalidation_stats().hashes_invalidated, 0);
+        assert_eq!(node.invalidation_stats().mutations, 3);
+        node.reset_invalidation_stats();
+
+        node.merkle_root();
+        node.insert(3, 4);
+        node.insert(3, 5);
+        node.merkle_root();
+        node.update(2, |value| *value += 1);
+        let stats = *node.invalidation_stats();
+        // Root, key 1's node and key 3's node; then nothing; then the root and key 2's two.
+        assert_eq!((stats.mutations, stats.min, stats.max), (3, 0, 3));
+        assert_eq!(stats.hashes_invalidated, 6);
+        assert_eq!(stats.average(), 2.0);
+        assert_eq!(InvalidationStats::default().average(), 0.0);
+    }
+
+    #[test]
+    fn removing_a_subtree_counts_the_roots_on_its_path() {
+        let mut node: TrieNode<u32> = [(1, 1), (3, 3), (5, 5)].into_iter().collect();
+        node.merkle_root();
+        node.reset_invalidation_stats();
+
+        // Key 1's node keeps key 5's branch, so it stays internal.
+        assert!(node.remove_subtree(0b11, 2));
+        assert!(!node.find_by_key(1).unwrap().is_leaf());
+        assert_eq!(node.invalidation_stats().hashes_invalidated, 2);
+        assert!(!node.remove_subtree(0b11, 2));
+        assert_eq!(node.invalidation_stats().mutations, 1);
+
+        node.merkle_root();
+        assert!(node.remove_subtree(0, 0));
+        let stats = *node.invalidation_stats();
+        assert_eq!((stats.mutations, stats.hashes_invalidated), (2, 3));
+    }
+}
@@ -0,0 +1,181 @@
+use std::borrow::Cow;
+
+use crate::trie_node::trie_node::TrieNode;
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+// Key bytes per level; the rest of a level's 32-bit key holds the markers below.
+const CHUNK_BYTES: usize = 3;
+
+/// The key `chunk` is stored under in its level of a `StrTrie`. The top bit is always set, so
+/// every chunk sits at the same depth; bit 30 marks chunks that continue into a further level,
+/// so no key's path is a prefix of another's, and a key's last chunk carries its length (0 to 3
+/// bytes) in bits 24 and 25.
+pub fn chunk_key(chunk: &[u8], last: bool) -> u32 {
+    debug_assert!(chunk.len() <= CHUNK_BYTES);
+    let bytes = chunk
+        .iter()
+        .fold(0u32, |bytes, byte| bytes << 8 | *byte as u32)
+        << (8 * (CHUNK_BYTES - chunk.len()));
+    let marker = if last {
+        (chunk.len() as u32) << 24
+    } else {
+        1 << 30
+    };
+    1 << 31 | marker | bytes
+}
+
+/// What a level of a `StrTrie` holds under a chunk: the value, for a key's last chunk, or the
+/// level for the chunks after it. Commits to a tag byte followed by the value's bytes or the
+/// level's root.
+#[derive(Debug, Clone)]
+pub enum StrEntry<T: MerkleData, H: MerkleHasher, const N: usize> {
+    Value(T),
+    Level(TrieNode<StrEntry<T, H, N>, H, N>),
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> MerkleData for StrEntry<T, H, N> {
+    fn merkle_bytes(&self) -> Cow<'_, [u8]> {
+        let (tag, bytes) = match self {
+            StrEntry::Value(value) => (0, value.merkle_bytes()),
+            StrEntry::Level(level) => (1, level.merkle_bytes()),
+        };
+        let mut tagged = Vec::with_capacity(1 + bytes.len());
+        tagged.push(tag);
+        tagged.extend_from_slice(&bytes);
+        Cow::Owned(tagged)
+    }
+}
+
+impl<T: MerkleData + PartialEq, H: MerkleHasher, const N: usize> PartialEq for StrEntry<T, H, N> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (StrEntry::Value(value), StrEntry::Value(other)) => value == other,
+            (StrEntry::Level(level), StrEntry::Level(other)) => level == other,
+            _ => false,
+        }
+    }
+}
+
+/// A trie keyed by strings of any length, such as `"accounts/alice/balance"`. Keys are split
+/// into 3-byte chunks, each addressing one level: a trie whose values are either a key's value
+/// or the trie of the next level, so a key's bit path grows with its length rather than being
+/// capped at 32 bits. Levels are nested tries, and their roots are cached as they change.
+pub struct StrTrie<T: MerkleData, H: MerkleHasher, const N: usize = 2> {
+    root: TrieNode<StrEntry<T, H, N>, H, N>,
+}
+
+impl<T: MerkleData + PartialEq, H: MerkleHasher + Clone + Default, const N: usize> Default
+    for StrTrie<T, H, N>
+{
+    fn default() -> Self {
+        StrTrie::with_hasher(H::default())
+    }
+}
+
+impl<T: MerkleData + PartialEq, H: MerkleHasher + Clone, const N: usize> StrTrie<T, H, N> {
+    pub fn with_hasher(hasher: H) -> Self {
+        StrTrie {
+            root: TrieNode::with_hasher(hasher),
+        }
+    }
+
+    /// The top level, keyed by `chunk_key` of each key's first chunk.
+    pub fn trie(&self) -> &TrieNode<StrEntry<T, H, N>, H, N> {
+        &self.root
+    }
+
+    pub fn insert(&mut self, key: &str, value: T) {
+        let chunks: Vec<&[u8]> = Self::chunks(key);
+        Self::insert_at(&mut self.root, &chunks, value);
+    }
+
+    fn insert_at(level: &mut TrieNode<StrEntry<T, H, N>, H, N>, chunks: &[&[u8]], value: T) {
+        let (chunk, rest) = chunks.split_first().expect("every key has a chunk");
+        let key = chunk_key(chunk, rest.is_empty());
+        if rest.is_empty() {
+            level.insert(key, StrEntry::Value(value));
+        } else if level.get(key).is_some() {
+            level.update(key, |entry| match entry {
+                StrEntry::Level(next) => {
+                    Self::insert_at(next, rest, value);
+                    next.merkle_root();
+                }
+                StrEntry::Value(_) => unreachable!("continuing chunks never hold values"),
+            });
+        } else {
+            let mut next = TrieNode::with_hasher(level.hasher().clone());
+            Self::insert_at(&mut next, rest, value);
+            next.merkle_root();
+            level.insert(key, StrEntry::Level(next));
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&T> {
+        let chunks = Self::chunks(key);
+        let mut level = &self.root;
+        for (position, chunk) in chunks.iter().enumerate() {
+            let last = position + 1 == chunks.len();
+            match level.get(chunk_key(chunk, last))? {
+                StrEntry::Value(value) if last => return Some(value),
+                StrEntry::Level(next) if !last => level = next,
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    pub fn merkle_root(&mut self) -> H::Hash {
+        self.root.merkle_root()
+    }
+
+    // The empty key still has one, empty, chunk.
+    fn chunks(key: &str) -> Vec<&[u8]> {
+        match key.as_bytes() {
+            [] => vec![&[]],
+            bytes => bytes.chunks(CHUNK_BYTES).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn long_keys_nest_without_prefix_clashes() {
+        let keys = [
+            "accounts/alice/balance",
+            "accounts/alice/nonce",
+            "accounts/bob/balance",
+            "",
+            "abc",
+            "abcd",
+            "ab",
+        ];
+        let mut trie: StrTrie<u32, StdMerkleHasher> = StrTrie::default();
+        for (value, key) in keys.iter().enumerate() {
+            trie.insert(key, value as u32);
+        }
+        for (value, key) in keys.iter().enumerate() {
+            assert_eq!(trie.get(key), Some(&(value as u32)));
+        }
+        assert_eq!(trie.get("accounts/alice"), None);
+        assert_eq!(trie.get("a"), None);
+        assert_eq!(trie.get("abc\0"), None);
+        let root = trie.merkle_root();
+
+        let mut reversed: StrTrie<u32, StdMerkleHasher> = StrTrie::default();
+        for (value, key) in keys.iter().enumerate().rev() {
+            reversed.insert(key, value as u32);
+        }
+        assert_eq!(reversed.merkle_root(), root);
+
+        trie.insert("accounts/bob/balance", 100);
+        assert_eq!(trie.get("accounts/bob/balance"), Some(&100));
+        assert_ne!(trie.merkle_root(), root);
+        assert_ne!(chunk_key(b"ab", true), chunk_key(b"ab\0", true));
+        assert_ne!(chunk_key(b"abc", true), chunk_key(b"abc", false));
+    }
+}
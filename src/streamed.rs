@@ -0,0 +1,240 @@
+use std::borrow::Cow;
+use std::io::{self, Read};
+
+use crate::embedded::StreamingHasher;
+use crate::hasher::hashes_equal;
+use crate::merkle_data::MerkleData;
+
+// Bytes read into memory at a time.
+const READ_CHUNK: usize = 64 * 1024;
+
+/// A value stored as the hash of its content, for values too large to hold in memory. The
+/// content is hashed as it is read and then dropped; the trie commits to the hash, so a proof
+/// for the value takes the hash's bytes as the value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentDigest<D> {
+    pub hash: D,
+    pub len: u64,
+}
+
+impl<D: AsRef<[u8]>> ContentDigest<D> {
+    /// Hashes everything `reader` yields, `READ_CHUNK` bytes at a time.
+    pub fn from_reader<H>(hasher: &H, mut reader: impl Read) -> io::Result<Self>
+    where
+        H: StreamingHasher<Digest = D>,
+    {
+        let mut state = hasher.start();
+        let mut buffer = vec![0; READ_CHUNK];
+        let mut len = 0;
+        loop {
+            match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => {
+                    hasher.update(&mut state, &buffer[..read]);
+                    len += read as u64;
+                }
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(ContentDigest {
+            hash: hasher.finish(state),
+            len,
+        })
+    }
+
+    /// Hashes the concatenation of `chunks`.
+    pub fn from_chunks<H, I>(hasher: &H, chunks: I) -> Self
+    where
+        H: StreamingHasher<Digest = D>,
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let mut state = hasher.start();
+        let mut len = 0;
+        for chunk in chunks {
+            hasher.update(&mut state, chunk.as_ref());
+            len += chunk.as_ref().len() as u64;
+        }
+        ContentDigest {
+            hash: hasher.finish(state),
+            len,
+        }
+    }
+}
+
+impl<D: AsRef<[u8]>> MerkleData for ContentDigest<D> {
+    fn merkle_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.hash.as_ref())
+    }
+}
+
+/// Content kept outside the trie, e.g. a file in an attested tree: where it lives, how long it
+/// is and its hash. The trie commits to the hash alone, like `ContentDigest`, so moving the
+/// content doesn't change the root; `verifying` checks the content as it is read back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlobRef<D> {
+    /// A path or URI, as the application resolves it.
+    pub location: String,
+    pub len: u64,
+    pub content_hash: D,
+}
+
+impl<D: AsRef<[u8]>> BlobRef<D> {
+    /// Hashes the content at `location` from `reader`.
+    pub fn from_reader<H>(hasher: &H, location: String, reader: impl Read) -> io::Result<Self>
+    where
+        H: StreamingHasher<Digest = D>,
+    {
+        let digest = ContentDigest::from_reader(hasher, reader)?;
+        Ok(BlobRef {
+            location,
+            len: digest.len,
+            content_hash: digest.hash,
+        })
+    }
+
+    /// Wraps `reader`, the blob's content, so that reading it fails with `InvalidData` if it
+    /// runs past `len`, or ends with a different length or hash.
+    pub fn verifying<'a, H, R>(&'a self, hasher: &'a H, reader: R) -> VerifyingReader<'a, D, H, R>
+    where
+        H: StreamingHasher<Digest = D>,
+        R: Read,
+    {
+        VerifyingReader {
+            blob: self,
+            hasher,
+            inner: reader,
+            state: Some(hasher.start()),
+            read: 0,
+        }
+    }
+
+    /// Whether `reader` yields exactly the blob's content.
+    pub fn verify<H>(&self, hasher: &H, reader: impl Read) -> io::Result<bool>
+    where
+        H: StreamingHasher<Digest = D>,
+    {
+        let digest = ContentDigest::from_reader(hasher, reader)?;
+        Ok(
+            digest.len == self.len
+                && hashes_equal(digest.hash.as_ref(), self.content_hash.as_ref()),
+        )
+    }
+}
+
+impl<D: AsRef<[u8]>> MerkleData for BlobRef<D> {
+    fn merkle_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.content_hash.as_ref())
+    }
+}
+
+/// Reads a blob's content through, checking it against its `BlobRef`. See `BlobRef::verifying`.
+pub struct VerifyingReader<'a, D, H: StreamingHasher, R> {
+    blob: &'a BlobRef<D>,
+    hasher: &'a H,
+    inner: R,
+    // Taken once the end of the content is reached and checked.
+    state: Option<H::State>,
+    read: u64,
+}
+
+impl<D: AsRef<[u8]>, H: StreamingHasher<Digest = D>, R: Read> Read
+    for VerifyingReader<'_, D, H, R>
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        let Some(state) = &mut self.state else {
+            return Ok(read);
+        };
+        if read > 0 {
+            self.hasher.update(state, &buf[..read]);
+            self.read += read as u64;
+            if self.read > self.blob.len {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "blob is longer than its reference",
+                ));
+            }
+        } else if !buf.is_empty() {
+            let hash = self.hasher.finish(self.state.take().unwrap());
+            if self.read != self.blob.len
+                || !hashes_equal(hash.as_ref(), self.blob.content_hash.as_ref())
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "blob does not match its reference",
+                ));
+            }
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(all(test, feature = "digest"))]
+mod tests {
+
+    use super::*;
+    use crate::hasher::{DigestHasher, MerkleHasher};
+    use crate::trie_node::trie_node::TrieNode;
+
+    #[test]
+    fn streamed_values_hash_like_whole_values() {
+        let hasher = DigestHasher::<sha2::Sha256>::new();
+        let content: Vec<u8> = (0..200_000u32).map(|byte| byte as u8).collect();
+        let read = ContentDigest::from_reader(&hasher, content.as_slice()).unwrap();
+        assert_eq!(read.hash, hasher.hash(&content));
+        assert_eq!(read.len, content.len() as u64);
+        assert_eq!(
+            ContentDigest::from_chunks(&hasher, content.chunks(999)),
+            read
+        );
+
+        let mut node: TrieNode<ContentDigest<_>, DigestHasher<sha2::Sha256>> =
+            TrieNode::with_hasher(hasher.clone());
+        node.insert(7, read.clone());
+        let root = node.merkle_root();
+        let proof = node.generate_proof(7).unwrap();
+        assert!(proof.verify(&hasher, &root, read.hash.as_slice()));
+        assert!(!proof.verify(&hasher, &root, content.as_slice()));
+    }
+
+    #[test]
+    fn blob_refs_commit_to_content_and_check_it_on_read() {
+        let hasher = DigestHasher::<sha2::Sha256>::new();
+        let content = b"attested file contents".repeat(1000);
+        let blob =
+            BlobRef::from_reader(&hasher, "docs/a.txt".to_string(), content.as_slice()).unwrap();
+        assert!(blob.verify(&hasher, content.as_slice()).unwrap());
+
+        let mut read = vec![];
+        blob.verifying(&hasher, content.as_slice())
+            .read_to_end(&mut read)
+            .unwrap();
+        assert_eq!(read, content);
+        let mut tampered = content.clone();
+        tampered[500] ^= 1;
+        assert!(!blob.verify(&hasher, tampered.as_slice()).unwrap());
+        let error = blob
+            .verifying(&hasher, tampered.as_slice())
+            .read_to_end(&mut vec![])
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(blob
+            .verifying(&hasher, &content[1..])
+            .read_to_end(&mut vec![])
+            .is_err());
+
+        // Where the blob lives isn't committed to.
+        let moved = BlobRef {
+            location: "archive/a.txt".to_string(),
+            ..blob.clone()
+        };
+        let mut node: TrieNode<BlobRef<_>, DigestHasher<sha2::Sha256>> =
+            TrieNode::with_hasher(hasher.clone());
+        node.insert(1, blob);
+        let root = node.merkle_root();
+        node.insert(1, moved);
+        assert_eq!(node.merkle_root(), root);
+    }
+}
@@ -0,0 +1,182 @@
+use crate::error::TrieError;
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+// A node on the spine: the ancestors of the last entry pushed, whose subtrees are still open.
+#[derive(Debug, Clone)]
+struct Frame<D> {
+    path: u32,
+    data_hash: Option<D>,
+    children: Vec<Option<D>>,
+}
+
+/// Computes the root of a trie of arity `N` from its entries one at a time, holding only the
+/// spine of the tree: the nodes on the path to the last entry, with the roots of their finished
+/// children. Memory stays at O(depth · N) hashes however many entries go by, so the root of a
+/// dataset far larger than memory can be computed in one pass without building the trie.
+///
+/// Entries must come in trie order, the order `TrieNode::iter` yields: depth first, digits in
+/// ascending order, each key before the keys below it. Keys are read least significant digit
+/// first, so this is not ascending numeric order; sorting by `trie_order` gives it.
+#[derive(Debug, Clone)]
+pub struct StreamingRoot<H: MerkleHasher, const N: usize = 2> {
+    hasher: H,
+    spine: Vec<Frame<H::Hash>>,
+    last: Option<u32>,
+}
+
+impl<H: MerkleHasher, const N: usize> StreamingRoot<H, N> {
+    const BITS_PER_DIGIT: u32 = N.trailing_zeros();
+
+    pub fn new(hasher: H) -> Self {
+        const {
+            assert!(
+                N.is_power_of_two() && N >= 2 && N <= 256,
+                "arity must be a power of two between 2 and 256"
+            )
+        };
+        StreamingRoot {
+            spine: vec![Frame {
+                path: 0,
+                data_hash: None,
+                children: vec![None; N],
+            }],
+            hasher,
+            last: None,
+        }
+    }
+
+    fn key_depth(key: u32) -> u32 {
+        (u32::BITS - key.leading_zeros()).div_ceil(Self::BITS_PER_DIGIT)
+    }
+
+    fn digit_at(key: u32, depth: u32) -> usize {
+        ((key >> (depth * Self::BITS_PER_DIGIT)) as usize) & (N - 1)
+    }
+
+    /// A sort key putting keys in trie order: their digits reversed, so the first digit is the
+    /// most significant, then their depth, so a key comes before the keys below it.
+    pub fn trie_order(key: u32) -> u64 {
+        let reversed = (0..Self::key_depth(key)).fold(0u64, |reversed, depth| {
+            reversed << Self::BITS_PER_DIGIT | Self::digit_at(key, depth) as u64
+        });
+        let max_depth = u32::BITS.div_ceil(Self::BITS_PER_DIGIT);
+        let padding = (max_depth - Self::key_depth(key)) * Self::BITS_PER_DIGIT;
+        (reversed << padding) << 8 | Self::key_depth(key) as u64
+    }
+
+    /// Adds the entry for `key`, which must come after every key pushed so far in trie order.
+    pub fn push<T: MerkleData + ?Sized>(&mut self, key: u32, value: &T) -> Result<(), TrieError> {
+        if let Some(previous) = self.last {
+            if Self::trie_order(key) <= Self::trie_order(previous) {
+                return Err(TrieError::OutOfOrder { key, previous });
+            }
+        }
+        self.last = Some(key);
+
+        let depth = Self::key_depth(key);
+        let mask = |depth: u32| ((1u64 << (depth * Self::BITS_PER_DIGIT)) - 1) as u32;
+        // Close the frames that aren't ancestors of `key`; the root always is.
+        while self.spine.len() as u32 - 1 > depth
+            || self.spine.last().unwrap().path != key & mask(self.spine.len() as u32 - 1)
+        {
+            self.close_frame();
+        }
+        for child_depth in self.spine.len() as u32..=depth {
+            self.spine.push(Frame {
+                path: key & mask(child_depth),
+                data_hash: None,
+                children: vec![None; N],
+            });
+        }
+        self.spine.last_mut().unwrap().data_hash = Some(self.hasher.hash(&value.merkle_bytes()));
+        Ok(())
+    }
+
+    // Pops the deepest frame and records its root in its parent.
+    fn close_frame(&mut self) {
+        let frame = self.spine.pop().unwrap();
+        let depth = self.spine.len() as u32;
+        let digit = Self::digit_at(frame.path, depth - 1);
+        let root = self.frame_root(frame);
+        self.spine.last_mut().unwrap().children[digit] = Some(root);
+    }
+
+    fn frame_root(&self, frame: Frame<H::Hash>) -> H::Hash {
+        let data_hash = frame.data_hash.unwrap_or_else(|| self.hasher.empty_hash());
+        if frame.children.iter().all(Option::is_none) {
+            return data_hash;
+        }
+        let children: Vec<H::Hash> = frame
+            .children
+            .into_iter()
+            .map(|child| child.unwrap_or_else(|| self.hasher.empty_hash()))
+            .collect();
+        self.hasher.combine_children(&data_hash, &children)
+    }
+
+    /// The root of the trie holding every entry pushed.
+    pub fn finish(mut self) -> H::Hash {
+        while self.spine.len() > 1 {
+            self.close_frame();
+        }
+        let root = self.spine.pop().unwrap();
+        self.frame_root(root)
+    }
+
+    /// The root over `entries`, which must be in trie order.
+    pub fn root_of<T, I>(hasher: H, entries: I) -> Result<H::Hash, TrieError>
+    where
+        T: MerkleData,
+        I: IntoIterator<Item = (u32, T)>,
+    {
+        let mut streaming = Self::new(hasher);
+        for (key, value) in entries {
+            streaming.push(key, &value)?;
+        }
+        Ok(streaming.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+    use crate::trie_node::trie_node::TrieNode;
+
+    #[test]
+    fn streamed_root_matches_the_built_trie() {
+        let mut keys: Vec<u32> = (0..2000).map(|key| key * 37 % 5003).collect();
+        keys.push(u32::MAX);
+        let mut expected: TrieNode<u32, StdMerkleHasher, 8> =
+            keys.iter().map(|key| (*key, key / 3)).collect();
+        let order: Vec<u32> = expected.iter().map(|(key, _)| key).collect();
+        keys.sort_by_key(|key| StreamingRoot::<StdMerkleHasher, 8>::trie_order(*key));
+        assert_eq!(keys, order);
+
+        let root =
+            StreamingRoot::<_, 8>::root_of(StdMerkleHasher, keys.iter().map(|key| (*key, key / 3)));
+        assert_eq!(root, Ok(expected.merkle_root()));
+        assert_eq!(
+            StreamingRoot::<_, 2>::root_of(StdMerkleHasher, std::iter::empty::<(u32, u32)>()),
+            Ok(TrieNode::<u32>::new().merkle_root())
+        );
+
+        let mut streaming = StreamingRoot::<_, 2>::new(StdMerkleHasher);
+        streaming.push(1, &1u32).unwrap();
+        assert_eq!(
+            streaming.push(2, &2u32),
+            Err(TrieError::OutOfOrder {
+                key: 2,
+                previous: 1
+            })
+        );
+        assert_eq!(
+            streaming.push(1, &1u32),
+            Err(TrieError::OutOfOrder {
+                key: 1,
+                previous: 1
+            })
+        );
+    }
+}
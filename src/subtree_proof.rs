@@ -0,0 +1,161 @@
+use crate::hasher::{hashes_equal, MerkleHasher};
+use crate::proof::{MerkleProof, ProofLevel};
+use crate::state_sync::{prefix_mask, NodePosition};
+use crate::trie_node::trie_node::{NodeIndex, TrieNode, ROOT};
+use crate::{merkle_data::MerkleData, proof::SUPPORTED_PROOF_VERSIONS};
+
+/// Proof that the subtree at `prefix` has root `subtree_root`: one level per ancestor of the
+/// subtree's node, nearest first, as in `MerkleProof`. Once it is checked against the trie's
+/// root, keys under the prefix can be checked against `subtree_root` alone with
+/// `MerkleProof::verify_in_subtree`, e.g. by a light client syncing one part of the trie.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+pub struct SubtreeProof<D> {
+    pub version: u8,
+    pub prefix: NodePosition,
+    pub arity: usize,
+    pub subtree_root: D,
+    pub levels: Vec<ProofLevel<D>>,
+}
+
+impl<D: Clone + AsRef<[u8]>> SubtreeProof<D> {
+    pub fn verify<H: MerkleHasher<Hash = D>>(&self, hasher: &H, root: &D) -> bool {
+        if !SUPPORTED_PROOF_VERSIONS.contains(&self.version)
+            || !self.arity.is_power_of_two()
+            || !(2..=256).contains(&self.arity)
+            || self.levels.len() != self.prefix.depth as usize
+        {
+            return false;
+        }
+        let bits_per_digit = self.arity.trailing_zeros();
+        let mut hash = self.subtree_root.clone();
+        for (level, ancestor_depth) in self.levels.iter().zip((0..self.prefix.depth).rev()) {
+            if level.siblings.len() != self.arity - 1 {
+                return false;
+            }
+            let shifted = self
+                .prefix
+                .path
+                .checked_shr(ancestor_depth * bits_per_digit);
+            let digit = shifted.unwrap_or(0) as usize & (self.arity - 1);
+            let mut children = level.siblings.clone();
+            children.insert(digit, hash);
+            hash = hasher.combine_children(&level.data_hash, &children);
+        }
+        hashes_equal(hash.as_ref(), root.as_ref())
+    }
+}
+
+impl<D: Clone + AsRef<[u8]>> MerkleProof<D> {
+    /// Whether this proof shows `value` under its key within the subtree at `prefix` whose
+    /// root is `subtree_root`, as established by a `SubtreeProof`. Only the levels below the
+    /// prefix are used; a key outside the prefix never verifies.
+    pub fn verify_in_subtree<H, V>(
+        &self,
+        hasher: &H,
+        prefix: &NodePosition,
+        subtree_root: &D,
+        value: &V,
+    ) -> bool
+    where
+        H: MerkleHasher<Hash = D>,
+        V: MerkleData + ?Sized,
+    {
+        let mask = prefix_mask(prefix.depth, self.arity.trailing_zeros());
+        self.key & mask == prefix.path & mask
+            && self
+                .root_at_depth(hasher, value, prefix.depth)
+                .is_some_and(|computed| hashes_equal(computed.as_ref(), subtree_root.as_ref()))
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    /// Proves the root of the subtree reached by the first `prefix_len` digits of `prefix`.
+    /// `None` if there is no node there.
+    pub fn generate_subtree_proof(
+        &mut self,
+        prefix: u32,
+        prefix_len: u32,
+    ) -> Option<SubtreeProof<H::Hash>> {
+        if !Self::prefix_fits(prefix_len) {
+            return None;
+        }
+        let mut path: Vec<NodeIndex> = vec![ROOT];
+        for depth in 0..prefix_len {
+            let digit = Self::digit_at(prefix, depth);
+            path.push(self.node(*path.last().unwrap()).child(digit)?);
+        }
+        let subtree_root = self.merkle_root_at(path.pop().unwrap());
+        let mut levels = Vec::with_capacity(path.len());
+        for (ancestor_depth, index) in path.into_iter().enumerate().rev() {
+            let digit = Self::digit_at(prefix, ancestor_depth as u32);
+            levels.push(ProofLevel {
+                data_hash: self.data_hash_at(index),
+                siblings: self.child_roots(index, Some(digit)),
+                position: digit as u8,
+            });
+        }
+        Some(SubtreeProof {
+            version: crate::proof::PROOF_FORMAT_VERSION,
+            prefix: NodePosition {
+                path: prefix & prefix_mask(prefix_len, Self::BITS_PER_DIGIT),
+                depth: prefix_len,
+            },
+            arity: N,
+            subtree_root,
+            levels,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn keys_check_against_a_proven_subtree() {
+        let mut node: TrieNode<u32, StdMerkleHasher, 4> = (0..200).map(|key| (key, key)).collect();
+        let root = node.merkle_root();
+        // Keys whose lowest base-4 digits are 2 then 1.
+        let proof = node.generate_subtree_proof(0b0110, 2).unwrap();
+        assert!(proof.verify(&StdMerkleHasher, &root));
+        assert!(!proof.verify(
+            &StdMerkleHasher,
+            &node.generate_subtree_proof(0b0111, 2).unwrap().subtree_root
+        ));
+
+        for key in [6, 22, 38, 150] {
+            let key_proof = node.generate_proof(key).unwrap();
+            assert!(key_proof.verify_in_subtree(
+                &StdMerkleHasher,
+                &proof.prefix,
+                &proof.subtree_root,
+                &key
+            ));
+            assert!(!key_proof.verify_in_subtree(
+                &StdMerkleHasher,
+                &proof.prefix,
+                &proof.subtree_root,
+                &(key + 1)
+            ));
+        }
+        let outside = node.generate_proof(7).unwrap();
+        assert!(!outside.verify_in_subtree(
+            &StdMerkleHasher,
+            &proof.prefix,
+            &proof.subtree_root,
+            &7u32
+        ));
+
+        let whole = node.generate_subtree_proof(0, 0).unwrap();
+        assert_eq!(whole.subtree_root, root);
+        assert!(whole.verify(&StdMerkleHasher, &root));
+        assert_eq!(node.generate_subtree_proof(0x333, 5), None);
+    }
+}
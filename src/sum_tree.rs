@@ -0,0 +1,161 @@
+use crate::hasher::MerkleHasher;
+use crate::merkle_data::MerkleData;
+use crate::trie_node::trie_node::TrieNode;
+
+const SUM_LEN: usize = 16;
+
+/// A `SumHasher` hash: the inner hash's bytes followed by the total amount under the node, a
+/// big-endian `u128`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SumHash(Vec<u8>);
+
+impl SumHash {
+    fn new(digest: &[u8], sum: u128) -> Self {
+        let mut bytes = digest.to_vec();
+        bytes.extend_from_slice(&sum.to_be_bytes());
+        SumHash(bytes)
+    }
+
+    pub fn digest(&self) -> &[u8] {
+        &self.0[..self.0.len() - SUM_LEN]
+    }
+
+    /// The total of the amounts of the values under the node.
+    pub fn sum(&self) -> u128 {
+        u128::from_be_bytes(self.0[self.0.len() - SUM_LEN..].try_into().unwrap())
+    }
+}
+
+impl AsRef<[u8]> for SumHash {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Augments a hasher so every node commits to the total of an amount taken from each value
+/// under it, such as a balance, as well as to its hash: a sum tree. The root then shows the
+/// total held by the trie, and an ordinary `MerkleProof` verified against it shows a value is
+/// part of that total, since each level's hash commits to its children's totals.
+///
+/// Totals are `u128`, which no trie of `u64` amounts can overflow. Hashes read from elsewhere
+/// can claim any total; one that would overflow is pinned at `u128::MAX`, and the hash still
+/// commits to the claims it was combined from, so it can't match an honest root.
+#[derive(Debug, Clone, Copy)]
+pub struct SumHasher<H> {
+    pub hasher: H,
+    /// The amount a value contributes, from its `merkle_bytes`.
+    pub amount: fn(&[u8]) -> u64,
+}
+
+impl<H: MerkleHasher> SumHasher<H> {
+    pub fn new(hasher: H, amount: fn(&[u8]) -> u64) -> Self {
+        SumHasher { hasher, amount }
+    }
+
+    /// Sums `u64` values themselves, from their big-endian bytes. Values of any other width
+    /// count as 0.
+    pub fn of_u64(hasher: H) -> Self {
+        SumHasher::new(hasher, |bytes| {
+            bytes.try_into().map_or(0, u64::from_be_bytes)
+        })
+    }
+}
+
+impl<H: MerkleHasher> MerkleHasher for SumHasher<H> {
+    type Hash = SumHash;
+
+    fn hash(&self, bytes: &[u8]) -> SumHash {
+        SumHash::new(
+            self.hasher.hash(bytes).as_ref(),
+            (self.amount)(bytes) as u128,
+        )
+    }
+
+    fn hash_batch(&self, inputs: &[&[u8]]) -> Vec<SumHash> {
+        self.hasher
+            .hash_batch(inputs)
+            .into_iter()
+            .zip(inputs)
+            .map(|(hash, bytes)| SumHash::new(hash.as_ref(), (self.amount)(bytes) as u128))
+            .collect()
+    }
+
+    fn hash_from_bytes(bytes: &[u8]) -> Option<SumHash> {
+        let digest = bytes.get(..bytes.len().checked_sub(SUM_LEN)?)?;
+        H::hash_from_bytes(digest)?;
+        Some(SumHash(bytes.to_vec()))
+    }
+
+    fn combine(&self, data: &SumHash, left: &SumHash, right: &SumHash) -> SumHash {
+        self.combine_children(data, &[left.clone(), right.clone()])
+    }
+
+    fn combine_children(&self, data: &SumHash, children: &[SumHash]) -> SumHash {
+        let sum = children
+            .iter()
+            .try_fold(data.sum(), |sum, child| sum.checked_add(child.sum()))
+            .unwrap_or(u128::MAX);
+        let mut bytes = data.0.clone();
+        for child in children {
+            bytes.extend_from_slice(&child.0);
+        }
+        bytes.extend_from_slice(&sum.to_be_bytes());
+        SumHash::new(self.hasher.hash(&bytes).as_ref(), sum)
+    }
+
+    fn empty_hash(&self) -> SumHash {
+        SumHash::new(self.hasher.empty_hash().as_ref(), 0)
+    }
+
+    fn max_hash_len(&self) -> usize {
+        self.hasher.max_hash_len() + SUM_LEN
+    }
+
+    fn heap_bytes(hash: &SumHash) -> usize {
+        hash.0.capacity()
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, SumHasher<H>, N> {
+    /// The total amount held by the trie, as committed to by its root.
+    pub fn total(&mut self) -> u128 {
+        self.merkle_root().sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+    use crate::proof::MerkleProof;
+
+    #[test]
+    fn roots_commit_to_the_total() {
+        let hasher = SumHasher::of_u64(StdMerkleHasher);
+        let mut trie: TrieNode<u64, _, 4> = TrieNode::with_hasher(hasher);
+        trie.insert_batch((0..100).map(|key| (key * 3, key as u64 * 10)));
+        assert_eq!(trie.total(), 49_500);
+        let root = trie.merkle_root();
+
+        let proof = trie.generate_proof(30).unwrap();
+        assert!(proof.verify(&hasher, &root, &100u64));
+        assert!(!proof.verify(&hasher, &root, &101u64));
+        let decoded =
+            MerkleProof::from_bytes::<SumHasher<StdMerkleHasher>>(&proof.to_bytes()).unwrap();
+        assert!(decoded.verify(&hasher, &root, &100u64));
+
+        // A sibling claiming another total doesn't verify.
+        let mut forged = proof.clone();
+        let sibling = &mut forged.levels[0].siblings[0];
+        *sibling = SumHash::new(sibling.digest(), sibling.sum() + 1);
+        assert!(!forged.verify(&hasher, &root, &100u64));
+        let overflow = SumHash::new(b"", u128::MAX);
+        forged.levels[1].siblings[0] = overflow;
+        assert!(!forged.verify(&hasher, &root, &100u64));
+
+        trie.insert(30, 0);
+        assert_eq!(trie.total(), 49_400);
+        assert_eq!(TrieNode::<u64, _>::with_hasher(hasher).total(), 0);
+    }
+}
@@ -0,0 +1,145 @@
+use std::{
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicPtr, AtomicUsize, Ordering::SeqCst},
+        Arc, Mutex,
+    },
+};
+
+use crate::trie_node::trie_node::TrieNode;
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// A published trie that readers load without locking: `load` is a handful of atomic
+/// operations and returns a consistent, fully hashed version that later writes never change.
+/// Writers are serialised; each one works on a copy of the current version, caches included,
+/// and publishes it with a pointer swap, so writes are best batched through `update`.
+pub struct SwapTrie<T: MerkleData, H: MerkleHasher, const N: usize = 2> {
+    // Owns one strong count of the current version, from `Arc::into_raw`.
+    current: AtomicPtr<TrieNode<T, H, N>>,
+    // Readers between reading `current` and taking their own strong count, split by the parity
+    // of the `epoch` they started in. A writer ends two epochs, waiting for each one's readers
+    // in turn, so that a reader counted under either parity has left before a version goes.
+    readers: [AtomicUsize; 2],
+    epoch: AtomicUsize,
+    writer: Mutex<()>,
+    versions: PhantomData<Arc<TrieNode<T, H, N>>>,
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> SwapTrie<T, H, N> {
+    pub fn new(mut trie: TrieNode<T, H, N>) -> Self {
+        trie.merkle_root();
+        SwapTrie {
+            current: AtomicPtr::new(Arc::into_raw(Arc::new(trie)).cast_mut()),
+            readers: [AtomicUsize::new(0), AtomicUsize::new(0)],
+            epoch: AtomicUsize::new(0),
+            writer: Mutex::new(()),
+            versions: PhantomData,
+        }
+    }
+
+    /// The latest published version. Its root is always cached.
+    pub fn load(&self) -> Arc<TrieNode<T, H, N>> {
+        let readers = &self.readers[self.epoch.load(SeqCst) & 1];
+        readers.fetch_add(1, SeqCst);
+        let current = self.current.load(SeqCst);
+        // SAFETY: `current` came from `Arc::into_raw` and is still owned by `self`: a writer
+        // only releases a replaced version after every reader that could have loaded it has
+        // left this section.
+        let version = unsafe {
+            Arc::increment_strong_count(current);
+            Arc::from_raw(current)
+        };
+        readers.fetch_sub(1, SeqCst);
+        version
+    }
+
+    /// Replaces the published version with `trie`, hashing it first so readers never have to.
+    pub fn publish(&self, mut trie: TrieNode<T, H, N>) {
+        trie.merkle_root();
+        let _writer = self.writer.lock().unwrap();
+        self.swap_in(trie);
+    }
+
+    fn swap_in(&self, trie: TrieNode<T, H, N>) {
+        let replaced = self
+            .current
+            .swap(Arc::into_raw(Arc::new(trie)).cast_mut(), SeqCst);
+        // Readers that start from here on load the new version. The ones that might hold the
+        // replaced pointer entered before the swap, so neither wait below can miss them, and
+        // only readers of the ended epoch add to the counter being waited on.
+        for _ in 0..2 {
+            let ended = self.epoch.fetch_add(1, SeqCst) & 1;
+            while self.readers[ended].load(SeqCst) != 0 {
+                std::hint::spin_loop();
+            }
+        }
+        // SAFETY: `replaced` held `self`'s strong count, and no reader is still about to take
+        // one of its own from the raw pointer.
+        drop(unsafe { Arc::from_raw(replaced) });
+    }
+}
+
+impl<T, H, const N: usize> SwapTrie<T, H, N>
+where
+    T: MerkleData + Clone,
+    H: MerkleHasher + Clone,
+{
+    /// Runs `mutate` on a copy of the current version and publishes the result.
+    pub fn update<R>(&self, mutate: impl FnOnce(&mut TrieNode<T, H, N>) -> R) -> R {
+        let _writer = self.writer.lock().unwrap();
+        let mut trie = TrieNode::clone(&self.load());
+        let result = mutate(&mut trie);
+        trie.merkle_root();
+        self.swap_in(trie);
+        result
+    }
+
+    pub fn insert(&self, key: u32, data: T)
+    where
+        T: PartialEq,
+    {
+        self.update(|trie| trie.insert(key, data));
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> Drop for SwapTrie<T, H, N> {
+    fn drop(&mut self) {
+        // SAFETY: the pointer still holds `self`'s strong count, and with `&mut self` there are
+        // no readers.
+        drop(unsafe { Arc::from_raw(*self.current.get_mut()) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn readers_see_whole_versions_while_writers_publish() {
+        let trie: SwapTrie<u32, StdMerkleHasher> = SwapTrie::new(TrieNode::new());
+        let before = trie.load();
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..1000 {
+                        let version = trie.load();
+                        let count = version.iter().count() as u32;
+                        // Writers insert keys in order, so a version holds a prefix of them.
+                        assert!(version.iter().all(|(key, _)| key < count));
+                        assert!(version.current_root().is_some());
+                    }
+                });
+            }
+            scope.spawn(|| {
+                for key in 0..200 {
+                    trie.insert(key, key);
+                }
+            });
+        });
+        assert_eq!(before.iter().count(), 0);
+        let mut expected: TrieNode<u32> = (0..200).map(|key| (key, key)).collect();
+        assert_eq!(trie.load().current_root(), Some(&expected.merkle_root()));
+    }
+}
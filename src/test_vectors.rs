@@ -0,0 +1,289 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::hasher::MerkleHasher;
+use crate::multiproof::MultiProof;
+use crate::proof::MerkleProof;
+use crate::trie_node::trie_node::TrieNode;
+
+// splitmix64, so the same seed yields the same vectors on every platform and release.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// A deterministic key/value set with its expected root and a sample of proofs, for checking
+/// other implementations against this crate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestVectors<D> {
+    pub seed: u64,
+    pub arity: usize,
+    /// Distinct keys in ascending order, with byte-string values.
+    pub entries: Vec<(u32, Vec<u8>)>,
+    pub root: D,
+    pub proofs: Vec<MerkleProof<D>>,
+    /// Multiproofs with the keys each one proves, in ascending order.
+    pub multiproofs: Vec<(Vec<u32>, MultiProof<D>)>,
+}
+
+/// Generates `size` entries from `seed`, half of them under small keys so paths share
+/// prefixes, and proofs for up to `proof_count` of them.
+pub fn generate<H: MerkleHasher + Default, const N: usize>(
+    seed: u64,
+    size: usize,
+    proof_count: usize,
+) -> TestVectors<H::Hash> {
+    let mut rng = SplitMix64(seed);
+    let mut entries = BTreeMap::new();
+    while entries.len() < size {
+        let random = rng.next();
+        let key = if random & 1 == 0 {
+            (random >> 32) as u32
+        } else {
+            (random >> 32) as u32 % (size as u32).saturating_mul(4).max(1)
+        };
+        let len = (rng.next() % 33) as usize;
+        let value: Vec<u8> = (0..len).map(|_| rng.next() as u8).collect();
+        entries.insert(key, value);
+    }
+    let entries: Vec<(u32, Vec<u8>)> = entries.into_iter().collect();
+    let proof_keys: Vec<u32> = (0..proof_count.min(entries.len()))
+        .map(|_| entries[(rng.next() % entries.len() as u64) as usize].0)
+        .collect();
+    from_entries::<H, N>(seed, entries, &proof_keys)
+}
+
+/// Vectors for the trie holding `entries`, with a proof for each of `proof_keys` and, if there
+/// are any, one multiproof covering them all. `seed` is only recorded.
+pub fn from_entries<H: MerkleHasher + Default, const N: usize>(
+    seed: u64,
+    mut entries: Vec<(u32, Vec<u8>)>,
+    proof_keys: &[u32],
+) -> TestVectors<H::Hash> {
+    entries.sort_by_key(|(key, _)| *key);
+    entries.dedup_by_key(|(key, _)| *key);
+    let mut trie: TrieNode<Vec<u8>, H, N> = TrieNode::with_hasher(H::default());
+    trie.insert_batch(entries.iter().cloned());
+    let root = trie.merkle_root();
+    let proofs = proof_keys
+        .iter()
+        .map(|key| {
+            trie.generate_proof(*key)
+                .expect("proof keys must be in the entries")
+        })
+        .collect();
+    let mut multiproofs = vec![];
+    if !proof_keys.is_empty() {
+        let mut keys = proof_keys.to_vec();
+        keys.sort_unstable();
+        keys.dedup();
+        let multiproof = trie.generate_multiproof(&keys).unwrap();
+        multiproofs.push((keys, multiproof));
+    }
+    TestVectors {
+        seed,
+        arity: N,
+        entries,
+        root,
+        proofs,
+        multiproofs,
+    }
+}
+
+/// A named entry set for conformance fixtures, with the keys to prove.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixtureShape {
+    pub name: &'static str,
+    pub entries: Vec<(u32, Vec<u8>)>,
+    pub proof_keys: Vec<u32>,
+}
+
+/// The shapes conformance fixtures cover: an empty trie, a lone root value, a dense run of
+/// small keys, keys deep enough to use every digit, and `generate`'s random mix.
+pub fn fixture_shapes(seed: u64) -> Vec<FixtureShape> {
+    let value = |key: u32| key.to_be_bytes().to_vec();
+    let entries = |keys: &[u32]| keys.iter().map(|key| (*key, value(*key))).collect();
+    let dense: Vec<u32> = (0..64).collect();
+    let deep = [1, u32::MAX, 1 << 31, 0x8000_0001, 0x5555_5555];
+    let random = generate::<crate::hasher::StdMerkleHasher, 2>(seed, 100, 0).entries;
+    let random_keys = random.iter().step_by(10).map(|(key, _)| *key).collect();
+    let shape = |name, entries, proof_keys| FixtureShape {
+        name,
+        entries,
+        proof_keys,
+    };
+    vec![
+        shape("empty", vec![], vec![]),
+        shape("root-only", entries(&[0]), vec![0]),
+        shape("dense", entries(&dense), vec![0, 1, 17, 63]),
+        shape("deep", entries(&deep), deep.to_vec()),
+        shape("random", random, random_keys),
+    ]
+}
+
+fn json_string(out: &mut String, string: &str) {
+    out.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c < ' ' => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn json_hashes<H: MerkleHasher>(out: &mut String, hashes: &[H::Hash]) {
+    out.push('[');
+    for (i, hash) in hashes.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        json_string(out, &H::hash_to_string(hash));
+    }
+    out.push(']');
+}
+
+impl<D> TestVectors<D> {
+    /// Renders the vectors as JSON. Values are hex; hashes use `H::hash_to_string`. A proof's
+    /// levels go from the proven node's parent up to the root, and each level's siblings are in
+    /// digit order with the child on the path left out; that child is at the level's position.
+    /// A multiproof's hashes are in the order `MultiProof` documents, with `null` for an empty
+    /// sibling.
+    pub fn to_json<H: MerkleHasher<Hash = D>>(&self) -> String {
+        let mut out = String::new();
+        write!(
+            out,
+            "{{\"seed\":{},\"arity\":{},\"entries\":[",
+            self.seed, self.arity
+        )
+        .unwrap();
+        let value_of = |key: u32| {
+            let position = self.entries.binary_search_by_key(&key, |(key, _)| *key);
+            crate::hex::encode(&self.entries[position.unwrap()].1)
+        };
+        for (i, (key, value)) in self.entries.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            write!(out, "{{\"key\":{key},\"value\":").unwrap();
+            json_string(&mut out, &crate::hex::encode(value));
+            out.push('}');
+        }
+        out.push_str("],\"root\":");
+        json_string(&mut out, &H::hash_to_string(&self.root));
+        out.push_str(",\"proofs\":[");
+        for (i, proof) in self.proofs.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            write!(out, "{{\"key\":{},\"value\":", proof.key).unwrap();
+            json_string(&mut out, &value_of(proof.key));
+            out.push_str(",\"children_roots\":");
+            json_hashes::<H>(&mut out, &proof.children_roots);
+            out.push_str(",\"levels\":[");
+            for (j, level) in proof.levels.iter().enumerate() {
+                if j > 0 {
+                    out.push(',');
+                }
+                out.push_str("{\"data_hash\":");
+                json_string(&mut out, &H::hash_to_string(&level.data_hash));
+                out.push_str(",\"siblings\":");
+                json_hashes::<H>(&mut out, &level.siblings);
+                write!(out, ",\"position\":{}}}", level.position).unwrap();
+            }
+            out.push_str("]}");
+        }
+        out.push_str("],\"multiproofs\":[");
+        for (i, (keys, multiproof)) in self.multiproofs.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let keys: Vec<String> = keys.iter().map(u32::to_string).collect();
+            write!(out, "{{\"keys\":[{}],\"data_hashes\":", keys.join(",")).unwrap();
+            json_hashes::<H>(&mut out, &multiproof.data_hashes);
+            out.push_str(",\"siblings\":[");
+            for (j, sibling) in multiproof.siblings.iter().enumerate() {
+                if j > 0 {
+                    out.push(',');
+                }
+                match sibling {
+                    Some(sibling) => json_string(&mut out, &H::hash_to_string(sibling)),
+                    None => out.push_str("null"),
+                }
+            }
+            out.push_str("]}");
+        }
+        out.push_str("]}");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn vectors_are_deterministic_and_verify() {
+        let vectors = generate::<StdMerkleHasher, 4>(42, 200, 5);
+        assert_eq!(vectors, generate::<StdMerkleHasher, 4>(42, 200, 5));
+        assert_ne!(
+            vectors.root,
+            generate::<StdMerkleHasher, 4>(43, 200, 5).root
+        );
+        assert_eq!(vectors.entries.len(), 200);
+        assert_eq!(vectors.proofs.len(), 5);
+        for proof in &vectors.proofs {
+            let (_, value) = vectors
+                .entries
+                .iter()
+                .find(|(key, _)| *key == proof.key)
+                .unwrap();
+            assert!(proof.verify(&StdMerkleHasher, &vectors.root, value));
+        }
+
+        let json = vectors.to_json::<StdMerkleHasher>();
+        assert!(json.starts_with("{\"seed\":42,\"arity\":4,\"entries\":[{\"key\":"));
+        assert!(json.contains(&format!("\"root\":\"{}\"", vectors.root)));
+        assert_eq!(json.matches("\"children_roots\"").count(), 5);
+
+        for shape in fixture_shapes(7) {
+            let vectors = from_entries::<StdMerkleHasher, 16>(7, shape.entries, &shape.proof_keys);
+            assert_eq!(
+                vectors.proofs.len(),
+                shape.proof_keys.len(),
+                "{}",
+                shape.name
+            );
+            let (keys, multiproof) = match vectors.multiproofs.first() {
+                Some(multiproof) => multiproof,
+                None => continue,
+            };
+            let proven: Vec<(u32, Vec<u8>)> = vectors
+                .entries
+                .iter()
+                .filter(|(key, _)| keys.contains(key))
+                .cloned()
+                .collect();
+            assert!(crate::multiproof::verify_multiproof(
+                &StdMerkleHasher,
+                &vectors.root,
+                &proven,
+                multiproof
+            ));
+            assert!(vectors
+                .to_json::<StdMerkleHasher>()
+                .contains("\"multiproofs\":[{\"keys\":["));
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::trie_node::trie_node::TrieNode;
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// A trie for async services. Inserting and hashing run on Tokio's blocking thread pool, so a
+/// large batch or a cold root doesn't stall the executor thread awaiting it. The trie is shared
+/// with the blocking task rather than moved into it, so dropping a future part way loses
+/// nothing: the work carries on in the background and the next call sees its result.
+pub struct TokioTrie<T: MerkleData, H: MerkleHasher, const N: usize = 2> {
+    trie: Arc<Mutex<TrieNode<T, H, N>>>,
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> Clone for TokioTrie<T, H, N> {
+    fn clone(&self) -> Self {
+        TokioTrie {
+            trie: Arc::clone(&self.trie),
+        }
+    }
+}
+
+impl<T, H, const N: usize> TokioTrie<T, H, N>
+where
+    T: MerkleData + PartialEq + Send + 'static,
+    H: MerkleHasher + Send + 'static,
+    H::Hash: Send,
+{
+    pub fn new(trie: TrieNode<T, H, N>) -> Self {
+        TokioTrie {
+            trie: Arc::new(Mutex::new(trie)),
+        }
+    }
+
+    /// Locks the trie for quick synchronous access. Blocks while a batch or root computation
+    /// holds the lock.
+    pub fn lock(&self) -> MutexGuard<'_, TrieNode<T, H, N>> {
+        self.trie.lock().unwrap()
+    }
+
+    async fn run<R: Send + 'static>(
+        &self,
+        work: impl FnOnce(&mut TrieNode<T, H, N>) -> R + Send + 'static,
+    ) -> R {
+        let trie = Arc::clone(&self.trie);
+        tokio::task::spawn_blocking(move || work(&mut trie.lock().unwrap()))
+            .await
+            .expect("trie task panicked")
+    }
+
+    pub async fn insert_batch_async(&self, entries: Vec<(u32, T)>) {
+        self.run(|trie| trie.insert_batch(entries)).await
+    }
+
+    pub async fn merkle_root_async(&self) -> H::Hash {
+        self.run(|trie| trie.merkle_root()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[tokio::test]
+    async fn batches_and_roots_run_off_the_executor() {
+        let trie: TokioTrie<u32, crate::hasher::StdMerkleHasher> = TokioTrie::new(TrieNode::new());
+        let entries: Vec<(u32, u32)> = (0..1000).map(|key| (key, key * 2)).collect();
+        trie.insert_batch_async(entries.clone()).await;
+        let mut expected: TrieNode<u32> = entries.into_iter().collect();
+        assert_eq!(trie.merkle_root_async().await, expected.merkle_root());
+        assert_eq!(trie.lock().get(7), Some(&14));
+
+        let handle = trie.clone();
+        tokio::spawn(async move { handle.insert_batch_async(vec![(7, 0)]).await })
+            .await
+            .unwrap();
+        expected.insert(7, 0);
+        assert_eq!(trie.merkle_root_async().await, expected.merkle_root());
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+
+use crate::error::TrieError;
+use crate::state_sync::NodePosition;
+use crate::trie_node::trie_node::{NodeIndex, TrieNode, ROOT};
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    /// A trie with the same nodes, holding `f` of each value. Nothing is hashed until the
+    /// new trie's root is asked for (or straight away, in eager mode).
+    pub fn map_values<U, F>(&self, mut f: F) -> TrieNode<U, H, N>
+    where
+        U: MerkleData,
+        H: Clone,
+        F: FnMut(&T) -> U,
+    {
+        self.rebuild(self.hasher.clone(), |_, data| Some(f(data)), false)
+    }
+
+    /// A trie with only the entries for which `f` holds. Subtrees left without values are
+    /// dropped, so the result is shaped as if the kept entries had been inserted afresh.
+    pub fn filter<F>(&self, mut f: F) -> Self
+    where
+        T: Clone,
+        H: Clone,
+        F: FnMut(u32, &T) -> bool,
+    {
+        self.rebuild(
+            self.hasher.clone(),
+            |key, data| f(key, data).then(|| data.clone()),
+            true,
+        )
+    }
+
+    /// A trie holding each value under `f` of its key, for migrating to a new key scheme. The
+    /// entries are moved in one pass and hashed from scratch on the next root computation.
+    /// Fails if two keys map to the same new key.
+    pub fn rebase_keys<F>(&self, mut f: F) -> Result<Self, TrieError>
+    where
+        T: Clone + PartialEq,
+        H: Clone,
+        F: FnMut(u32) -> u32,
+    {
+        // New key -> the old key moved there.
+        let mut moved = HashMap::new();
+        let mut out = TrieNode::with_capacity(self.hasher.clone(), self.nodes.len());
+        out.cache_policy = self.cache_policy;
+        for (key, data) in self.iter() {
+            let new_key = f(key);
+            if let Some(existing) = moved.insert(new_key, key) {
+                return Err(TrieError::KeyCollision { key, existing });
+            }
+            out.insert(new_key, data.clone());
+        }
+        out.eager_hashing = self.eager_hashing;
+        out.rehash_if_eager();
+        Ok(out)
+    }
+
+    /// Keeps only the entries for which `f` holds, in one pass. `f` may also change the values
+    /// it keeps. Only the cached hashes on paths to removed or changed values are invalidated,
+    /// and subtrees left without values are detached.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(u32, &mut T) -> bool,
+    {
+        self.retain_at(ROOT, NodePosition { path: 0, depth: 0 }, &mut f);
+        self.rehash_if_eager();
+    }
+
+    // Returns whether anything in the subtree at `index` changed.
+    fn retain_at<F>(&mut self, index: NodeIndex, position: NodePosition, f: &mut F) -> bool
+    where
+        F: FnMut(u32, &mut T) -> bool,
+    {
+        let mut changed = false;
+        for digit in 0..N {
+            let Some(child) = self.node(index).child(digit) else {
+                continue;
+            };
+            changed |= self.retain_at(child, Self::child_position(position, digit), f);
+            let child_node = self.node(child);
+            if child_node.is_leaf() && child_node.get_data().is_none() {
+                self.node_mut(index).take_child(digit);
+                self.free_subtrees.push(child);
+                changed = true;
+            }
+        }
+
+        let generation = self.cache_generation;
+        let node = self.node_mut(index);
+        // Without a cached data hash there is nothing to compare against, so a kept value counts
+        // as changed. That costs little: usually nothing above is cached either, unless
+        // `evict_caches` dropped this node's hashes but kept its ancestors'.
+        let hashed = node.cached_data_hash(generation).is_some();
+        if let Some(data) = node.data_mut() {
+            let before = hashed.then(|| data.merkle_bytes().into_owned());
+            if !f(position.path, data) {
+                node.take_data();
+                changed = true;
+            } else if before.is_none_or(|before| *before != *data.merkle_bytes()) {
+                node.clear_cached_hashes();
+                changed = true;
+            }
+        }
+        if changed {
+            self.node_mut(index).invalidate_merkle_root();
+        }
+        changed
+    }
+
+    // Copies the trie's nodes into a trie hashed with `hasher`, holding `f` of each value.
+    // With `prune`, subtrees left without values are dropped.
+    pub(crate) fn rebuild<U, G, F>(&self, hasher: G, mut f: F, prune: bool) -> TrieNode<U, G, N>
+    where
+        U: MerkleData,
+        G: MerkleHasher,
+        F: FnMut(u32, &T) -> Option<U>,
+    {
+        let mut out = TrieNode::with_hasher(hasher);
+        let root = NodePosition { path: 0, depth: 0 };
+        for digit in 0..N {
+            if let Some(child) = self.node(ROOT).child(digit) {
+                let position = Self::child_position(root, digit);
+                if let Some(copy) = self.copy_subtree(child, position, &mut out, &mut f, prune) {
+                    out.node_mut(ROOT).set_child(digit, copy);
+                }
+            }
+        }
+        if let Some(data) = self.node(ROOT).get_data().and_then(|data| f(0, data)) {
+            out.node_mut(ROOT).replace_data(data);
+        }
+        out.eager_hashing = self.eager_hashing;
+        out.cache_policy = self.cache_policy;
+        out.rehash_if_eager();
+        out
+    }
+
+    // Copies the subtree at `index` into `out`, children first. `None` if it was pruned away.
+    fn copy_subtree<U, G, F>(
+        &self,
+        index: NodeIndex,
+        position: NodePosition,
+        out: &mut TrieNode<U, G, N>,
+        f: &mut F,
+        prune: bool,
+    ) -> Option<NodeIndex>
+    where
+        U: MerkleData,
+        G: MerkleHasher,
+        F: FnMut(u32, &T) -> Option<U>,
+    {
+        let mut children = vec![];
+        for digit in 0..N {
+            if let Some(child) = self.node(index).child(digit) {
+                let child_position = Self::child_position(position, digit);
+                if let Some(copy) = self.copy_subtree(child, child_position, out, f, prune) {
+                    children.push((digit, copy));
+                }
+            }
+        }
+        let data = self
+            .node(index)
+            .get_data()
+            .and_then(|data| f(position.path, data));
+        if prune && data.is_none() && children.is_empty() {
+            return None;
+        }
+        let copy = out.push_node(data);
+        for (digit, child) in children {
+            out.node_mut(copy).set_child(digit, child);
+        }
+        Some(copy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn map_and_filter_match_fresh_tries() {
+        let mut node: TrieNode<u32> = (0..100).map(|key| (key * 5, key)).collect();
+        node.merkle_root();
+
+        let mut mapped = node.map_values(|value| value.to_string());
+        assert_eq!(mapped.current_root(), None);
+        let mut expected: TrieNode<String> =
+            (0..100).map(|key| (key * 5, key.to_string())).collect();
+        assert_eq!(mapped.merkle_root(), expected.merkle_root());
+
+        let mut filtered = node.filter(|key, value| key % 2 == 0 && *value < 60);
+        let mut expected: TrieNode<u32> = (0..60)
+            .filter(|key| key % 2 == 0)
+            .map(|key| (key * 5, key))
+            .collect();
+        assert_eq!(filtered.merkle_root(), expected.merkle_root());
+        assert_eq!(filtered.metrics().node_count, expected.metrics().node_count);
+    }
+
+    #[test]
+    fn rebase_keys_moves_values_and_detects_collisions() {
+        let node: TrieNode<u32> = (0..100).map(|key| (key * 5, key)).collect();
+        let mut rebased = node.rebase_keys(|key| key / 5 + 1000).unwrap();
+        let mut expected: TrieNode<u32> = (0..100).map(|key| (key + 1000, key)).collect();
+        assert_eq!(rebased.merkle_root(), expected.merkle_root());
+        let collision = node.rebase_keys(|key| if key == 495 { 5 } else { key });
+        assert!(matches!(
+            collision,
+            Err(TrieError::KeyCollision { key, existing }) if key + existing == 500
+        ));
+    }
+
+    #[test]
+    fn retain_removes_and_updates_in_place() {
+        let mut node: TrieNode<u32> = (0..100).map(|key| (key * 5, key)).collect();
+        node.merkle_root();
+        node.retain(|key, value| {
+            *value += (key == 45) as u32;
+            value.is_multiple_of(3) || key == 45
+        });
+        let mut expected: TrieNode<u32> = (0..100)
+            .filter(|key| key % 3 == 0 || *key == 9)
+            .map(|key| (key * 5, key + (key == 9) as u32))
+            .collect();
+        assert_eq!(node.merkle_root(), expected.merkle_root());
+        assert_eq!(node.metrics().node_count, expected.metrics().node_count);
+    }
+}
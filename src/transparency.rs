@@ -0,0 +1,338 @@
+use crate::hasher::{hashes_equal, MerkleHasher, StdMerkleHasher};
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+/// An append-only log hashed as in RFC 6962 (Certificate Transparency): leaves are
+/// `H(0x00 || data)`, interior nodes `H(0x01 || left || right)`, and a tree of `n` leaves splits at
+/// the largest power of two below `n`. It proves that a leaf is in the log (audit paths) and that
+/// an older tree head is a prefix of a newer one (consistency proofs).
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransparencyLog<H: MerkleHasher = StdMerkleHasher> {
+    hasher: H,
+    leaf_hashes: Vec<H::Hash>,
+}
+
+impl<H: MerkleHasher + Default> Default for TransparencyLog<H> {
+    fn default() -> Self {
+        TransparencyLog::with_hasher(H::default())
+    }
+}
+
+impl<H: MerkleHasher + Default> TransparencyLog<H> {
+    pub fn new() -> Self {
+        TransparencyLog::default()
+    }
+}
+
+pub fn leaf_hash<H: MerkleHasher>(hasher: &H, data: &[u8]) -> H::Hash {
+    let mut prefixed = Vec::with_capacity(data.len() + 1);
+    prefixed.push(LEAF_PREFIX);
+    prefixed.extend_from_slice(data);
+    hasher.hash(&prefixed)
+}
+
+pub fn node_hash<H: MerkleHasher>(hasher: &H, left: &H::Hash, right: &H::Hash) -> H::Hash {
+    let mut prefixed = vec![NODE_PREFIX];
+    prefixed.extend_from_slice(left.as_ref());
+    prefixed.extend_from_slice(right.as_ref());
+    hasher.hash(&prefixed)
+}
+
+fn largest_power_of_two_below(n: usize) -> usize {
+    1 << (usize::BITS - 1 - (n - 1).leading_zeros())
+}
+
+impl<H: MerkleHasher> TransparencyLog<H> {
+    pub fn with_hasher(hasher: H) -> Self {
+        TransparencyLog {
+            hasher,
+            leaf_hashes: vec![],
+        }
+    }
+
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    pub fn append(&mut self, data: &[u8]) -> usize {
+        let hash = leaf_hash(&self.hasher, data);
+        self.leaf_hashes.push(hash);
+        self.leaf_hashes.len() - 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.leaf_hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaf_hashes.is_empty()
+    }
+
+    pub fn root(&self) -> H::Hash {
+        self.subtree_root(&self.leaf_hashes)
+    }
+
+    pub fn root_at(&self, size: usize) -> Option<H::Hash> {
+        Some(self.subtree_root(self.leaf_hashes.get(..size)?))
+    }
+
+    fn subtree_root(&self, leaves: &[H::Hash]) -> H::Hash {
+        match leaves.len() {
+            0 => self.hasher.empty_hash(),
+            1 => leaves[0].clone(),
+            n => {
+                let k = largest_power_of_two_below(n);
+                node_hash(
+                    &self.hasher,
+                    &self.subtree_root(&leaves[..k]),
+                    &self.subtree_root(&leaves[k..]),
+                )
+            }
+        }
+    }
+
+    /// The audit path for leaf `index` in the tree of the first `size` leaves.
+    pub fn inclusion_proof(&self, index: usize, size: usize) -> Option<Vec<H::Hash>> {
+        if index >= size || size > self.len() {
+            return None;
+        }
+        let mut proof = vec![];
+        self.path(index, &self.leaf_hashes[..size], &mut proof);
+        Some(proof)
+    }
+
+    fn path(&self, index: usize, leaves: &[H::Hash], proof: &mut Vec<H::Hash>) {
+        if leaves.len() <= 1 {
+            return;
+        }
+        let k = largest_power_of_two_below(leaves.len());
+        if index < k {
+            self.path(index, &leaves[..k], proof);
+            proof.push(self.subtree_root(&leaves[k..]));
+        } else {
+            self.path(index - k, &leaves[k..], proof);
+            proof.push(self.subtree_root(&leaves[..k]));
+        }
+    }
+
+    /// Proof that the tree of the first `old_size` leaves is a prefix of the tree of the first
+    /// `new_size` leaves.
+    pub fn consistency_proof(&self, old_size: usize, new_size: usize) -> Option<Vec<H::Hash>> {
+        if old_size > new_size || new_size > self.len() {
+            return None;
+        }
+        let mut proof = vec![];
+        if old_size > 0 {
+            self.subproof(old_size, &self.leaf_hashes[..new_size], true, &mut proof);
+        }
+        Some(proof)
+    }
+
+    fn subproof(
+        &self,
+        old_size: usize,
+        leaves: &[H::Hash],
+        is_complete_subtree: bool,
+        proof: &mut Vec<H::Hash>,
+    ) {
+        if old_size == leaves.len() {
+            if !is_complete_subtree {
+                proof.push(self.subtree_root(leaves));
+            }
+            return;
+        }
+        let k = largest_power_of_two_below(leaves.len());
+        if old_size <= k {
+            self.subproof(old_size, &leaves[..k], is_complete_subtree, proof);
+            proof.push(self.subtree_root(&leaves[k..]));
+        } else {
+            self.subproof(old_size - k, &leaves[k..], false, proof);
+            proof.push(self.subtree_root(&leaves[..k]));
+        }
+    }
+}
+
+/// Verifies an audit path as specified in RFC 9162, section 2.1.3.2.
+pub fn verify_inclusion<H: MerkleHasher>(
+    hasher: &H,
+    index: usize,
+    size: usize,
+    leaf_hash: &H::Hash,
+    proof: &[H::Hash],
+    root: &H::Hash,
+) -> bool {
+    if index >= size {
+        return false;
+    }
+    let (mut f, mut s) = (index, size - 1);
+    let mut r = leaf_hash.clone();
+    for p in proof {
+        if s == 0 {
+            return false;
+        }
+        if f & 1 == 1 || f == s {
+            r = node_hash(hasher, p, &r);
+            while f & 1 == 0 && f != 0 {
+                f >>= 1;
+                s >>= 1;
+            }
+        } else {
+            r = node_hash(hasher, &r, p);
+        }
+        f >>= 1;
+        s >>= 1;
+    }
+    s == 0 && hashes_equal(r.as_ref(), root.as_ref())
+}
+
+/// Verifies a consistency proof as specified in RFC 9162, section 2.1.4.2.
+pub fn verify_consistency<H: MerkleHasher>(
+    hasher: &H,
+    old_size: usize,
+    new_size: usize,
+    old_root: &H::Hash,
+    new_root: &H::Hash,
+    proof: &[H::Hash],
+) -> bool {
+    if old_size > new_size {
+        return false;
+    }
+    if old_size == new_size {
+        return proof.is_empty() && hashes_equal(old_root.as_ref(), new_root.as_ref());
+    }
+    if old_size == 0 {
+        return proof.is_empty();
+    }
+
+    let mut path: Vec<&H::Hash> = proof.iter().collect();
+    if old_size.is_power_of_two() {
+        path.insert(0, old_root);
+    }
+    let (mut f, mut s) = (old_size - 1, new_size - 1);
+    while f & 1 == 1 {
+        f >>= 1;
+        s >>= 1;
+    }
+    let Some((first, rest)) = path.split_first() else {
+        return false;
+    };
+    let mut fr = (*first).clone();
+    let mut sr = (*first).clone();
+    for c in rest {
+        if s == 0 {
+            return false;
+        }
+        if f & 1 == 1 || f == s {
+            fr = node_hash(hasher, c, &fr);
+            sr = node_hash(hasher, c, &sr);
+            while f & 1 == 0 && f != 0 {
+                f >>= 1;
+                s >>= 1;
+            }
+        } else {
+            sr = node_hash(hasher, &sr, c);
+        }
+        f >>= 1;
+        s >>= 1;
+    }
+    hashes_equal(fr.as_ref(), old_root.as_ref())
+        && hashes_equal(sr.as_ref(), new_root.as_ref())
+        && s == 0
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn log_of(size: usize) -> TransparencyLog {
+        let mut log = TransparencyLog::new();
+        for i in 0..size {
+            log.append(format!("entry {i}").as_bytes());
+        }
+        log
+    }
+
+    #[test]
+    fn inclusion_proofs_verify() {
+        let log = log_of(11);
+        let hasher = log.hasher();
+        for size in 1..=11 {
+            let root = log.root_at(size).unwrap();
+            for index in 0..size {
+                let proof = log.inclusion_proof(index, size).unwrap();
+                let leaf = leaf_hash(hasher, format!("entry {index}").as_bytes());
+                assert!(verify_inclusion(hasher, index, size, &leaf, &proof, &root));
+                let wrong_leaf = leaf_hash(hasher, b"forged");
+                assert!(!verify_inclusion(
+                    hasher,
+                    index,
+                    size,
+                    &wrong_leaf,
+                    &proof,
+                    &root
+                ));
+            }
+        }
+        assert_eq!(log.inclusion_proof(3, 3), None);
+    }
+
+    #[test]
+    fn consistency_proofs_verify() {
+        let log = log_of(13);
+        let hasher = log.hasher();
+        for new_size in 0..=13 {
+            let new_root = log.root_at(new_size).unwrap();
+            for old_size in 0..=new_size {
+                let old_root = log.root_at(old_size).unwrap();
+                let proof = log.consistency_proof(old_size, new_size).unwrap();
+                assert!(verify_consistency(
+                    hasher, old_size, new_size, &old_root, &new_root, &proof
+                ));
+                if old_size > 0 && old_size < new_size {
+                    let forged = leaf_hash(hasher, b"forged");
+                    assert!(!verify_consistency(
+                        hasher, old_size, new_size, &forged, &new_root, &proof
+                    ));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn equivocating_log_is_detected() {
+        let honest = log_of(8);
+        let mut forked = log_of(5);
+        forked.append(b"rewritten history");
+        forked.append(b"entry 6");
+        forked.append(b"entry 7");
+        let hasher = honest.hasher();
+        let proof = forked.consistency_proof(6, 8).unwrap();
+        assert!(!verify_consistency(
+            hasher,
+            6,
+            8,
+            &honest.root_at(6).unwrap(),
+            &forked.root(),
+            &proof
+        ));
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn sha256_empty_and_single_leaf_roots() {
+        use crate::{hasher::DigestHasher, hex};
+
+        let mut log: TransparencyLog<DigestHasher<sha2::Sha256>> = TransparencyLog::new();
+        assert_eq!(
+            hex::encode(&log.root()),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        log.append(b"");
+        assert_eq!(
+            hex::encode(&log.root()),
+            "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d"
+        );
+    }
+}
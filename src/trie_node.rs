@@ -1,154 +1,971 @@
+#[allow(clippy::module_inception)]
 pub mod trie_node {
-    use std::{
-        collections::hash_map::DefaultHasher,
-        fmt::Display,
-        hash::{Hash, Hasher},
+    use std::ops::{Index, IndexMut};
+    use std::sync::OnceLock;
+
+    use crate::{
+        bit_path::BitPath,
+        bloom::BloomFilter,
+        cache_policy::CachePolicy,
+        empty_hashes::EmptyHashes,
+        hasher::{MerkleHasher, StdMerkleHasher},
+        instrumentation,
+        merkle_data::MerkleData,
+        stats::InvalidationStats,
     };
 
-    type MaybeNode<T> = Option<Box<TrieNode<T>>>;
+    pub type NodeIndex = u32;
+
+    /// The merkle root of a trie holding no values: the hash of the empty string.
+    pub fn empty_merkle_root() -> String {
+        StdMerkleHasher.empty_hash()
+    }
+
+    pub(crate) const ROOT: NodeIndex = 0;
 
-    #[derive(Debug, Default, PartialEq)]
-    pub struct TrieNode<T: ToString> {
-        maybe_data: Option<T>,
-        children: [MaybeNode<T>; 2],
-        maybe_cached_merkle_root: Option<String>,
+    /// A leaf carries no children array and a single cached hash, since its merkle root is its
+    /// data hash. An internal node keeps its children boxed so that leaves, the bulk of any
+    /// trie, stay small in the arena.
+    ///
+    /// Cached hashes are stamped with the trie's cache generation when they are set, and only
+    /// count as cached while the trie is still at that generation; see
+    /// `TrieNode::invalidate_all`.
+    ///
+    /// With the `borsh` feature a node encodes as its value and children only; it decodes with
+    /// nothing cached.
+    #[derive(Debug, Clone, PartialEq)]
+    #[cfg_attr(
+        feature = "borsh",
+        derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+    )]
+    pub enum Node<T, D = String, const N: usize = 2> {
+        Leaf {
+            maybe_data: Option<T>,
+            #[cfg_attr(feature = "borsh", borsh(skip))]
+            maybe_cached_hash: Option<D>,
+            #[cfg_attr(feature = "borsh", borsh(skip))]
+            generation: u32,
+        },
+        Internal {
+            maybe_data: Option<T>,
+            #[cfg_attr(feature = "borsh", borsh(skip))]
+            maybe_cached_data_hash: Option<D>,
+            #[cfg_attr(feature = "borsh", borsh(skip))]
+            maybe_cached_merkle_root: Option<D>,
+            #[cfg_attr(feature = "borsh", borsh(skip))]
+            generation: u32,
+            children: Box<[Option<NodeIndex>; N]>,
+        },
     }
 
-    impl<T: ToString> From<TrieNode<T>> for MaybeNode<T> {
-        fn from(node: TrieNode<T>) -> Self {
-            Some(Box::new(node))
+    impl<T, D, const N: usize> Node<T, D, N> {
+        pub(crate) fn new(maybe_data: Option<T>) -> Self {
+            Node::Leaf {
+                maybe_data,
+                maybe_cached_hash: None,
+                generation: 0,
+            }
+        }
+
+        fn generation(&self) -> u32 {
+            match self {
+                Node::Leaf { generation, .. } | Node::Internal { generation, .. } => *generation,
+            }
+        }
+
+        // Drops hashes cached in an earlier generation before one from `generation` is set.
+        fn enter_generation(&mut self, generation: u32) {
+            if self.generation() != generation {
+                self.clear_cached_hashes();
+                match self {
+                    Node::Leaf {
+                        generation: stamp, ..
+                    }
+                    | Node::Internal {
+                        generation: stamp, ..
+                    } => *stamp = generation,
+                }
+            }
+        }
+
+        pub(crate) fn replace_data(&mut self, data: T) {
+            match self {
+                Node::Leaf {
+                    maybe_data,
+                    maybe_cached_hash,
+                    ..
+                } => {
+                    *maybe_data = Some(data);
+                    *maybe_cached_hash = None;
+                }
+                Node::Internal {
+                    maybe_data,
+                    maybe_cached_data_hash,
+                    maybe_cached_merkle_root,
+                    ..
+                } => {
+                    *maybe_data = Some(data);
+                    *maybe_cached_data_hash = None;
+                    *maybe_cached_merkle_root = None;
+                }
+            }
+        }
+
+        pub(crate) fn take_data(&mut self) -> Option<T> {
+            self.clear_cached_hashes();
+            match self {
+                Node::Leaf { maybe_data, .. } | Node::Internal { maybe_data, .. } => {
+                    maybe_data.take()
+                }
+            }
+        }
+
+        pub fn get_data(&self) -> Option<&T> {
+            match self {
+                Node::Leaf { maybe_data, .. } | Node::Internal { maybe_data, .. } => {
+                    maybe_data.as_ref()
+                }
+            }
+        }
+
+        /// Mutable access that leaves the cached hashes alone; callers that change the value
+        /// must clear them.
+        pub(crate) fn data_mut(&mut self) -> Option<&mut T> {
+            match self {
+                Node::Leaf { maybe_data, .. } | Node::Internal { maybe_data, .. } => {
+                    maybe_data.as_mut()
+                }
+            }
+        }
+
+        /// Whether the node only carries the path to deeper keys: it was created on the way to
+        /// one and no value has been inserted at its own position.
+        pub fn is_branch(&self) -> bool {
+            self.get_data().is_none()
+        }
+
+        pub fn is_leaf(&self) -> bool {
+            matches!(self, Node::Leaf { .. })
+        }
+
+        /// The child slots, indexed by digit; empty for a leaf.
+        pub(crate) fn children(&self) -> &[Option<NodeIndex>] {
+            match self {
+                Node::Leaf { .. } => &[],
+                Node::Internal { children, .. } => &children[..],
+            }
+        }
+
+        pub(crate) fn child(&self, digit: usize) -> Option<NodeIndex> {
+            self.children().get(digit).copied().flatten()
+        }
+
+        /// Attaches `child` under `digit`, turning a leaf into an internal node.
+        pub(crate) fn set_child(&mut self, digit: usize, child: NodeIndex) {
+            if let Node::Leaf {
+                maybe_data,
+                maybe_cached_hash,
+                generation,
+            } = self
+            {
+                *self = Node::Internal {
+                    maybe_data: maybe_data.take(),
+                    maybe_cached_data_hash: maybe_cached_hash.take(),
+                    maybe_cached_merkle_root: None,
+                    generation: *generation,
+                    children: Box::new([None; N]),
+                };
+            }
+            if let Node::Internal {
+                children,
+                maybe_cached_merkle_root,
+                ..
+            } = self
+            {
+                children[digit] = Some(child);
+                *maybe_cached_merkle_root = None;
+            }
+        }
+
+        /// Renumbers every child through `f`, for moving nodes between arenas. The cached hashes
+        /// stay valid.
+        pub(crate) fn map_children(&mut self, mut f: impl FnMut(NodeIndex) -> NodeIndex) {
+            if let Node::Internal { children, .. } = self {
+                for child in children.iter_mut().flatten() {
+                    *child = f(*child);
+                }
+            }
+        }
+
+        /// Shifts every child index by `offset`, for moving nodes between arenas. The cached
+        /// hashes stay valid.
+        #[cfg(feature = "rayon")]
+        pub(crate) fn offset_children(&mut self, offset: NodeIndex) {
+            if let Node::Internal { children, .. } = self {
+                for child in children.iter_mut().flatten() {
+                    *child += offset;
+                }
+            }
+        }
+
+        /// Detaches the child under `digit`, turning the node back into a leaf if it was the
+        /// last one.
+        pub(crate) fn take_child(&mut self, digit: usize) -> Option<NodeIndex> {
+            let Node::Internal {
+                maybe_data,
+                maybe_cached_data_hash,
+                maybe_cached_merkle_root,
+                generation,
+                children,
+            } = self
+            else {
+                return None;
+            };
+            let taken = children[digit].take()?;
+            *maybe_cached_merkle_root = None;
+            if children.iter().all(|child| child.is_none()) {
+                *self = Node::Leaf {
+                    maybe_data: maybe_data.take(),
+                    maybe_cached_hash: maybe_cached_data_hash.take(),
+                    generation: *generation,
+                };
+            }
+            Some(taken)
+        }
+
+        pub(crate) fn cached_data_hash(&self, generation: u32) -> Option<&D> {
+            if self.generation() != generation {
+                return None;
+            }
+            match self {
+                Node::Leaf {
+                    maybe_cached_hash, ..
+                } => maybe_cached_hash.as_ref(),
+                Node::Internal {
+                    maybe_cached_data_hash,
+                    ..
+                } => maybe_cached_data_hash.as_ref(),
+            }
+        }
+
+        pub(crate) fn cached_merkle_root(&self, generation: u32) -> Option<&D> {
+            if self.generation() != generation {
+                return None;
+            }
+            match self {
+                Node::Leaf {
+                    maybe_cached_hash, ..
+                } => maybe_cached_hash.as_ref(),
+                Node::Internal {
+                    maybe_cached_merkle_root,
+                    ..
+                } => maybe_cached_merkle_root.as_ref(),
+            }
+        }
+
+        /// Every distinct cached hash: one for a leaf, up to two for an internal node.
+        pub(crate) fn cached_hashes(&self, generation: u32) -> impl Iterator<Item = &D> {
+            let (first, second) = match self {
+                _ if self.generation() != generation => (&None, &None),
+                Node::Leaf {
+                    maybe_cached_hash, ..
+                } => (maybe_cached_hash, &None),
+                Node::Internal {
+                    maybe_cached_data_hash,
+                    maybe_cached_merkle_root,
+                    ..
+                } => (maybe_cached_data_hash, maybe_cached_merkle_root),
+            };
+            first.iter().chain(second.iter())
+        }
+
+        pub(crate) fn set_cached_data_hash(&mut self, hash: D, generation: u32) {
+            self.enter_generation(generation);
+            match self {
+                Node::Leaf {
+                    maybe_cached_hash, ..
+                } => *maybe_cached_hash = Some(hash),
+                Node::Internal {
+                    maybe_cached_data_hash,
+                    ..
+                } => *maybe_cached_data_hash = Some(hash),
+            }
+        }
+
+        pub(crate) fn set_cached_merkle_root(&mut self, hash: D, generation: u32) {
+            self.enter_generation(generation);
+            match self {
+                Node::Leaf {
+                    maybe_cached_hash, ..
+                } => *maybe_cached_hash = Some(hash),
+                Node::Internal {
+                    maybe_cached_merkle_root,
+                    ..
+                } => *maybe_cached_merkle_root = Some(hash),
+            }
+        }
+
+        // A leaf's root is its data hash, which only a data change can invalidate.
+        pub(crate) fn invalidate_merkle_root(&mut self) {
+            if let Node::Internal {
+                maybe_cached_merkle_root,
+                ..
+            } = self
+            {
+                *maybe_cached_merkle_root = None;
+            }
+        }
+
+        pub(crate) fn clear_cached_hashes(&mut self) {
+            match self {
+                Node::Leaf {
+                    maybe_cached_hash, ..
+                } => *maybe_cached_hash = None,
+                Node::Internal {
+                    maybe_cached_data_hash,
+                    maybe_cached_merkle_root,
+                    ..
+                } => {
+                    *maybe_cached_data_hash = None;
+                    *maybe_cached_merkle_root = None;
+                }
+            }
+        }
+
+        /// Carries hashes cached in generation `from` over to generation `to` and drops any
+        /// others, for nodes moved into a trie with another cache generation.
+        pub(crate) fn restamp(&mut self, from: u32, to: u32) {
+            if self.generation() != from {
+                self.clear_cached_hashes();
+            }
+            match self {
+                Node::Leaf { generation, .. } | Node::Internal { generation, .. } => {
+                    *generation = to
+                }
+            }
         }
     }
 
-    impl<T: Default + ToString + Display> TrieNode<T> {
-        pub fn new() -> Self {
-            TrieNode::default()
+    /// A trie whose nodes have `N` children (a power of two from 2 to 256). Keys are split into
+    /// base-`N` digits, least significant first, so wider tries are shallower: proofs get fewer
+    /// levels but each level carries `N - 1` sibling hashes.
+    /// Cloning copies the cached hashes along with the nodes, so the clone needs no rehashing.
+    /// See `ArcTrie` for sharing a trie without copying it.
+    #[derive(Debug, Clone)]
+    pub struct TrieNode<T: MerkleData, H: MerkleHasher = StdMerkleHasher, const N: usize = 2> {
+        pub(crate) nodes: Vec<Node<T, H::Hash, N>>,
+        // Roots of detached subtrees whose slots are recycled lazily by `push_node`.
+        pub(crate) free_subtrees: Vec<NodeIndex>,
+        pub(crate) eager_hashing: bool,
+        // Cached hashes from any other generation are stale.
+        pub(crate) cache_generation: u32,
+        pub(crate) hasher: H,
+        pub(crate) bloom: Option<BloomFilter>,
+        pub(crate) empty_hashes: OnceLock<EmptyHashes<H::Hash>>,
+        pub(crate) invalidations: InvalidationStats,
+        pub(crate) cache_policy: CachePolicy,
+        // Nodes a budgeted root computation cached although the policy doesn't keep them, so
+        // that its next call can pick up from there; cleared once the root is known.
+        pub(crate) held_hashes: Vec<NodeIndex>,
+    }
+
+    impl<T: MerkleData, H: MerkleHasher + Default, const N: usize> Default for TrieNode<T, H, N> {
+        fn default() -> Self {
+            TrieNode::with_hasher(H::default())
         }
+    }
 
-        pub fn new_with(data: T) -> Self {
+    /// Tries are equal when they hold the same values under the same keys, whatever their
+    /// arena layout. See `content_eq`.
+    impl<T: MerkleData + PartialEq, H: MerkleHasher, const N: usize> PartialEq for TrieNode<T, H, N> {
+        fn eq(&self, other: &Self) -> bool {
+            self.content_eq(other)
+        }
+    }
+
+    impl<T: MerkleData + Eq, H: MerkleHasher, const N: usize> Eq for TrieNode<T, H, N> {}
+
+    impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+        pub(crate) const BITS_PER_DIGIT: u32 = N.trailing_zeros();
+
+        // Whether a prefix of `prefix_len` digits fits in a u32 key.
+        pub(crate) fn prefix_fits(prefix_len: u32) -> bool {
+            prefix_len
+                .checked_mul(Self::BITS_PER_DIGIT)
+                .is_some_and(|bits| bits <= u32::BITS)
+        }
+
+        pub fn with_hasher(hasher: H) -> Self {
+            const {
+                assert!(
+                    N.is_power_of_two() && N >= 2 && N <= 256,
+                    "arity must be a power of two between 2 and 256"
+                )
+            };
             TrieNode {
-                maybe_data: Some(data),
-                ..TrieNode::new()
+                nodes: vec![Node::new(None)],
+                free_subtrees: vec![],
+                eager_hashing: false,
+                cache_generation: 0,
+                hasher,
+                bloom: None,
+                empty_hashes: OnceLock::new(),
+                invalidations: InvalidationStats::default(),
+                cache_policy: CachePolicy::default(),
+                held_hashes: vec![],
             }
         }
 
-        pub fn set_data(&mut self, data: T) {
-            self.maybe_data = Some(data);
+        /// A trie whose node arena has room for `nodes` nodes up front, so building it up to
+        /// that size doesn't reallocate. Node memory lives in that one arena; only the children
+        /// of internal nodes and heap-backed hashes are allocated separately.
+        pub fn with_capacity(hasher: H, nodes: usize) -> Self {
+            let mut trie = Self::with_hasher(hasher);
+            trie.reserve(nodes.saturating_sub(1));
+            trie
         }
 
-        pub fn get_data(&self) -> Option<&T> {
-            self.maybe_data.as_ref()
+        /// Makes room in the node arena for `additional` more nodes.
+        pub fn reserve(&mut self, additional: usize) {
+            self.nodes.reserve(additional);
         }
 
-        pub fn path_to_node(key: u32) -> Vec<u8> {
-            format!("{key:b}")
-                .split("")
-                .filter(|digit| *digit != "")
-                .map(|digit| digit.parse::<u8>().unwrap())
-                .collect::<Vec<u8>>()
+        /// Compares contents, settling any subtree whose root is cached on both sides by
+        /// comparing the roots and only walking the nodes where a cache is missing. Agrees with
+        /// comparing merkle roots, so both tries are assumed to hash alike, and node shape
+        /// matters as it does to the root: a value-less node left behind by `remove_subtree`
+        /// still counts, unless it has no children either and so hashes as a missing child.
+        pub fn content_eq(&self, other: &Self) -> bool
+        where
+            T: PartialEq,
+        {
+            self.subtree_eq(ROOT, other, ROOT)
         }
 
-        pub fn merkle_root(&mut self) -> String {
-            if let Some(cached_merkle_root) = &self.maybe_cached_merkle_root {
-                return cached_merkle_root.clone();
+        fn subtree_eq(&self, index: NodeIndex, other: &Self, other_index: NodeIndex) -> bool
+        where
+            T: PartialEq,
+        {
+            let (node, other_node) = (self.node(index), other.node(other_index));
+            if let (Some(root), Some(other_root)) = (
+                node.cached_merkle_root(self.cache_generation),
+                other_node.cached_merkle_root(other.cache_generation),
+            ) {
+                return root == other_root;
+            }
+            node.get_data() == other_node.get_data()
+                && node.is_leaf() == other_node.is_leaf()
+                && (0..N).all(|digit| match (node.child(digit), other_node.child(digit)) {
+                    (Some(child), Some(other_child)) => self.subtree_eq(child, other, other_child),
+                    (child, other_child) => {
+                        self.hashes_as_absent(child) && other.hashes_as_absent(other_child)
+                    }
+                })
+        }
+
+        // A missing child, or one left with neither a value nor children, which hashes the same.
+        fn hashes_as_absent(&self, child: Option<NodeIndex>) -> bool {
+            child.is_none_or(|child| {
+                let node = self.node(child);
+                node.get_data().is_none() && node.is_leaf()
+            })
+        }
+
+        pub fn hasher(&self) -> &H {
+            &self.hasher
+        }
+
+        pub(crate) fn node(&self, index: NodeIndex) -> &Node<T, H::Hash, N> {
+            &self.nodes[index as usize]
+        }
+
+        pub(crate) fn node_mut(&mut self, index: NodeIndex) -> &mut Node<T, H::Hash, N> {
+            &mut self.nodes[index as usize]
+        }
+
+        // Both cache a hash only if the cache policy lets the node keep it, and otherwise drop
+        // whatever the node has cached, which may be stale.
+        pub(crate) fn cache_data_hash(&mut self, index: NodeIndex, hash: H::Hash) {
+            let generation = self.cache_generation;
+            if self.may_cache(index, None) {
+                self.node_mut(index).set_cached_data_hash(hash, generation);
+            } else {
+                self.node_mut(index).clear_cached_hashes();
+            }
+        }
+
+        pub(crate) fn cache_merkle_root(&mut self, index: NodeIndex, hash: H::Hash) {
+            let generation = self.cache_generation;
+            if self.may_cache(index, None) {
+                self.node_mut(index)
+                    .set_cached_merkle_root(hash, generation);
+            } else {
+                self.node_mut(index).clear_cached_hashes();
+            }
+        }
+
+        pub(crate) fn push_node(&mut self, maybe_data: Option<T>) -> NodeIndex {
+            if let Some(index) = self.free_subtrees.pop() {
+                let released = std::mem::replace(self.node_mut(index), Node::new(maybe_data));
+                self.free_subtrees
+                    .extend(released.children().iter().flatten());
+                return index;
+            }
+            self.nodes.push(Node::new(maybe_data));
+            (self.nodes.len() - 1) as NodeIndex
+        }
+
+        /// The number of base-`N` digits in `key`, which is the depth of its node.
+        pub(crate) fn key_depth(key: u32) -> u32 {
+            (u32::BITS - key.leading_zeros()).div_ceil(Self::BITS_PER_DIGIT)
+        }
+
+        /// The path to `key`'s node: its digits, least significant first.
+        pub fn key_path(key: u32) -> BitPath {
+            BitPath::new(key as u64, Self::key_depth(key) * Self::BITS_PER_DIGIT)
+        }
+
+        /// Walks the first `depth` digits of `path` from the root, creating missing nodes and
+        /// invalidating the roots of the nodes passed through. Returns the node reached.
+        pub(crate) fn create_path(&mut self, path: u32, depth: u32) -> NodeIndex {
+            self.create_at(&BitPath::new(path as u64, depth * Self::BITS_PER_DIGIT))
+        }
+
+        pub(crate) fn create_at(&mut self, path: &BitPath) -> NodeIndex {
+            self.create_counting(path).0
+        }
+
+        // `create_at`, along with how many of the nodes passed through had a current root.
+        pub(crate) fn create_counting(&mut self, path: &BitPath) -> (NodeIndex, usize) {
+            let mut index = ROOT;
+            let mut dirtied = 0;
+            for digit in path.digits(Self::BITS_PER_DIGIT) {
+                dirtied += self.has_current_root(index) as usize;
+                self.node_mut(index).invalidate_merkle_root();
+                index = match self.node(index).child(digit) {
+                    Some(child) => child,
+                    None => {
+                        let child = self.push_node(None);
+                        self.node_mut(index).set_child(digit, child);
+                        child
+                    }
+                };
             }
+            (index, dirtied)
+        }
+
+        pub(crate) fn has_current_root(&self, index: NodeIndex) -> bool {
+            self.node(index)
+                .cached_merkle_root(self.cache_generation)
+                .is_some()
+        }
 
-            let is_leaf_node = self
-                .children
+        // Counts the nodes on `path` whose root is current, as a mutation is about to
+        // invalidate them.
+        pub(crate) fn record_invalidation(&mut self, path: &[NodeIndex]) {
+            let dirtied = path
                 .iter()
-                .filter(|&node| node.is_some())
-                .peekable()
-                .peek()
-                .is_none();
-            let data = self
-                .get_data()
-                .map(|d| d.to_string())
-                .unwrap_or_else(|| "".to_string());
-            let mut hashing = DefaultHasher::new();
-            data.hash(&mut hashing);
-            let hash_of_data = hashing.finish().to_string();
-            if is_leaf_node {
-                self.maybe_cached_merkle_root = Some(hash_of_data.clone());
+                .filter(|index| self.has_current_root(**index))
+                .count();
+            self.invalidations.record(dirtied);
+        }
+
+        pub(crate) fn digit_at(key: u32, depth: u32) -> usize {
+            ((key >> (depth * Self::BITS_PER_DIGIT)) as usize) & (N - 1)
+        }
+
+        pub fn set_eager_hashing(&mut self, eager_hashing: bool) {
+            self.eager_hashing = eager_hashing;
+            self.rehash_if_eager();
+        }
+
+        pub fn is_eager_hashing(&self) -> bool {
+            self.eager_hashing
+        }
+
+        /// The root as of the last computation, or `None` if a mutation has invalidated it
+        /// since or the cache policy doesn't keep it. In eager mode, with a policy that keeps
+        /// it, this is always `Some`.
+        pub fn current_root(&self) -> Option<&H::Hash> {
+            self.node(ROOT).cached_merkle_root(self.cache_generation)
+        }
+
+        /// Makes every cached hash stale at once by moving to a new cache generation; nodes
+        /// drop their old hashes as they are rehashed. When the generation counter wraps, the
+        /// caches are cleared outright so that no old hash can come back into date.
+        pub fn invalidate_all(&mut self) {
+            self.cache_generation = self.cache_generation.wrapping_add(1);
+            if self.cache_generation == 0 {
+                for node in &mut self.nodes {
+                    node.clear_cached_hashes();
+                }
+            }
+            self.rehash_if_eager();
+        }
+
+        /// The generation that cached hashes currently count in. Only `invalidate_all` moves
+        /// it on, so it tells apart hashes read from the caches before and after one.
+        pub fn cache_generation(&self) -> u32 {
+            self.cache_generation
+        }
+
+        // Mutations invalidate the cached hashes on the modified path; in eager mode the path is
+        // rehashed straight away, which costs O(depth) because every sibling is still cached.
+        pub(crate) fn rehash_if_eager(&mut self) {
+            if self.eager_hashing {
+                self.merkle_root();
+            }
+        }
+
+        pub fn merkle_root(&mut self) -> H::Hash {
+            if self.current_root().is_some() {
+                return self.merkle_root_at(ROOT);
+            }
+            instrumentation::observe_root_recomputation(|| self.recompute_root())
+        }
+
+        // `merkle_root_at(ROOT)` for a trie with a stale root, in two passes: the values under
+        // the stale roots are hashed with `MerkleHasher::hash_batch`, then the stale nodes are
+        // combined children first. Every stale node is cached while combining, and the hashes
+        // the cache policy doesn't keep are dropped once the root is known.
+        fn recompute_root(&mut self) -> H::Hash {
+            const BATCH: usize = 256;
+            let generation = self.cache_generation;
+            // Parents before children, so combining in reverse sees every child root cached.
+            let mut stale = vec![];
+            let mut stack = vec![(ROOT, 0)];
+            while let Some((index, depth)) = stack.pop() {
+                let node = self.node(index);
+                if node.cached_merkle_root(self.cache_generation).is_some() {
+                    instrumentation::record_cache_hit();
+                    continue;
+                }
+                instrumentation::record_rehash();
+                stale.push((index, depth));
+                stack.extend(
+                    node.children()
+                        .iter()
+                        .flatten()
+                        .map(|child| (*child, depth + 1)),
+                );
+            }
+
+            let mut unhashed = vec![];
+            for (index, _) in &stale {
+                let node = self.node(*index);
+                if node.cached_data_hash(self.cache_generation).is_some() {
+                    continue;
+                }
+                match node.get_data() {
+                    Some(_) => unhashed.push(*index),
+                    None => {
+                        let empty = self.empty_hash().clone();
+                        self.node_mut(*index)
+                            .set_cached_data_hash(empty, generation);
+                    }
+                }
+            }
+            for batch in unhashed.chunks(BATCH) {
+                let bytes: Vec<_> = batch
+                    .iter()
+                    .map(|index| self.node(*index).get_data().unwrap().merkle_bytes())
+                    .collect();
+                let inputs: Vec<&[u8]> = bytes.iter().map(|bytes| &**bytes).collect();
+                let hashes = self.hasher.hash_batch(&inputs);
+                for (index, hash) in batch.iter().zip(hashes) {
+                    self.node_mut(*index).set_cached_data_hash(hash, generation);
+                }
+            }
+
+            for (index, _) in stale.iter().rev() {
+                if !self.node(*index).is_leaf() {
+                    let hash = self.combine_cached(*index);
+                    self.node_mut(*index)
+                        .set_cached_merkle_root(hash, generation);
+                }
+            }
+            let root = self
+                .node(ROOT)
+                .cached_merkle_root(self.cache_generation)
+                .unwrap()
+                .clone();
+            for (index, depth) in stale {
+                if !self.may_cache(index, Some(depth)) {
+                    self.node_mut(index).clear_cached_hashes();
+                }
+            }
+            self.drop_held_hashes();
+            root
+        }
+
+        pub(crate) fn drop_held_hashes(&mut self) {
+            for index in std::mem::take(&mut self.held_hashes) {
+                // The arena may have shrunk since, e.g. in `clear`.
+                if let Some(node) = self.nodes.get_mut(index as usize) {
+                    node.clear_cached_hashes();
+                }
+            }
+        }
+
+        // The root of the internal node `index` from its cached data hash and its children's
+        // cached roots, which must all be current.
+        pub(crate) fn combine_cached(&self, index: NodeIndex) -> H::Hash {
+            let node = self.node(index);
+            let hashes: Vec<H::Hash> = node
+                .children()
+                .iter()
+                .map(|child| match child {
+                    Some(child) => self
+                        .node(*child)
+                        .cached_merkle_root(self.cache_generation)
+                        .unwrap()
+                        .clone(),
+                    None => self.empty_hash().clone(),
+                })
+                .collect();
+            self.hasher.combine_children(
+                node.cached_data_hash(self.cache_generation).unwrap(),
+                &hashes,
+            )
+        }
+
+        pub(crate) fn merkle_root_at(&mut self, index: NodeIndex) -> H::Hash {
+            if let Some(cached_merkle_root) =
+                self.node(index).cached_merkle_root(self.cache_generation)
+            {
+                instrumentation::record_cache_hit();
+                return cached_merkle_root.clone();
+            }
+            instrumentation::record_rehash();
+
+            let hash_of_data = self.data_hash_at(index);
+            if self.node(index).is_leaf() {
                 hash_of_data
             } else {
-                let hashes: Vec<String> = self
-                    .children
-                    .iter_mut()
-                    .map(|child| match child.as_deref_mut() {
-                        Some(c) => c.merkle_root(),
-                        None => {
-                            let mut hashing = DefaultHasher::new();
-                            "".hash(&mut hashing);
-                            hashing.finish().to_string()
-                        }
+                let children: [Option<NodeIndex>; N] =
+                    self.node(index).children().try_into().unwrap();
+                let hashes: Vec<H::Hash> = children
+                    .iter()
+                    .map(|child| match child {
+                        Some(c) => self.merkle_root_at(*c),
+                        None => self.empty_hash().clone(),
                     })
                     .collect();
-                let hash_of_left = hashes.get(0).unwrap();
-                let hash_of_right = hashes.get(1).unwrap();
-                let mut hashing = DefaultHasher::new();
-                format!("{hash_of_data}{hash_of_left}{hash_of_right}").hash(&mut hashing);
-                let hash = hashing.finish().to_string();
-                self.maybe_cached_merkle_root = Some(hash.clone());
+                let hash = self.hasher.combine_children(&hash_of_data, &hashes);
+                self.cache_merkle_root(index, hash.clone());
                 hash
             }
         }
 
-        pub fn find_by_key(&self, key: u32) -> Option<&TrieNode<T>> {
-            let path_to_node = Self::path_to_node(key);
-            let length = path_to_node.len();
-            let mut index: usize = length - 1;
-            let mut maybe_node: Option<&TrieNode<T>> = Some(self);
-            while let Some(node) = maybe_node {
-                let child_number = path_to_node[index] as usize;
-                let next_node = node.children[child_number].as_deref();
-                if index == 0 {
-                    return next_node;
+        pub(crate) fn data_hash_at(&mut self, index: NodeIndex) -> H::Hash {
+            if let Some(cached_data_hash) = self.node(index).cached_data_hash(self.cache_generation)
+            {
+                return cached_data_hash.clone();
+            }
+
+            let hash_of_data = match self.node(index).get_data() {
+                Some(data) => self.hasher.hash(&data.merkle_bytes()),
+                None => self.empty_hash().clone(),
+            };
+            self.cache_data_hash(index, hash_of_data.clone());
+            hash_of_data
+        }
+
+        /// Empties the trie, keeping the node arena's memory for reuse.
+        pub fn clear(&mut self) {
+            self.nodes.clear();
+            self.nodes.push(Node::new(None));
+            self.free_subtrees.clear();
+            self.bloom = self.bloom.as_ref().map(BloomFilter::emptied);
+            self.rehash_if_eager();
+        }
+
+        pub fn is_empty(&self) -> bool {
+            let root = self.node(ROOT);
+            root.get_data().is_none() && root.is_leaf()
+        }
+
+        /// Detaches the subtree reached by following the lowest `prefix_len` digits of `prefix`
+        /// (bits, in a binary trie), least significant first.
+        pub fn remove_subtree(&mut self, prefix: u32, prefix_len: u32) -> bool {
+            assert!(
+                Self::prefix_fits(prefix_len),
+                "prefix must fit in a u32 key"
+            );
+            if prefix_len == 0 {
+                self.record_invalidation(&[ROOT]);
+                let root = self.node_mut(ROOT);
+                let removed_anything = root.get_data().is_some() || !root.is_leaf();
+                let released = std::mem::replace(root, Node::new(None));
+                self.free_subtrees
+                    .extend(released.children().iter().flatten());
+                self.rehash_if_eager();
+                return removed_anything;
+            }
+
+            let mut path = vec![ROOT];
+            for depth in 0..prefix_len - 1 {
+                let digit = Self::digit_at(prefix, depth);
+                match self.node(*path.last().unwrap()).child(digit) {
+                    Some(child) => path.push(child),
+                    None => return false,
                 }
+            }
+            let digit = Self::digit_at(prefix, prefix_len - 1);
+            let parent = *path.last().unwrap();
+            if self.node(parent).child(digit).is_none() {
+                return false;
+            }
+            // Before detaching the child, which drops the parent's cached root.
+            self.record_invalidation(&path);
+            let removed = self.node_mut(parent).take_child(digit).unwrap();
+            for index in path {
+                self.node_mut(index).invalidate_merkle_root();
+            }
+            self.free_subtrees.push(removed);
+            self.rehash_if_eager();
+            true
+        }
 
-                maybe_node = next_node;
-                index -= 1;
+        /// The node holding the value under `key`. A branch node at the key's position counts
+        /// as absent, as a key that was never inserted should; `find_by_path` reaches it.
+        pub fn find_by_key(&self, key: u32) -> Option<&Node<T, H::Hash, N>> {
+            if key != 0
+                && self
+                    .bloom
+                    .as_ref()
+                    .is_some_and(|bloom| !bloom.might_contain(key))
+            {
+                return None;
             }
-            return maybe_node;
+            self.find_by_path(&Self::key_path(key))
+                .filter(|node| !node.is_branch())
+        }
+
+        /// The node at the end of `path`, whether or not a key reaches it.
+        pub fn find_by_path(&self, path: &BitPath) -> Option<&Node<T, H::Hash, N>> {
+            Some(self.node(self.index_by_path(path)?))
+        }
+
+        pub(crate) fn index_by_path(&self, path: &BitPath) -> Option<NodeIndex> {
+            let mut index = ROOT;
+            for digit in path.digits(Self::BITS_PER_DIGIT) {
+                index = self.node(index).child(digit)?;
+            }
+            Some(index)
+        }
+
+        /// The value under `key`.
+        pub fn get(&self, key: u32) -> Option<&T> {
+            self.find_by_key(key)?.get_data()
+        }
+    }
+
+    /// Panics if no value is stored under the key; `get` is the non-panicking form.
+    impl<T: MerkleData, H: MerkleHasher, const N: usize> Index<u32> for TrieNode<T, H, N> {
+        type Output = T;
+
+        fn index(&self, key: u32) -> &T {
+            self.get(key).expect("no value under the key")
+        }
+    }
+
+    /// Invalidates the cached hashes on the key's path up front, whether or not the value is
+    /// then changed. An eager trie is left to rehash on the next `merkle_root`. Panics if no
+    /// value is stored under the key.
+    impl<T: MerkleData, H: MerkleHasher, const N: usize> IndexMut<u32> for TrieNode<T, H, N> {
+        fn index_mut(&mut self, key: u32) -> &mut T {
+            let mut path = vec![ROOT];
+            for depth in 0..Self::key_depth(key) {
+                let child = self
+                    .node(*path.last().unwrap())
+                    .child(Self::digit_at(key, depth));
+                path.push(child.expect("no value under the key"));
+            }
+            let target = *path.last().unwrap();
+            assert!(
+                self.node(target).get_data().is_some(),
+                "no value under the key"
+            );
+            self.record_invalidation(&path);
+            self.node_mut(target).clear_cached_hashes();
+            for index in path {
+                self.node_mut(index).invalidate_merkle_root();
+            }
+            self.node_mut(target).data_mut().unwrap()
+        }
+    }
+
+    impl<T: MerkleData + PartialEq, H: MerkleHasher + Default, const N: usize> TrieNode<T, H, N> {
+        pub fn new() -> Self {
+            TrieNode::default()
+        }
+
+        pub fn new_eager() -> Self {
+            let mut node = TrieNode::new();
+            node.set_eager_hashing(true);
+            node
+        }
+
+        pub fn new_with(data: T) -> Self {
+            let mut node = TrieNode::new();
+            node.set_data(data);
+            node
+        }
+    }
+
+    impl<T: MerkleData + PartialEq, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+        pub fn set_data(&mut self, data: T) {
+            self.node_mut(ROOT).replace_data(data);
+            self.note_key(0);
+            self.rehash_if_eager();
+        }
+
+        pub fn get_data(&self) -> Option<&T> {
+            self.node(ROOT).get_data()
+        }
+
+        /// Base-`N` digits of `key`, most significant first, without leading zeros. Nodes are
+        /// reached by following these digits in reverse (least significant digit first), so a
+        /// key sits at a depth equal to its digit count and key 0 is the root itself.
+        pub fn path_to_node(key: u32) -> Vec<u8> {
+            let mut digits: Vec<u8> = Self::key_path(key)
+                .digits(Self::BITS_PER_DIGIT)
+                .map(|digit| digit as u8)
+                .collect();
+            digits.reverse();
+            digits
         }
 
         pub fn insert(&mut self, key: u32, data: T) {
-            let path_to_node = Self::path_to_node(key);
-            let length = path_to_node.len();
-
-            fn insert_recurse<T: Default + Display>(
-                node: &mut TrieNode<T>,
-                data: T,
-                path_to_node: Vec<u8>,
-                index: usize,
-            ) {
-                node.maybe_cached_merkle_root = None;
-                let index_of_child: usize = if path_to_node[index] == 1 { 1 } else { 0 };
-                if index == 0 {
-                    match node.children[index_of_child] {
-                        Some(ref mut child_node) => {
-                            child_node.maybe_cached_merkle_root = None;
-                            child_node.set_data(data)
-                        }
-                        None => {
-                            let new_node = TrieNode::<T>::new_with(data);
-                            node.children[index_of_child] = new_node.into();
-                        }
-                    }
-                } else {
-                    if node.children[index_of_child].is_none() {
-                        let new_node = TrieNode::<T>::new();
-                        node.children[index_of_child] = new_node.into();
-                    }
-                    insert_recurse(
-                        node.children[index_of_child].as_deref_mut().unwrap(),
-                        data,
-                        path_to_node,
-                        index - 1,
-                    );
+            if let Some(existing) = self.find_by_key(key) {
+                if existing.get_data() == Some(&data) {
+                    return;
                 }
             }
 
-            insert_recurse(self, data, path_to_node, length - 1);
+            let (index, dirtied) = self.create_counting(&Self::key_path(key));
+            self.invalidations
+                .record(dirtied + self.has_current_root(index) as usize);
+            self.node_mut(index).replace_data(data);
+            self.note_key(key);
+            instrumentation::record_insert();
+            instrumentation::record_node_count(self.nodes.len());
+            self.rehash_if_eager();
+        }
+
+        /// Inserts every entry in order. In eager mode the root is rehashed once at the end
+        /// rather than after each entry.
+        pub fn insert_batch<I: IntoIterator<Item = (u32, T)>>(&mut self, entries: I) {
+            let entries = entries.into_iter();
+            instrumentation::observe_batch_insert(entries.size_hint().0, || {
+                let eager_hashing = std::mem::replace(&mut self.eager_hashing, false);
+                for (key, data) in entries {
+                    self.insert(key, data);
+                }
+                self.eager_hashing = eager_hashing;
+                self.rehash_if_eager();
+            })
         }
     }
 }
@@ -157,6 +974,7 @@ pub mod trie_node {
 mod tests {
 
     use super::trie_node::*;
+    use crate::hasher::{MerkleHasher, StdMerkleHasher};
 
     #[test]
     fn insert_i32() {
@@ -166,7 +984,11 @@ mod tests {
         node.insert(10, 9);
         assert_eq!(node.find_by_key(10).unwrap().get_data(), Some(&9));
         assert_eq!(node.find_by_key(3), None);
-        assert_eq!(node.find_by_key(2).unwrap().get_data(), None);
+        assert_eq!(node.find_by_key(2), None);
+        let branch = node.find_by_path(&TrieNode::<i32>::key_path(2)).unwrap();
+        assert!(branch.is_branch() && !branch.is_leaf());
+        assert!(!node.find_by_key(10).unwrap().is_branch());
+        assert_eq!(node.iter().collect::<Vec<_>>(), [(10, &9)]);
     }
 
     #[test]
@@ -183,12 +1005,297 @@ mod tests {
             Some(&"9".to_string())
         );
         assert_eq!(node.find_by_key(4), None);
-        assert_eq!(node.find_by_key(1).unwrap().get_data(), None);
+        assert_eq!(node.find_by_key(1), None);
+    }
+
+    #[test]
+    fn arena_allocates_one_slot_per_node() {
+        let mut node: TrieNode<i32> = TrieNode::new();
+        node.insert(4, 1);
+        node.insert(4, 2);
+        node.insert(6, 3);
+        assert_eq!(node.nodes.len(), 6);
+        assert_eq!(node.find_by_key(6).unwrap().get_data(), Some(&3));
+    }
+
+    #[test]
+    fn equality_ignores_layout_and_uses_cached_roots() {
+        let mut node: TrieNode<u32> = TrieNode::new();
+        let mut other: TrieNode<u32> = TrieNode::new();
+        for key in 1..20 {
+            node.insert(key, key);
+            other.insert(20 - key, 20 - key);
+        }
+        node.remove_subtree(0b11, 2);
+        for key in (3..20).step_by(4).rev() {
+            node.insert(key, key);
+        }
+        assert_ne!(node.nodes, other.nodes);
+        assert_eq!(node, other);
+
+        node.merkle_root();
+        assert!(node.content_eq(&other));
+        other.merkle_root();
+        assert!(node.content_eq(&other));
+        other.insert(7, 8);
+        assert_ne!(node, other);
+        other.merkle_root();
+        assert_ne!(node, other);
+    }
+
+    #[test]
+    fn emptied_leaves_compare_as_missing_children() {
+        let mut node: TrieNode<u32> = TrieNode::new();
+        node.insert(1, 1);
+        node.insert(2, 2);
+        assert!(node.remove_subtree(0b10, 2));
+        let mut other: TrieNode<u32> = TrieNode::new();
+        other.insert(1, 1);
+
+        // Without cached roots the nodes are walked; with them the roots are compared.
+        assert_eq!(node, other);
+        assert_eq!(other, node);
+        assert_eq!(node.merkle_root(), other.merkle_root());
+        assert_eq!(node, other);
+        assert_eq!(other, node);
+    }
+
+    #[test]
+    fn nodes_switch_between_leaf_and_internal() {
+        let mut node: TrieNode<u32> = TrieNode::new();
+        node.insert(1, 1);
+        assert!(node.find_by_key(1).unwrap().is_leaf());
+        node.insert(3, 3);
+        assert!(!node.find_by_key(1).unwrap().is_leaf());
+
+        let mut leaf_only: TrieNode<u32> = TrieNode::new();
+        leaf_only.insert(1, 1);
+        node.remove_subtree(0b11, 2);
+        assert!(node.find_by_key(1).unwrap().is_leaf());
+        assert_eq!(node.merkle_root(), leaf_only.merkle_root());
+    }
+
+    #[test]
+    fn descendant_insert_keeps_ancestor_data_hash() {
+        let mut node: TrieNode<String> = TrieNode::new();
+        node.insert(1, "foo".to_string());
+        node.insert(3, "bar".to_string());
+        let first_root = node.merkle_root();
+        let foo_data_hash = node.find_by_key(1).unwrap().cached_data_hash(0).cloned();
+        assert!(foo_data_hash.is_some());
+
+        node.insert(3, "baz".to_string());
+        let foo = node.find_by_key(1).unwrap();
+        assert_eq!(foo.cached_merkle_root(0), None);
+        assert_eq!(foo.cached_data_hash(0).cloned(), foo_data_hash);
+        assert_ne!(node.merkle_root(), first_root);
+
+        node.insert(1, "qux".to_string());
+        assert_eq!(node.find_by_key(1).unwrap().cached_data_hash(0), None);
+    }
+
+    #[test]
+    fn invalidate_all_makes_every_cache_stale() {
+        let mut node: TrieNode<u32> = (0..100).map(|key| (key, key)).collect();
+        let root = node.merkle_root();
+        node.invalidate_all();
+        assert_eq!(node.cache_generation(), 1);
+        assert_eq!(node.current_root(), None);
+        assert!(node
+            .nodes
+            .iter()
+            .all(|node| node.cached_hashes(1).next().is_none()));
+        assert_eq!(node.merkle_root(), root);
+        assert!(node
+            .nodes
+            .iter()
+            .all(|node| node.cached_merkle_root(1).is_some()));
+
+        node.cache_generation = u32::MAX;
+        node.invalidate_all();
+        assert_eq!(node.cache_generation(), 0);
+        assert!(node
+            .nodes
+            .iter()
+            .all(|node| node.cached_hashes(0).next().is_none()));
+        assert_eq!(node.merkle_root(), root);
+    }
+
+    #[test]
+    fn reinserting_equal_value_keeps_caches() {
+        let mut node: TrieNode<String> = TrieNode::new();
+        node.insert(1, "foo".to_string());
+        node.insert(2, "bar".to_string());
+        let root = node.merkle_root();
+        node.insert(2, "bar".to_string());
+        assert_eq!(node.node(ROOT).cached_merkle_root(0), Some(&root));
+        node.insert(2, "baz".to_string());
+        assert_eq!(node.node(ROOT).cached_merkle_root(0), None);
+    }
+
+    #[test]
+    fn remove_subtree_drops_keys_under_prefix() {
+        let mut node: TrieNode<u32> = TrieNode::new();
+        let mut evens: TrieNode<u32> = TrieNode::new();
+        for key in 1..16 {
+            node.insert(key, key);
+            if key % 2 == 0 {
+                evens.insert(key, key);
+            }
+        }
+        node.merkle_root();
+
+        assert!(node.remove_subtree(0b1, 1));
+        assert!(!node.remove_subtree(0b1, 1));
+        assert_eq!(node.find_by_key(3), None);
+        assert_eq!(node.find_by_key(6).unwrap().get_data(), Some(&6));
+        assert_eq!(node.merkle_root(), evens.merkle_root());
+
+        let allocated = node.nodes.len();
+        node.insert(7, 7);
+        assert_eq!(node.nodes.len(), allocated);
+        assert_eq!(node.find_by_key(7).unwrap().get_data(), Some(&7));
+
+        assert!(node.remove_subtree(0, 0));
+        assert_eq!(node.merkle_root(), TrieNode::<u32>::new().merkle_root());
+    }
+
+    #[test]
+    #[should_panic(expected = "prefix must fit in a u32 key")]
+    fn remove_subtree_rejects_prefixes_longer_than_a_key() {
+        let mut node: TrieNode<u32, StdMerkleHasher, 16> = (0..20).map(|key| (key, key)).collect();
+        assert!(node.generate_subtree_proof(0, u32::MAX).is_none());
+        node.remove_subtree(0, u32::MAX);
+    }
+
+    #[test]
+    fn clear_resets_to_canonical_empty_root() {
+        let mut node: TrieNode<String> = TrieNode::new();
+        assert!(node.is_empty());
+        assert_eq!(node.merkle_root(), empty_merkle_root());
+
+        node.insert(5, "foo".to_string());
+        assert!(!node.is_empty());
+        assert_ne!(node.merkle_root(), empty_merkle_root());
+
+        node.clear();
+        assert!(node.is_empty());
+        assert_eq!(node.find_by_key(5), None);
+        assert_eq!(node.merkle_root(), empty_merkle_root());
+    }
+
+    #[test]
+    fn preallocated_arena_is_kept_across_clear() {
+        let mut node: TrieNode<u32> = TrieNode::with_capacity(StdMerkleHasher, 256);
+        let capacity = node.nodes.capacity();
+        assert!(capacity >= 256);
+        node.insert_batch((0..64).map(|key| (key, key)));
+        node.clear();
+        node.insert_batch((0..64).map(|key| (key * 2, key)));
+        assert_eq!(node.nodes.capacity(), capacity);
+    }
+
+    #[test]
+    fn key_zero_is_the_root() {
+        assert_eq!(TrieNode::<i32>::path_to_node(0), Vec::<u8>::new());
+        let mut node: TrieNode<String> = TrieNode::new();
+        node.insert(0, "zero".to_string());
+        assert_eq!(node.get_data(), Some(&"zero".to_string()));
+        assert_eq!(
+            node.find_by_key(0).unwrap().get_data(),
+            Some(&"zero".to_string())
+        );
+        assert_eq!(node.merkle_root(), "8474691169544981608");
+    }
+
+    #[test]
+    fn full_key_range() {
+        let mut node: TrieNode<String> = TrieNode::new();
+        for key in [0, 1, 2, u32::MAX - 1, u32::MAX] {
+            node.insert(key, key.to_string());
+        }
+        for key in [0, 1, 2, u32::MAX - 1, u32::MAX] {
+            assert_eq!(
+                node.find_by_key(key).unwrap().get_data(),
+                Some(&key.to_string())
+            );
+        }
+        assert_eq!(node.metrics().max_depth, 32);
+        assert_eq!(node.merkle_root(), "1965148217520390863");
+    }
+
+    #[test]
+    fn insert_batch_matches_individual_inserts() {
+        let entries = [(1, "foo"), (2, "bar"), (9, "baz"), (2, "qux")]
+            .map(|(key, value)| (key, value.to_string()));
+        let mut batched: TrieNode<String> = TrieNode::new_eager();
+        batched.insert_batch(entries.clone());
+        let mut individually: TrieNode<String> = TrieNode::new();
+        for (key, value) in entries {
+            individually.insert(key, value);
+        }
+        assert_eq!(batched.current_root(), Some(&individually.merkle_root()));
+        assert!(batched.is_eager_hashing());
+    }
+
+    #[test]
+    fn eager_mode_keeps_root_current() {
+        let mut eager: TrieNode<String> = TrieNode::new_eager();
+        let mut lazy: TrieNode<String> = TrieNode::new();
+        assert_eq!(eager.current_root(), Some(&empty_merkle_root()));
+        for (key, value) in [(1, "foo"), (2, "bar"), (9, "baz")] {
+            eager.insert(key, value.to_string());
+            lazy.insert(key, value.to_string());
+            assert_eq!(lazy.current_root(), None);
+            assert_eq!(eager.current_root(), Some(&lazy.merkle_root()));
+        }
+        eager.remove_subtree(0b1, 1);
+        lazy.remove_subtree(0b1, 1);
+        assert_eq!(eager.current_root(), Some(&lazy.merkle_root()));
+    }
+
+    #[test]
+    fn wider_arity_tries() {
+        assert_eq!(
+            TrieNode::<i32, StdMerkleHasher, 16>::path_to_node(0x1a3),
+            vec![1, 10, 3]
+        );
+        assert_eq!(
+            TrieNode::<i32, StdMerkleHasher, 4>::path_to_node(0b1011),
+            vec![2, 3]
+        );
+
+        let mut node: TrieNode<String, StdMerkleHasher, 16> = TrieNode::new();
+        for key in [1, 17, 0x1a3, u32::MAX] {
+            node.insert(key, key.to_string());
+        }
+        for key in [1, 17, 0x1a3, u32::MAX] {
+            assert_eq!(
+                node.find_by_key(key).unwrap().get_data(),
+                Some(&key.to_string())
+            );
+        }
+        assert_eq!(node.find_by_key(2), None);
+        assert_eq!(node.metrics().max_depth, 8);
+
+        let hasher = StdMerkleHasher;
+        let mut single: TrieNode<String, StdMerkleHasher, 4> = TrieNode::new();
+        single.insert(3, "foo".to_string());
+        let mut children = vec![hasher.empty_hash(); 4];
+        children[3] = hasher.hash(b"foo");
+        assert_eq!(
+            single.merkle_root(),
+            hasher.combine_children(&hasher.empty_hash(), &children)
+        );
+
+        assert!(node.remove_subtree(1, 1));
+        assert_eq!(node.find_by_key(17), None);
+        assert!(node.find_by_key(u32::MAX).is_some());
     }
 
     #[test]
     fn test_get_go_rights() {
-        let actual = TrieNode::<i32>::path_to_node(4 as u32);
+        let actual = TrieNode::<i32>::path_to_node(4_u32);
         assert_eq!(vec![1, 0, 0], actual);
     }
 
@@ -200,6 +1307,48 @@ mod tests {
         assert_eq!(node.merkle_root(), "13830055607334163982");
     }
 
+    #[test]
+    fn get_and_index_return_values() {
+        let mut node: TrieNode<String> = TrieNode::new();
+        node.insert(1, "foo".to_string());
+        node.insert(5, "bar".to_string());
+        node.merkle_root();
+        assert_eq!(node.get(5), Some(&"bar".to_string()));
+        assert_eq!(node.get(3), None);
+        assert_eq!(node.get(2), None);
+        assert_eq!(node[1], "foo");
+
+        node[5].push('!');
+        let mut expected: TrieNode<String> = TrieNode::new();
+        expected.insert(1, "foo".to_string());
+        expected.insert(5, "bar!".to_string());
+        assert_eq!(node.merkle_root(), expected.merkle_root());
+    }
+
+    #[test]
+    #[should_panic(expected = "no value under the key")]
+    fn index_panics_without_a_value() {
+        let mut node: TrieNode<String> = TrieNode::new();
+        node.insert(5, "bar".to_string());
+        node[1].push('!');
+    }
+
+    #[cfg(feature = "borsh")]
+    #[test]
+    fn nodes_borsh_encode_without_their_caches() {
+        let mut node: TrieNode<u32> = TrieNode::new();
+        node.insert(1, 10);
+        node.insert(3, 30);
+        node.merkle_root();
+        let internal = node.find_by_key(1).unwrap();
+        let bytes = borsh::to_vec(internal).unwrap();
+        let decoded: Node<u32> = borsh::from_slice(&bytes).unwrap();
+        assert_eq!(decoded.get_data(), Some(&10));
+        assert_eq!(decoded.children(), internal.children());
+        assert_eq!(decoded.cached_merkle_root(0), None);
+        assert_eq!(borsh::to_vec(&decoded).unwrap(), bytes);
+    }
+
     #[test]
     fn cached_merkle_root() {
         // There is not an easy way to test the caching... maybe I could time the calls and compare the time for the first
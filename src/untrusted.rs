@@ -0,0 +1,83 @@
+use std::io;
+
+use crate::checkpoint::invalid;
+use crate::codec::ValueCodec;
+use crate::trie_node::trie_node::TrieNode;
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// A trie received from a peer that has been checked against a root obtained some other way,
+/// such as from a block header. A checkpoint carries cached hashes, and reading through a trie
+/// whose caches lie would hand out proofs and roots for data it doesn't hold. So `from_bytes`
+/// recomputes every hash from the values, rejecting the trie if any cached hash or the final
+/// root disagrees. What it holds afterwards can be read and proved without further checks.
+pub struct UntrustedTrie<T: MerkleData, H: MerkleHasher, const N: usize = 2> {
+    trie: TrieNode<T, H, N>,
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> UntrustedTrie<T, H, N> {
+    /// Decodes a trie written by `TrieNode::checkpoint_bytes_with_codec` and verifies it against
+    /// `trusted_root`. Malformed bytes, a stale cache and a different root are all reported as
+    /// `InvalidData`.
+    pub fn from_bytes<C: ValueCodec<T>>(
+        bytes: &[u8],
+        codec: &C,
+        hasher: H,
+        trusted_root: &H::Hash,
+    ) -> io::Result<Self> {
+        let mut trie = TrieNode::decode_checkpoint(bytes, hasher, |bytes| codec.decode(bytes))?;
+        if trie.merkle_root() != *trusted_root {
+            return Err(invalid("trie does not have the trusted root"));
+        }
+        Ok(UntrustedTrie { trie })
+    }
+
+    pub fn trie(&self) -> &TrieNode<T, H, N> {
+        &self.trie
+    }
+
+    pub fn into_trie(self) -> TrieNode<T, H, N> {
+        self.trie
+    }
+
+    pub fn get(&self, key: u32) -> Option<&T> {
+        self.trie.get(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::codec::DisplayCodec;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn peers_tries_are_checked_before_use() {
+        let mut sent: TrieNode<u32, StdMerkleHasher, 4> =
+            (0..100).map(|key| (key * 3, key)).collect();
+        let root = sent.merkle_root();
+        let bytes = sent.checkpoint_bytes_with_codec(&DisplayCodec);
+        let from_bytes = |bytes: &[u8], root: &String| {
+            UntrustedTrie::<u32, _, 4>::from_bytes(bytes, &DisplayCodec, StdMerkleHasher, root)
+        };
+
+        let received = from_bytes(&bytes, &root).unwrap();
+        assert_eq!(received.get(42), Some(&14));
+        assert_eq!(received.trie().current_root(), Some(&root));
+
+        let other = StdMerkleHasher.hash(b"other");
+        let error = from_bytes(&bytes, &other).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // The last bytes are the cached hash of the last node.
+        let mut stale = bytes.clone();
+        let last = stale.last_mut().unwrap();
+        *last = b'0' + (*last - b'0' + 1) % 10;
+        assert!(from_bytes(&stale, &root).is_err());
+
+        // A node count the bytes can't hold is refused before anything is allocated for it.
+        let mut oversized = bytes[..8 + 1 + 2 + 1].to_vec();
+        oversized.extend_from_slice(&(u32::MAX - 1).to_be_bytes());
+        assert!(from_bytes(&oversized, &root).is_err());
+    }
+}
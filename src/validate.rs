@@ -0,0 +1,214 @@
+use crate::state_sync::NodePosition;
+use crate::trie_node::trie_node::{NodeIndex, TrieNode, ROOT};
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// A problem `validate` found with one node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntegrityIssue<D> {
+    /// The cached merkle root disagrees with the one recomputed from the node's contents.
+    StaleRoot {
+        position: NodePosition,
+        cached: D,
+        computed: D,
+    },
+    /// The cached hash of the node's value disagrees with the value.
+    StaleDataHash {
+        position: NodePosition,
+        cached: D,
+        computed: D,
+    },
+    /// The top of a subtree holding no values, e.g. a chain of value-less nodes left behind by
+    /// `remove_subtree`. It hashes like any other node, but a fresh trie wouldn't have it.
+    EmptySubtree { position: NodePosition },
+    /// A value at a position no key leads to: one whose last digit is 0.
+    ValueOffKey { position: NodePosition },
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    /// Recomputes every hash bottom-up, ignoring the caches, and reports each cached hash that
+    /// disagrees along with the structural problems below. Nothing is changed. An empty result
+    /// means the trie is sound; `current_root`, if cached, is then its true root.
+    pub fn validate(&self) -> Vec<IntegrityIssue<H::Hash>> {
+        let mut issues = vec![];
+        let root = NodePosition { path: 0, depth: 0 };
+        let (_, has_values) = self.validate_at(ROOT, root, &mut issues);
+        if !has_values && !self.node(ROOT).is_leaf() {
+            issues.push(IntegrityIssue::EmptySubtree { position: root });
+        }
+        issues
+    }
+
+    /// Drops every cached hash, including detached nodes', and recomputes the root from
+    /// scratch.
+    pub fn rehash_all(&mut self) -> H::Hash {
+        self.invalidate_all();
+        self.merkle_root()
+    }
+
+    /// Overwrites each cached hash `validate` finds stale with the recomputed one, leaving the
+    /// rest of the caches alone. Structural issues are left as they are, since fixing them
+    /// would change the root. Returns the number of hashes fixed.
+    pub fn repair(&mut self) -> usize {
+        let mut fixed = 0;
+        for issue in self.validate() {
+            match issue {
+                IntegrityIssue::StaleRoot {
+                    position, computed, ..
+                } => {
+                    let index = self.index_at(position);
+                    self.cache_merkle_root(index, computed);
+                }
+                IntegrityIssue::StaleDataHash {
+                    position, computed, ..
+                } => {
+                    let index = self.index_at(position);
+                    self.cache_data_hash(index, computed);
+                }
+                IntegrityIssue::EmptySubtree { .. } | IntegrityIssue::ValueOffKey { .. } => {
+                    continue;
+                }
+            }
+            fixed += 1;
+        }
+        fixed
+    }
+
+    // The node at a position `validate` reported, which therefore exists.
+    fn index_at(&self, position: NodePosition) -> NodeIndex {
+        (0..position.depth).fold(ROOT, |index, depth| {
+            let digit = Self::digit_at(position.path, depth);
+            self.node(index).child(digit).unwrap()
+        })
+    }
+
+    // The recomputed root of the subtree at `index` and whether it holds any values.
+    fn validate_at(
+        &self,
+        index: NodeIndex,
+        position: NodePosition,
+        issues: &mut Vec<IntegrityIssue<H::Hash>>,
+    ) -> (H::Hash, bool) {
+        let node = self.node(index);
+        let data_hash = match node.get_data() {
+            Some(data) => self.hasher.hash(&data.merkle_bytes()),
+            None => self.empty_hash().clone(),
+        };
+        let mut has_values = node.get_data().is_some();
+        if has_values && Self::key_depth(position.path) != position.depth {
+            issues.push(IntegrityIssue::ValueOffKey { position });
+        }
+
+        let root = if node.is_leaf() {
+            data_hash.clone()
+        } else {
+            let mut roots = Vec::with_capacity(N);
+            let mut empty_children = vec![];
+            for digit in 0..N {
+                roots.push(match node.child(digit) {
+                    Some(child) => {
+                        let child_position = Self::child_position(position, digit);
+                        let (root, child_has_values) =
+                            self.validate_at(child, child_position, issues);
+                        if !child_has_values {
+                            empty_children.push(child_position);
+                        }
+                        has_values |= child_has_values;
+                        root
+                    }
+                    None => self.empty_hash().clone(),
+                });
+            }
+            // Without values here either, only this node's parent reports the subtree.
+            if has_values {
+                issues.extend(
+                    empty_children
+                        .into_iter()
+                        .map(|position| IntegrityIssue::EmptySubtree { position }),
+                );
+            }
+            if let Some(cached) = node.cached_data_hash(self.cache_generation) {
+                if *cached != data_hash {
+                    issues.push(IntegrityIssue::StaleDataHash {
+                        position,
+                        cached: cached.clone(),
+                        computed: data_hash.clone(),
+                    });
+                }
+            }
+            self.hasher.combine_children(&data_hash, &roots)
+        };
+        // A leaf caches one hash, its value's, which is also its root.
+        if let Some(cached) = node.cached_merkle_root(self.cache_generation) {
+            if *cached != root {
+                issues.push(IntegrityIssue::StaleRoot {
+                    position,
+                    cached: cached.clone(),
+                    computed: root.clone(),
+                });
+            }
+        }
+        (root, has_values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn validate_reports_stale_hashes_and_empty_chains() {
+        let mut node: TrieNode<u32> = (1..20).map(|key| (key, key)).collect();
+        node.merkle_root();
+        assert_eq!(node.validate(), vec![]);
+
+        // Leaves a chain of two value-less nodes below key 4.
+        node.insert(0b100100, 1);
+        node.remove_subtree(0b100100, 6);
+        let root = node.merkle_root();
+        // Key 1's node has children, so it caches its value's hash apart from its root.
+        let corrupt = node.hasher().hash(b"corrupt");
+        node.cache_merkle_root(ROOT, corrupt.clone());
+        let key_1 = node.node(ROOT).child(1).unwrap();
+        node.cache_data_hash(key_1, corrupt.clone());
+
+        let at = |path, depth| NodePosition { path, depth };
+        assert_eq!(
+            node.validate(),
+            vec![
+                IntegrityIssue::EmptySubtree {
+                    position: at(0b0100, 4)
+                },
+                IntegrityIssue::StaleDataHash {
+                    position: at(1, 1),
+                    cached: corrupt.clone(),
+                    computed: node.hasher().hash(&1u32.to_be_bytes()),
+                },
+                IntegrityIssue::StaleRoot {
+                    position: at(0, 0),
+                    cached: corrupt,
+                    computed: root,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn repair_and_rehash_all_restore_the_root() {
+        let mut node: TrieNode<u32> = (1..50).map(|key| (key * 3, key)).collect();
+        let root = node.merkle_root();
+        let corrupt = node.hasher().hash(b"corrupt");
+        node.cache_merkle_root(ROOT, corrupt.clone());
+        let child = node.node(ROOT).child(1).unwrap();
+        node.cache_data_hash(child, corrupt.clone());
+
+        let mut rehashed = node.clone();
+        assert_eq!(rehashed.rehash_all(), root);
+        assert_eq!(rehashed.validate(), vec![]);
+
+        assert_eq!(node.repair(), 2);
+        assert_eq!(node.validate(), vec![]);
+        assert_eq!(node.current_root(), Some(&root));
+        assert_eq!(node.repair(), 0);
+    }
+}
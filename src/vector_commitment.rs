@@ -0,0 +1,212 @@
+use crate::hasher::{hashes_equal, MerkleHasher};
+use crate::merkle_data::MerkleData;
+use crate::trie_node::trie_node::{NodeIndex, TrieNode, ROOT};
+
+// Experimental. A node commits to a vector of slots: slot 0 is its data hash and slot `1 + d`
+// the root of child `d` (the empty hash if there is none). With plain hashing, opening one slot
+// means revealing all the others, so proofs grow with the arity; a vector commitment such as
+// KZG or IPA opens a slot with a constant-size proof instead. A scheme plugs in by implementing
+// `MerkleHasher`, with `combine_children` computing the commitment, and `NodeCommitment`.
+
+/// Opens single slots of the commitments computed by `combine_children`.
+pub trait NodeCommitment: MerkleHasher {
+    type Opening;
+
+    /// Proves that `slots[slot]` is committed to by `combine_children(&slots[0], &slots[1..])`.
+    fn open(&self, slots: &[Self::Hash], slot: usize) -> Self::Opening;
+
+    fn verify_opening(
+        &self,
+        commitment: &Self::Hash,
+        slot: usize,
+        value: &Self::Hash,
+        opening: &Self::Opening,
+    ) -> bool;
+}
+
+/// Hash concatenation as a `NodeCommitment`: an opening is every other slot. This is the
+/// reference the vector schemes are measured against, and what `MerkleProof` amounts to.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct HashCommitment<H>(pub H);
+
+impl<H: MerkleHasher> MerkleHasher for HashCommitment<H> {
+    type Hash = H::Hash;
+
+    fn hash(&self, bytes: &[u8]) -> H::Hash {
+        self.0.hash(bytes)
+    }
+
+    fn hash_batch(&self, inputs: &[&[u8]]) -> Vec<H::Hash> {
+        self.0.hash_batch(inputs)
+    }
+
+    fn hash_from_bytes(bytes: &[u8]) -> Option<H::Hash> {
+        H::hash_from_bytes(bytes)
+    }
+
+    fn combine(&self, data: &H::Hash, left: &H::Hash, right: &H::Hash) -> H::Hash {
+        self.0.combine(data, left, right)
+    }
+
+    fn combine_children(&self, data: &H::Hash, children: &[H::Hash]) -> H::Hash {
+        self.0.combine_children(data, children)
+    }
+
+    fn empty_hash(&self) -> H::Hash {
+        self.0.empty_hash()
+    }
+
+    fn max_hash_len(&self) -> usize {
+        self.0.max_hash_len()
+    }
+
+    fn heap_bytes(hash: &H::Hash) -> usize {
+        H::heap_bytes(hash)
+    }
+
+    fn hash_to_string(hash: &H::Hash) -> String {
+        H::hash_to_string(hash)
+    }
+
+    fn hash_from_string(string: &str) -> Option<H::Hash> {
+        H::hash_from_string(string)
+    }
+}
+
+impl<H: MerkleHasher> NodeCommitment for HashCommitment<H> {
+    type Opening = Vec<H::Hash>;
+
+    fn open(&self, slots: &[H::Hash], slot: usize) -> Vec<H::Hash> {
+        let mut others = slots.to_vec();
+        others.remove(slot);
+        others
+    }
+
+    fn verify_opening(
+        &self,
+        commitment: &H::Hash,
+        slot: usize,
+        value: &H::Hash,
+        opening: &Vec<H::Hash>,
+    ) -> bool {
+        if slot > opening.len() {
+            return false;
+        }
+        let mut slots = opening.clone();
+        slots.insert(slot, value.clone());
+        let computed = self.0.combine_children(&slots[0], &slots[1..]);
+        hashes_equal(computed.as_ref(), commitment.as_ref())
+    }
+}
+
+/// One step of a `VectorProof`: a node's commitment and the opening of the slot on the path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VectorLevel<D, O> {
+    pub commitment: D,
+    pub opening: O,
+}
+
+/// Proof that a value is stored under `key`, one opening per node on the path, nearest first.
+/// If the keyed node has children, the first level opens its data slot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VectorProof<D, O> {
+    pub key: u32,
+    pub levels: Vec<VectorLevel<D, O>>,
+}
+
+impl<D: Clone + PartialEq, O> VectorProof<D, O> {
+    pub fn verify<C, V>(&self, scheme: &C, arity: usize, root: &D, value: &V) -> bool
+    where
+        C: NodeCommitment<Hash = D, Opening = O>,
+        V: MerkleData + ?Sized,
+        D: AsRef<[u8]>,
+    {
+        if !arity.is_power_of_two() || arity < 2 {
+            return false;
+        }
+        let bits_per_digit = arity.trailing_zeros();
+        let depth = (u32::BITS - self.key.leading_zeros()).div_ceil(bits_per_digit) as usize;
+        let opens_data = match self.levels.len() {
+            len if len == depth => false,
+            len if len == depth + 1 => true,
+            _ => return false,
+        };
+        let mut hash = scheme.hash(&value.merkle_bytes());
+        for (i, level) in self.levels.iter().enumerate() {
+            let slot = if opens_data && i == 0 {
+                0
+            } else {
+                let ancestor_depth = (depth - 1 - (i - opens_data as usize)) as u32;
+                1 + ((self.key >> (ancestor_depth * bits_per_digit)) as usize & (arity - 1))
+            };
+            if !scheme.verify_opening(&level.commitment, slot, &hash, &level.opening) {
+                return false;
+            }
+            hash = level.commitment.clone();
+        }
+        hashes_equal(hash.as_ref(), root.as_ref())
+    }
+}
+
+impl<T: MerkleData, C: NodeCommitment, const N: usize> TrieNode<T, C, N> {
+    fn commitment_slots(&mut self, index: NodeIndex) -> Vec<C::Hash> {
+        let mut slots = vec![self.data_hash_at(index)];
+        slots.extend(self.child_roots(index, None));
+        slots
+    }
+
+    /// Proves the value under `key` with openings from the trie's `NodeCommitment`; `None` if
+    /// no value is stored there.
+    pub fn generate_vector_proof(&mut self, key: u32) -> Option<VectorProof<C::Hash, C::Opening>> {
+        let depth = Self::key_depth(key);
+        let mut path: Vec<NodeIndex> = vec![ROOT];
+        for d in 0..depth {
+            let digit = Self::digit_at(key, d);
+            path.push(self.node(*path.last().unwrap()).child(digit)?);
+        }
+        let target = path.pop().unwrap();
+        self.node(target).get_data()?;
+
+        let mut levels = Vec::with_capacity(path.len() + 1);
+        if !self.node(target).is_leaf() {
+            let slots = self.commitment_slots(target);
+            levels.push(VectorLevel {
+                commitment: self.merkle_root_at(target),
+                opening: self.hasher.open(&slots, 0),
+            });
+        }
+        for (ancestor_depth, index) in path.into_iter().enumerate().rev() {
+            let digit = Self::digit_at(key, ancestor_depth as u32);
+            let slots = self.commitment_slots(index);
+            levels.push(VectorLevel {
+                commitment: self.merkle_root_at(index),
+                opening: self.hasher.open(&slots, 1 + digit),
+            });
+        }
+        Some(VectorProof { key, levels })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn hash_commitment_proofs_verify() {
+        let scheme = HashCommitment(StdMerkleHasher);
+        let mut node: TrieNode<u32, HashCommitment<StdMerkleHasher>, 4> =
+            TrieNode::with_hasher(scheme);
+        for key in [0, 1, 5, 6, 21, 300, 1000] {
+            node.insert(key, key * 10);
+        }
+        let root = node.merkle_root();
+        for key in [0, 1, 5, 21, 1000] {
+            let proof = node.generate_vector_proof(key).unwrap();
+            assert!(proof.verify(&scheme, 4, &root, &(key * 10)));
+            assert!(!proof.verify(&scheme, 4, &root, &(key * 10 + 1)));
+        }
+        assert_eq!(node.generate_vector_proof(2), None);
+    }
+}
@@ -0,0 +1,361 @@
+use crate::codec::ValueCodec;
+use crate::hasher::{hashes_equal, MerkleHasher};
+use crate::merkle_data::MerkleData;
+use crate::proof::{MerkleProof, ProofLevel, PROOF_FORMAT_VERSION};
+use crate::trie_node::trie_node::{TrieNode, ROOT};
+use crate::verifier;
+
+/// The nodes on a key's path as they stand, down to the key's own node, or to its deepest
+/// ancestor if the path stops short. It shows what is under the key, including that nothing
+/// is, and what the root becomes once a value is put there.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathWitness<D> {
+    /// The deepest node's data hash, the empty hash if it holds no value.
+    pub data_hash: D,
+    /// Laid out as a proof of the deepest node: the levels are its ancestors, nearest first,
+    /// and the children roots its own.
+    pub proof: MerkleProof<D>,
+}
+
+/// A server's answer to a get or a put: the value under the key before the request, if any,
+/// and the key's path as it was then.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KvResponse<T, D> {
+    pub value: Option<T>,
+    pub witness: PathWitness<D>,
+}
+
+// present u8 | value (u32 len + bytes), if present | data hash (u16 len + bytes) | proof, as
+// `MerkleProof::to_bytes`
+impl<T, D: AsRef<[u8]>> KvResponse<T, D> {
+    pub fn to_bytes<C: ValueCodec<T>>(&self, codec: &C) -> Vec<u8> {
+        let mut bytes = vec![self.value.is_some() as u8];
+        if let Some(value) = &self.value {
+            let value = codec.encode(value);
+            bytes.extend_from_slice(&(value.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(&value);
+        }
+        let data_hash = self.witness.data_hash.as_ref();
+        bytes.extend_from_slice(&(data_hash.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(data_hash);
+        bytes.extend_from_slice(&self.witness.proof.to_bytes());
+        bytes
+    }
+
+    /// `None` if the bytes are malformed. The response still has to be checked by a client.
+    pub fn from_bytes<H, C>(bytes: &[u8], codec: &C) -> Option<Self>
+    where
+        H: MerkleHasher<Hash = D>,
+        C: ValueCodec<T>,
+    {
+        fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+            let taken = bytes.get(..len)?;
+            *bytes = &bytes[len..];
+            Some(taken)
+        }
+
+        let mut bytes = bytes;
+        let value = if take(&mut bytes, 1)?[0] != 0 {
+            let len = u32::from_be_bytes(take(&mut bytes, 4)?.try_into().ok()?) as usize;
+            Some(codec.decode(take(&mut bytes, len)?)?)
+        } else {
+            None
+        };
+        let len = u16::from_be_bytes(take(&mut bytes, 2)?.try_into().ok()?) as usize;
+        let data_hash = H::hash_from_bytes(take(&mut bytes, len)?)?;
+        let proof = MerkleProof::from_bytes::<H>(bytes)?;
+        Some(KvResponse {
+            value,
+            witness: PathWitness { data_hash, proof },
+        })
+    }
+}
+
+/// Why a client refused a response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KvError {
+    /// The witness isn't laid out for the request's key and the client's arity.
+    Malformed,
+    /// The witness doesn't lead up to the client's root.
+    RootMismatch,
+    /// The witness doesn't show the response's value under the key.
+    ValueMismatch,
+}
+
+/// A key-value store that answers every get and put with the key's path witness, so that a
+/// `VerifiableKvClient` holding only the root can check it. The server is trusted for nothing.
+pub struct VerifiableKvServer<T: MerkleData, H: MerkleHasher, const N: usize = 2> {
+    trie: TrieNode<T, H, N>,
+}
+
+impl<T: MerkleData + Clone + PartialEq, H: MerkleHasher + Default, const N: usize> Default
+    for VerifiableKvServer<T, H, N>
+{
+    fn default() -> Self {
+        VerifiableKvServer::with_hasher(H::default())
+    }
+}
+
+impl<T: MerkleData + Clone + PartialEq, H: MerkleHasher, const N: usize>
+    VerifiableKvServer<T, H, N>
+{
+    pub fn with_hasher(hasher: H) -> Self {
+        VerifiableKvServer {
+            trie: TrieNode::with_hasher(hasher),
+        }
+    }
+
+    pub fn trie(&self) -> &TrieNode<T, H, N> {
+        &self.trie
+    }
+
+    pub fn root(&mut self) -> H::Hash {
+        self.trie.merkle_root()
+    }
+
+    pub fn get(&mut self, key: u32) -> KvResponse<T, H::Hash> {
+        KvResponse {
+            value: self.trie.get(key).cloned(),
+            witness: self.witness(key),
+        }
+    }
+
+    /// Stores `value` under `key`. The response is for the trie before the put, from which
+    /// the client works out the root after it.
+    pub fn put(&mut self, key: u32, value: T) -> KvResponse<T, H::Hash> {
+        let response = self.get(key);
+        self.trie.insert(key, value);
+        response
+    }
+
+    fn witness(&mut self, key: u32) -> PathWitness<H::Hash> {
+        let mut levels = vec![];
+        let mut index = ROOT;
+        for depth in 0..TrieNode::<T, H, N>::key_depth(key) {
+            let digit = TrieNode::<T, H, N>::digit_at(key, depth);
+            let Some(child) = self.trie.node(index).child(digit) else {
+                break;
+            };
+            levels.push(ProofLevel {
+                data_hash: self.trie.data_hash_at(index),
+                siblings: self.trie.child_roots(index, Some(digit)),
+                position: digit as u8,
+            });
+            index = child;
+        }
+        levels.reverse();
+        let children_roots = if self.trie.node(index).is_leaf() {
+            vec![]
+        } else {
+            self.trie.child_roots(index, None)
+        };
+        PathWitness {
+            data_hash: self.trie.data_hash_at(index),
+            proof: MerkleProof {
+                version: PROOF_FORMAT_VERSION,
+                key,
+                arity: N,
+                children_roots,
+                levels,
+            },
+        }
+    }
+}
+
+/// The other side of a `VerifiableKvServer`: it keeps only the trie's root, checks each
+/// response against it, and moves it on by itself after a put.
+#[derive(Debug, Clone)]
+pub struct VerifiableKvClient<H: MerkleHasher, const N: usize = 2> {
+    hasher: H,
+    root: H::Hash,
+}
+
+impl<H: MerkleHasher, const N: usize> VerifiableKvClient<H, N> {
+    /// A client for a store with root `root`, obtained some other way.
+    pub fn new(hasher: H, root: H::Hash) -> Self {
+        VerifiableKvClient { hasher, root }
+    }
+
+    pub fn root(&self) -> &H::Hash {
+        &self.root
+    }
+
+    fn depth(key: u32) -> usize {
+        (u32::BITS - key.leading_zeros()).div_ceil(N.trailing_zeros()) as usize
+    }
+
+    fn digit(key: u32, depth: usize) -> usize {
+        (key >> (depth as u32 * N.trailing_zeros())) as usize & (N - 1)
+    }
+
+    /// The value under `key`, if `response` shows it, or shows there is none, against the
+    /// client's root.
+    pub fn verify_get<T: MerkleData>(
+        &self,
+        key: u32,
+        response: KvResponse<T, H::Hash>,
+    ) -> Result<Option<T>, KvError> {
+        self.check(key, &response)?;
+        Ok(response.value)
+    }
+
+    /// Checks the response to putting `value` under `key` and moves the client's root to the
+    /// one after the put, worked out from the witness. Returns the value the put replaced.
+    pub fn verify_put<T: MerkleData>(
+        &mut self,
+        key: u32,
+        value: &T,
+        response: KvResponse<T, H::Hash>,
+    ) -> Result<Option<T>, KvError> {
+        self.check(key, &response)?;
+        let witness = &response.witness;
+        let bottom = witness.proof.levels.len();
+        let value_hash = self.hasher.hash(&value.merkle_bytes());
+        let (data_hash, children_roots) = if bottom == Self::depth(key) {
+            (value_hash, witness.proof.children_roots.clone())
+        } else {
+            // The put adds a chain of nodes without values down to its own.
+            let empty = self.hasher.empty_hash();
+            let mut hash = value_hash;
+            for depth in (bottom + 1..Self::depth(key)).rev() {
+                let mut children = vec![empty.clone(); N];
+                children[Self::digit(key, depth)] = hash;
+                hash = self.hasher.combine_children(&empty, &children);
+            }
+            let mut children = witness.proof.children_roots.clone();
+            children.resize(N, empty);
+            children[Self::digit(key, bottom)] = hash;
+            (witness.data_hash.clone(), children)
+        };
+        self.root = self
+            .fold(&data_hash, &children_roots, &witness.proof.levels)
+            .ok_or(KvError::Malformed)?;
+        Ok(response.value)
+    }
+
+    fn fold(
+        &self,
+        data_hash: &H::Hash,
+        children_roots: &[H::Hash],
+        levels: &[ProofLevel<H::Hash>],
+    ) -> Option<H::Hash> {
+        let hash = if children_roots.is_empty() {
+            data_hash.clone()
+        } else {
+            self.hasher.combine_children(data_hash, children_roots)
+        };
+        verifier::fold_levels(&self.hasher, N, hash, levels)
+    }
+
+    fn check<T: MerkleData>(
+        &self,
+        key: u32,
+        response: &KvResponse<T, H::Hash>,
+    ) -> Result<(), KvError> {
+        let witness = &response.witness;
+        let proof = &witness.proof;
+        let bottom = proof.levels.len();
+        let well_formed = proof.key == key
+            && proof.arity == N
+            && bottom <= Self::depth(key)
+            && (proof.children_roots.is_empty() || proof.children_roots.len() == N)
+            && proof
+                .levels
+                .iter()
+                .zip((0..bottom).rev())
+                .all(|(level, depth)| level.position as usize == Self::digit(key, depth));
+        if !well_formed {
+            return Err(KvError::Malformed);
+        }
+        let root = self
+            .fold(&witness.data_hash, &proof.children_roots, &proof.levels)
+            .ok_or(KvError::Malformed)?;
+        if !hashes_equal(root.as_ref(), self.root.as_ref()) {
+            return Err(KvError::RootMismatch);
+        }
+
+        let empty = self.hasher.empty_hash();
+        let shown = if bottom == Self::depth(key) {
+            let expected = match &response.value {
+                Some(value) => self.hasher.hash(&value.merkle_bytes()),
+                None => empty,
+            };
+            hashes_equal(witness.data_hash.as_ref(), expected.as_ref())
+        } else {
+            // The path stops short, so the key's node must not exist.
+            response.value.is_none()
+                && proof
+                    .children_roots
+                    .get(Self::digit(key, bottom))
+                    .is_none_or(|child| hashes_equal(child.as_ref(), empty.as_ref()))
+        };
+        if !shown {
+            return Err(KvError::ValueMismatch);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::codec::DisplayCodec;
+    use crate::hasher::StdMerkleHasher;
+
+    #[test]
+    fn clients_track_the_root_through_checked_responses() {
+        let mut server: VerifiableKvServer<u32, StdMerkleHasher, 4> = VerifiableKvServer::default();
+        let mut client = VerifiableKvClient::<_, 4>::new(StdMerkleHasher, server.root());
+        let wire = |response: KvResponse<u32, String>| {
+            KvResponse::from_bytes::<StdMerkleHasher, _>(
+                &response.to_bytes(&DisplayCodec),
+                &DisplayCodec,
+            )
+            .unwrap()
+        };
+        // Fresh keys on new and existing paths, an interior node, and overwrites.
+        for (key, value) in [(0, 1), (300, 2), (44, 3), (11, 4), (300, 5), (2, 6), (0, 7)] {
+            let response = wire(server.put(key, value));
+            client.verify_put(key, &value, response).unwrap();
+            assert_eq!(client.root(), &server.root());
+        }
+        assert_eq!(client.verify_get(300, wire(server.get(300))), Ok(Some(5)));
+        assert_eq!(client.verify_get(44, wire(server.get(44))), Ok(Some(3)));
+        // Absent on a path that stops short, and at a node without a value.
+        assert_eq!(client.verify_get(1000, wire(server.get(1000))), Ok(None));
+        assert_eq!(client.verify_get(12, wire(server.get(12))), Ok(None));
+
+        // A changed value, a hidden one, or a response for another key is caught.
+        let mut lying = server.get(44);
+        lying.value = Some(4);
+        assert_eq!(client.verify_get(44, lying), Err(KvError::ValueMismatch));
+        let mut hiding = server.get(44);
+        hiding.value = None;
+        assert_eq!(client.verify_get(44, hiding), Err(KvError::ValueMismatch));
+        let mut cut = server.get(44);
+        let level = cut.witness.proof.levels.remove(0);
+        cut.witness.data_hash = level.data_hash;
+        cut.witness.proof.children_roots = level.siblings;
+        cut.witness
+            .proof
+            .children_roots
+            .insert(level.position as usize, StdMerkleHasher.empty_hash());
+        cut.value = None;
+        assert_eq!(client.verify_get(44, cut), Err(KvError::RootMismatch));
+        assert_eq!(
+            client.verify_get(45, server.get(44)),
+            Err(KvError::Malformed)
+        );
+
+        // A stale client refuses responses from the newer trie.
+        let stale = client.clone();
+        let response = server.put(9, 9);
+        client.verify_put(9, &9, response).unwrap();
+        assert_eq!(
+            stale.verify_get(9, server.get(9)),
+            Err(KvError::RootMismatch)
+        );
+        assert_eq!(client.verify_get(9, server.get(9)), Ok(Some(9)));
+    }
+}
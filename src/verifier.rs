@@ -0,0 +1,236 @@
+// Proof verification, kept apart from the trie so it can be audited on its own and used where
+// little else of the crate is wanted, such as a smart-contract runtime. It depends only on the
+// `MerkleHasher` trait and the proof types, and holds to these for every input, however
+// malformed:
+//
+// - It never panics. The arity is checked to be a power of two in 2..=256, the level count
+//   against the key's depth, each position against the arity and each sibling count against
+//   arity - 1, all before any of them is used to index or shift.
+// - It terminates after a bounded amount of work: one hash of the value, one combination for
+//   the proven node's children and one per level, each over at most `arity` hashes, with no
+//   recursion.
+// - Roots are compared in constant time.
+//
+// The hasher's own code is the only thing that can break these. `verify_proof_bytes` checks a
+// proof in its byte encoding under the same rules, without allocating.
+
+use crate::hasher::{hashes_equal, MerkleHasher};
+use crate::proof::{MerkleProof, ProofLevel, SUPPORTED_PROOF_VERSIONS};
+
+pub use crate::embedded::{verify_proof_bytes, StreamingHasher};
+
+/// Whether `proof` shows the value with merkle bytes `value` stored under the proof's key in the
+/// trie with root `root`.
+pub fn verify<H: MerkleHasher>(
+    hasher: &H,
+    proof: &MerkleProof<H::Hash>,
+    root: &H::Hash,
+    value: &[u8],
+) -> bool {
+    root_for(hasher, proof, value)
+        .is_some_and(|computed| hashes_equal(computed.as_ref(), root.as_ref()))
+}
+
+/// The root `proof` commits `value` to, or `None` if the proof is malformed for its key and
+/// arity.
+pub fn root_for<H: MerkleHasher>(
+    hasher: &H,
+    proof: &MerkleProof<H::Hash>,
+    value: &[u8],
+) -> Option<H::Hash> {
+    root_at_depth(hasher, proof, value, 0)
+}
+
+/// The root `proof` commits `value` to, going up by each level's position without looking at
+/// the key.
+pub fn root_by_position<H: MerkleHasher>(
+    hasher: &H,
+    proof: &MerkleProof<H::Hash>,
+    value: &[u8],
+) -> Option<H::Hash> {
+    usable_arity(proof.arity)?;
+    fold(hasher, proof, value, &proof.levels)
+}
+
+fn usable_arity(arity: usize) -> Option<()> {
+    (arity.is_power_of_two() && (2..=256).contains(&arity)).then_some(())
+}
+
+// The root of the subtree at `top` digits deep on the path to the proof's key. Only the levels
+// below `top` are folded in.
+pub(crate) fn root_at_depth<H: MerkleHasher>(
+    hasher: &H,
+    proof: &MerkleProof<H::Hash>,
+    value: &[u8],
+    top: u32,
+) -> Option<H::Hash> {
+    usable_arity(proof.arity)?;
+    let bits_per_digit = proof.arity.trailing_zeros();
+    let depth = (u32::BITS - proof.key.leading_zeros()).div_ceil(bits_per_digit);
+    if proof.levels.len() != depth as usize || top > depth {
+        return None;
+    }
+    let levels = &proof.levels[..(depth - top) as usize];
+    // An ancestor is shallower than the key, so its shift stays below 32.
+    for (level, ancestor_depth) in levels.iter().zip((top..depth).rev()) {
+        let digit = (proof.key >> (ancestor_depth * bits_per_digit)) as usize & (proof.arity - 1);
+        if level.position as usize != digit {
+            return None;
+        }
+    }
+    fold(hasher, proof, value, levels)
+}
+
+// Folds `levels`, nearest first, over the proven node. The arity must already have been
+// checked.
+fn fold<H: MerkleHasher>(
+    hasher: &H,
+    proof: &MerkleProof<H::Hash>,
+    value: &[u8],
+    levels: &[ProofLevel<H::Hash>],
+) -> Option<H::Hash> {
+    if !SUPPORTED_PROOF_VERSIONS.contains(&proof.version) {
+        return None;
+    }
+    if !proof.children_roots.is_empty() && proof.children_roots.len() != proof.arity {
+        return None;
+    }
+    let data_hash = hasher.hash(value);
+    let hash = if proof.children_roots.is_empty() {
+        data_hash
+    } else {
+        hasher.combine_children(&data_hash, &proof.children_roots)
+    };
+    fold_levels(hasher, proof.arity, hash, levels)
+}
+
+// Folds `levels` over a node with root `hash`, placing the hash so far at each level's
+// position.
+pub(crate) fn fold_levels<H: MerkleHasher>(
+    hasher: &H,
+    arity: usize,
+    mut hash: H::Hash,
+    levels: &[ProofLevel<H::Hash>],
+) -> Option<H::Hash> {
+    for level in levels {
+        if level.siblings.len() != arity - 1 || level.position as usize >= arity {
+            return None;
+        }
+        let mut children = level.siblings.clone();
+        children.insert(level.position as usize, hash);
+        hash = hasher.combine_children(&level.data_hash, &children);
+    }
+    Some(hash)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+    use crate::merkle_data::MerkleData;
+    use crate::trie_node::trie_node::TrieNode;
+
+    // Counts hash and combine calls, to check the work done is bounded by the proof's shape.
+    #[derive(Default)]
+    struct Counting(Cell<usize>);
+
+    impl MerkleHasher for Counting {
+        type Hash = <StdMerkleHasher as MerkleHasher>::Hash;
+
+        fn hash(&self, bytes: &[u8]) -> Self::Hash {
+            self.0.set(self.0.get() + 1);
+            StdMerkleHasher.hash(bytes)
+        }
+
+        fn hash_from_bytes(bytes: &[u8]) -> Option<Self::Hash> {
+            StdMerkleHasher::hash_from_bytes(bytes)
+        }
+    }
+
+    #[test]
+    fn malformed_proofs_are_rejected_without_panicking() {
+        let mut trie: TrieNode<u32, StdMerkleHasher, 4> =
+            (0..200).map(|key| (key * 7, key)).collect();
+        trie.insert(u32::MAX, 1);
+        let root = trie.merkle_root();
+        let value = 30u32.merkle_bytes();
+        let proof = trie.generate_proof(210).unwrap();
+        assert!(verify(&StdMerkleHasher, &proof, &root, &value));
+        assert_eq!(
+            root_by_position(&StdMerkleHasher, &proof, &value),
+            Some(root.clone())
+        );
+        let deepest = trie.generate_proof(u32::MAX).unwrap();
+        assert!(verify(
+            &StdMerkleHasher,
+            &deepest,
+            &root,
+            &1u32.merkle_bytes()
+        ));
+
+        let mut malformed = vec![];
+        for arity in [0, 1, 3, 512, usize::MAX] {
+            malformed.push(MerkleProof {
+                arity,
+                ..proof.clone()
+            });
+        }
+        for key in [0, 1, u32::MAX] {
+            malformed.push(MerkleProof {
+                key,
+                ..proof.clone()
+            });
+        }
+        for version in [0, 3, u8::MAX] {
+            malformed.push(MerkleProof {
+                version,
+                ..proof.clone()
+            });
+        }
+        let mut levels = proof.clone();
+        levels.levels.pop();
+        malformed.push(levels);
+        let mut children = proof.clone();
+        children.children_roots = vec![root.clone(); 3];
+        malformed.push(children);
+        for position in [1, 4, u8::MAX] {
+            let mut positioned = proof.clone();
+            positioned.levels[0].position = position;
+            malformed.push(positioned);
+        }
+        for siblings in [0, 2, 4] {
+            let mut short = proof.clone();
+            short.levels[0].siblings.truncate(siblings);
+            short.levels[0].siblings.resize(siblings, root.clone());
+            malformed.push(short);
+        }
+        for bad in &malformed {
+            assert!(!verify(&StdMerkleHasher, bad, &root, &value));
+            root_by_position(&StdMerkleHasher, bad, &value);
+        }
+
+        // Work is bounded by the proof's shape whatever it holds.
+        for candidate in malformed.iter().chain([&proof, &deepest]) {
+            let counting = Counting::default();
+            root_for(&counting, candidate, &value);
+            root_by_position(&counting, candidate, &value);
+            assert!(counting.0.get() <= 2 * (2 + candidate.levels.len()));
+        }
+
+        // So does decoding, on truncated and corrupted encodings.
+        let bytes = proof.to_bytes();
+        for len in 0..bytes.len() {
+            MerkleProof::from_bytes::<StdMerkleHasher>(&bytes[..len]);
+        }
+        for index in 0..bytes.len() {
+            let mut corrupted = bytes.clone();
+            corrupted[index] ^= 0xff;
+            if let Some(decoded) = MerkleProof::from_bytes::<StdMerkleHasher>(&corrupted) {
+                root_for(&StdMerkleHasher, &decoded, &value);
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use crate::proof::MerkleProof;
+use crate::proof_size::encoded_proof_size;
+
+/// The work a verifier does to check one proof, for choosing the arity, depth and hash function
+/// of a trie verified on chain or in a circuit. Counted for hashers that combine by
+/// concatenating hashes, as `MerkleHasher`'s defaults do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerificationCost {
+    /// The input length of every hash invocation, the value's first and the root's last.
+    pub hash_inputs: Vec<usize>,
+    /// The length of the proof as `MerkleProof::to_bytes` writes it.
+    pub proof_bytes: usize,
+}
+
+impl VerificationCost {
+    pub fn hashes(&self) -> usize {
+        self.hash_inputs.len()
+    }
+
+    pub fn bytes_hashed(&self) -> usize {
+        self.hash_inputs.iter().sum()
+    }
+
+    /// Gas for the hashing on the EVM with keccak256: 30 per call and 6 per 32-byte word.
+    /// Calldata and memory are not included.
+    pub fn keccak_gas(&self) -> u64 {
+        self.hash_inputs
+            .iter()
+            .map(|len| 30 + 6 * len.div_ceil(32) as u64)
+            .sum()
+    }
+
+    /// SHA-256 compression function calls, which dominate the constraints of a circuit
+    /// verifying with SHA-256: each input is padded by at least 9 bytes to 64-byte blocks.
+    pub fn sha256_blocks(&self) -> usize {
+        self.hash_inputs
+            .iter()
+            .map(|len| (len + 9).div_ceil(64))
+            .sum()
+    }
+}
+
+/// The cost of verifying a proof of a `value_len`-byte value `depth` digits deep in a trie of
+/// `arity`, with every hash `hash_len` bytes; `with_children` if the proven node has children.
+/// With `MerkleHasher::max_hash_len` as the hash length this bounds the cost of any such proof.
+pub fn verification_cost(
+    arity: usize,
+    depth: u32,
+    with_children: bool,
+    hash_len: usize,
+    value_len: usize,
+) -> VerificationCost {
+    let mut hash_inputs = vec![value_len];
+    if with_children {
+        hash_inputs.push((arity + 1) * hash_len);
+    }
+    hash_inputs.extend((0..depth).map(|_| (arity + 1) * hash_len));
+    VerificationCost {
+        hash_inputs,
+        proof_bytes: encoded_proof_size(arity, depth, with_children, hash_len),
+    }
+}
+
+impl<D: AsRef<[u8]>> MerkleProof<D> {
+    /// The cost of verifying this proof for a value of `value_len` bytes, from the lengths of
+    /// the hashes it carries. Hashes it doesn't carry are taken to be as long as those it does.
+    pub fn verification_cost(&self, value_len: usize) -> VerificationCost {
+        let hash_len = |hash: &D| hash.as_ref().len();
+        let sum = |hashes: &[D]| hashes.iter().map(hash_len).sum::<usize>();
+        let mut hash_inputs = vec![value_len];
+        if let Some(first) = self.children_roots.first() {
+            // The value's hash, taken to be as long as a child root, with the children.
+            hash_inputs.push(hash_len(first) + sum(&self.children_roots));
+        }
+        for level in &self.levels {
+            // The hash folded in so far, taken to be as long as the level's data hash.
+            hash_inputs.push(2 * hash_len(&level.data_hash) + sum(&level.siblings));
+        }
+        VerificationCost {
+            hash_inputs,
+            proof_bytes: self.to_bytes().len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::{MerkleHasher, StdMerkleHasher};
+    use crate::trie_node::trie_node::TrieNode;
+
+    #[test]
+    fn proof_costs_match_the_configuration() {
+        let mut trie: TrieNode<u32, StdMerkleHasher, 4> =
+            (0..200).map(|key| (key * 5, key)).collect();
+        let hash_len = StdMerkleHasher.max_hash_len();
+        for (key, with_children) in [(5, true), (995, false)] {
+            let proof = trie.generate_proof(key).unwrap();
+            let value_len = key.to_string().len();
+            let cost = proof.verification_cost(value_len);
+            let depth = TrieNode::<u32, StdMerkleHasher, 4>::key_depth(key);
+            // Std hashes vary in length, so the configuration gives a bound.
+            let bound = verification_cost(4, depth, with_children, hash_len, value_len);
+            assert_eq!(cost.hashes(), bound.hashes());
+            assert_eq!(cost.hashes(), 1 + with_children as usize + depth as usize);
+            assert!(cost
+                .hash_inputs
+                .iter()
+                .zip(&bound.hash_inputs)
+                .all(|(a, b)| a <= b));
+            assert!(cost.proof_bytes <= bound.proof_bytes);
+            assert_eq!(cost.proof_bytes, proof.to_bytes().len());
+        }
+
+        let cost = verification_cost(2, 3, false, 32, 40);
+        assert_eq!(cost.hash_inputs, [40, 96, 96, 96]);
+        assert_eq!(cost.bytes_hashed(), 328);
+        assert_eq!(cost.keccak_gas(), (30 + 12) + 3 * (30 + 18));
+        assert_eq!(cost.sha256_blocks(), 1 + 3 * 2);
+    }
+}
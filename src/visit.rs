@@ -0,0 +1,138 @@
+use crate::state_sync::NodePosition;
+use crate::trie_node::trie_node::{NodeIndex, TrieNode, ROOT};
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+/// What a `TrieVisitor` callback wants done next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VisitControl {
+    Continue,
+    /// From `pre`: don't descend into this node's children. `post` is still called.
+    SkipSubtree,
+    Stop,
+}
+
+/// A node as seen by a `TrieVisitor`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VisitedNode<'a, T, D> {
+    pub position: NodePosition,
+    pub data: Option<&'a T>,
+    /// The node's merkle root, if it is cached.
+    pub cached_hash: Option<&'a D>,
+    pub child_count: usize,
+}
+
+impl<T, D> VisitedNode<'_, T, D> {
+    /// The key stored at this node, or `None` for a value-less node that no key can reach.
+    pub fn key(&self, arity: usize) -> Option<u32> {
+        let bits_per_digit = arity.trailing_zeros();
+        let key_depth = (u32::BITS - self.position.path.leading_zeros()).div_ceil(bits_per_digit);
+        (key_depth == self.position.depth).then_some(self.position.path)
+    }
+}
+
+/// Callbacks for `TrieNode::visit`, before and after a node's children.
+pub trait TrieVisitor<T, D> {
+    fn pre(&mut self, _node: &VisitedNode<'_, T, D>) -> VisitControl {
+        VisitControl::Continue
+    }
+
+    fn post(&mut self, _node: &VisitedNode<'_, T, D>) -> VisitControl {
+        VisitControl::Continue
+    }
+}
+
+impl<T, D, V: TrieVisitor<T, D> + ?Sized> TrieVisitor<T, D> for &mut V {
+    fn pre(&mut self, node: &VisitedNode<'_, T, D>) -> VisitControl {
+        (**self).pre(node)
+    }
+
+    fn post(&mut self, node: &VisitedNode<'_, T, D>) -> VisitControl {
+        (**self).post(node)
+    }
+}
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    /// Walks every reachable node depth first, digits in ascending order. Returns `false` if a
+    /// callback stopped the walk. Pass `&mut visitor` to keep the visitor's state afterwards.
+    pub fn visit(&self, mut visitor: impl TrieVisitor<T, H::Hash>) -> bool {
+        // `true` once a node's children have been pushed, so the next pop is its `post`.
+        let mut stack: Vec<(NodeIndex, NodePosition, bool)> =
+            vec![(ROOT, NodePosition { path: 0, depth: 0 }, false)];
+        while let Some((index, position, entered)) = stack.pop() {
+            let node = self.node(index);
+            let visited = VisitedNode {
+                position,
+                data: node.get_data(),
+                cached_hash: node.cached_merkle_root(self.cache_generation),
+                child_count: node.children().iter().flatten().count(),
+            };
+            if entered {
+                if visitor.post(&visited) == VisitControl::Stop {
+                    return false;
+                }
+                continue;
+            }
+            stack.push((index, position, true));
+            match visitor.pre(&visited) {
+                VisitControl::Stop => return false,
+                VisitControl::SkipSubtree => continue,
+                VisitControl::Continue => {}
+            }
+            for digit in (0..N).rev() {
+                if let Some(child) = node.child(digit) {
+                    stack.push((child, Self::child_position(position, digit), false));
+                }
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Collect {
+        events: Vec<(&'static str, u32, u32)>,
+    }
+
+    impl TrieVisitor<u32, String> for Collect {
+        fn pre(&mut self, node: &VisitedNode<'_, u32, String>) -> VisitControl {
+            self.events
+                .push(("pre", node.position.path, node.position.depth));
+            match node.key(2) {
+                Some(1) => VisitControl::SkipSubtree,
+                Some(6) => VisitControl::Stop,
+                _ => VisitControl::Continue,
+            }
+        }
+
+        fn post(&mut self, node: &VisitedNode<'_, u32, String>) -> VisitControl {
+            self.events
+                .push(("post", node.position.path, node.position.depth));
+            VisitControl::Continue
+        }
+    }
+
+    #[test]
+    fn visitor_sees_pre_and_post_and_controls_the_walk() {
+        let node: TrieNode<u32> = [(2, 2), (1, 1), (3, 3), (6, 6)].into_iter().collect();
+        let mut collect = Collect::default();
+        assert!(!node.visit(&mut collect));
+        assert_eq!(
+            collect.events,
+            [("pre", 0, 0), ("pre", 0, 1), ("pre", 2, 2), ("pre", 6, 3)]
+        );
+
+        let mut collect = Collect::default();
+        let subtree: TrieNode<u32> = [(1, 1), (3, 3)].into_iter().collect();
+        assert!(subtree.visit(&mut collect));
+        // Key 3 sits below key 1, whose subtree is skipped.
+        assert_eq!(
+            collect.events,
+            [("pre", 0, 0), ("pre", 1, 1), ("post", 1, 1), ("post", 0, 0)]
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use std::fmt::{self, Display, Write};
+
+use crate::trie_node::trie_node::{NodeIndex, TrieNode, ROOT};
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DotOptions {
+    pub show_cached_hashes: bool,
+    pub max_hash_chars: Option<usize>,
+}
+
+impl Default for DotOptions {
+    fn default() -> Self {
+        DotOptions {
+            show_cached_hashes: true,
+            max_hash_chars: Some(8),
+        }
+    }
+}
+
+struct NodeLabel {
+    id: String,
+    lines: Vec<String>,
+    children: Vec<(String, String)>,
+}
+
+// One hex character per digit up to arity 16, two for arity 256, so paths stay unambiguous.
+fn digit_label(digit: usize, arity: usize) -> String {
+    if arity <= 16 {
+        format!("{digit:x}")
+    } else {
+        format!("{digit:02x}")
+    }
+}
+
+fn truncate(hash: &str, max_hash_chars: Option<usize>) -> &str {
+    match max_hash_chars {
+        Some(max) if max < hash.len() => &hash[..max],
+        _ => hash,
+    }
+}
+
+impl<T: MerkleData + Display, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    fn node_labels(&self, options: &DotOptions) -> Vec<NodeLabel> {
+        let mut labels = vec![];
+        let mut stack: Vec<(NodeIndex, String)> = vec![(ROOT, String::new())];
+        while let Some((index, path)) = stack.pop() {
+            let node = self.node(index);
+            let id = format!("n{path}");
+            let shown_path = if path.is_empty() { "root" } else { &path };
+            let mut lines = vec![shown_path.to_string()];
+            match node.get_data() {
+                Some(data) => lines.push(data.to_string()),
+                None => lines.push("-".to_string()),
+            }
+            if options.show_cached_hashes {
+                if let Some(hash) = node.cached_merkle_root(self.cache_generation) {
+                    let hash = H::hash_to_string(hash);
+                    lines.push(format!("#{}", truncate(&hash, options.max_hash_chars)));
+                }
+            }
+
+            let mut children = vec![];
+            for (digit, child) in node.children().iter().enumerate().rev() {
+                if let Some(child) = child {
+                    let digit = digit_label(digit, N);
+                    let child_path = format!("{path}{digit}");
+                    children.push((format!("n{child_path}"), digit));
+                    stack.push((*child, child_path));
+                }
+            }
+            children.reverse();
+            labels.push(NodeLabel {
+                id,
+                lines,
+                children,
+            });
+        }
+        labels
+    }
+
+    pub fn to_dot(&self) -> String {
+        self.to_dot_with(&DotOptions::default())
+    }
+
+    pub fn to_dot_with(&self, options: &DotOptions) -> String {
+        let mut dot = String::from("digraph trie {\n    node [shape=box];\n");
+        for node in self.node_labels(options) {
+            let label = node
+                .lines
+                .iter()
+                .map(|line| line.replace('\\', "\\\\").replace('"', "\\\""))
+                .collect::<Vec<_>>()
+                .join("\\n");
+            writeln!(dot, "    {} [label=\"{label}\"];", node.id).unwrap();
+            for (child_id, digit) in node.children {
+                writeln!(dot, "    {} -> {child_id} [label=\"{digit}\"];", node.id).unwrap();
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        self.to_mermaid_with(&DotOptions::default())
+    }
+
+    pub fn to_mermaid_with(&self, options: &DotOptions) -> String {
+        let mut mermaid = String::from("graph TD\n");
+        for node in self.node_labels(options) {
+            let label = node
+                .lines
+                .iter()
+                .map(|line| line.replace('"', "#quot;"))
+                .collect::<Vec<_>>()
+                .join("<br/>");
+            writeln!(mermaid, "    {}[\"{label}\"]", node.id).unwrap();
+            for (child_id, digit) in node.children {
+                writeln!(mermaid, "    {} -->|{digit}| {child_id}", node.id).unwrap();
+            }
+        }
+        mermaid
+    }
+
+    pub fn pretty_print(&self) -> String {
+        let mut printed = String::new();
+        let mut stack: Vec<(NodeIndex, String, String, bool)> =
+            vec![(ROOT, String::new(), String::new(), true)];
+        while let Some((index, path, indent, is_last)) = stack.pop() {
+            let node = self.node(index);
+            let shown_path = if path.is_empty() { "root" } else { &path };
+            let branch = match (path.is_empty(), is_last) {
+                (true, _) => "",
+                (false, true) => "└── ",
+                (false, false) => "├── ",
+            };
+            let data = match node.get_data() {
+                Some(data) => format!("{:?}", data.to_string()),
+                None => "-".to_string(),
+            };
+            let cache = if node.cached_merkle_root(self.cache_generation).is_some() {
+                "cached"
+            } else {
+                "dirty"
+            };
+            writeln!(printed, "{indent}{branch}{shown_path} {data} [{cache}]").unwrap();
+
+            let child_indent = match (path.is_empty(), is_last) {
+                (true, _) => String::new(),
+                (false, true) => format!("{indent}    "),
+                (false, false) => format!("{indent}│   "),
+            };
+            let children: Vec<(usize, NodeIndex)> = node
+                .children()
+                .iter()
+                .enumerate()
+                .filter_map(|(digit, child)| child.map(|c| (digit, c)))
+                .collect();
+            let last_digit = children.last().map(|(digit, _)| *digit);
+            for (digit, child) in children.into_iter().rev() {
+                stack.push((
+                    child,
+                    format!("{path}{}", digit_label(digit, N)),
+                    child_indent.clone(),
+                    Some(digit) == last_digit,
+                ));
+            }
+        }
+        printed
+    }
+}
+
+impl<T: MerkleData + Display, H: MerkleHasher, const N: usize> Display for TrieNode<T, H, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.pretty_print())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn dot_export() {
+        let mut node: TrieNode<String> = TrieNode::new();
+        node.insert(1, "foo".to_string());
+        node.insert(2, "bar".to_string());
+        assert_eq!(
+            node.to_dot(),
+            "digraph trie {\n    node [shape=box];\n    n [label=\"root\\n-\"];\n    n -> n0 [label=\"0\"];\n    n -> n1 [label=\"1\"];\n    n0 [label=\"0\\n-\"];\n    n0 -> n01 [label=\"1\"];\n    n01 [label=\"01\\nbar\"];\n    n1 [label=\"1\\nfoo\"];\n}\n"
+        );
+
+        node.merkle_root();
+        let dot = node.to_dot_with(&DotOptions {
+            show_cached_hashes: true,
+            max_hash_chars: Some(4),
+        });
+        assert!(dot.contains("n [label=\"root\\n-\\n#1383\"];"));
+    }
+
+    #[test]
+    fn mermaid_export() {
+        let mut node: TrieNode<String> = TrieNode::new();
+        node.insert(1, "foo".to_string());
+        assert_eq!(
+            node.to_mermaid(),
+            "graph TD\n    n[\"root<br/>-\"]\n    n -->|1| n1\n    n1[\"1<br/>foo\"]\n"
+        );
+    }
+
+    #[test]
+    fn pretty_print_shows_paths_and_cache_state() {
+        let mut node: TrieNode<String> = TrieNode::new();
+        node.insert(1, "foo".to_string());
+        node.insert(2, "bar".to_string());
+        node.insert(4, "baz".to_string());
+        node.merkle_root();
+        node.insert(1, "qux".to_string());
+        assert_eq!(
+            node.to_string(),
+            "root - [dirty]\n\
+             ├── 0 - [cached]\n\
+             │   ├── 00 - [cached]\n\
+             │   │   └── 001 \"baz\" [cached]\n\
+             │   └── 01 \"bar\" [cached]\n\
+             └── 1 \"qux\" [dirty]\n"
+        );
+    }
+}
@@ -0,0 +1,608 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::codec::{DisplayCodec, ValueCodec};
+use crate::state_sync::prefix_mask;
+use crate::trie_node::trie_node::TrieNode;
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+// Log layout, all integers big-endian:
+//
+//   header: magic (8) | root of the checkpoint the log applies to (u16 len + bytes)
+//   record: tag u8 | payload | crc32 of tag and payload
+//
+//   INSERT: key u32 | value (u32 len + codec bytes)
+//   REMOVE: prefix u32 | prefix_len u32
+//   COMMIT: root after the batch (u16 len + bytes)
+//
+// A batch only counts once its COMMIT record is on disk; anything after the last COMMIT was
+// cut short by a crash and is dropped on open.
+const MAGIC: &[u8; 8] = b"MRKLWAL1";
+const INSERT: u8 = 1;
+const REMOVE: u8 = 2;
+const COMMIT: u8 = 3;
+
+fn invalid(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+fn push_hash(out: &mut Vec<u8>, hash: &[u8]) {
+    out.extend_from_slice(&(hash.len() as u16).to_be_bytes());
+    out.extend_from_slice(hash);
+}
+
+/// One mutation in a `DurableTrie` batch.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WalOp<T> {
+    Insert(u32, T),
+    RemoveSubtree { prefix: u32, prefix_len: u32 },
+}
+
+impl<T> WalOp<T> {
+    // Whether applying the op in a trie with `bits_per_digit` can change the value of `key`.
+    fn touches(&self, key: u32, bits_per_digit: u32) -> bool {
+        match self {
+            WalOp::Insert(inserted, _) => *inserted == key,
+            WalOp::RemoveSubtree { prefix, prefix_len } => {
+                let mask = prefix_mask(*prefix_len, bits_per_digit);
+                let depth = (u32::BITS - key.leading_zeros()).div_ceil(bits_per_digit);
+                key & mask == prefix & mask && depth >= *prefix_len
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Parser<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn hash(&mut self) -> Option<&'a [u8]> {
+        let len = u16::from_be_bytes(self.take(2)?.try_into().unwrap());
+        self.take(len as usize)
+    }
+
+    // The next record, or `None` at the end of the log or at a torn or corrupted record.
+    fn record(&mut self) -> Option<(u8, &'a [u8])> {
+        let start = self.bytes;
+        let tag = self.take(1)?[0];
+        match tag {
+            INSERT => {
+                self.u32()?;
+                let len = self.u32()?;
+                self.take(len as usize)?;
+            }
+            REMOVE => {
+                self.take(8)?;
+            }
+            COMMIT => {
+                self.hash()?;
+            }
+            _ => return None,
+        }
+        let record = &start[..start.len() - self.bytes.len()];
+        let crc = self.u32()?;
+        (crc32(record) == crc).then_some((tag, &record[1..]))
+    }
+}
+
+/// A trie kept durable in a directory as a checkpoint plus a write-ahead log of the batches
+/// applied since. Every batch is appended and synced before `apply` returns, and `checkpoint`
+/// folds the log into a fresh checkpoint. `open` replays the committed batches, checking the
+/// root after each one against the root the log recorded. Values are written through `C`.
+pub struct DurableTrie<T: MerkleData, H: MerkleHasher, const N: usize = 2, C = DisplayCodec> {
+    trie: TrieNode<T, H, N>,
+    dir: PathBuf,
+    log: File,
+    // How much of the log holds committed batches.
+    log_len: u64,
+    codec: C,
+    // Batches committed to the log.
+    version: u64,
+    // The checkpoint's value, encoded, of every key the log's batches write to.
+    before: BTreeMap<u32, Option<Vec<u8>>>,
+}
+
+impl<T, H, const N: usize> DurableTrie<T, H, N>
+where
+    T: MerkleData + PartialEq + ToString + FromStr,
+    H: MerkleHasher + Default,
+{
+    /// Opens the trie kept in `dir`, creating an empty one if there is none, and recovers the
+    /// batches committed since the last checkpoint.
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        Self::open_with_codec(dir, DisplayCodec)
+    }
+}
+
+impl<T, H, const N: usize, C> DurableTrie<T, H, N, C>
+where
+    T: MerkleData + PartialEq,
+    H: MerkleHasher + Default,
+    C: ValueCodec<T>,
+{
+    fn checkpoint_path(dir: &Path) -> PathBuf {
+        dir.join("checkpoint")
+    }
+
+    fn log_path(dir: &Path) -> PathBuf {
+        dir.join("wal")
+    }
+
+    /// `open`, with values in the checkpoint and the log written by `codec`.
+    pub fn open_with_codec<P: AsRef<Path>>(dir: P, codec: C) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let checkpoint = TrieNode::load_checkpoint_with_codec(Self::checkpoint_path(&dir), &codec);
+        let mut trie = match checkpoint {
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                TrieNode::with_hasher(H::default())
+            }
+            loaded => loaded?,
+        };
+        let checkpoint_root = trie.merkle_root();
+
+        let log_path = Self::log_path(&dir);
+        let bytes = match fs::read(&log_path) {
+            Err(error) if error.kind() == io::ErrorKind::NotFound => vec![],
+            read => read?,
+        };
+        let mut parser = Parser { bytes: &bytes };
+        let base_root = match parser.take(MAGIC.len()) {
+            None => None,
+            Some(magic) if magic == MAGIC => parser.hash(),
+            Some(_) => return Err(invalid("not a write-ahead log")),
+        };
+        // How much of the log survives recovery; 0 if it has to be started afresh.
+        let mut durable_len = 0;
+        let mut version = 0;
+        let mut before = BTreeMap::new();
+        if base_root.is_some_and(|root| root == checkpoint_root.as_ref()) {
+            durable_len = bytes.len() - parser.bytes.len();
+            let mut batch = vec![];
+            while let Some((tag, payload)) = parser.record() {
+                if tag != COMMIT {
+                    batch.push(Self::decode_op(&codec, tag, payload)?);
+                    continue;
+                }
+                for op in batch.drain(..) {
+                    Self::remember_before(&trie, &codec, &mut before, &op);
+                    Self::apply_to(&mut trie, op);
+                }
+                if trie.merkle_root().as_ref() != (Parser { bytes: payload }).hash().unwrap() {
+                    return Err(invalid("recovered root does not match the log"));
+                }
+                durable_len = bytes.len() - parser.bytes.len();
+                version += 1;
+            }
+        } else if base_root.is_some() {
+            // A crash between writing a checkpoint and resetting the log: the checkpoint
+            // already holds every batch, so the log must end at the checkpoint's root.
+            let mut last_root = None;
+            while let Some((tag, payload)) = parser.record() {
+                if tag == COMMIT {
+                    last_root = Parser { bytes: payload }.hash();
+                }
+            }
+            if last_root != Some(checkpoint_root.as_ref()) {
+                return Err(invalid("log does not belong to the checkpoint"));
+            }
+        }
+
+        let mut durable = DurableTrie {
+            trie,
+            log: OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&log_path)?,
+            log_len: durable_len as u64,
+            dir,
+            codec,
+            version,
+            before,
+        };
+        if durable_len == 0 {
+            durable.reset_log()?;
+        } else if durable_len < bytes.len() {
+            durable.log.set_len(durable_len as u64)?;
+            durable.log.sync_all()?;
+        }
+        Ok(durable)
+    }
+
+    // The op in an INSERT or REMOVE record's payload.
+    fn decode_op(codec: &C, tag: u8, payload: &[u8]) -> io::Result<WalOp<T>> {
+        let mut payload = Parser { bytes: payload };
+        if tag == INSERT {
+            let key = payload.u32().unwrap();
+            let len = payload.u32().unwrap() as usize;
+            let value = codec
+                .decode(payload.take(len).unwrap())
+                .ok_or_else(|| invalid("unparseable value in log"))?;
+            return Ok(WalOp::Insert(key, value));
+        }
+        let prefix = payload.u32().unwrap();
+        let prefix_len = payload.u32().unwrap();
+        if !TrieNode::<T, H, N>::prefix_fits(prefix_len) {
+            return Err(invalid("bad prefix in log"));
+        }
+        Ok(WalOp::RemoveSubtree { prefix, prefix_len })
+    }
+
+    // Notes the values `op` is about to change, unless an earlier batch already changed them.
+    fn remember_before(
+        trie: &TrieNode<T, H, N>,
+        codec: &C,
+        before: &mut BTreeMap<u32, Option<Vec<u8>>>,
+        op: &WalOp<T>,
+    ) {
+        let bits_per_digit = TrieNode::<T, H, N>::BITS_PER_DIGIT;
+        match op {
+            WalOp::Insert(key, _) => {
+                before
+                    .entry(*key)
+                    .or_insert_with(|| trie.get(*key).map(|value| codec.encode(value)));
+            }
+            WalOp::RemoveSubtree { .. } => {
+                for (key, value) in trie.iter() {
+                    if op.touches(key, bits_per_digit) {
+                        before
+                            .entry(key)
+                            .or_insert_with(|| Some(codec.encode(value)));
+                    }
+                }
+            }
+        }
+    }
+
+    fn apply_to(trie: &mut TrieNode<T, H, N>, op: WalOp<T>) {
+        match op {
+            WalOp::Insert(key, value) => trie.insert(key, value),
+            WalOp::RemoveSubtree { prefix, prefix_len } => {
+                trie.remove_subtree(prefix, prefix_len);
+            }
+        }
+    }
+
+    // Starts an empty log based on the current root, replacing the old one atomically.
+    fn reset_log(&mut self) -> io::Result<()> {
+        let mut header = MAGIC.to_vec();
+        push_hash(&mut header, self.trie.merkle_root().as_ref());
+        let log_path = Self::log_path(&self.dir);
+        let temporary = log_path.with_extension("tmp");
+        let mut log = File::create(&temporary)?;
+        log.write_all(&header)?;
+        log.sync_all()?;
+        fs::rename(temporary, &log_path)?;
+        self.log = OpenOptions::new().append(true).open(log_path)?;
+        self.log_len = header.len() as u64;
+        self.version = 0;
+        self.before.clear();
+        Ok(())
+    }
+
+    pub fn trie(&self) -> &TrieNode<T, H, N> {
+        &self.trie
+    }
+
+    pub fn merkle_root(&mut self) -> H::Hash {
+        self.trie.merkle_root()
+    }
+
+    /// The batches committed since the last checkpoint. Version 0 is the checkpoint itself.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// The value under `key` as of `version`, found by replaying that key's writes from the
+    /// log rather than the whole trie. Only versions since the last checkpoint can be read;
+    /// later ones are an `InvalidInput` error.
+    pub fn read_at(&self, key: u32, version: u64) -> io::Result<Option<T>>
+    where
+        T: Clone,
+    {
+        if version > self.version {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "version is not in the log",
+            ));
+        }
+        let mut value = match self.before.get(&key) {
+            Some(Some(bytes)) => Some(
+                self.codec
+                    .decode(bytes)
+                    .ok_or_else(|| invalid("unparseable value in log"))?,
+            ),
+            Some(None) => None,
+            // Never written since the checkpoint, so what it was then is what it is now.
+            None => return Ok(self.trie.get(key).cloned()),
+        };
+        let bytes = fs::read(Self::log_path(&self.dir))?;
+        let mut parser = Parser {
+            bytes: &bytes[MAGIC.len()..],
+        };
+        parser.hash();
+        let bits_per_digit = TrieNode::<T, H, N>::BITS_PER_DIGIT;
+        let mut batch = vec![];
+        for _ in 0..version {
+            loop {
+                let (tag, payload) = parser.record().ok_or_else(|| invalid("log cut short"))?;
+                if tag == COMMIT {
+                    break;
+                }
+                batch.push(Self::decode_op(&self.codec, tag, payload)?);
+            }
+            for op in batch.drain(..) {
+                if op.touches(key, bits_per_digit) {
+                    value = match op {
+                        WalOp::Insert(_, inserted) => Some(inserted),
+                        WalOp::RemoveSubtree { .. } => None,
+                    };
+                }
+            }
+        }
+        Ok(value)
+    }
+
+    /// Applies `ops` as one batch: after a crash the trie recovers either all of them or none.
+    /// The batch is applied to a copy of the trie, which replaces it once the batch is synced,
+    /// so a failed write leaves the trie, its version and the log as they were.
+    pub fn apply<I: IntoIterator<Item = WalOp<T>>>(&mut self, ops: I) -> io::Result<()>
+    where
+        T: Clone,
+        H: Clone,
+    {
+        let ops: Vec<WalOp<T>> = ops.into_iter().collect();
+        let fits = |op: &WalOp<T>| match op {
+            WalOp::Insert(..) => true,
+            WalOp::RemoveSubtree { prefix_len, .. } => {
+                TrieNode::<T, H, N>::prefix_fits(*prefix_len)
+            }
+        };
+        if !ops.iter().all(fits) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "prefix must fit in a u32 key",
+            ));
+        }
+        let mut records = vec![];
+        let push_record = |records: &mut Vec<u8>, record: Vec<u8>| {
+            records.extend_from_slice(&record);
+            records.extend_from_slice(&crc32(&record).to_be_bytes());
+        };
+        let mut trie = self.trie.clone();
+        // The values the batch changes as of before it, for keys it is the first to write.
+        let mut before = BTreeMap::new();
+        for op in ops {
+            let mut record = vec![];
+            match &op {
+                WalOp::Insert(key, value) => {
+                    let value = self.codec.encode(value);
+                    record.push(INSERT);
+                    record.extend_from_slice(&key.to_be_bytes());
+                    record.extend_from_slice(&(value.len() as u32).to_be_bytes());
+                    record.extend_from_slice(&value);
+                }
+                WalOp::RemoveSubtree { prefix, prefix_len } => {
+                    record.push(REMOVE);
+                    record.extend_from_slice(&prefix.to_be_bytes());
+                    record.extend_from_slice(&prefix_len.to_be_bytes());
+                }
+            }
+            push_record(&mut records, record);
+            Self::remember_before(&self.trie, &self.codec, &mut before, &op);
+            Self::apply_to(&mut trie, op);
+        }
+        let mut commit = vec![COMMIT];
+        push_hash(&mut commit, trie.merkle_root().as_ref());
+        push_record(&mut records, commit);
+
+        // An earlier batch whose write failed may still be in the log if cutting it off failed
+        // too, and records written after it would be replayed as part of it.
+        if self.log.metadata()?.len() != self.log_len {
+            self.log.set_len(self.log_len)?;
+        }
+        let written = self
+            .log
+            .write_all(&records)
+            .and_then(|()| self.log.sync_data());
+        if let Err(error) = written {
+            let _ = self.log.set_len(self.log_len);
+            return Err(error);
+        }
+        self.log_len += records.len() as u64;
+        self.trie = trie;
+        for (key, value) in before {
+            self.before.entry(key).or_insert(value);
+        }
+        self.version += 1;
+        Ok(())
+    }
+
+    pub fn insert(&mut self, key: u32, value: T) -> io::Result<()>
+    where
+        T: Clone,
+        H: Clone,
+    {
+        self.apply([WalOp::Insert(key, value)])
+    }
+
+    /// Folds the log into a new checkpoint and starts an empty log.
+    pub fn checkpoint(&mut self) -> io::Result<()> {
+        self.trie
+            .save_checkpoint_with_codec(Self::checkpoint_path(&self.dir), &self.codec)?;
+        self.reset_log()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::hasher::StdMerkleHasher;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn crash_mid_batch_recovers_last_committed_batch() {
+        let dir = test_dir("wal_crash_mid_batch");
+        let mut durable: DurableTrie<u32, StdMerkleHasher> = DurableTrie::open(&dir).unwrap();
+        durable
+            .apply((0..20).map(|key| WalOp::Insert(key, key)))
+            .unwrap();
+        durable.checkpoint().unwrap();
+        durable
+            .apply([
+                WalOp::Insert(100, 1),
+                WalOp::RemoveSubtree {
+                    prefix: 0b11,
+                    prefix_len: 2,
+                },
+            ])
+            .unwrap();
+        let committed_root = durable.merkle_root();
+        let log_len = fs::metadata(dir.join("wal")).unwrap().len();
+        durable.insert(5, 500).unwrap();
+        drop(durable);
+
+        // Simulate a crash part way through writing the last batch.
+        let log = OpenOptions::new()
+            .write(true)
+            .open(dir.join("wal"))
+            .unwrap();
+        log.set_len(log_len + 7).unwrap();
+        let mut recovered: DurableTrie<u32, StdMerkleHasher> = DurableTrie::open(&dir).unwrap();
+        assert_eq!(recovered.merkle_root(), committed_root);
+        assert_eq!(
+            recovered.trie().find_by_key(5).unwrap().get_data(),
+            Some(&5)
+        );
+        assert_eq!(fs::metadata(dir.join("wal")).unwrap().len(), log_len);
+
+        // And a crash after writing a checkpoint but before resetting the log.
+        let stale_log = fs::read(dir.join("wal")).unwrap();
+        recovered.checkpoint().unwrap();
+        fs::write(dir.join("wal"), stale_log).unwrap();
+        let mut reopened: DurableTrie<u32, StdMerkleHasher> = DurableTrie::open(&dir).unwrap();
+        assert_eq!(reopened.merkle_root(), committed_root);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reads_go_back_to_any_version_in_the_log() {
+        let dir = test_dir("wal_read_at");
+        let mut durable: DurableTrie<u32, StdMerkleHasher> = DurableTrie::open(&dir).unwrap();
+        durable
+            .apply((0..8).map(|key| WalOp::Insert(key, key)))
+            .unwrap();
+        durable.checkpoint().unwrap();
+        durable.insert(3, 30).unwrap();
+        durable
+            .apply([WalOp::RemoveSubtree {
+                prefix: 0b11,
+                prefix_len: 2,
+            }])
+            .unwrap();
+        durable.insert(3, 300).unwrap();
+        durable.insert(9, 9).unwrap();
+        assert_eq!(durable.version(), 4);
+        let oversized = WalOp::RemoveSubtree {
+            prefix: 0,
+            prefix_len: u32::MAX,
+        };
+        assert!(durable.apply([WalOp::Insert(1, 1), oversized]).is_err());
+        assert_eq!(durable.version(), 4);
+
+        let history = |durable: &DurableTrie<u32, StdMerkleHasher>, key| {
+            (0..=4)
+                .map(|version| durable.read_at(key, version).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let expected_3 = [Some(3), Some(30), None, Some(300), Some(300)];
+        assert_eq!(history(&durable, 3), expected_3);
+        assert_eq!(history(&durable, 7), [Some(7), Some(7), None, None, None]);
+        assert_eq!(history(&durable, 9), [None, None, None, None, Some(9)]);
+        assert_eq!(history(&durable, 5), [Some(5); 5]);
+        assert!(durable.read_at(3, 5).is_err());
+
+        drop(durable);
+        let mut reopened: DurableTrie<u32, StdMerkleHasher> = DurableTrie::open(&dir).unwrap();
+        assert_eq!(reopened.version(), 4);
+        assert_eq!(history(&reopened, 3), expected_3);
+        reopened.checkpoint().unwrap();
+        assert_eq!(reopened.version(), 0);
+        assert_eq!(reopened.read_at(3, 0).unwrap(), Some(300));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_writes_leave_the_trie_and_log_as_they_were() {
+        let dir = test_dir("wal_failed_write");
+        let mut durable: DurableTrie<u32, StdMerkleHasher> = DurableTrie::open(&dir).unwrap();
+        durable.insert(3, 3).unwrap();
+        let root = durable.merkle_root();
+        let log_len = fs::metadata(dir.join("wal")).unwrap().len();
+
+        // A handle that can't be written to stands in for a full or failing disk.
+        let log = std::mem::replace(&mut durable.log, File::open(dir.join("wal")).unwrap());
+        let batch = [
+            WalOp::Insert(3, 30),
+            WalOp::RemoveSubtree {
+                prefix: 0,
+                prefix_len: 0,
+            },
+        ];
+        assert!(durable.apply(batch.clone()).is_err());
+        assert_eq!(durable.merkle_root(), root);
+        assert_eq!(durable.version(), 1);
+        assert_eq!(durable.read_at(3, 1).unwrap(), Some(3));
+        assert_eq!(durable.trie().get(3), Some(&3));
+        assert_eq!(fs::metadata(dir.join("wal")).unwrap().len(), log_len);
+
+        // Part of a failed batch left in the log is cut off before the next one is written.
+        durable.log = log;
+        durable.log.write_all(&[INSERT, 0, 0]).unwrap();
+        durable.apply(batch).unwrap();
+        let root = durable.merkle_root();
+        assert_eq!(durable.read_at(3, 1).unwrap(), Some(3));
+        assert_eq!(durable.read_at(3, 2).unwrap(), None);
+        drop(durable);
+        let mut reopened: DurableTrie<u32, StdMerkleHasher> = DurableTrie::open(&dir).unwrap();
+        assert_eq!(reopened.version(), 2);
+        assert_eq!(reopened.merkle_root(), root);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,125 @@
+use crate::bit_path::BitPath;
+use crate::trie_node::trie_node::{NodeIndex, TrieNode, ROOT};
+use crate::{hasher::MerkleHasher, merkle_data::MerkleData};
+
+impl<T: MerkleData, H: MerkleHasher, const N: usize> TrieNode<T, H, N> {
+    /// Hashes the subtrees at `prefixes` ahead of time, so that a latency-critical
+    /// `merkle_root` or proof later finds their hashes cached and only combines the levels above
+    /// them. A key's own subtree is at `TrieNode::key_path(key)`. Prefixes the trie has no node
+    /// at are skipped, and the cache policy still decides which hashes are kept. Returns the
+    /// number of subtrees hashed.
+    pub fn warm_cache<I: IntoIterator<Item = BitPath>>(&mut self, prefixes: I) -> usize {
+        let indexes = self.prefix_indexes(prefixes);
+        for index in &indexes {
+            self.merkle_root_at(*index);
+        }
+        indexes.len()
+    }
+
+    /// The prefixes of the regions changed since their hashes were last cached, depth first and
+    /// none under another, for targeted recomputation, replication or `warm_cache`. Every node
+    /// with no cached root is either in one of their subtrees or on the path above one, where
+    /// it only needs recombining. A stale node whose own value is unchanged is left to its stale
+    /// children, unless it has none, as when a subtree was only detached from under it. Hashes
+    /// the cache policy declines to keep count as stale too.
+    pub fn dirty_prefixes(&self) -> impl Iterator<Item = BitPath> + '_ {
+        let generation = self.cache_generation;
+        let stale =
+            move |index: NodeIndex| self.node(index).cached_merkle_root(generation).is_none();
+        let mut stack = vec![];
+        if stale(ROOT) {
+            stack.push((ROOT, BitPath::default()));
+        }
+        std::iter::from_fn(move || {
+            while let Some((index, path)) = stack.pop() {
+                let node = self.node(index);
+                let stale_children: Vec<_> = node
+                    .children()
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(digit, child)| Some((digit, (*child)?)))
+                    .filter(|(_, child)| stale(*child))
+                    .collect();
+                if node.cached_data_hash(generation).is_none() || stale_children.is_empty() {
+                    return Some(path);
+                }
+                for (digit, child) in stale_children.into_iter().rev() {
+                    stack.push((child, path.child(digit, Self::BITS_PER_DIGIT)));
+                }
+            }
+            None
+        })
+    }
+
+    pub(crate) fn prefix_indexes<I: IntoIterator<Item = BitPath>>(
+        &self,
+        prefixes: I,
+    ) -> Vec<NodeIndex> {
+        prefixes
+            .into_iter()
+            .filter_map(|prefix| self.index_by_path(&prefix))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn warmed_subtrees_are_cached_before_the_root() {
+        let mut node: TrieNode<u32> = (0..300).map(|key| (key * 3, key)).collect();
+        let mut expected = node.clone();
+        let left = BitPath::new(0b0, 1);
+        let missing = BitPath::new(0, 40);
+        assert_eq!(node.warm_cache([left, missing]), 1);
+        assert!(node
+            .find_by_path(&left)
+            .unwrap()
+            .cached_merkle_root(0)
+            .is_some());
+        assert_eq!(
+            node.find_by_path(&BitPath::new(1, 1))
+                .unwrap()
+                .cached_merkle_root(0),
+            None
+        );
+        assert_eq!(node.current_root(), None);
+        assert_eq!(node.merkle_root(), expected.merkle_root());
+    }
+
+    #[test]
+    fn dirty_prefixes_cover_the_changes() {
+        let mut node: TrieNode<u32, crate::hasher::StdMerkleHasher, 4> =
+            (0..300).map(|key| (key * 3, key)).collect();
+        assert_eq!(
+            node.dirty_prefixes().collect::<Vec<_>>(),
+            [BitPath::default()]
+        );
+        node.merkle_root();
+        assert_eq!(node.dirty_prefixes().count(), 0);
+
+        // A changed value, a new key below an existing one and a detached subtree.
+        node.insert(0b1011, 1);
+        node.insert(0b01_1110, 1);
+        node.remove_subtree(0b1101, 2);
+        let dirty: Vec<BitPath> = node.dirty_prefixes().collect();
+        assert_eq!(
+            dirty,
+            [
+                BitPath::new(0b01, 2),
+                BitPath::new(0b01_1110, 6),
+                BitPath::new(0b1011, 4),
+            ]
+        );
+        let mut warmed = node.clone();
+        assert_eq!(warmed.warm_cache(dirty), 3);
+        // Left with the nodes above them, which now have no stale children.
+        assert_eq!(
+            warmed.dirty_prefixes().collect::<Vec<_>>(),
+            [BitPath::new(0b1110, 4), BitPath::new(0b11, 2)]
+        );
+        assert_eq!(warmed.merkle_root(), node.merkle_root());
+    }
+}